@@ -699,41 +699,62 @@ impl SyntaxHighlighter {
         range: &Range<usize>,
         theme: &HighlightTheme,
     ) -> Vec<(Range<usize>, HighlightStyle)> {
-        let mut styles = vec![];
-        let start_offset = range.start;
-
-        let highlights = self.match_styles(range.clone());
+        token_styles(range, &self.tokens(range), theme)
+    }
 
-        // let mut iter_count = 0;
-        for item in highlights {
-            // iter_count += 1;
-            let node_range = &item.range;
-            let name = &item.name;
+    /// Returns the highlight tokens in the given range, each token is the byte range
+    /// and the highlight name, like `function`, `string`, `comment`, etc.
+    ///
+    /// The tokens are independent of the theme, use [`token_styles`] to map them to
+    /// the styles of a theme.
+    pub fn tokens(&self, range: &Range<usize>) -> Vec<(Range<usize>, SharedString)> {
+        self.match_styles(range.clone())
+            .into_iter()
+            .map(|item| {
+                // Avoid start larger than end
+                let mut node_range =
+                    item.range.start.max(range.start)..item.range.end.min(range.end);
+                if node_range.start > node_range.end {
+                    node_range.end = node_range.start;
+                }
 
-            // Avoid start larger than end
-            let mut node_range = node_range.start.max(range.start)..node_range.end.min(range.end);
-            if node_range.start > node_range.end {
-                node_range.end = node_range.start;
-            }
+                (node_range, item.name)
+            })
+            .collect()
+    }
+}
 
-            styles.push((node_range, theme.style(name.as_ref()).unwrap_or_default()));
-        }
+/// Map the highlight tokens (from [`SyntaxHighlighter::tokens`]) in the range to the
+/// styles of the theme, without re-parsing the text.
+pub fn token_styles(
+    range: &Range<usize>,
+    tokens: &[(Range<usize>, SharedString)],
+    theme: &HighlightTheme,
+) -> Vec<(Range<usize>, HighlightStyle)> {
+    let styles = tokens
+        .iter()
+        .map(|(range, name)| {
+            (
+                range.clone(),
+                theme.style(name.as_ref()).unwrap_or_default(),
+            )
+        })
+        .collect::<Vec<_>>();
 
-        // If the matched styles is empty, return a default range.
-        if styles.len() == 0 {
-            return vec![(start_offset..range.end, HighlightStyle::default())];
-        }
+    // If the matched styles is empty, return a default range.
+    if styles.is_empty() {
+        return vec![(range.clone(), HighlightStyle::default())];
+    }
 
-        let styles = unique_styles(&range, styles);
+    let styles = unique_styles(range, styles);
 
-        // NOTE: DO NOT remove this comment, it is used for debugging.
-        // for style in &styles {
-        //     println!("---- style: {:?} - {:?}", style.0, style.1.color);
-        // }
-        // println!("--------------------------------");
+    // NOTE: DO NOT remove this comment, it is used for debugging.
+    // for style in &styles {
+    //     println!("---- style: {:?} - {:?}", style.0, style.1.color);
+    // }
+    // println!("--------------------------------");
 
-        styles
-    }
+    styles
 }
 
 /// To merge intersection ranges, let the subsequent range cover
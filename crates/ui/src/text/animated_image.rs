@@ -0,0 +1,205 @@
+//! The element to play the animated images (e.g.: GIF) in the text view.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use gpui::{
+    App, Corners, Div, ElementId, InteractiveElement, Interactivity, IntoElement, ObjectFit,
+    ParentElement as _, RenderImage, RenderOnce, SharedString, Stateful,
+    StatefulInteractiveElement, StyleRefinement, Styled, Window, canvas, div,
+};
+
+/// The delay of the frames without a delay (or too short), like the browsers.
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+/// The frame delays shorter than or equal to this are replaced by [`DEFAULT_FRAME_DELAY`].
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(10);
+
+/// Play the frames of a multi-frame [`RenderImage`] by the frame delays.
+///
+/// The frames are advanced on paint by the window frame clock, and paused (not requesting the
+/// next frame) while the image is out of the visible area, e.g.: scrolled out of the view.
+/// If `animate` is false, only the first frame is painted.
+///
+/// NOTE: The decoded [`RenderImage`] does not keep the loop count of the GIF,
+/// the animation is looped forever.
+#[derive(IntoElement)]
+pub(super) struct AnimatedImage {
+    id: ElementId,
+    base: Stateful<Div>,
+    data: Arc<RenderImage>,
+    animate: bool,
+}
+
+impl AnimatedImage {
+    pub(super) fn new(id: impl Into<ElementId>, data: Arc<RenderImage>) -> Self {
+        let id = id.into();
+        Self {
+            base: div().id(id.clone()),
+            id,
+            data,
+            animate: true,
+        }
+    }
+
+    /// Set to play the animation, default is true.
+    pub(super) fn animate(mut self, animate: bool) -> Self {
+        self.animate = animate;
+        self
+    }
+}
+
+impl Styled for AnimatedImage {
+    fn style(&mut self) -> &mut StyleRefinement {
+        self.base.style()
+    }
+}
+
+impl InteractiveElement for AnimatedImage {
+    fn interactivity(&mut self) -> &mut Interactivity {
+        self.base.interactivity()
+    }
+}
+
+impl StatefulInteractiveElement for AnimatedImage {}
+
+impl RenderOnce for AnimatedImage {
+    fn render(self, window: &mut Window, cx: &mut App) -> impl IntoElement {
+        let state = window.use_keyed_state(
+            SharedString::from(format!("{}/frames", self.id)),
+            cx,
+            |_, _| FrameState::default(),
+        );
+        let data = self.data;
+        let animate = self.animate;
+
+        self.base.child(
+            canvas(
+                |_, _, _| {},
+                move |bounds, _, window, cx| {
+                    let visible = window.content_mask().bounds.intersects(&bounds);
+                    let playing = animate && visible;
+                    let frame_ix = if animate {
+                        let delays = (0..data.frame_count())
+                            .map(|ix| frame_delay(Duration::from(data.delay(ix))))
+                            .collect::<Vec<_>>();
+                        state.update(cx, |state, _| {
+                            state.advance(&delays, playing, Instant::now())
+                        })
+                    } else {
+                        0
+                    };
+
+                    let bounds = ObjectFit::Contain.get_bounds(bounds, data.size(frame_ix));
+                    _ = window.paint_image(
+                        bounds,
+                        Corners::default(),
+                        data.clone(),
+                        frame_ix,
+                        false,
+                    );
+                    if playing {
+                        window.request_animation_frame();
+                    }
+                },
+            )
+            .size_full(),
+        )
+    }
+}
+
+fn frame_delay(delay: Duration) -> Duration {
+    if delay <= MIN_FRAME_DELAY {
+        DEFAULT_FRAME_DELAY
+    } else {
+        delay
+    }
+}
+
+#[derive(Default)]
+struct FrameState {
+    frame_ix: usize,
+    /// The time of the current frame shown, None if paused.
+    frame_time: Option<Instant>,
+}
+
+impl FrameState {
+    /// Advance the frames by the elapsed time since the current frame shown, return the frame
+    /// index to paint. The paused animation is resumed from the current frame.
+    fn advance(&mut self, delays: &[Duration], playing: bool, now: Instant) -> usize {
+        if !playing || delays.len() < 2 {
+            self.frame_time = None;
+            return self.frame_ix.min(delays.len().saturating_sub(1));
+        }
+
+        let Some(frame_time) = self.frame_time else {
+            self.frame_time = Some(now);
+            return self.frame_ix;
+        };
+
+        // Skip the full loops, e.g.: the window was not painted for a while.
+        let total = delays.iter().sum::<Duration>();
+        let mut elapsed = now.saturating_duration_since(frame_time);
+        if elapsed >= total {
+            elapsed = Duration::from_nanos((elapsed.as_nanos() % total.as_nanos()) as u64);
+        }
+
+        let mut frame_time = now - elapsed;
+        while elapsed >= delays[self.frame_ix] {
+            elapsed -= delays[self.frame_ix];
+            frame_time += delays[self.frame_ix];
+            self.frame_ix = (self.frame_ix + 1) % delays.len();
+        }
+        self.frame_time = Some(frame_time);
+        self.frame_ix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{FrameState, frame_delay};
+
+    #[test]
+    fn test_frame_delay() {
+        assert_eq!(frame_delay(Duration::ZERO), Duration::from_millis(100));
+        assert_eq!(
+            frame_delay(Duration::from_millis(10)),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            frame_delay(Duration::from_millis(50)),
+            Duration::from_millis(50)
+        );
+    }
+
+    #[test]
+    fn test_advance_frames() {
+        let delays = [
+            Duration::from_millis(100),
+            Duration::from_millis(200),
+            Duration::from_millis(100),
+        ];
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+
+        let mut state = FrameState::default();
+        assert_eq!(state.advance(&delays, true, start), 0);
+        assert_eq!(state.advance(&delays, true, ms(50)), 0);
+        assert_eq!(state.advance(&delays, true, ms(100)), 1);
+        assert_eq!(state.advance(&delays, true, ms(250)), 1);
+        assert_eq!(state.advance(&delays, true, ms(300)), 2);
+        // Loop to the first frame.
+        assert_eq!(state.advance(&delays, true, ms(400)), 0);
+        // Skip the full loops.
+        assert_eq!(state.advance(&delays, true, ms(400 + 4000 + 150)), 1);
+
+        // Paused at the current frame, then resumed from it.
+        assert_eq!(state.advance(&delays, false, ms(10_000)), 1);
+        assert_eq!(state.advance(&delays, true, ms(20_000)), 1);
+        assert_eq!(state.advance(&delays, true, ms(20_199)), 1);
+        assert_eq!(state.advance(&delays, true, ms(20_200)), 2);
+    }
+}
@@ -0,0 +1,332 @@
+use std::sync::Arc;
+
+use gpui::{SharedString, SharedUri};
+
+use crate::{
+    highlighter::HighlightTheme,
+    text::{
+        document::ParsedDocument,
+        node::{BlockNode, CodeBlock, ImageNode, InlineNode, LinkMark, Paragraph, Span, TextMark},
+    },
+};
+
+/// A builder to construct a [`ParsedDocument`] from code, e.g.: assembling a chat message.
+///
+/// ```ignore
+/// let document = DocumentBuilder::new()
+///     .heading(2, "Title")
+///     .paragraph(|p| p.text("Hello ").bold("World").text(", see ").link("docs", "https://a.com"))
+///     .code_block("rust", "fn main() {}")
+///     .build();
+///
+/// state.update(cx, |state, cx| state.set_document(document, cx));
+/// ```
+pub struct DocumentBuilder {
+    blocks: Vec<BlockNode>,
+    highlight_theme: Arc<HighlightTheme>,
+}
+
+impl Default for DocumentBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocumentBuilder {
+    /// Create a new empty document builder.
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![],
+            highlight_theme: HighlightTheme::default_light(),
+        }
+    }
+
+    /// Set the highlight theme for the code blocks, default is [`HighlightTheme::default_light`].
+    ///
+    /// This must be called before adding the code blocks.
+    pub fn highlight_theme(mut self, highlight_theme: Arc<HighlightTheme>) -> Self {
+        self.highlight_theme = highlight_theme;
+        self
+    }
+
+    /// Add a heading with the plain text, the `level` is 1 to 6.
+    pub fn heading(mut self, level: u8, text: impl Into<SharedString>) -> Self {
+        self.blocks.push(BlockNode::Heading {
+            level: level.clamp(1, 6),
+            children: ParagraphBuilder::new().text(text).paragraph,
+            id: None,
+            span: None,
+        });
+        self
+    }
+
+    /// Add a paragraph with the inline content.
+    pub fn paragraph(mut self, f: impl FnOnce(ParagraphBuilder) -> ParagraphBuilder) -> Self {
+        self.blocks
+            .push(BlockNode::Paragraph(f(ParagraphBuilder::new()).paragraph));
+        self
+    }
+
+    /// Add a code block with the language, e.g.: `rust`.
+    pub fn code_block(
+        mut self,
+        lang: impl Into<SharedString>,
+        code: impl Into<SharedString>,
+    ) -> Self {
+        let lang: SharedString = lang.into();
+        self.blocks.push(BlockNode::CodeBlock(CodeBlock::new(
+            code.into(),
+            (!lang.is_empty()).then_some(lang),
+            &self.highlight_theme,
+            None::<Span>,
+        )));
+        self
+    }
+
+    /// Add a blockquote with the nested blocks.
+    pub fn blockquote(mut self, f: impl FnOnce(DocumentBuilder) -> DocumentBuilder) -> Self {
+        let builder = f(self.nested());
+        self.blocks.push(BlockNode::Blockquote {
+            children: builder.blocks,
+            span: None,
+        });
+        self
+    }
+
+    /// Add a list, each item is built by a nested [`DocumentBuilder`].
+    ///
+    /// ```ignore
+    /// builder.list(false, |list| list.item(|item| item.paragraph(|p| p.text("Item 1"))))
+    /// ```
+    pub fn list(mut self, ordered: bool, f: impl FnOnce(ListBuilder) -> ListBuilder) -> Self {
+        let builder = f(ListBuilder {
+            items: vec![],
+            highlight_theme: self.highlight_theme.clone(),
+        });
+        self.blocks.push(BlockNode::List {
+            children: builder.items,
+            ordered,
+            start: 1,
+            marker: None,
+            reversed: false,
+            span: None,
+        });
+        self
+    }
+
+    /// Add a horizontal rule.
+    pub fn horizontal_rule(mut self) -> Self {
+        self.blocks.push(BlockNode::HorizontalRule {
+            style: Default::default(),
+            span: None,
+        });
+        self
+    }
+
+    /// Build the document, the Markdown of the document is used as the source.
+    pub fn build(self) -> ParsedDocument {
+        let mut document = ParsedDocument {
+            source: SharedString::default(),
+            blocks: self.blocks,
+            frontmatter: None,
+        };
+        document.source = document.to_markdown().into();
+        document.assign_heading_ids();
+        document
+    }
+
+    fn nested(&self) -> Self {
+        Self {
+            blocks: vec![],
+            highlight_theme: self.highlight_theme.clone(),
+        }
+    }
+}
+
+/// A builder for the list items, see [`DocumentBuilder::list`].
+pub struct ListBuilder {
+    items: Vec<BlockNode>,
+    highlight_theme: Arc<HighlightTheme>,
+}
+
+impl ListBuilder {
+    /// Add a list item with the nested blocks.
+    pub fn item(self, f: impl FnOnce(DocumentBuilder) -> DocumentBuilder) -> Self {
+        self.push_item(None, f)
+    }
+
+    /// Add a task list item with the checked state.
+    pub fn task(self, checked: bool, f: impl FnOnce(DocumentBuilder) -> DocumentBuilder) -> Self {
+        self.push_item(Some(checked), f)
+    }
+
+    fn push_item(
+        mut self,
+        checked: Option<bool>,
+        f: impl FnOnce(DocumentBuilder) -> DocumentBuilder,
+    ) -> Self {
+        let builder = f(DocumentBuilder::new().highlight_theme(self.highlight_theme.clone()));
+        self.items.push(BlockNode::ListItem {
+            children: builder.blocks,
+            spread: false,
+            checked,
+            value: None,
+            span: None,
+        });
+        self
+    }
+}
+
+/// A builder for the inline content of a paragraph, see [`DocumentBuilder::paragraph`].
+pub struct ParagraphBuilder {
+    paragraph: Paragraph,
+}
+
+impl ParagraphBuilder {
+    fn new() -> Self {
+        Self {
+            paragraph: Paragraph::default(),
+        }
+    }
+
+    fn push(mut self, text: impl Into<SharedString>, mark: TextMark) -> Self {
+        let text: SharedString = text.into();
+        if text.is_empty() {
+            return self;
+        }
+
+        let marks = if mark == TextMark::default() {
+            vec![]
+        } else {
+            vec![(0..text.len(), mark)]
+        };
+        self.paragraph.push(InlineNode::new(text).marks(marks));
+        self
+    }
+
+    /// Add the plain text.
+    pub fn text(self, text: impl Into<SharedString>) -> Self {
+        self.push(text, TextMark::default())
+    }
+
+    /// Add the bold text.
+    pub fn bold(self, text: impl Into<SharedString>) -> Self {
+        self.push(text, TextMark::default().bold())
+    }
+
+    /// Add the italic text.
+    pub fn italic(self, text: impl Into<SharedString>) -> Self {
+        self.push(text, TextMark::default().italic())
+    }
+
+    /// Add the strikethrough text.
+    pub fn strikethrough(self, text: impl Into<SharedString>) -> Self {
+        self.push(text, TextMark::default().strikethrough())
+    }
+
+    /// Add the underlined text.
+    pub fn underline(self, text: impl Into<SharedString>) -> Self {
+        self.push(text, TextMark::default().underline())
+    }
+
+    /// Add the inline code.
+    pub fn code(self, text: impl Into<SharedString>) -> Self {
+        self.push(text, TextMark::default().code())
+    }
+
+    /// Add the text with the custom [`TextMark`], e.g.: bold and italic.
+    pub fn styled(self, text: impl Into<SharedString>, mark: TextMark) -> Self {
+        self.push(text, mark)
+    }
+
+    /// Add a link.
+    pub fn link(self, text: impl Into<SharedString>, url: impl Into<SharedString>) -> Self {
+        self.push(
+            text,
+            TextMark::default().link(LinkMark {
+                url: url.into(),
+                ..Default::default()
+            }),
+        )
+    }
+
+    /// Add an inline image, e.g.: emoji.
+    pub fn image(mut self, url: impl Into<SharedUri>, alt: impl Into<SharedString>) -> Self {
+        self.paragraph.push_image(ImageNode {
+            url: url.into(),
+            alt: Some(alt.into()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Add a hard line break.
+    pub fn line_break(mut self) -> Self {
+        self.paragraph.push_break();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        highlighter::HighlightTheme,
+        text::{format, node::NodeContext},
+    };
+
+    use super::DocumentBuilder;
+
+    #[test]
+    fn test_builder() {
+        let document = DocumentBuilder::new()
+            .heading(2, "Title")
+            .paragraph(|p| {
+                p.text("Hello ")
+                    .bold("bold")
+                    .text(" and ")
+                    .link("link", "https://a.com")
+                    .line_break()
+                    .code("code")
+                    .text(" ")
+                    .image("https://a.com/1.png", "img")
+            })
+            .blockquote(|b| b.paragraph(|p| p.italic("Quote")))
+            .list(false, |list| {
+                list.item(|item| item.paragraph(|p| p.text("Item 1")))
+                    .task(true, |item| item.paragraph(|p| p.text("Done")))
+            })
+            .code_block("rust", "fn main() {}")
+            .horizontal_rule()
+            .build();
+
+        let source = indoc::indoc! {r#"
+            ## Title
+
+            Hello **bold** and [link](https://a.com)\
+            `code` ![img](https://a.com/1.png)
+
+            > *Quote*
+
+            - Item 1
+            - [x] Done
+
+            ```rust
+            fn main() {}
+            ```
+
+            ---
+        "#};
+        let mut parsed = format::markdown::parse(
+            source,
+            &mut NodeContext::default(),
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+        parsed.assign_heading_ids();
+
+        assert_eq!(document.to_markdown(), parsed.to_markdown());
+        assert_eq!(document.to_html(), parsed.to_html());
+        assert_eq!(document.to_plain_text(), parsed.to_plain_text());
+        assert_eq!(document.table_of_contents(), parsed.table_of_contents());
+    }
+}
@@ -1,17 +1,51 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    rc::Rc,
+    time::Duration,
+};
+
 use gpui::{
-    App, InteractiveElement as _, IntoElement, ListState, ParentElement as _, SharedString,
-    Styled as _, Window, div,
+    App, Bounds, InteractiveElement as _, IntoElement, ListState, ParentElement as _, Pixels,
+    SharedString, Styled as _, Window, div,
 };
 
-use crate::text::node::{BlockNode, NodeContext};
+use crate::{
+    ElementExt as _,
+    text::{
+        node::{BlockNode, ImageNode, LinkMark, NodeContext, Paragraph, footnote_reference_anchor},
+        utils::{dedup_by_key, slugify},
+        visitor::VisitorMut,
+    },
+};
 
 /// The parsed document AST.
+///
+/// Use [`crate::text::DocumentBuilder`] to construct it from code.
 #[derive(Debug, Clone, PartialEq, Default)]
-pub(crate) struct ParsedDocument {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParsedDocument {
     pub(crate) source: SharedString,
     pub(crate) blocks: Vec<BlockNode>,
+    /// The `key: value` pairs of the leading YAML frontmatter, see [`Self::frontmatter`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) frontmatter: Option<HashMap<String, String>>,
 }
 
+/// An entry of the table of contents, see [`crate::text::TextViewState::table_of_contents`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    /// The heading level, 1 to 6.
+    pub level: u8,
+    /// The plain text of the heading.
+    pub text: SharedString,
+    /// The anchor id of the heading, can be used as `#id` link.
+    pub id: SharedString,
+}
+
+/// The callback when a top-level block is laid out in the scrollable list, with the block index and bounds.
+pub(crate) type BlockPrepaintFn = dyn Fn(usize, Bounds<Pixels>, &mut App);
+
 #[derive(Default, Clone, Copy)]
 pub(crate) struct NodeRenderOptions {
     pub(crate) ix: usize,
@@ -19,7 +53,14 @@ pub(crate) struct NodeRenderOptions {
     pub(crate) todo: bool,
     pub(crate) ordered: bool,
     pub(crate) depth: usize,
+    /// The nesting depth of the blockquote, 0 is outside of any blockquote.
+    pub(crate) quote_depth: usize,
     pub(crate) is_last: bool,
+    /// The index of the first checkbox in the block, counting the task list items of the
+    /// document in order, see [`TextView::on_checkbox_toggle`].
+    ///
+    /// [`TextView::on_checkbox_toggle`]: crate::text::TextView::on_checkbox_toggle
+    pub(crate) checkbox_ix: usize,
 }
 
 impl NodeRenderOptions {
@@ -30,14 +71,367 @@ impl NodeRenderOptions {
 }
 
 impl ParsedDocument {
-    pub(super) fn selected_text(&self) -> String {
+    /// Returns the top-level blocks of the document.
+    pub fn blocks(&self) -> &[BlockNode] {
+        &self.blocks
+    }
+
+    /// Returns the metadata of the leading YAML frontmatter of the Markdown, e.g.: `title`, `date`.
+    ///
+    /// ```markdown
+    /// ---
+    /// title: Hello
+    /// date: 2024-01-01
+    /// ---
+    /// ```
+    ///
+    /// Only the flat `key: value` lines are supported, the frontmatter with other YAML (e.g.:
+    /// lists, nested maps) is rendered as a code block in the body, and this returns `None`.
+    pub fn frontmatter(&self) -> Option<&HashMap<String, String>> {
+        self.frontmatter.as_ref()
+    }
+
+    /// Returns the table of contents from the headings in document order.
+    ///
+    /// The heading ids are assigned by [`Self::assign_heading_ids`].
+    pub(crate) fn table_of_contents(&self) -> Vec<TocEntry> {
+        fn walk(blocks: &[BlockNode], entries: &mut Vec<TocEntry>) {
+            for block in blocks {
+                match block {
+                    BlockNode::Heading {
+                        level,
+                        children,
+                        id,
+                        ..
+                    } => entries.push(TocEntry {
+                        level: *level,
+                        text: heading_text(children).into(),
+                        id: id.clone().unwrap_or_default(),
+                    }),
+                    BlockNode::Root { children, .. }
+                    | BlockNode::Blockquote { children, .. }
+                    | BlockNode::Admonition { children, .. }
+                    | BlockNode::List { children, .. }
+                    | BlockNode::ListItem { children, .. } => walk(children, entries),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut entries = vec![];
+        walk(&self.blocks, &mut entries);
+        entries
+    }
+
+    /// Returns all links in document order, including the links of images.
+    ///
+    /// The link references are not resolved, the `url` is empty and the `identifier` is set.
+    /// If `dedup` is true, the duplicate links are removed (keep the first).
+    pub(crate) fn extract_links(&self, dedup: bool) -> Vec<LinkMark> {
+        let mut links: Vec<LinkMark> = vec![];
+        for_each_paragraph(&self.blocks, &mut |paragraph| {
+            // The link across multiple inline nodes (e.g.: `[**bold** text](url)`) is counted once.
+            let mut prev_link: Option<&LinkMark> = None;
+            for node in paragraph.children.iter() {
+                if let Some(image) = &node.image {
+                    if let Some(link) = &image.link
+                        && prev_link != Some(link)
+                    {
+                        links.push(link.clone());
+                    }
+                    prev_link = image.link.as_ref();
+                    continue;
+                }
+
+                let mut node_links = node
+                    .marks
+                    .iter()
+                    .filter_map(|(range, mark)| mark.link.as_ref().map(|link| (range, link)))
+                    .collect::<Vec<_>>();
+                node_links.sort_by_key(|(range, _)| range.start);
+
+                let mut last_end = 0;
+                for (range, link) in node_links {
+                    let continued = range.start == 0 && prev_link == Some(link);
+                    if !continued {
+                        links.push(link.clone());
+                    }
+                    prev_link = Some(link);
+                    last_end = last_end.max(range.end);
+                }
+                if last_end < node.text.len() {
+                    prev_link = None;
+                }
+            }
+        });
+
+        if dedup {
+            dedup_by_key(&mut links, |link| {
+                (
+                    link.url.clone(),
+                    link.identifier.clone(),
+                    link.title.clone(),
+                )
+            });
+        }
+
+        links
+    }
+
+    /// Returns all images in document order.
+    ///
+    /// If `dedup` is true, the images with the same url are removed (keep the first).
+    pub(crate) fn extract_images(&self, dedup: bool) -> Vec<ImageNode> {
+        let mut images: Vec<ImageNode> = vec![];
+        for_each_paragraph(&self.blocks, &mut |paragraph| {
+            for node in paragraph.children.iter() {
+                let Some(image) = &node.image else {
+                    continue;
+                };
+
+                images.push(image.clone());
+            }
+        });
+
+        if dedup {
+            dedup_by_key(&mut images, |image| image.url.clone());
+        }
+
+        images
+    }
+
+    /// Returns the number of words in the document.
+    ///
+    /// The image alt texts (including emoji) are not counted, and the code blocks
+    /// are only counted if `include_code` is true.
+    pub fn word_count(&self, include_code: bool) -> usize {
+        let mut count = 0;
+        for_each_block(&self.blocks, &mut |block| match block {
+            BlockNode::Paragraph(paragraph)
+            | BlockNode::Heading {
+                children: paragraph,
+                ..
+            } => {
+                count += paragraph
+                    .children
+                    .iter()
+                    .filter(|node| node.image.is_none())
+                    .map(|node| count_words(&node.text))
+                    .sum::<usize>();
+            }
+            BlockNode::CodeBlock(code_block) if include_code => {
+                count += count_words(&code_block.code());
+            }
+            _ => {}
+        });
+        count
+    }
+
+    /// Returns the estimated reading time with the `words_per_minute`, the code blocks are not counted.
+    pub fn reading_time(&self, words_per_minute: usize) -> Duration {
+        let words = self.word_count(false) as u64;
+        Duration::from_secs((words * 60).div_ceil(words_per_minute.max(1) as u64))
+    }
+
+    /// Returns the index of the top-level block that contains the heading with the anchor id.
+    ///
+    /// The anchors of the footnotes are also supported, e.g.: `fn-note` for the definition and
+    /// `fnref-note` (`fnref-note-2` for the second one) for the reference.
+    pub(crate) fn anchor_block_index(&self, id: &str) -> Option<usize> {
+        fn contains(block: &BlockNode, id: &str) -> bool {
+            match block {
+                BlockNode::Heading {
+                    id: Some(heading_id),
+                    ..
+                } => heading_id.as_ref() == id,
+                BlockNode::Root { children, .. }
+                | BlockNode::Blockquote { children, .. }
+                | BlockNode::Admonition { children, .. }
+                | BlockNode::List { children, .. }
+                | BlockNode::ListItem { children, .. } => {
+                    children.iter().any(|child| contains(child, id))
+                }
+                _ => false,
+            }
+        }
+
+        if let Some(ix) = self.blocks.iter().position(|block| contains(block, id)) {
+            return Some(ix);
+        }
+
+        if id.starts_with("fn-") {
+            return self
+                .blocks
+                .iter()
+                .position(|block| matches!(block, BlockNode::Footnotes { .. }));
+        }
+
+        if id.starts_with("fnref-") {
+            let mut references: HashMap<SharedString, usize> = HashMap::new();
+            for (ix, block) in self.blocks.iter().enumerate() {
+                let mut found = false;
+                for_each_paragraph(std::slice::from_ref(block), &mut |paragraph| {
+                    for node in paragraph.children.iter() {
+                        for (_, mark) in node.marks.iter() {
+                            let Some(identifier) = &mark.footnote else {
+                                continue;
+                            };
+                            let n = references.entry(identifier.clone()).or_default();
+                            *n += 1;
+                            found |= footnote_reference_anchor(identifier, *n) == id;
+                        }
+                    }
+                });
+                if found {
+                    return Some(ix);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Set the checked state of the task list item at `index` (in the task list items of the
+    /// document in order), returns false if not found.
+    pub(crate) fn set_checked(&mut self, index: usize, checked: bool) -> bool {
+        struct SetChecked {
+            index: usize,
+            checked: bool,
+            current: usize,
+            found: bool,
+        }
+
+        impl VisitorMut for SetChecked {
+            fn visit_block_mut(&mut self, block: &mut BlockNode) {
+                if let BlockNode::ListItem {
+                    checked: Some(checked),
+                    ..
+                } = block
+                {
+                    if self.current == self.index {
+                        *checked = self.checked;
+                        self.found = true;
+                    }
+                    self.current += 1;
+                }
+            }
+        }
+
+        let mut visitor = SetChecked {
+            index,
+            checked,
+            current: 0,
+            found: false,
+        };
+        self.walk_mut(&mut visitor);
+        visitor.found
+    }
+
+    /// Assign the slugified anchor id to the headings without id.
+    ///
+    /// The duplicate ids are disambiguated with `-2`, `-3` ... suffixes, the explicit ids are
+    /// reserved first, so the generated ids never collide with them.
+    pub(crate) fn assign_heading_ids(&mut self) {
+        fn walk(
+            blocks: &mut [BlockNode],
+            f: &mut impl FnMut(&Paragraph, &mut Option<SharedString>),
+        ) {
+            for block in blocks {
+                match block {
+                    BlockNode::Heading { children, id, .. } => f(children, id),
+                    BlockNode::Root { children, .. }
+                    | BlockNode::Blockquote { children, .. }
+                    | BlockNode::Admonition { children, .. }
+                    | BlockNode::List { children, .. }
+                    | BlockNode::ListItem { children, .. } => walk(children, f),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut used_ids = HashSet::new();
+        walk(&mut self.blocks, &mut |_, id| {
+            if let Some(id) = id {
+                used_ids.insert(id.clone());
+            }
+        });
+        walk(&mut self.blocks, &mut |children, id| {
+            if id.is_some() {
+                return;
+            }
+
+            let slug = slugify(&heading_text(children));
+            let slug = if slug.is_empty() {
+                "heading".to_string()
+            } else {
+                slug
+            };
+
+            let mut new_id = SharedString::from(slug.clone());
+            let mut n = 2;
+            while used_ids.contains(&new_id) {
+                new_id = format!("{}-{}", slug, n).into();
+                n += 1;
+            }
+
+            used_ids.insert(new_id.clone());
+            *id = Some(new_id);
+        });
+    }
+
+    /// Returns the selected text.
+    ///
+    /// If `block_range` is given (in the scrollable mode), the blocks between the first and last
+    /// blocks of the range are fully selected, even they are not laid out (scrolled out of view).
+    pub(super) fn selected_text(&self, block_range: Option<Range<usize>>) -> String {
+        let Some(block_range) = block_range else {
+            let mut text = String::new();
+            for block in self.blocks.iter() {
+                text.push_str(&block.selected_text());
+            }
+            return text;
+        };
+
+        let first = block_range.start;
+        let last = block_range.end.saturating_sub(1);
         let mut text = String::new();
-        for block in self.blocks.iter() {
-            text.push_str(&block.selected_text());
+        for (ix, block) in self.blocks.iter().enumerate() {
+            if !block_range.contains(&ix) {
+                continue;
+            }
+
+            if ix == first || ix == last {
+                text.push_str(&block.selected_text());
+            } else {
+                let block_text = block.to_plain_text();
+                if !block_text.is_empty() {
+                    text.push_str(&block_text);
+                    text.push('\n');
+                }
+            }
         }
         text
     }
 
+    /// Returns the selection in markdown, the blocks covered by the selection are fully included.
+    ///
+    /// The `block_range` is the same as [`ParsedDocument::selected_text`].
+    pub(super) fn selected_markdown(&self, block_range: Option<Range<usize>>) -> String {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(ix, block)| match &block_range {
+                Some(block_range) if !block_range.contains(&ix) => String::new(),
+                Some(block_range) if ix != block_range.start && ix + 1 != block_range.end => {
+                    block.to_markdown()
+                }
+                _ => block.selected_markdown(),
+            })
+            .filter(|markdown| !markdown.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
     /// Converts the node to markdown format.
     ///
     /// This is used to generate markdown for test.
@@ -50,14 +444,47 @@ impl ParsedDocument {
             .join("\n\n")
     }
 
+    /// Converts the document to semantic HTML.
+    pub(crate) fn to_html(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| block.to_html())
+            .filter(|html| !html.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Converts the document to plain text, e.g.: for search indexing or previews.
+    ///
+    /// The marks are stripped, and the images (including emoji) are represented by their alt text.
+    pub(crate) fn to_plain_text(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| block.to_plain_text())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
     pub(super) fn render_root(
         &self,
-        list_state: Option<ListState>,
+        list: Option<(ListState, Rc<BlockPrepaintFn>)>,
         node_cx: &NodeContext,
         window: &mut Window,
         cx: &mut App,
     ) -> impl IntoElement {
-        let Some(list_state) = list_state else {
+        let mut checkbox_ix = 0;
+        let checkbox_starts = self
+            .blocks
+            .iter()
+            .map(|block| {
+                let start = checkbox_ix;
+                checkbox_ix += block.checkbox_count();
+                start
+            })
+            .collect::<Vec<_>>();
+
+        let Some((list_state, on_block_prepaint)) = list else {
             let blocks_len = self.blocks.len();
             return div()
                 .id("document")
@@ -67,6 +494,7 @@ impl ParsedDocument {
                         NodeRenderOptions {
                             ix,
                             is_last,
+                            checkbox_ix: checkbox_starts[ix],
                             ..Default::default()
                         },
                         node_cx,
@@ -93,17 +521,21 @@ impl ParsedDocument {
                 let blocks = blocks.clone();
                 move |ix, window, cx| {
                     let is_last = ix + 1 == blocks.len();
-                    blocks[ix]
-                        .render_block(
+                    let on_block_prepaint = on_block_prepaint.clone();
+                    div()
+                        .w_full()
+                        .child(blocks[ix].render_block(
                             NodeRenderOptions {
                                 ix,
                                 is_last,
+                                checkbox_ix: checkbox_starts[ix],
                                 ..options
                             },
                             &node_cx,
                             window,
                             cx,
-                        )
+                        ))
+                        .on_prepaint(move |bounds, _, cx| on_block_prepaint(ix, bounds, cx))
                         .into_any_element()
                 }
             })
@@ -111,3 +543,530 @@ impl ParsedDocument {
         )
     }
 }
+
+/// Call `f` for each block (including the nested blocks and table cells) in document order.
+pub(super) fn for_each_block<'a>(blocks: &'a [BlockNode], f: &mut impl FnMut(&'a BlockNode)) {
+    for block in blocks {
+        f(block);
+        match block {
+            BlockNode::Root { children, .. }
+            | BlockNode::Blockquote { children, .. }
+            | BlockNode::Admonition { children, .. }
+            | BlockNode::List { children, .. }
+            | BlockNode::ListItem { children, .. }
+            | BlockNode::Footnotes { children }
+            | BlockNode::FootnoteDefinition { children, .. } => for_each_block(children, f),
+            BlockNode::Table(table) => {
+                for row in table.children.iter() {
+                    for cell in row.children.iter() {
+                        for_each_block(&cell.children, f);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Call `f` for each paragraph (including headings and table cells) in document order.
+fn for_each_paragraph<'a>(blocks: &'a [BlockNode], f: &mut impl FnMut(&'a Paragraph)) {
+    for_each_block(blocks, &mut |block| match block {
+        BlockNode::Paragraph(paragraph)
+        | BlockNode::Heading {
+            children: paragraph,
+            ..
+        } => f(paragraph),
+        _ => {}
+    });
+}
+
+/// Count the words in the text, each CJK character is counted as a word.
+///
+/// The tokens without any alphanumeric character (e.g.: emoji, punctuation) are not counted.
+fn count_words(text: &str) -> usize {
+    let mut count = 0;
+    for token in text.split_whitespace() {
+        let mut in_word = false;
+        for c in token.chars() {
+            if is_cjk(c) {
+                count += 1;
+                in_word = false;
+            } else if c.is_alphanumeric() {
+                if !in_word {
+                    count += 1;
+                }
+                in_word = true;
+            }
+        }
+    }
+    count
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF // Hiragana, Katakana
+        | 0x3400..=0x4DBF // CJK Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF // Hangul Syllables
+        | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+        | 0x20000..=0x2FA1F // CJK Extension B - F
+    )
+}
+
+/// Returns the plain text of the heading, the hard breaks are replaced with spaces.
+fn heading_text(paragraph: &Paragraph) -> String {
+    paragraph
+        .children
+        .iter()
+        .map(|node| {
+            if node.is_hard_break() {
+                " "
+            } else {
+                node.text.as_str()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        highlighter::HighlightTheme,
+        text::{
+            TocEntry, format,
+            node::{BlockNode, NodeContext},
+        },
+    };
+
+    #[test]
+    fn test_heading_ids() {
+        // The generated id before the explicit one does not collide with it.
+        let html = r#"<h1>Intro</h1><h2 id="intro">Other</h2><h2>Intro</h2>"#;
+        let mut cx = NodeContext::default();
+        let mut document = format::html::parse(html, &mut cx).unwrap();
+        document.assign_heading_ids();
+        assert_eq!(
+            document.to_html(),
+            "<h1 id=\"intro-2\">Intro</h1>\n<h2 id=\"intro\">Other</h2>\n<h2 id=\"intro-3\">Intro</h2>"
+        );
+    }
+
+    #[test]
+    fn test_table_of_contents() {
+        let source = indoc::indoc! {r#"
+            # Introduction
+
+            ## Getting Started
+
+            ## Getting Started
+
+            > ## Getting **Started**
+
+            ## Hello, World!
+        "#};
+        let mut cx = NodeContext::default();
+        let mut document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        document.assign_heading_ids();
+
+        let entry = |level: u8, text: &str, id: &str| TocEntry {
+            level,
+            text: text.to_string().into(),
+            id: id.to_string().into(),
+        };
+        assert_eq!(
+            document.table_of_contents(),
+            vec![
+                entry(1, "Introduction", "introduction"),
+                entry(2, "Getting Started", "getting-started"),
+                entry(2, "Getting Started", "getting-started-2"),
+                entry(2, "Getting Started", "getting-started-3"),
+                entry(2, "Hello, World!", "hello-world"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_links_and_images() {
+        let source = indoc::indoc! {r#"
+            # Heading [link](https://a.com)
+
+            Visit [**bold** text](https://b.com) and [again](https://a.com).
+
+            - Item [ref] ![img](https://img.com/1.png)
+
+            | Column |
+            | ------ |
+            | [cell](https://c.com) ![img](https://img.com/1.png) |
+
+            [ref]: https://ref.com
+        "#};
+        let mut cx = NodeContext::default();
+        let document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+
+        let urls = |dedup: bool| {
+            document
+                .extract_links(dedup)
+                .into_iter()
+                .map(|link| link.identifier.unwrap_or(link.url).to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            urls(false),
+            vec![
+                "https://a.com",
+                "https://b.com",
+                "https://a.com",
+                "ref",
+                "https://c.com"
+            ]
+        );
+        assert_eq!(
+            urls(true),
+            vec!["https://a.com", "https://b.com", "ref", "https://c.com"]
+        );
+
+        assert_eq!(document.extract_images(false).len(), 2);
+        assert_eq!(document.extract_images(true).len(), 1);
+    }
+
+    #[test]
+    fn test_link_segments() {
+        let source = indoc::indoc! {r#"
+            Visit [**bold** text](https://b.com) ![img](https://img.com/1.png) [ref]
+
+            - Item [a](https://a.com)
+
+            [ref]: https://ref.com
+        "#};
+        let mut cx = NodeContext::default();
+        let document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+
+        let mut segments = vec![];
+        for block in document.blocks.iter() {
+            block.link_segments(&cx, &mut segments);
+        }
+        // The partly bold link is merged, the image splits the paragraph text.
+        assert_eq!(
+            segments
+                .iter()
+                .map(|(_, range, link)| (range.clone(), link.url.to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                (6..15, "https://b.com".to_string()),
+                (1..4, "https://ref.com".to_string()),
+                (5..6, "https://a.com".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unresolved_link_segments() {
+        let source = "[ref]\n\n[ref]: https://ref.com";
+        let mut cx = NodeContext::default();
+        let document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        // The definition is removed, e.g.: for the disallowed scheme.
+        cx.link_refs.clear();
+
+        let mut segments = vec![];
+        document.blocks[0].link_segments(&cx, &mut segments);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].2.identifier.as_deref(), Some("ref"));
+
+        // Only skipped with sanitize.
+        cx.sanitize = true;
+        let mut segments = vec![];
+        document.blocks[0].link_segments(&cx, &mut segments);
+        assert!(segments.is_empty());
+    }
+
+    #[test]
+    fn test_to_plain_text() {
+        let source = indoc::indoc! {r#"
+            # Hello **World**
+
+            This is *italic* and [link](https://a.com) ![:smile:](https://a.com/smile.png)
+            next line.
+
+            - Item 1
+            - Item 2
+              - Nested
+
+            ```rust
+            fn main() {}
+            ```
+
+            | A | B |
+            | - | - |
+            | 1 | `2` |
+        "#};
+        let mut cx = NodeContext::default();
+        let document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+
+        assert_eq!(
+            document.to_plain_text(),
+            indoc::indoc! {"
+                Hello World
+
+                This is italic and link :smile:
+                next line.
+
+                Item 1
+                Item 2
+                Nested
+
+                fn main() {}
+
+                A\tB
+                1\t2"}
+        );
+    }
+
+    #[test]
+    fn test_to_html() {
+        let source = indoc::indoc! {r#"
+            ## Hello & World
+
+            This is **bold**, [link](https://a.com?a=1&b=2 "Title") and `a < b`.
+            ![alt "x"](https://a.com/1.png)
+
+            - [x] Done
+            - Todo
+
+            ```rust
+            let a = "<b>";
+            ```
+
+            | A | B |
+            | - | :-: |
+            | 1 | *2* |
+        "#};
+        let mut cx = NodeContext::default();
+        let mut document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        document.assign_heading_ids();
+
+        assert_eq!(
+            document.to_html(),
+            indoc::indoc! {r#"
+                <h2 id="hello-world">Hello &amp; World</h2>
+                <p>This is <strong>bold</strong>, <a href="https://a.com?a=1&amp;b=2" title="Title">link</a> and <code>a &lt; b</code>.
+                <img src="https://a.com/1.png" alt="alt &quot;x&quot;"></p>
+                <ul>
+                <li><input type="checkbox" disabled checked> Done</li>
+                <li>Todo</li>
+                </ul>
+                <pre><code class="language-rust">let a = &quot;&lt;b&gt;&quot;;</code></pre>
+                <table>
+                <thead>
+                <tr>
+                <th>A</th>
+                <th align="center">B</th>
+                </tr>
+                </thead>
+                <tbody>
+                <tr>
+                <td>1</td>
+                <td align="center"><em>2</em></td>
+                </tr>
+                </tbody>
+                </table>"#}
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        use crate::text::{document::ParsedDocument, format::html};
+
+        let source = indoc::indoc! {r#"
+            # Title
+
+            Hello **bold** [link](https://a.com "Title") ![img](https://a.com/1.png)
+
+            - [x] Done
+            - Todo
+
+            ```rust
+            fn main() {}
+            ```
+
+            | A | B |
+            | :- | -: |
+            | 1 | 2 |
+
+            ---
+        "#};
+        let mut cx = NodeContext::default();
+        let document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+
+        let json = serde_json::to_string(&document).unwrap();
+        assert!(json.contains(r#""type":"heading""#));
+        let parsed: ParsedDocument = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, document);
+
+        let html = r#"<p><img src="https://a.com/1.png" width="100" height="50%"></p>
+            <table><tr><td width="30%">A</td></tr></table>"#;
+        let document = html::parse(html, &mut NodeContext::default()).unwrap();
+        let json = serde_json::to_string(&document).unwrap();
+        let parsed: ParsedDocument = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, document);
+    }
+
+    #[test]
+    fn test_word_count() {
+        let source = indoc::indoc! {r#"
+            # Hello World
+
+            This is a **bold** paragraph, with [a link](https://a.com) ![alt text](https://a.com/1.png) 😀.
+
+            - First item
+            - Second item: 你好世界
+
+            ```rust
+            fn main() {}
+            ```
+        "#};
+        let mut cx = NodeContext::default();
+        let document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+
+        // 2 + 8 + 2 + (2 + 4 CJK)
+        assert_eq!(document.word_count(false), 18);
+        // fn, main
+        assert_eq!(document.word_count(true), 20);
+        // 18 words at 200 wpm is 5.4s
+        assert_eq!(document.reading_time(200), Duration::from_secs(6));
+        assert_eq!(document.reading_time(10), Duration::from_secs(108));
+    }
+
+    #[test]
+    fn test_selected_text_in_block_range() {
+        let mut cx = NodeContext::default();
+        let document = format::markdown::parse(
+            "First\n\n- Item 1\n- Item 2\n\nThird\n\nLast",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+
+        // No block is laid out, so there is no selection state.
+        assert_eq!(document.selected_text(None), "");
+        // The blocks between the first and last are fully selected.
+        assert_eq!(
+            document.selected_text(Some(0..4)),
+            "Item 1\nItem 2\nThird\n"
+        );
+        assert_eq!(document.selected_text(Some(1..3)), "");
+    }
+
+    #[test]
+    fn test_selected_markdown_in_block_range() {
+        let mut cx = NodeContext::default();
+        let document = format::markdown::parse(
+            "First\n\n- Item 1\n- **Item 2**\n\nThird\n\nLast",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+
+        assert_eq!(document.selected_markdown(None), "");
+        // The blocks between the first and last are fully included with the marks.
+        assert_eq!(
+            document.selected_markdown(Some(0..4)),
+            "- Item 1\n- **Item 2**\n\nThird"
+        );
+        assert_eq!(document.selected_markdown(Some(1..3)), "");
+    }
+
+    #[test]
+    fn test_selected_table_cells() {
+        let mut cx = NodeContext::default();
+        let document = format::markdown::parse(
+            "| A | B | C |\n|---|:-:|---|\n| 1 | 2 | 3 |\n| 4 | 5 \\| 6 | 7 |",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+        let BlockNode::Table(table) = &document.blocks[0] else {
+            panic!("expected table");
+        };
+
+        let select = |row: usize, col: usize| {
+            let paragraph = table.children[row].children[col].paragraph().unwrap();
+            let text = paragraph.to_plain_text();
+            let mut state = paragraph.state.lock().unwrap();
+            state.set_text(text.clone().into());
+            state.selection = Some((0..text.len()).into());
+        };
+        // The selection starts from the middle of the first row.
+        select(1, 1);
+        select(1, 2);
+        select(2, 0);
+        select(2, 1);
+
+        assert_eq!(document.selected_text(None), "\t2\t3\n4\t5 | 6\t\n");
+        assert_eq!(
+            document.selected_markdown(None),
+            "|  | 2 | 3 |\n| :-- | :-: | :-- |\n| 4 | 5 \\| 6 |  |"
+        );
+    }
+
+    #[test]
+    fn test_anchor_block_index() {
+        let source = indoc::indoc! {r#"
+            # Title
+
+            Some text.
+
+            - Item
+
+              ## Nested
+        "#};
+        let mut cx = NodeContext::default();
+        let mut document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        document.assign_heading_ids();
+
+        assert_eq!(document.anchor_block_index("title"), Some(0));
+        assert_eq!(document.anchor_block_index("nested"), Some(2));
+        assert_eq!(document.anchor_block_index("missing"), None);
+    }
+
+    #[test]
+    fn test_set_checked() {
+        let source = indoc::indoc! {r#"
+            - [ ] First
+              - [x] Nested
+            - Plain
+
+            > - [ ] Quoted"#};
+        let mut cx = NodeContext::default();
+        let mut document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+
+        assert_eq!(
+            document
+                .blocks
+                .iter()
+                .map(BlockNode::checkbox_count)
+                .collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+
+        assert!(document.set_checked(1, false));
+        assert!(document.set_checked(2, true));
+        assert!(!document.set_checked(3, true));
+        assert_eq!(
+            document.to_markdown(),
+            "- [ ] First\n  - [ ] Nested\n- Plain\n\n> - [x] Quoted"
+        );
+    }
+}
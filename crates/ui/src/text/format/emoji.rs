@@ -0,0 +1,428 @@
+//! Render the `:shortcode:` emoji as the native Unicode emoji, see
+//! [`TextViewState::set_emoji_shortcodes`].
+//!
+//! The emoji are rendered by the fallback color emoji font of the platform (e.g.: Apple Color
+//! Emoji, Segoe UI Emoji, Noto Color Emoji) as the normal text runs.
+//!
+//! [`TextViewState::set_emoji_shortcodes`]: crate::text::TextViewState::set_emoji_shortcodes
+
+use std::ops::Range;
+
+use crate::text::{
+    document::ParsedDocument,
+    node::{InlineNode, TextMark},
+    visitor::VisitorMut,
+};
+
+/// The common shortcodes (GitHub, Slack and Discourse) and their emoji, sorted by the shortcode.
+const EMOJI: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("angry", "😠"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("beers", "🍻"),
+    ("blush", "😊"),
+    ("broken_heart", "💔"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("calendar", "📆"),
+    ("check", "✔️"),
+    ("clap", "👏"),
+    ("coffee", "☕"),
+    ("confused", "😕"),
+    ("cry", "😢"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("hourglass", "⌛"),
+    ("hugs", "🤗"),
+    ("information_source", "ℹ️"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("kissing_heart", "😘"),
+    ("laughing", "😆"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("memo", "📝"),
+    ("muscle", "💪"),
+    ("neutral_face", "😐"),
+    ("no_entry", "⛔"),
+    ("ok_hand", "👌"),
+    ("open_mouth", "😮"),
+    ("package", "📦"),
+    ("party_popper", "🎉"),
+    ("pencil2", "✏️"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("pray", "🙏"),
+    ("question", "❓"),
+    ("raised_hand", "✋"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("relaxed", "☺️"),
+    ("rocket", "🚀"),
+    ("rofl", "🤣"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("shrug", "🤷"),
+    ("slightly_frowning_face", "🙁"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("sunglasses", "😎"),
+    ("sweat_smile", "😅"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("tired_face", "😫"),
+    ("trophy", "🏆"),
+    ("unamused", "😒"),
+    ("upside_down_face", "🙃"),
+    ("v", "✌️"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+    ("zzz", "💤"),
+];
+
+/// The shortcodes of the emoji supporting the skin tones, sorted.
+const SKIN_TONE_EMOJI: &[&str] = &[
+    "+1",
+    "-1",
+    "clap",
+    "muscle",
+    "ok_hand",
+    "point_down",
+    "point_left",
+    "point_right",
+    "point_up",
+    "pray",
+    "raised_hand",
+    "raised_hands",
+    "shrug",
+    "thumbsdown",
+    "thumbsup",
+    "v",
+    "wave",
+];
+
+/// The skin tone modifiers (U+1F3FB..U+1F3FF) of the tones `t2` to `t6`, the `t1` is the default
+/// yellow tone without the modifier.
+const SKIN_TONE_MODIFIERS: [char; 5] = [
+    '\u{1F3FB}',
+    '\u{1F3FC}',
+    '\u{1F3FD}',
+    '\u{1F3FE}',
+    '\u{1F3FF}',
+];
+
+/// Get the Unicode emoji of the shortcode without the colons, e.g.: `smile` is `😄`.
+fn emoji_for_shortcode(shortcode: &str) -> Option<&'static str> {
+    EMOJI
+        .binary_search_by(|(name, _)| (*name).cmp(shortcode))
+        .ok()
+        .map(|ix| EMOJI[ix].1)
+}
+
+/// Get the Unicode emoji of the shortcode with the optional skin tone suffix, e.g.: `wave:t3`
+/// (Discourse) is `👋🏼`.
+///
+/// The unknown tones (e.g.: `wave:t9`) and the emoji without the skin tones fall back to the
+/// base emoji.
+fn emoji_with_tone(shortcode: &str) -> Option<String> {
+    let (name, tone) = match shortcode.split_once(':') {
+        Some((name, tone)) => (name, Some(tone)),
+        None => (shortcode, None),
+    };
+    let emoji = emoji_for_shortcode(name)?;
+
+    let modifier = tone
+        .and_then(|tone| tone.strip_prefix('t')?.parse::<usize>().ok())
+        .and_then(|tone| SKIN_TONE_MODIFIERS.get(tone.checked_sub(2)?))
+        .filter(|_| SKIN_TONE_EMOJI.binary_search(&name).is_ok());
+    let Some(modifier) = modifier else {
+        return Some(emoji.to_string());
+    };
+
+    // The modifier follows the base character, replacing the emoji presentation selector, e.g.:
+    // `☝️` (U+261D U+FE0F) is `☝🏼`, the ZWJ sequences (e.g.: `🤷‍♂️`) keep the rest.
+    let mut chars = emoji.chars();
+    let mut text = String::new();
+    text.extend(chars.next());
+    text.push(*modifier);
+    text.extend(chars.skip_while(|c| *c == '\u{FE0F}'));
+    Some(text)
+}
+
+/// Replace the known `:shortcode:` with the Unicode emoji and add the emoji mark to keep the
+/// shortcode, the unknown ones are kept as the plain text.
+pub(super) fn replace_shortcodes(document: &mut ParsedDocument) {
+    document.walk_mut(&mut ReplaceShortcodes);
+}
+
+struct ReplaceShortcodes;
+
+impl VisitorMut for ReplaceShortcodes {
+    fn visit_inline_mut(&mut self, inline: &mut InlineNode) {
+        if inline.image.is_some() {
+            return;
+        }
+
+        let mut text = inline.text.to_string();
+        // From the end, so the ranges of the previous shortcodes are not shifted.
+        for range in find_shortcodes(&text).into_iter().rev() {
+            // The shortcodes inside the code spans, the math or the mentions are skipped.
+            let overlapped = inline.marks.iter().any(|(mark_range, mark)| {
+                (mark.code || mark.math || mark.mention.is_some() || mark.emoji.is_some())
+                    && mark_range.start < range.end
+                    && range.start < mark_range.end
+            });
+            if overlapped {
+                continue;
+            }
+
+            let shortcode = &text[range.start + 1..range.end - 1];
+            let Some(emoji) = emoji_with_tone(shortcode) else {
+                continue;
+            };
+            let emoji = emoji.as_str();
+
+            let mark = TextMark {
+                emoji: Some(shortcode.to_string().into()),
+                ..Default::default()
+            };
+            text.replace_range(range.clone(), emoji);
+
+            // Shift the marks after the emoji, the marks around it are extended.
+            let shift = |offset: usize| {
+                if offset >= range.end {
+                    offset + emoji.len() - range.len()
+                } else {
+                    offset.min(range.start + emoji.len())
+                }
+            };
+            for (mark_range, _) in inline.marks.iter_mut() {
+                *mark_range = shift(mark_range.start)..shift(mark_range.end);
+            }
+            inline
+                .marks
+                .push((range.start..range.start + emoji.len(), mark));
+        }
+
+        if text != inline.text.as_ref() {
+            inline.text = text.into();
+        }
+    }
+}
+
+/// Find the `:shortcode:` in the text, return the byte ranges including the colons.
+///
+/// The shortcode contains the lowercase alphanumeric, `_`, `+` and `-`, and the opening colon
+/// must not follow a word, e.g.: the time `10:30:00` is not a shortcode.
+/// The skin tone suffix (e.g.: `:wave:t3:`) is included in the range.
+fn find_shortcodes(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut start = 0;
+
+    while let Some(ix) = text[start..].find(':').map(|ix| start + ix) {
+        start = ix + 1;
+        let is_boundary = text[..ix]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric());
+        if !is_boundary {
+            continue;
+        }
+
+        let Some(len) = text[ix + 1..]
+            .find(|c: char| !(c.is_ascii_lowercase() || c.is_ascii_digit() || "_+-".contains(c)))
+        else {
+            break;
+        };
+        let end = ix + 1 + len;
+        if len > 0
+            && text[end..].starts_with(':')
+            && emoji_for_shortcode(&text[ix + 1..end]).is_some()
+        {
+            let tone_len = skin_tone_len(&text[end + 1..]);
+            ranges.push(ix..end + 1 + tone_len);
+            start = end + 1 + tone_len;
+        }
+    }
+
+    ranges
+}
+
+/// The length of the skin tone suffix after the shortcode, e.g.: `t3:` of `:wave:t3:`.
+fn skin_tone_len(text: &str) -> usize {
+    let Some(rest) = text.strip_prefix('t') else {
+        return 0;
+    };
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && rest[digits..].starts_with(':') {
+        digits + 2
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        highlighter::HighlightTheme,
+        text::node::{BlockNode, NodeContext},
+    };
+
+    use super::{EMOJI, SKIN_TONE_EMOJI, emoji_for_shortcode, emoji_with_tone, find_shortcodes};
+
+    #[test]
+    fn test_emoji_sorted() {
+        assert!(EMOJI.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(SKIN_TONE_EMOJI.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(
+            SKIN_TONE_EMOJI
+                .iter()
+                .all(|name| emoji_for_shortcode(name).is_some())
+        );
+    }
+
+    #[test]
+    fn test_emoji_with_tone() {
+        assert_eq!(emoji_with_tone("wave").as_deref(), Some("👋"));
+        assert_eq!(emoji_with_tone("wave:t1").as_deref(), Some("👋"));
+        assert_eq!(emoji_with_tone("wave:t3").as_deref(), Some("👋\u{1F3FC}"));
+        assert_eq!(emoji_with_tone("+1:t6").as_deref(), Some("👍\u{1F3FF}"));
+        // The emoji presentation selector is replaced by the modifier.
+        assert_eq!(
+            emoji_with_tone("point_up:t2").as_deref(),
+            Some("\u{261D}\u{1F3FB}")
+        );
+        // Fall back to the base emoji.
+        assert_eq!(emoji_with_tone("wave:t9").as_deref(), Some("👋"));
+        assert_eq!(emoji_with_tone("rocket:t3").as_deref(), Some("🚀"));
+        assert_eq!(emoji_with_tone("unknown:t3"), None);
+    }
+
+    #[test]
+    fn test_find_shortcodes() {
+        let text = ":smile::+1: at 10:30:00, :unknown: :tada:x a:wave: :Smile:";
+        let shortcodes = find_shortcodes(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect::<Vec<_>>();
+        assert_eq!(shortcodes, vec![":smile:", ":+1:", ":tada:"]);
+
+        let text = ":wave:t3: :+1:t9: :ok_hand:tone:";
+        let shortcodes = find_shortcodes(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect::<Vec<_>>();
+        assert_eq!(shortcodes, vec![":wave:t3:", ":+1:t9:", ":ok_hand:"]);
+        assert_eq!(emoji_for_shortcode("wave"), Some("👋"));
+        assert_eq!(emoji_for_shortcode("unknown"), None);
+    }
+
+    #[test]
+    fn test_replace_shortcodes() {
+        let mut cx = NodeContext {
+            emoji_shortcodes: true,
+            ..Default::default()
+        };
+        let document = crate::text::format::markdown::parse(
+            "Ship it **:rocket:** :unknown: `:tada:`",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+
+        let BlockNode::Paragraph(paragraph) = &document.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        assert_eq!(paragraph.to_plain_text(), "Ship it 🚀 :unknown: :tada:");
+
+        let (range, mark) = paragraph
+            .children
+            .iter()
+            .flat_map(|node| node.marks.iter())
+            .find(|(_, mark)| mark.emoji.is_some())
+            .unwrap();
+        assert_eq!(range.len(), "🚀".len());
+        assert_eq!(mark.emoji.as_deref(), Some("rocket"));
+
+        // The shortcode is kept in the Markdown.
+        assert_eq!(
+            document.to_markdown(),
+            "Ship it **:rocket:** :unknown: `:tada:`"
+        );
+
+        let html = crate::text::format::html::parse("<p>Hi :wave: :wave:t3:</p>", &mut cx).unwrap();
+        assert_eq!(html.to_plain_text(), "Hi 👋 👋\u{1F3FC}");
+        // The skin tone is kept in the shortcode.
+        assert_eq!(html.to_markdown(), "Hi :wave: :wave:t3:");
+    }
+
+    #[test]
+    fn test_replace_adjacent_shortcodes() {
+        let mut cx = NodeContext {
+            emoji_shortcodes: true,
+            ..Default::default()
+        };
+        let document = crate::text::format::markdown::parse(
+            ":smile::smile::+1::+1:t3::tada:",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+        let BlockNode::Paragraph(paragraph) = &document.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        let text = paragraph.to_plain_text();
+        assert_eq!(text, "😄😄👍👍\u{1F3FC}🎉");
+
+        // Each emoji has its own range, not merged with the same adjacent emoji.
+        let mut emojis = paragraph
+            .children
+            .iter()
+            .flat_map(|node| node.marks.iter())
+            .filter_map(|(range, mark)| Some((range.clone(), mark.emoji.clone()?)))
+            .collect::<Vec<_>>();
+        emojis.sort_by_key(|(range, _)| range.start);
+        assert_eq!(
+            emojis
+                .iter()
+                .map(|(range, shortcode)| (&text[range.clone()], shortcode.as_ref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("😄", "smile"),
+                ("😄", "smile"),
+                ("👍", "+1"),
+                ("👍\u{1F3FC}", "+1:t3"),
+                ("🎉", "tada"),
+            ]
+        );
+        assert!(
+            emojis
+                .windows(2)
+                .all(|pair| pair[0].0.end <= pair[1].0.start)
+        );
+        assert_eq!(document.to_markdown(), ":smile::smile::+1::+1:t3::tada:");
+    }
+}
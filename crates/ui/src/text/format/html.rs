@@ -3,18 +3,25 @@ use std::collections::HashMap;
 use std::ops::Range;
 use std::rc::Rc;
 
-use gpui::{DefiniteLength, SharedString, px, relative};
+use cssparser::{
+    AtRuleParser, CowRcStr, DeclarationParser, ParseError, Parser, ParserInput, ParserState,
+    QualifiedRuleParser, RuleBodyItemParser, RuleBodyParser, Token,
+};
+use gpui::{AbsoluteLength, DefiniteLength, Hsla, Pixels, Rgba, SharedString, px, relative, rems};
 use html5ever::tendril::TendrilSink;
 use html5ever::{LocalName, ParseOpts, local_name, parse_document};
 use markup5ever_rcdom::{Node, NodeData, RcDom};
 
+use crate::Colorize as _;
 use crate::text::document::ParsedDocument;
 use crate::text::node::{
-    self, BlockNode, ImageNode, InlineNode, LinkMark, NodeContext, Paragraph, Table, TableRow,
-    TextMark,
+    self, BlockNode, CodeBlock, CustomBlock, DiagnosticKind, ImageLoading, ImageNode, InlineNode,
+    LinkMark, ListMarker, NodeContext, Paragraph, RuleStyle, SemanticTag, Span, Table, TableRow,
+    TextAlign, TextDirection, TextMark, WhiteSpace,
 };
+use crate::text::visitor::{VisitorMut, walk_block_mut};
 
-const BLOCK_ELEMENTS: [&str; 35] = [
+const BLOCK_ELEMENTS: [&str; 38] = [
     "html",
     "body",
     "head",
@@ -22,6 +29,7 @@ const BLOCK_ELEMENTS: [&str; 35] = [
     "article",
     "aside",
     "blockquote",
+    "center",
     "details",
     "summary",
     "dialog",
@@ -45,6 +53,8 @@ const BLOCK_ELEMENTS: [&str; 35] = [
     "ol",
     "p",
     "pre",
+    "textarea",
+    "xmp",
     "section",
     "table",
     "ul",
@@ -52,6 +62,10 @@ const BLOCK_ELEMENTS: [&str; 35] = [
     "script",
 ];
 
+/// The tags handled without rendering the content as is, e.g.: the `<style>` and the `<script>`
+/// are dropped, the `<iframe>` is the embed.
+const IGNORED_TAGS: [&str; 4] = ["style", "script", "iframe", "title"];
+
 /// Parse HTML into AST Node.
 pub(crate) fn parse(source: &str, cx: &mut NodeContext) -> Result<ParsedDocument, SharedString> {
     let opts = ParseOpts {
@@ -67,16 +81,59 @@ pub(crate) fn parse(source: &str, cx: &mut NodeContext) -> Result<ParsedDocument
         .read_from(&mut cursor)
         .map_err(|e| SharedString::from(format!("{:?}", e)))?;
 
+    // Before sanitizing, so the unwrapped tags are also reported.
+    if cx.diagnostics.is_some() {
+        check_node(&dom.document, cx);
+    }
+    if cx.sanitize {
+        super::sanitize::sanitize_node(&dom.document, cx);
+    }
+
     let mut paragraph = Paragraph::default();
     // NOTE: The outer paragraph is not used.
-    let node: BlockNode =
+    let mut node: BlockNode =
         parse_node(&dom.document, &mut paragraph, cx).unwrap_or(BlockNode::Unknown);
-    let node = node.compact();
+    collapse_block_whitespace(&mut node);
+    let mut node = node.compact();
+    super::linkify::linkify_block(&mut node);
 
-    Ok(ParsedDocument {
+    let mut document = ParsedDocument {
         source: source.to_string().into(),
         blocks: vec![node],
-    })
+        frontmatter: None,
+    };
+    if let Some(resolver) = cx.mention_resolver.clone() {
+        super::mention::resolve_mentions(&mut document, &resolver);
+    }
+    if cx.emoji_shortcodes {
+        super::emoji::replace_shortcodes(&mut document);
+    }
+    Ok(document)
+}
+
+/// Report the unsupported tags and the malformed `style` attributes to the diagnostics.
+///
+/// The HTML has no source positions, so the spans are unknown.
+fn check_node(node: &Node, cx: &NodeContext) {
+    if let NodeData::Element { name, attrs, .. } = &node.data {
+        let tag = &*name.local;
+        if !BLOCK_ELEMENTS.contains(&tag)
+            && !super::sanitize::is_allowed_tag(tag)
+            && !IGNORED_TAGS.contains(&tag)
+        {
+            cx.report(DiagnosticKind::UnsupportedTag, tag, None);
+        }
+
+        if let Some(css_text) = attr_value(attrs, local_name!("style"))
+            && is_malformed_style(&css_text)
+        {
+            cx.report(DiagnosticKind::MalformedStyle, &css_text, None);
+        }
+    }
+
+    for child in node.children.borrow().iter() {
+        check_node(child, cx);
+    }
 }
 
 fn cleanup_html(source: &str) -> Vec<u8> {
@@ -102,77 +159,440 @@ fn attr_value(attrs: &RefCell<Vec<html5ever::Attribute>>, name: LocalName) -> Op
 }
 
 /// Get style properties to HashMap
-/// TODO: Use cssparser to parse style attribute.
 fn style_attrs(attrs: &RefCell<Vec<html5ever::Attribute>>) -> HashMap<String, String> {
-    let mut styles = HashMap::new();
     let Some(css_text) = attr_value(attrs, local_name!("style")) else {
-        return styles;
+        return HashMap::new();
     };
 
-    for decl in css_text.split(';') {
-        let mut parts = decl.splitn(2, ':');
-        if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
-            styles.insert(
-                key.trim().to_lowercase().to_string(),
-                value.trim().to_string(),
-            );
+    parse_style(&css_text)
+}
+
+/// Parse the declarations of the `style` attribute, the property names are lowercased.
+///
+/// The invalid declarations are skipped, and the last one wins for the duplicated properties.
+fn parse_style(css_text: &str) -> HashMap<String, String> {
+    let mut input = ParserInput::new(css_text);
+    let mut parser = Parser::new(&mut input);
+
+    RuleBodyParser::new(&mut parser, &mut StyleDeclarationParser)
+        .flatten()
+        .collect()
+}
+
+/// Return true if the `style` attribute has any invalid declaration.
+fn is_malformed_style(css_text: &str) -> bool {
+    let mut input = ParserInput::new(css_text);
+    let mut parser = Parser::new(&mut input);
+
+    RuleBodyParser::new(&mut parser, &mut StyleDeclarationParser)
+        .any(|declaration| declaration.is_err())
+}
+
+/// Parse a declaration to the `(name, value)`, the value keeps the quotes and functions,
+/// e.g.: `url(data:image/png;base64,...)`, with the comments stripped.
+struct StyleDeclarationParser;
+
+impl<'i> DeclarationParser<'i> for StyleDeclarationParser {
+    type Declaration = (String, String);
+    type Error = ();
+
+    fn parse_value<'t>(
+        &mut self,
+        name: CowRcStr<'i>,
+        input: &mut Parser<'i, 't>,
+        _: &ParserState,
+    ) -> Result<Self::Declaration, ParseError<'i, Self::Error>> {
+        let mut value = String::new();
+        loop {
+            let start = input.position();
+            let Ok(token) = input.next_including_whitespace().cloned() else {
+                break;
+            };
+
+            match token {
+                Token::WhiteSpace(_) => value.push(' '),
+                Token::Function(_)
+                | Token::ParenthesisBlock
+                | Token::SquareBracketBlock
+                | Token::CurlyBracketBlock => {
+                    input.parse_nested_block(|input| {
+                        while input.next_including_whitespace_and_comments().is_ok() {}
+                        Ok::<_, ParseError<'i, Self::Error>>(())
+                    })?;
+                    value.push_str(input.slice_from(start));
+                }
+                _ => value.push_str(input.slice_from(start)),
+            }
         }
+
+        Ok((name.to_ascii_lowercase(), value.trim().to_string()))
+    }
+}
+
+impl<'i> AtRuleParser<'i> for StyleDeclarationParser {
+    type Prelude = ();
+    type AtRule = (String, String);
+    type Error = ();
+}
+
+impl<'i> QualifiedRuleParser<'i> for StyleDeclarationParser {
+    type Prelude = ();
+    type QualifiedRule = (String, String);
+    type Error = ();
+}
+
+impl<'i> RuleBodyItemParser<'i, (String, String), ()> for StyleDeclarationParser {
+    fn parse_declarations(&self) -> bool {
+        true
     }
 
-    styles
+    fn parse_qualified(&self) -> bool {
+        false
+    }
 }
 
 /// Parse length value from style attribute.
 ///
 /// When is percentage, it will be converted to relative length.
-/// Else, it will be converted to pixels.
-fn value_to_length(value: &str) -> Option<DefiniteLength> {
-    if value.ends_with("%") {
-        value
-            .trim_end_matches("%")
+/// When is `rem`, it will be converted to rems, which is resolved against the root font size on
+/// layout.
+/// When is `em`, it will be resolved against the font size of the enclosing heading or the body
+/// text, see [`NodeContext::em_size`].
+/// When is `pt` (1pt = 4/3px at 96dpi), it will be converted to pixels.
+/// When is `vw` or `vh`, it will be resolved against the [`NodeContext::viewport_size`],
+/// `None` if the viewport size is unknown.
+/// Else (including unitless), it will be converted to pixels.
+///
+/// Returns `None` for the invalid, zero or negative value.
+fn value_to_length(value: &str, cx: &NodeContext) -> Option<DefiniteLength> {
+    let value = value.trim().to_ascii_lowercase();
+    let parse = |number: &str| {
+        number
+            .trim()
             .parse::<f32>()
             .ok()
-            .map(|v| relative(v / 100.))
+            .filter(|v| v.is_finite() && *v > 0.)
+    };
+
+    if let Some(number) = value.strip_suffix('%') {
+        parse(number).map(|v| relative(v / 100.))
+    } else if let Some(number) = value.strip_suffix("rem") {
+        parse(number).map(|v| rems(v).into())
+    } else if let Some(number) = value.strip_suffix("em") {
+        parse(number).map(|v| (cx.em_size() * v).into())
+    } else if let Some(number) = value.strip_suffix("pt") {
+        parse(number).map(|v| px(v * 4. / 3.).into())
+    } else if let Some(number) = value.strip_suffix("vw") {
+        cx.use_viewport();
+        let viewport_size = cx.viewport_size?;
+        parse(number).map(|v| (viewport_size.width * (v / 100.)).into())
+    } else if let Some(number) = value.strip_suffix("vh") {
+        cx.use_viewport();
+        let viewport_size = cx.viewport_size?;
+        parse(number).map(|v| (viewport_size.height * (v / 100.)).into())
     } else {
-        value
-            .trim_end_matches("px")
-            .parse()
-            .ok()
-            .map(|v| px(v).into())
+        parse(value.strip_suffix("px").unwrap_or(&value)).map(|v| px(v).into())
+    }
+}
+
+/// Parse the `border` styles of `<hr>` to the [`RuleStyle`], e.g.: `border-top: 2px dashed #ccc`.
+///
+/// The longhands (e.g.: `border-top-color`) override the shorthands, the unparseable values are
+/// ignored, so the default theme divider is used.
+fn parse_rule_style(styles: &HashMap<String, String>, cx: &NodeContext) -> RuleStyle {
+    let mut style = RuleStyle::default();
+    for name in ["border", "border-top"] {
+        if let Some(value) = styles.get(name) {
+            for value in split_css_values(value) {
+                apply_border_value(&mut style, value, cx);
+            }
+        }
+    }
+    for name in [
+        "border-width",
+        "border-style",
+        "border-color",
+        "border-top-width",
+        "border-top-style",
+        "border-top-color",
+    ] {
+        // The first value is the top border, e.g.: `border-width: 2px 0 0`.
+        if let Some(value) = styles
+            .get(name)
+            .and_then(|value| split_css_values(value).next())
+        {
+            apply_border_value(&mut style, value, cx);
+        }
+    }
+    style
+}
+
+/// Apply a value of the `border` shorthand or longhands, the width, the style or the color.
+fn apply_border_value(style: &mut RuleStyle, value: &str, cx: &NodeContext) {
+    match value.to_ascii_lowercase().as_str() {
+        "solid" | "double" | "groove" | "ridge" | "inset" | "outset" => style.dashed = false,
+        "dashed" | "dotted" => style.dashed = true,
+        "thin" => style.thickness = Some(px(1.)),
+        "medium" => style.thickness = Some(px(3.)),
+        "thick" => style.thickness = Some(px(5.)),
+        _ => {
+            if let Some(color) = parse_css_color(value) {
+                style.color = Some(color);
+            } else if let Some(DefiniteLength::Absolute(AbsoluteLength::Pixels(thickness))) =
+                value_to_length(value, cx)
+            {
+                style.thickness = Some(thickness);
+            }
+        }
+    }
+}
+
+/// Split the space separated values of a CSS property, keep the spaces in the functions,
+/// e.g.: `1px solid rgb(0, 0, 0)`.
+fn split_css_values(value: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0;
+    value
+        .split(move |c: char| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            c.is_whitespace() && depth == 0
+        })
+        .filter(|value| !value.is_empty())
+}
+
+/// Parse the CSS color, e.g.: `#ccc`, `#ff000080`, `rgb(255 0 0)` or `rgba(255, 0, 0, 50%)`.
+///
+/// The named colors are not supported.
+fn parse_css_color(value: &str) -> Option<Hsla> {
+    let value = value.trim().to_ascii_lowercase();
+    if let Some(hex) = value.strip_prefix('#') {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let hex: String = match hex.len() {
+            3 | 4 => hex.chars().flat_map(|c| [c, c]).collect(),
+            6 | 8 => hex.to_string(),
+            _ => return None,
+        };
+        return Hsla::parse_hex(&hex).ok();
     }
+
+    let args = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let parts = args
+        .split([',', '/', ' '])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>();
+    let number = |part: &str, max: f32| {
+        match part.strip_suffix('%') {
+            Some(percent) => percent.parse::<f32>().ok().map(|v| v / 100.),
+            None => part.parse::<f32>().ok().map(|v| v / max),
+        }
+        .filter(|v| v.is_finite())
+        .map(|v| v.clamp(0., 1.))
+    };
+    let (r, g, b, a) = match parts.as_slice() {
+        [r, g, b] => (number(r, 255.)?, number(g, 255.)?, number(b, 255.)?, 1.),
+        [r, g, b, a] => (
+            number(r, 255.)?,
+            number(g, 255.)?,
+            number(b, 255.)?,
+            number(a, 1.)?,
+        ),
+        _ => return None,
+    };
+    Some(Rgba { r, g, b, a }.into())
 }
 
 /// Get width, height from attributes or parse them from style attribute.
 fn attr_width_height(
     attrs: &RefCell<Vec<html5ever::Attribute>>,
+    cx: &NodeContext,
 ) -> (Option<DefiniteLength>, Option<DefiniteLength>) {
     let mut width = None;
     let mut height = None;
 
     if let Some(value) = attr_value(attrs, local_name!("width")) {
-        width = value_to_length(&value);
+        width = value_to_length(&value, cx);
     }
 
     if let Some(value) = attr_value(attrs, local_name!("height")) {
-        height = value_to_length(&value);
+        height = value_to_length(&value, cx);
     }
 
     if width.is_none() || height.is_none() {
         let styles = style_attrs(attrs);
         if width.is_none() {
-            width = styles.get("width").and_then(|v| value_to_length(&v));
+            width = styles.get("width").and_then(|v| value_to_length(&v, cx));
         }
         if height.is_none() {
-            height = styles.get("height").and_then(|v| value_to_length(&v));
+            height = styles.get("height").and_then(|v| value_to_length(&v, cx));
         }
     }
 
     (width, height)
 }
 
-fn parse_table_row(table: &mut Table, node: &Rc<Node>) {
+/// Get the text alignment from the `text-align` style or the `align` attribute.
+fn attr_text_align(attrs: &RefCell<Vec<html5ever::Attribute>>) -> Option<TextAlign> {
+    style_attrs(attrs)
+        .get("text-align")
+        .and_then(|value| TextAlign::parse(value))
+        .or_else(|| attr_value(attrs, local_name!("align")).and_then(|v| TextAlign::parse(&v)))
+}
+
+/// Whether the element has the `spoiler` class, e.g.: the `[spoiler]` of Discourse is cooked to
+/// `<span class="spoiler">` or `<div class="spoiler">`.
+fn is_spoiler(attrs: &RefCell<Vec<html5ever::Attribute>>) -> bool {
+    attr_value(attrs, local_name!("class"))
+        .is_some_and(|class| class.split_whitespace().any(|class| class == "spoiler"))
+}
+
+/// Add the `mark` to the text of the blocks, e.g.: hide the text as the spoiler, the images are
+/// kept visible.
+fn apply_mark(blocks: &mut [BlockNode], mark: TextMark) {
+    struct ApplyMark(TextMark);
+
+    impl VisitorMut for ApplyMark {
+        fn visit_inline_mut(&mut self, inline: &mut InlineNode) {
+            if inline.image.is_none() && !inline.text.is_empty() {
+                inline.marks.push((0..inline.text.len(), self.0.clone()));
+            }
+        }
+    }
+
+    let mut visitor = ApplyMark(mark);
+    for block in blocks.iter_mut() {
+        walk_block_mut(block, &mut visitor);
+    }
+}
+
+/// Align the paragraphs and headings in the `blocks` without their own alignment.
+fn apply_text_align(blocks: &mut [BlockNode], align: TextAlign) {
+    for block in blocks.iter_mut() {
+        match block {
+            BlockNode::Paragraph(paragraph)
+            | BlockNode::Heading {
+                children: paragraph,
+                ..
+            } => {
+                paragraph.align.get_or_insert(align);
+            }
+            BlockNode::Root { children, .. } => apply_text_align(children, align),
+            _ => {}
+        }
+    }
+}
+
+/// Set the semantic block tag of the paragraphs in the `blocks`, e.g.: `<address>`.
+fn apply_semantic_tag(blocks: &mut [BlockNode], tag: SemanticTag) {
+    for block in blocks.iter_mut() {
+        match block {
+            BlockNode::Paragraph(paragraph) => {
+                paragraph.semantic.get_or_insert(tag);
+            }
+            BlockNode::Root { children, .. } => apply_semantic_tag(children, tag),
+            _ => {}
+        }
+    }
+}
+
+/// Get the text direction from the `direction` style or the `dir` attribute, e.g.: `rtl`, `auto`.
+fn attr_text_direction(attrs: &RefCell<Vec<html5ever::Attribute>>) -> Option<String> {
+    style_attrs(attrs)
+        .get("direction")
+        .cloned()
+        .or_else(|| attr_value(attrs, local_name!("dir")))
+}
+
+/// Set the direction of the paragraphs and headings in the `blocks` without their own direction,
+/// the `auto` direction is detected by the text of each paragraph.
+fn apply_text_direction(blocks: &mut [BlockNode], dir: &str) {
+    for block in blocks.iter_mut() {
+        match block {
+            BlockNode::Paragraph(paragraph)
+            | BlockNode::Heading {
+                children: paragraph,
+                ..
+            } => {
+                if paragraph.dir.is_none() {
+                    paragraph.dir = TextDirection::parse(dir, &paragraph.to_plain_text());
+                }
+            }
+            BlockNode::Root { children, .. }
+            | BlockNode::Blockquote { children, .. }
+            | BlockNode::Admonition { children, .. }
+            | BlockNode::List { children, .. }
+            | BlockNode::ListItem { children, .. } => apply_text_direction(children, dir),
+            _ => {}
+        }
+    }
+}
+
+/// Get the `max-width`, `max-height` from the style attribute.
+fn attr_max_width_height(
+    attrs: &RefCell<Vec<html5ever::Attribute>>,
+    cx: &NodeContext,
+) -> (Option<DefiniteLength>, Option<DefiniteLength>) {
+    let styles = style_attrs(attrs);
+    (
+        styles
+            .get("max-width")
+            .and_then(|v| value_to_length(&v, cx)),
+        styles
+            .get("max-height")
+            .and_then(|v| value_to_length(&v, cx)),
+    )
+}
+
+/// Parse the column widths of the `<colgroup>`, each `<col>` (or the `<colgroup>` without `<col>`)
+/// spans the columns by the `span` attribute.
+fn parse_table_columns(
+    widths: &mut Vec<Option<DefiniteLength>>,
+    node: &Node,
+    attrs: &RefCell<Vec<html5ever::Attribute>>,
+    cx: &NodeContext,
+) {
+    /// The maximum `span` of the HTML spec.
+    const MAX_SPAN: usize = 1000;
+
+    let mut push = |attrs: &RefCell<Vec<html5ever::Attribute>>| {
+        let span = attr_value(attrs, local_name!("span"))
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .unwrap_or(1)
+            .clamp(1, MAX_SPAN);
+        let width = attr_width_height(attrs, cx).0;
+        widths.extend(std::iter::repeat_n(width, span));
+    };
+
+    let mut has_col = false;
+    for child in node.children.borrow().iter() {
+        if let NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } = child.data
+            && name.local == local_name!("col")
+        {
+            has_col = true;
+            push(attrs);
+        }
+    }
+    if !has_col {
+        push(attrs);
+    }
+}
+
+/// Parse the `<tr>` into the `table`, the rows in `<thead>` or with only `<th>` cells are headers.
+fn parse_table_row(table: &mut Table, node: &Rc<Node>, in_thead: bool, cx: &mut NodeContext) {
     let mut row = TableRow::default();
     let mut count = 0;
+    let mut all_th = true;
     for child in node.children.borrow().iter() {
         match child.data {
             NodeData::Element {
@@ -185,13 +605,15 @@ fn parse_table_row(table: &mut Table, node: &Rc<Node>) {
                 }
 
                 count += 1;
-                parse_table_cell(&mut row, child, attrs);
+                all_th &= name.local == local_name!("th");
+                parse_table_cell(&mut row, child, attrs, cx);
             }
             _ => {}
         }
     }
 
     if count > 0 {
+        row.is_header = in_thead || all_th;
         table.children.push(row);
     }
 }
@@ -200,16 +622,26 @@ fn parse_table_cell(
     row: &mut node::TableRow,
     node: &Rc<Node>,
     attrs: &RefCell<Vec<html5ever::Attribute>>,
+    cx: &mut NodeContext,
 ) {
     let mut paragraph = Paragraph::default();
-    for child in node.children.borrow().iter() {
-        parse_paragraph(&mut paragraph, child);
-    }
-    let width = attr_width_height(attrs).0;
-    let table_cell = node::TableCell {
-        children: paragraph,
-        width,
+    let has_block = node.children.borrow().iter().any(|child| {
+        matches!(&child.data, NodeData::Element { name, .. } if BLOCK_ELEMENTS.contains(&name.local.trim()))
+    });
+
+    let children = if has_block {
+        consume_children_nodes(node, &mut paragraph, cx)
+    } else {
+        // Fast path for the most common case: only inline content in the cell.
+        for child in node.children.borrow().iter() {
+            parse_paragraph(&mut paragraph, child, cx);
+        }
+        trim_paragraph(&mut paragraph);
+        vec![BlockNode::Paragraph(paragraph)]
     };
+
+    let width = attr_width_height(attrs, cx).0;
+    let table_cell = node::TableCell { children, width };
     row.children.push(table_cell);
 }
 
@@ -217,12 +649,12 @@ fn parse_table_cell(
 ///
 /// - Before: " \r\n Hello world \t "
 /// - After: " Hello world "
-#[allow(dead_code)]
 fn trim_text(text: &str) -> String {
     let mut out = String::with_capacity(text.len());
 
     for (i, c) in text.chars().enumerate() {
-        if c.is_whitespace() {
+        // The non-breaking space (`&nbsp;`) is significant, only collapse the ASCII whitespace.
+        if c.is_ascii_whitespace() {
             if i > 0 && out.ends_with(' ') {
                 continue;
             }
@@ -236,6 +668,7 @@ fn trim_text(text: &str) -> String {
 fn parse_paragraph(
     paragraph: &mut Paragraph,
     node: &Rc<Node>,
+    cx: &NodeContext,
 ) {
     fn push_merged(paragraph: &mut Paragraph, text: String, marks: Vec<(Range<usize>, TextMark)>, new_mark: Option<TextMark>) {
         if text.is_empty() {
@@ -253,15 +686,27 @@ fn parse_paragraph(
         paragraph.push(node);
     }
 
-    fn merge_children_with_mark(node: &Node, paragraph: &mut Paragraph, new_mark: Option<TextMark>) {
+    fn merge_children_with_mark(node: &Node, paragraph: &mut Paragraph, new_mark: Option<TextMark>, cx: &NodeContext) {
         let mut merged_text = String::new();
         let mut merged_marks = Vec::new();
 
         for child in node.children.borrow().iter() {
             let mut child_paragraph = Paragraph::default();
-            parse_paragraph(&mut child_paragraph, &child);
+            parse_paragraph(&mut child_paragraph, &child, cx);
 
             for node in child_paragraph.children {
+                // Keep the hard line break as a separate node.
+                if node.is_hard_break() {
+                    push_merged(
+                        paragraph,
+                        std::mem::take(&mut merged_text),
+                        std::mem::take(&mut merged_marks),
+                        new_mark.clone(),
+                    );
+                    paragraph.push(node);
+                    continue;
+                }
+
                 let offset = merged_text.len();
                 merged_text.push_str(&node.text);
                 for (range, child_mark) in node.marks {
@@ -269,8 +714,10 @@ fn parse_paragraph(
                 }
 
                 if let Some(mut image) = node.image {
-                    if let Some(link_mark) = new_mark.as_ref().and_then(|mark| mark.link.clone()) {
-                        image.link = Some(link_mark);
+                    // The image wrapped in `<a>` (e.g. a thumbnail) links to the anchor's href,
+                    // the innermost anchor wins.
+                    if image.link.is_none() {
+                        image.link = new_mark.as_ref().and_then(|mark| mark.link.clone());
                     }
 
                     push_merged(paragraph, std::mem::take(&mut merged_text),
@@ -289,59 +736,307 @@ fn parse_paragraph(
             let part = &contents.borrow();
             paragraph.push_str(&part);
         }
-        NodeData::Element { name, attrs, .. } => match name.local {
-            local_name!("em") | local_name!("i") => {
-                merge_children_with_mark(node, paragraph, Some(TextMark::default().italic()));
-            }
-            local_name!("strong") | local_name!("b") => {
-                merge_children_with_mark(node, paragraph, Some(TextMark::default().bold()));
-            }
-            local_name!("del") | local_name!("s") => {
-                merge_children_with_mark(node, paragraph, Some(TextMark::default().strikethrough()));
-            }
-            local_name!("code") => {
-                merge_children_with_mark(node, paragraph, Some(TextMark::default().code()));
+        NodeData::Element { name, attrs, .. } => {
+            let mark = match name.local {
+                local_name!("br") => {
+                    paragraph.push_break();
+                    return;
+                }
+                local_name!("em") | local_name!("i") => Some(TextMark::default().italic()),
+                local_name!("strong") | local_name!("b") => Some(TextMark::default().bold()),
+                local_name!("del") | local_name!("s") => Some(TextMark::default().strikethrough()),
+                local_name!("u") | local_name!("ins") => Some(TextMark::default().underline()),
+                local_name!("code") => Some(TextMark::default().code()),
+                local_name!("cite") => Some(SemanticTag::Cite.mark(cx.keep_semantic_tags)),
+                local_name!("dfn") => Some(SemanticTag::Dfn.mark(cx.keep_semantic_tags)),
+                local_name!("var") => Some(SemanticTag::Var.mark(cx.keep_semantic_tags)),
+                local_name!("samp") => Some(SemanticTag::Samp.mark(cx.keep_semantic_tags)),
+                // The `datetime` is kept for the formatter of the displayed text.
+                local_name!("time") => attr_value(&attrs, local_name!("datetime"))
+                    .filter(|datetime| !datetime.trim().is_empty())
+                    .map(|datetime| TextMark::default().time(datetime.trim().to_string())),
+                // An abbreviation without title has nothing to show.
+                local_name!("abbr") => attr_value(&attrs, local_name!("title"))
+                    .filter(|title| !title.is_empty())
+                    .map(|title| TextMark::default().abbr(title)),
+                // An anchor without href (e.g. `<a name="top">`) is not a link.
+                local_name!("a") => attr_value(&attrs, local_name!("href")).map(|href| {
+                    TextMark::default().link(LinkMark {
+                        url: href.into(),
+                        title: attr_value(&attrs, local_name!("title")).map(Into::into),
+                        ..Default::default()
+                    })
+                }),
+                local_name!("img") => {
+                    parse_image(paragraph, attrs, cx);
+                    return;
+                }
+                local_name!("picture") => {
+                    parse_picture(paragraph, node, cx);
+                    return;
+                }
+                // Wrap the inline quote in the quote marks, the `cite` source links the text.
+                local_name!("q") => {
+                    let (open, close) = quote_marks(&crate::locale(), quote_depth(node));
+                    let mark = attr_value(&attrs, local_name!("cite")).map(|cite| {
+                        TextMark::default().link(LinkMark {
+                            url: cite.into(),
+                            ..Default::default()
+                        })
+                    });
+                    paragraph.push_str(open);
+                    merge_children_with_mark(node, paragraph, mark, cx);
+                    paragraph.push_str(close);
+                    return;
+                }
+                // NOTE: The `<small>` and `<big>` are rendered at the normal size, the text runs
+                // of a paragraph are laid out with the same font size by gpui.
+                _ => is_spoiler(attrs).then(|| TextMark::default().spoiler()),
+            };
+
+            // Combine with the marks of the inline style, e.g.: `<b style="font-style: italic">`.
+            let mark = match (mark, style_mark(attrs)) {
+                (Some(mut mark), Some(style_mark)) => {
+                    mark.merge(style_mark);
+                    Some(mark)
+                }
+                (mark, style_mark) => mark.or(style_mark),
+            };
+            merge_children_with_mark(node, paragraph, mark, cx);
+        }
+        _ => {
+            merge_children_with_mark(node, paragraph, None, cx);
+        }
+    }
+}
+
+/// The number of the `<q>` ancestors of the `node`, for the nested quote marks.
+fn quote_depth(node: &Node) -> usize {
+    fn parent(node: &Node) -> Option<Rc<Node>> {
+        let parent = node.parent.take();
+        node.parent.set(parent.clone());
+        parent.and_then(|parent| parent.upgrade())
+    }
+
+    let mut depth = 0;
+    let mut current = parent(node);
+    while let Some(node) = current {
+        if matches!(&node.data, NodeData::Element { name, .. } if name.local == local_name!("q")) {
+            depth += 1;
+        }
+        current = parent(&node);
+    }
+    depth
+}
+
+/// Returns the opening and closing quote marks of the `locale`, the nested quotes (odd `depth`)
+/// use the secondary quote marks.
+fn quote_marks(locale: &str, depth: usize) -> (&'static str, &'static str) {
+    let (primary, secondary) = match locale {
+        "zh-HK" | "zh-TW" | "ja" => (("「", "」"), ("『", "』")),
+        "de" => (("„", "“"), ("‚", "‘")),
+        "fr" | "it" => (("«", "»"), ("“", "”")),
+        "ru" => (("«", "»"), ("„", "“")),
+        _ => (("“", "”"), ("‘", "’")),
+    };
+    if depth % 2 == 0 { primary } else { secondary }
+}
+
+fn parse_image(
+    paragraph: &mut Paragraph,
+    attrs: &RefCell<Vec<html5ever::Attribute>>,
+    cx: &NodeContext,
+) {
+    let Some(src) = attr_value(attrs, local_name!("src")) else {
+        cx.report(DiagnosticKind::ImageMissingSrc, "<img>", None);
+        return;
+    };
+
+    paragraph.push_image(image_node(src, attrs, cx));
+}
+
+/// Build the image of the `src` with the attributes of `<img>`, e.g.: `alt` and `width`.
+fn image_node(
+    src: String,
+    attrs: &RefCell<Vec<html5ever::Attribute>>,
+    cx: &NodeContext,
+) -> ImageNode {
+    let alt = attr_value(attrs, local_name!("alt"));
+    let title = attr_value(attrs, local_name!("title"));
+    let (width, height) = attr_width_height(attrs, cx);
+    let (max_width, max_height) = attr_max_width_height(attrs, cx);
+    let loading = attr_value(attrs, LocalName::from("loading"))
+        .map(|value| ImageLoading::parse(&value))
+        .unwrap_or_default();
+
+    ImageNode {
+        url: src.into(),
+        link: None,
+        alt: alt.map(Into::into),
+        width,
+        height,
+        max_width,
+        max_height,
+        title: title.map(Into::into),
+        class: attr_value(attrs, local_name!("class")).map(Into::into),
+        loading,
+        ..Default::default()
+    }
+}
+
+/// The image formats can be decoded, for the `type` of `<source>`.
+const SUPPORTED_IMAGE_TYPES: [&str; 8] = [
+    "image/png",
+    "image/jpeg",
+    "image/jpg",
+    "image/gif",
+    "image/webp",
+    "image/bmp",
+    "image/tiff",
+    "image/svg+xml",
+];
+
+/// Parse the `<picture>`, the first `<source>` matching the viewport width and the supported
+/// image formats is used, or fall back to the `src` of the inner `<img>`.
+///
+/// The picture without any usable source is kept as a broken image with an empty url.
+fn parse_picture(paragraph: &mut Paragraph, node: &Node, cx: &NodeContext) {
+    let mut url = None;
+    let mut img_node = None;
+    for child in node.children.borrow().iter() {
+        let NodeData::Element { name, attrs, .. } = &child.data else {
+            continue;
+        };
+        match name.local {
+            local_name!("source") if url.is_none() => url = picture_source_url(attrs, cx),
+            local_name!("img") if img_node.is_none() => img_node = Some(child.clone()),
+            _ => {}
+        }
+    }
+
+    let image = match img_node.as_deref().map(|node| &node.data) {
+        Some(NodeData::Element { attrs, .. }) => {
+            let url = url.or_else(|| attr_value(attrs, local_name!("src")));
+            image_node(url.unwrap_or_default(), attrs, cx)
+        }
+        _ => ImageNode {
+            url: url.unwrap_or_default().into(),
+            ..Default::default()
+        },
+    };
+    paragraph.push_image(image);
+}
+
+/// Returns the url of the `<source>` in `<picture>`, `None` if the `media` does not match the
+/// viewport or the `type` is not supported.
+fn picture_source_url(
+    attrs: &RefCell<Vec<html5ever::Attribute>>,
+    cx: &NodeContext,
+) -> Option<String> {
+    if let Some(mime) = attr_value(attrs, local_name!("type")) {
+        let mime = mime.trim().to_ascii_lowercase();
+        if !SUPPORTED_IMAGE_TYPES.contains(&mime.as_str()) {
+            return None;
+        }
+    }
+
+    if let Some(media) = attr_value(attrs, local_name!("media")) {
+        cx.use_viewport();
+        let viewport_width = cx.viewport_size?.width;
+        if !media_matches(&media, viewport_width) {
+            return None;
+        }
+    }
+
+    // Use the first candidate of the `srcset`, e.g.: `a.png 1x, a@2x.png 2x`.
+    attr_value(attrs, local_name!("srcset"))?
+        .split(',')
+        .next()
+        .and_then(|candidate| candidate.split_whitespace().next())
+        .map(ToString::to_string)
+}
+
+/// Evaluate the media query of `<source>` with the viewport width.
+///
+/// Only the media types and the `min-width`, `max-width` features are supported, e.g.:
+/// `screen and (min-width: 600px), print`, the others never match.
+fn media_matches(media: &str, viewport_width: Pixels) -> bool {
+    let media = media.to_ascii_lowercase();
+    media.split(',').any(|query| {
+        query.split(" and ").all(|condition| {
+            let condition = condition.trim();
+            if matches!(condition, "" | "all" | "screen" | "only screen") {
+                return true;
             }
-            local_name!("a") => {
-                let link_mark = LinkMark {
-                    url: attr_value(&attrs, local_name!("href"))
-                        .unwrap_or_default()
-                        .into(),
-                    title: attr_value(&attrs, local_name!("title")).map(Into::into),
-                    ..Default::default()
-                };
 
-                merge_children_with_mark(node, paragraph, Some(TextMark::default().link(link_mark)));
+            let Some((feature, value)) = condition
+                .strip_prefix('(')
+                .and_then(|condition| condition.strip_suffix(')'))
+                .and_then(|condition| condition.split_once(':'))
+            else {
+                return false;
+            };
+
+            let value = value.trim();
+            let width = if let Some(number) = value.strip_suffix("px") {
+                number.trim().parse::<f32>().ok()
+            } else if let Some(number) = value
+                .strip_suffix("rem")
+                .or_else(|| value.strip_suffix("em"))
+            {
+                number.trim().parse::<f32>().ok().map(|v| v * 16.)
+            } else {
+                None
+            };
+            let Some(width) = width.map(px) else {
+                return false;
+            };
+
+            match feature.trim() {
+                "min-width" => viewport_width >= width,
+                "max-width" => viewport_width <= width,
+                _ => false,
             }
-            local_name!("img") => {
-                let Some(src) = attr_value(attrs, local_name!("src")) else {
-                    if cfg!(debug_assertions) {
-                        tracing::warn!("Image node missing src attribute");
-                    }
-                    return;
-                };
+        })
+    })
+}
 
-                let alt = attr_value(attrs, local_name!("alt"));
-                let title = attr_value(attrs, local_name!("title"));
-                let (width, height) = attr_width_height(attrs);
+/// Get the marks from the inline style, e.g.: `<span style="font-weight: bold">`.
+///
+/// - `font-weight`: `bold`, `bolder` or >= 600 to bold.
+/// - `font-style`: `italic` or `oblique` to italic.
+/// - `text-decoration`: `underline` to underline, `line-through` to strikethrough.
+fn style_mark(attrs: &RefCell<Vec<html5ever::Attribute>>) -> Option<TextMark> {
+    let styles = style_attrs(attrs);
+    if styles.is_empty() {
+        return None;
+    }
 
-                paragraph.push_image(ImageNode {
-                    url: src.into(),
-                    link: None,
-                    alt: alt.map(Into::into),
-                    width,
-                    height,
-                    title: title.map(Into::into),
-                });
-            }
-            _ => {
-                merge_children_with_mark(node, paragraph, None);
+    let mut mark = TextMark::default();
+    if let Some(weight) = styles.get("font-weight") {
+        mark.bold = match weight.to_ascii_lowercase().as_str() {
+            "bold" | "bolder" => true,
+            weight => weight.parse::<f32>().is_ok_and(|weight| weight >= 600.),
+        };
+    }
+    if let Some(style) = styles.get("font-style") {
+        let style = style.to_ascii_lowercase();
+        mark.italic = style == "italic" || style.starts_with("oblique");
+    }
+    if let Some(decoration) = styles
+        .get("text-decoration")
+        .or_else(|| styles.get("text-decoration-line"))
+    {
+        for line in decoration.to_ascii_lowercase().split_whitespace() {
+            match line {
+                "underline" => mark.underline = true,
+                "line-through" => mark.strikethrough = true,
+                _ => {}
             }
-        },
-        _ => {
-            merge_children_with_mark(node, paragraph, None);
         }
     }
+
+    (mark != TextMark::default()).then_some(mark)
 }
 
 fn parse_node(
@@ -358,15 +1053,65 @@ fn parse_node(
 
             None
         }
+        // The custom block is rendered by the app, the children are not parsed.
         NodeData::Element {
             ref name,
             ref attrs,
             ..
-        } => match name.local {
-            local_name!("br") => Some(BlockNode::Break {
-                html: true,
+        } if cx.custom_block_renderer.as_ref().is_some_and(|renderer| {
+            renderer.matches(
+                &name.local,
+                attr_value(attrs, local_name!("class")).as_deref(),
+            )
+        }) =>
+        {
+            let mut children = vec![];
+            consume_paragraph(&mut children, paragraph);
+
+            let mut text = String::new();
+            pre_text(node, &mut text);
+            let block = BlockNode::Custom(CustomBlock {
+                tag: name.local.to_string().into(),
+                attrs: attrs
+                    .borrow()
+                    .iter()
+                    .map(|attr| {
+                        (
+                            attr.name.local.to_string().into(),
+                            attr.value.to_string().into(),
+                        )
+                    })
+                    .collect(),
+                text: text.trim().to_string().into(),
                 span: None,
-            }),
+            });
+            if children.len() > 0 {
+                children.push(block);
+                Some(BlockNode::Root {
+                    children,
+                    span: None,
+                })
+            } else {
+                Some(block)
+            }
+        }
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } => match name.local {
+            local_name!("br") => {
+                // Inside a paragraph, it is a hard line break.
+                if !paragraph.is_empty() {
+                    paragraph.push_break();
+                    return None;
+                }
+
+                Some(BlockNode::Break {
+                    html: true,
+                    span: None,
+                })
+            }
             local_name!("h1")
             | local_name!("h2")
             | local_name!("h3")
@@ -385,13 +1130,20 @@ fn parse_node(
                     .unwrap_or(6) as u8;
 
                 let mut paragraph = Paragraph::default();
+                cx.heading_level = Some(level);
                 for child in node.children.borrow().iter() {
-                    parse_paragraph(&mut paragraph, child);
+                    parse_paragraph(&mut paragraph, child, cx);
+                }
+                cx.heading_level = None;
+                paragraph.align = attr_text_align(attrs);
+                if let Some(dir) = attr_text_direction(attrs) {
+                    paragraph.dir = TextDirection::parse(&dir, &paragraph.to_plain_text());
                 }
 
                 let heading = BlockNode::Heading {
                     level,
                     children: paragraph,
+                    id: attr_value(attrs, local_name!("id")).map(Into::into),
                     span: None,
                 };
                 if children.len() > 0 {
@@ -405,48 +1157,92 @@ fn parse_node(
                     Some(heading)
                 }
             }
-            local_name!("img") => {
+            local_name!("hr") => {
                 let mut children = vec![];
                 consume_paragraph(&mut children, paragraph);
 
-                let Some(src) = attr_value(attrs, local_name!("src")) else {
-                    if cfg!(debug_assertions) {
-                        tracing::warn!("image node missing src attribute");
-                    }
-                    return None;
+                let rule = BlockNode::HorizontalRule {
+                    style: parse_rule_style(&style_attrs(attrs), cx),
+                    span: None,
                 };
-
-                let alt = attr_value(&attrs, local_name!("alt"));
-                let title = attr_value(&attrs, local_name!("title"));
-                let (width, height) = attr_width_height(&attrs);
-
-                let mut paragraph = Paragraph::default();
-                paragraph.push_image(ImageNode {
-                    url: src.into(),
-                    link: None,
-                    title: title.map(Into::into),
-                    alt: alt.map(Into::into),
-                    width,
-                    height,
-                });
-
                 if children.len() > 0 {
-                    children.push(BlockNode::Paragraph(paragraph));
+                    children.push(rule);
+
                     Some(BlockNode::Root {
                         children,
                         span: None,
                     })
                 } else {
-                    Some(BlockNode::Paragraph(paragraph))
+                    Some(rule)
                 }
             }
-            local_name!("ul") | local_name!("ol") => {
-                let ordered = name.local == local_name!("ol");
-                let children = consume_children_nodes(node, paragraph, cx);
-                Some(BlockNode::List {
-                    children,
-                    ordered,
-                    span: None,
+            local_name!("img") => {
+                // Keep the image inline with the text (e.g. emoji), so the whitespace
+                // around it can be collapsed with the adjacent text.
+                parse_paragraph(paragraph, node, cx);
+                None
+            }
+            // The `<textarea>` and the legacy `<xmp>` are preformatted like `<pre>`, their inner
+            // markup is the text.
+            local_name!("pre") | local_name!("textarea") | local_name!("xmp") => {
+                let mut children = vec![];
+                consume_paragraph(&mut children, paragraph);
+
+                let mut code = String::new();
+                pre_text(node, &mut code);
+                let code = trim_pre_text(&code, starts_with_code(node)).to_string();
+                let lang = code_lang(node);
+                // The display math, e.g.: `<pre><code class="language-math">`.
+                let block = if lang.as_deref() == Some("math") {
+                    BlockNode::Math {
+                        formula: code.into(),
+                        span: None,
+                    }
+                } else {
+                    let mut code_block =
+                        CodeBlock::new(code.into(), lang, &cx.style.highlight_theme, None::<Span>);
+                    if let Some(filename) = attr_value(attrs, LocalName::from("data-filename"))
+                        .filter(|filename| !filename.trim().is_empty())
+                    {
+                        code_block = code_block.with_title(filename.into());
+                    }
+                    BlockNode::CodeBlock(code_block)
+                };
+
+                if children.len() > 0 {
+                    children.push(block);
+                    Some(BlockNode::Root {
+                        children,
+                        span: None,
+                    })
+                } else {
+                    Some(block)
+                }
+            }
+            local_name!("ul") | local_name!("ol") => {
+                let ordered = name.local == local_name!("ol");
+                let children = consume_children_nodes(node, paragraph, cx);
+                let reversed = ordered && attr_value(attrs, local_name!("reversed")).is_some();
+                let start = attr_value(attrs, local_name!("start"))
+                    .and_then(|value| value.trim().parse::<usize>().ok())
+                    .unwrap_or_else(|| {
+                        // The reversed list counts down from the number of items by default.
+                        if reversed {
+                            children.iter().filter(|child| child.is_list_item()).count()
+                        } else {
+                            1
+                        }
+                    });
+                let marker = attr_value(attrs, local_name!("type"))
+                    .and_then(|value| ListMarker::from_type_attr(&value));
+
+                Some(BlockNode::List {
+                    children,
+                    ordered,
+                    start,
+                    marker: if ordered { marker } else { None },
+                    reversed,
+                    span: None,
                 })
             }
             local_name!("li") => {
@@ -473,10 +1269,30 @@ fn parse_node(
 
                 consume_paragraph(&mut children, paragraph);
 
+                // The item is loose if it has multiple blocks (the nested lists are not counted),
+                // or the text is wrapped in `<p>`, e.g.: `<li><p>Item</p></li>`.
+                let blocks = children
+                    .iter()
+                    .filter(|child| !matches!(child, BlockNode::List { .. }))
+                    .count();
+                let has_p = node
+                    .children
+                    .borrow()
+                    .iter()
+                    .any(|child| match &child.data {
+                        NodeData::Element { name, .. } => name.local == local_name!("p"),
+                        _ => false,
+                    });
+                let spread = blocks > 1 || has_p;
+
+                let value = attr_value(attrs, local_name!("value"))
+                    .and_then(|value| value.trim().parse::<usize>().ok());
+
                 Some(BlockNode::ListItem {
                     children,
-                    spread: false,
+                    spread,
                     checked: None,
+                    value,
                     span: None,
                 })
             }
@@ -484,24 +1300,51 @@ fn parse_node(
                 let mut children = vec![];
                 consume_paragraph(&mut children, paragraph);
 
-                let mut table = Table::default();
+                // The header rows are always first and the footer rows are last, regardless of
+                // the order of `<thead>`, `<tbody>` and `<tfoot>` in the source.
+                let mut header = Table::default();
+                let mut body = Table::default();
+                let mut footer = Table::default();
+                let mut column_widths = vec![];
                 for child in node.children.borrow().iter() {
                     match child.data {
+                        NodeData::Element {
+                            ref name,
+                            ref attrs,
+                            ..
+                        } if name.local == local_name!("colgroup") => {
+                            parse_table_columns(&mut column_widths, child, attrs, cx);
+                        }
                         NodeData::Element { ref name, .. }
                             if name.local == local_name!("tbody")
-                                || name.local == local_name!("thead") =>
+                                || name.local == local_name!("thead")
+                                || name.local == local_name!("tfoot") =>
                         {
+                            let (group, in_thead) = match name.local {
+                                local_name!("thead") => (&mut header, true),
+                                local_name!("tfoot") => (&mut footer, false),
+                                _ => (&mut body, false),
+                            };
                             for sub_child in child.children.borrow().iter() {
-                                parse_table_row(&mut table, &sub_child);
+                                parse_table_row(group, &sub_child, in_thead, cx);
                             }
                         }
                         _ => {
-                            parse_table_row(&mut table, &child);
+                            parse_table_row(&mut body, &child, false, cx);
                         }
                     }
                 }
                 consume_paragraph(&mut children, paragraph);
 
+                let mut table = header;
+                table.column_widths = column_widths;
+                table.children.append(&mut body.children);
+                for mut row in footer.children {
+                    row.is_header = false;
+                    row.is_footer = true;
+                    table.children.push(row);
+                }
+
                 let table = BlockNode::Table(table);
                 if children.len() > 0 {
                     children.push(table);
@@ -515,10 +1358,30 @@ fn parse_node(
             }
             local_name!("blockquote") => {
                 let children = consume_children_nodes(node, paragraph, cx);
-                Some(BlockNode::Blockquote {
-                    children,
-                    span: None,
-                })
+                Some(
+                    BlockNode::Blockquote {
+                        children,
+                        span: None,
+                    }
+                    .into_admonition(),
+                )
+            }
+            local_name!("iframe") => {
+                let src = attr_value(attrs, local_name!("src"))?;
+                let mut children = vec![];
+                consume_paragraph(&mut children, paragraph);
+
+                let title = attr_value(attrs, local_name!("title")).map(Into::into);
+                let embed = BlockNode::Embed(node::embed_node(&src, title, &cx.embed_providers));
+                if !children.is_empty() {
+                    children.push(embed);
+                    Some(BlockNode::Root {
+                        children,
+                        span: None,
+                    })
+                } else {
+                    Some(embed)
+                }
             }
             local_name!("style") | local_name!("script") => None,
             _ => {
@@ -531,14 +1394,77 @@ fn parse_node(
 
                     // Insert before text as a node -- The "Hello"
                     consume_paragraph(&mut children, paragraph);
+                    let inner_start = children.len();
+
+                    // The whitespace handling is kept by the paragraphs of the inner content.
+                    let outer_white_space = paragraph.white_space;
+                    if let Some(value) = style_attrs(attrs).get("white-space") {
+                        paragraph.white_space = WhiteSpace::parse(value);
+                    }
+
+                    // The content of the closed `<details>` is hidden as the spoiler,
+                    // except the `<summary>`.
+                    let closed_details = name.local == local_name!("details")
+                        && attr_value(attrs, local_name!("open")).is_none();
+                    let mut summary = None;
 
                     // Inner of the block element -- The "Inner text of block element"
                     for child in node.children.borrow().iter() {
+                        let is_summary = closed_details
+                            && matches!(
+                                &child.data,
+                                NodeData::Element { name, .. }
+                                    if name.local == local_name!("summary")
+                            );
+                        if is_summary {
+                            consume_paragraph(&mut children, paragraph);
+                        }
+                        let start = children.len();
                         if let Some(child_node) = parse_node(child, paragraph, cx) {
                             children.push(child_node);
                         }
+                        if is_summary && summary.is_none() {
+                            consume_paragraph(&mut children, paragraph);
+                            summary = Some(start..children.len());
+                        }
                     }
                     consume_paragraph(&mut children, paragraph);
+                    paragraph.white_space = outer_white_space;
+
+                    if is_spoiler(attrs) {
+                        apply_mark(&mut children[inner_start..], TextMark::default().spoiler());
+                    } else if closed_details {
+                        let summary = summary.unwrap_or_default();
+                        for ix in inner_start..children.len() {
+                            if !summary.contains(&ix) {
+                                apply_mark(
+                                    &mut children[ix..ix + 1],
+                                    TextMark::default().spoiler(),
+                                );
+                            }
+                        }
+                    }
+
+                    if name.local == local_name!("address") {
+                        let mark = SemanticTag::Address.mark(cx.keep_semantic_tags);
+                        apply_mark(&mut children[inner_start..], mark);
+                        // Keep one `<address>` around the paragraph, not around each text.
+                        if cx.keep_semantic_tags {
+                            apply_semantic_tag(&mut children[inner_start..], SemanticTag::Address);
+                        }
+                    }
+
+                    let align = if name.local == local_name!("center") {
+                        Some(TextAlign::Center)
+                    } else {
+                        attr_text_align(attrs)
+                    };
+                    if let Some(align) = align {
+                        apply_text_align(&mut children[inner_start..], align);
+                    }
+                    if let Some(dir) = attr_text_direction(attrs) {
+                        apply_text_direction(&mut children[inner_start..], &dir);
+                    }
 
                     if children.is_empty() {
                         None
@@ -550,7 +1476,7 @@ fn parse_node(
                     }
                 } else {
                     // Others to as Inline
-                    parse_paragraph(paragraph, node);
+                    parse_paragraph(paragraph, node, cx);
 
                     if paragraph.is_image() {
                         Some(BlockNode::Paragraph(paragraph.take()))
@@ -590,7 +1516,259 @@ fn consume_children_nodes(
     children
 }
 
+/// Get the literal text of a `<pre>` element, the `<br>` is converted to a newline.
+fn pre_text(node: &Node, out: &mut String) {
+    for child in node.children.borrow().iter() {
+        match &child.data {
+            NodeData::Text { contents } => out.push_str(&contents.borrow()),
+            NodeData::Element { name, .. } if name.local == local_name!("br") => out.push('\n'),
+            _ => pre_text(child, out),
+        }
+    }
+}
+
+/// Trim a trailing whitespace-only line of the `<pre>` text, and a leading newline if the text
+/// starts with `<code>`, the internal blank lines and the indentation are kept.
+///
+/// The parser only drops the newline right after `<pre>`, not the one after `<pre><code>`.
+fn trim_pre_text(text: &str, starts_with_code: bool) -> &str {
+    let text = if starts_with_code {
+        text.strip_prefix("\r\n")
+            .or_else(|| text.strip_prefix('\n'))
+            .unwrap_or(text)
+    } else {
+        text
+    };
+    match text.rfind('\n') {
+        Some(ix) if text[ix + 1..].trim().is_empty() => text[..ix].trim_end_matches('\r'),
+        _ => text,
+    }
+}
+
+/// Whether the first child of the `<pre>` is `<code>`, e.g.: `<pre><code>`.
+fn starts_with_code(node: &Node) -> bool {
+    node.children.borrow().first().is_some_and(|child| {
+        matches!(&child.data, NodeData::Element { name, .. } if name.local == local_name!("code"))
+    })
+}
+
+/// Get the code language from the `language-*` or `lang-*` class of `<pre>` or its `<code>` child.
+fn code_lang(node: &Node) -> Option<SharedString> {
+    fn class_lang(node: &Node) -> Option<SharedString> {
+        let NodeData::Element { attrs, .. } = &node.data else {
+            return None;
+        };
+        let class = attr_value(attrs, local_name!("class"))?;
+        class.split_whitespace().find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+                .map(|lang| SharedString::from(lang.to_string()))
+        })
+    }
+
+    class_lang(node).or_else(|| {
+        node.children
+            .borrow()
+            .iter()
+            .find_map(|child| match &child.data {
+                NodeData::Element { name, .. } if name.local == local_name!("code") => {
+                    class_lang(child)
+                }
+                _ => None,
+            })
+    })
+}
+
+/// Collapse the whitespace of all paragraphs and the breaks between the blocks in the block node.
+fn collapse_block_whitespace(node: &mut BlockNode) {
+    match node {
+        BlockNode::Root { children, .. }
+        | BlockNode::Blockquote { children, .. }
+        | BlockNode::Admonition { children, .. }
+        | BlockNode::List { children, .. }
+        | BlockNode::ListItem { children, .. } => {
+            children.iter_mut().for_each(collapse_block_whitespace);
+            collapse_breaks(children);
+        }
+        BlockNode::Paragraph(paragraph)
+        | BlockNode::Heading {
+            children: paragraph,
+            ..
+        } if paragraph.white_space.is_none() => collapse_whitespace(paragraph),
+        BlockNode::Table(table) => {
+            for row in table.children.iter_mut() {
+                for cell in row.children.iter_mut() {
+                    cell.children.iter_mut().for_each(collapse_block_whitespace);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collapse the consecutive `<br>` between the blocks into one blank line, and remove the ones at
+/// the start and end, which only add the whitespace to the margins of the blocks.
+///
+/// The single `<br>` is kept, e.g.: the inline HTML in Markdown.
+fn collapse_breaks(children: &mut Vec<BlockNode>) {
+    children.dedup_by(|a, b| a.is_break() && b.is_break());
+    if children.len() == 1 {
+        return;
+    }
+
+    if children.first().is_some_and(|child| child.is_break()) {
+        children.remove(0);
+    }
+    if children.last().is_some_and(|child| child.is_break()) {
+        children.pop();
+    }
+}
+
+/// Collapse whitespace between adjacent inline runs, keep at most one space.
+///
+/// The whitespace in the code spans is kept as it is.
+fn collapse_whitespace(paragraph: &mut Paragraph) {
+    // Start with true to trim the leading whitespace of the paragraph.
+    let mut preceding_whitespace = true;
+    for node in paragraph.children.iter_mut() {
+        if node.image.is_some() {
+            preceding_whitespace = false;
+            continue;
+        }
+        if node.is_hard_break() {
+            preceding_whitespace = true;
+            continue;
+        }
+
+        let mut text = String::with_capacity(node.text.len());
+        let mut removed: Vec<usize> = vec![];
+        for (ix, c) in node.text.char_indices() {
+            let in_code = node
+                .marks
+                .iter()
+                .any(|(range, mark)| mark.code && range.contains(&ix));
+
+            if in_code {
+                preceding_whitespace = c.is_ascii_whitespace();
+                text.push(c);
+            } else if !c.is_ascii_whitespace() {
+                preceding_whitespace = false;
+                text.push(c);
+            } else if preceding_whitespace {
+                removed.push(ix);
+            } else {
+                preceding_whitespace = true;
+                text.push(' ');
+            }
+        }
+
+        if removed.is_empty() {
+            if text != node.text.as_str() {
+                node.text = text.into();
+            }
+            continue;
+        }
+
+        let new_offset = |offset: usize| offset - removed.iter().filter(|ix| **ix < offset).count();
+        node.marks = std::mem::take(&mut node.marks)
+            .into_iter()
+            .filter_map(|(range, mark)| {
+                let range = new_offset(range.start)..new_offset(range.end);
+                (!range.is_empty()).then_some((range, mark))
+            })
+            .collect();
+        node.text = text.into();
+    }
+}
+
+/// Trim the leading and trailing whitespace of the paragraph, and collapse the whitespace of the
+/// plain text nodes by [`trim_text`], so the inner spacing is kept as a single space.
+///
+/// The whitespace in the code spans is kept as it is.
+fn trim_paragraph(paragraph: &mut Paragraph) {
+    for node in paragraph.children.iter_mut() {
+        if node.image.is_none() && node.marks.is_empty() && !node.is_hard_break() {
+            let text = trim_text(&node.text);
+            if text != node.text.as_str() {
+                node.text = text.into();
+            }
+        }
+    }
+
+    // Remove the nodes that only contain the whitespace at the boundaries.
+    while let Some(node) = paragraph.children.first_mut() {
+        if !trim_node(node, true) {
+            break;
+        }
+        paragraph.children.remove(0);
+    }
+    while let Some(node) = paragraph.children.last_mut() {
+        if !trim_node(node, false) {
+            break;
+        }
+        paragraph.children.pop();
+    }
+}
+
+/// Trim the leading (`start`) or trailing whitespace of the text node, the marks are shifted,
+/// returns true if the node is empty after trimming.
+fn trim_node(node: &mut InlineNode, start: bool) -> bool {
+    if node.image.is_some() || node.is_hard_break() {
+        return false;
+    }
+
+    let text = node.text.as_str();
+    let (trimmed, edge) = if start {
+        (
+            text.trim_start_matches(|c: char| c.is_ascii_whitespace()),
+            0,
+        )
+    } else {
+        (
+            text.trim_end_matches(|c: char| c.is_ascii_whitespace()),
+            text.len().saturating_sub(1),
+        )
+    };
+    let in_code = node
+        .marks
+        .iter()
+        .any(|(range, mark)| mark.code && range.contains(&edge));
+    if trimmed.len() == text.len() || in_code {
+        return false;
+    }
+
+    let removed = text.len() - trimmed.len();
+    let trimmed = trimmed.to_string();
+    let new_offset = |offset: usize| {
+        if start {
+            offset.saturating_sub(removed)
+        } else {
+            offset.min(trimmed.len())
+        }
+    };
+    node.marks = std::mem::take(&mut node.marks)
+        .into_iter()
+        .filter_map(|(range, mark)| {
+            let range = new_offset(range.start)..new_offset(range.end);
+            (!range.is_empty()).then_some((range, mark))
+        })
+        .collect();
+    node.text = trimmed.into();
+    node.text.is_empty()
+}
+
 fn consume_paragraph(children: &mut Vec<BlockNode>, paragraph: &mut Paragraph) {
+    if paragraph.white_space.is_none() {
+        trim_paragraph(paragraph);
+    } else if paragraph
+        .children
+        .iter()
+        .all(|node| node.image.is_none() && node.text.trim().is_empty())
+    {
+        // The whitespace only between the blocks, e.g.: the indentation of the source.
+        paragraph.children.clear();
+    }
     if paragraph.is_empty() {
         return;
     }
@@ -600,11 +1778,18 @@ fn consume_paragraph(children: &mut Vec<BlockNode>, paragraph: &mut Paragraph) {
 
 #[cfg(test)]
 mod tests {
-    use gpui::{px, relative};
+    use gpui::{Hsla, IntoElement as _, Rgba, px, relative, rems, size};
 
+    use crate::Colorize as _;
     use crate::text::{
         document::ParsedDocument,
-        node::{BlockNode, ImageNode, InlineNode, NodeContext, Paragraph},
+        inline::Replaced,
+        node::{
+            BlockNode, CustomBlockRenderer, DiagnosticKind, FirstLine, ImageLoading, ImageNode,
+            InlineNode, LinkMark, NodeContext, Paragraph, RuleStyle, SemanticTag, TextMark,
+            WhiteSpace, display_text,
+        },
+        visitor::Visitor,
     };
 
     use super::trim_text;
@@ -639,6 +1824,58 @@ mod tests {
         assert_eq!(trim_text("  \n\tHello world \t\r "), " Hello world ",);
     }
 
+    #[test]
+    fn test_trim_paragraph() {
+        let mut paragraph = Paragraph::default();
+        paragraph.push(InlineNode::new("  \n "));
+        paragraph.push(InlineNode::new(" Hello  \t world "));
+        paragraph.push(InlineNode::new("bold ").marks(vec![(0..5, TextMark::default().bold())]));
+        paragraph.push(InlineNode::new("a  b").marks(vec![(0..4, TextMark::default().code())]));
+        paragraph.push(InlineNode::new(" end  "));
+        super::trim_paragraph(&mut paragraph);
+
+        let texts = paragraph
+            .children
+            .iter()
+            .map(|node| node.text.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec!["Hello world ", "bold ", "a  b", " end"]);
+        assert_eq!(paragraph.children[1].marks[0].0, 0..5);
+
+        // The whitespace in the code span is kept at the boundary.
+        let mut paragraph = Paragraph::default();
+        paragraph.push(InlineNode::new(" code ").marks(vec![(0..6, TextMark::default().code())]));
+        super::trim_paragraph(&mut paragraph);
+        assert_eq!(paragraph.children[0].text.as_str(), " code ");
+
+        // The whitespace only paragraph is empty.
+        let mut paragraph = Paragraph::default();
+        paragraph.push(InlineNode::new(" \n\t"));
+        super::trim_paragraph(&mut paragraph);
+        assert!(paragraph.is_empty());
+
+        let mut cx = NodeContext::default();
+        let node =
+            super::parse("<div>Hello <p>Inner <b>text</b></p> World</div>", &mut cx).unwrap();
+        assert_eq!(node.to_plain_text(), "Hello\n\nInner text\n\nWorld");
+    }
+
+    #[test]
+    fn test_entities() {
+        let mut cx = NodeContext::default();
+        let node = super::parse(
+            "<p>A&nbsp;&nbsp;B &mdash; C&hellip; &#169; &#x263A; &lt;&amp;&gt;</p>",
+            &mut cx,
+        )
+        .unwrap();
+        assert_eq!(node.to_markdown(), "A\u{a0}\u{a0}B — C… © ☺ <&>");
+
+        // The `&nbsp;` is not collapsed with the adjacent spaces or trimmed.
+        let node = super::parse("<p>&nbsp;Indent a &nbsp; b&nbsp;</p>", &mut cx).unwrap();
+        assert_eq!(node.to_markdown(), "\u{a0}Indent a \u{a0} b\u{a0}");
+        assert_eq!(trim_text("a \u{a0} \u{a0}b"), "a \u{a0} \u{a0}b");
+    }
+
     #[test]
     fn test_keep_spaces() {
         let html = r#"<p>and <code>code</code> text</p>"#;
@@ -667,7 +1904,7 @@ mod tests {
         assert_eq!(
             node.to_markdown(),
             indoc::indoc! {r#"
-            and *code italic* text
+            and *`code` italic* text
 
             ![Example](https://example.com/image.png "Example Image")
 
@@ -679,56 +1916,1505 @@ mod tests {
     }
 
     #[test]
-    fn test_value_to_length() {
-        assert_eq!(super::value_to_length("100px"), Some(px(100.).into()));
-        assert_eq!(super::value_to_length("100%"), Some(relative(1.)));
-        assert_eq!(super::value_to_length("56%"), Some(relative(0.56)));
-        assert_eq!(super::value_to_length("240"), Some(px(240.).into()));
+    fn test_compose_marks() {
+        fn mark_at(paragraph: &Paragraph, offset: usize) -> TextMark {
+            let mut mark = TextMark::default();
+            for (range, m) in &paragraph.children[0].marks {
+                if range.contains(&offset) {
+                    mark.merge(m.clone());
+                }
+            }
+            mark
+        }
+
+        let mut cx = NodeContext::default();
+
+        // Link wrapping bold
+        let html = r#"<p><a href="https://example.com"><strong>bold</strong> link</a></p>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        let mark = mark_at(paragraph, 0);
+        assert!(mark.bold);
+        assert_eq!(mark.link.unwrap().url, "https://example.com");
+        let mark = mark_at(paragraph, 5);
+        assert!(!mark.bold);
+        assert!(mark.link.is_some());
+
+        // Bold wrapping link
+        let html = r#"<p><strong><a href="https://example.com">link</a> bold</strong></p>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        let mark = mark_at(paragraph, 0);
+        assert!(mark.bold);
+        assert_eq!(mark.link.unwrap().url, "https://example.com");
+        let mark = mark_at(paragraph, 5);
+        assert!(mark.bold);
+        assert!(mark.link.is_none());
+        assert_eq!(node.to_markdown(), "**[link](https://example.com) bold**");
     }
 
     #[test]
-    fn test_image() {
-        let html = r#"<img src="https://example.com/image.png" alt="Example" width="100" height="200" title="Example Image" />"#;
+    fn test_abbr() {
+        let html =
+            r#"<p>The <abbr title="World Health Organization">WHO</abbr> and <abbr>UN</abbr>.</p>"#;
         let mut cx = NodeContext::default();
         let node = super::parse(html, &mut cx).unwrap();
         assert_eq!(
-            node,
-            ParsedDocument {
-                source: html.to_string().into(),
-                blocks: vec![BlockNode::Paragraph(Paragraph {
-                    span: None,
-                    children: vec![InlineNode::image(ImageNode {
-                        url: "https://example.com/image.png".to_string().into(),
-                        alt: Some("Example".to_string().into()),
-                        width: Some(px(100.).into()),
-                        height: Some(px(200.).into()),
-                        title: Some("Example Image".to_string().into()),
-                        ..Default::default()
-                    })],
-                    ..Default::default()
-                })]
+            node.to_markdown(),
+            r#"The <abbr title="World Health Organization">WHO</abbr> and UN."#
+        );
+        assert_eq!(
+            node.to_html(),
+            r#"<p>The <abbr title="World Health Organization">WHO</abbr> and UN.</p>"#
+        );
+    }
+
+    #[test]
+    fn test_spoiler() {
+        let html = r#"<p>The killer is <span class="spoiler">the butler</span>.</p>"#;
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        assert_eq!(
+            node.to_markdown(),
+            r#"The killer is <span class="spoiler">the butler</span>."#
+        );
+        assert_eq!(node.to_html(), html);
+
+        struct Spoilers(Vec<String>);
+        impl Visitor for Spoilers {
+            fn visit_inline(&mut self, inline: &InlineNode) {
+                for (range, mark) in inline.marks.iter() {
+                    if mark.spoiler {
+                        self.0.push(inline.text[range.clone()].to_string());
+                    }
+                }
             }
+        }
+
+        let spoilers = |html: &str| {
+            let mut cx = NodeContext::default();
+            let mut spoilers = Spoilers(vec![]);
+            super::parse(html, &mut cx).unwrap().walk(&mut spoilers);
+            spoilers.0
+        };
+
+        assert_eq!(
+            spoilers(r#"<div class="spoiler"><p>Hidden</p><p>Also <b>hidden</b></p></div>"#)
+                .concat(),
+            "HiddenAlso hidden"
+        );
+        // The closed `<details>` hides the content except the summary.
+        assert_eq!(
+            spoilers("<details><summary>Answer</summary><p>42</p></details>"),
+            vec!["42"]
         );
+        assert!(spoilers("<details open><summary>Answer</summary><p>42</p></details>").is_empty());
+    }
 
-        let html = r#"<img src="https://example.com/image.png" alt="Example" style="width: 80%" title="Example Image" />"#;
+    #[test]
+    fn test_quote() {
+        assert_eq!(super::quote_marks("en", 0), ("“", "”"));
+        assert_eq!(super::quote_marks("en", 1), ("‘", "’"));
+        assert_eq!(super::quote_marks("zh-HK", 0), ("「", "」"));
+        assert_eq!(super::quote_marks("zh-HK", 2), ("「", "」"));
+
+        let html = r#"<p>He said <q cite="https://a.com">Hi <q>there</q></q>.</p>"#;
+        let mut cx = NodeContext::default();
         let node = super::parse(html, &mut cx).unwrap();
+        let (open, close) = super::quote_marks(&crate::locale(), 0);
+        let (inner_open, inner_close) = super::quote_marks(&crate::locale(), 1);
         assert_eq!(
-            node,
-            ParsedDocument {
-                source: html.to_string().into(),
-                blocks: vec![BlockNode::Paragraph(Paragraph {
-                    span: None,
-                    children: vec![InlineNode::image(ImageNode {
-                        url: "https://example.com/image.png".to_string().into(),
-                        alt: Some("Example".to_string().into()),
-                        width: Some(relative(0.8)),
-                        height: None,
-                        title: Some("Example Image".to_string().into()),
+            node.to_plain_text(),
+            format!("He said {open}Hi {inner_open}there{inner_close}{close}.")
+        );
+        assert_eq!(
+            node.to_markdown(),
+            format!("He said {open}[Hi {inner_open}there{inner_close}](https://a.com){close}.")
+        );
+    }
+
+    #[test]
+    fn test_image_link() {
+        let html = r#"<p><a href="https://example.com/big.png" title="Full size"><img src="https://example.com/thumb.png" alt="Thumb" /></a></p>"#;
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        assert_eq!(
+            node.blocks,
+            vec![BlockNode::Paragraph(Paragraph {
+                children: vec![InlineNode::image(ImageNode {
+                    url: "https://example.com/thumb.png".to_string().into(),
+                    link: Some(LinkMark {
+                        url: "https://example.com/big.png".into(),
+                        title: Some("Full size".into()),
                         ..Default::default()
-                    })],
+                    }),
+                    alt: Some("Thumb".to_string().into()),
                     ..Default::default()
-                })]
-            }
+                })],
+                ..Default::default()
+            })]
+        );
+
+        // Nested in other inline elements.
+        let html = r#"<p>See <a href="https://example.com/big.png"><strong><img src="https://example.com/thumb.png" /></strong></a></p>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        let image = paragraph
+            .children
+            .iter()
+            .find_map(|node| node.image.as_ref())
+            .unwrap();
+        assert_eq!(
+            image.link.as_ref().map(|link| link.url.clone()),
+            Some("https://example.com/big.png".into())
+        );
+
+        // Anchor without href is not a link.
+        let html = r#"<p><a name="top"><img src="https://example.com/thumb.png" /></a></p>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        assert_eq!(paragraph.children[0].image.as_ref().unwrap().link, None);
+    }
+
+    #[test]
+    fn test_table_cell_blocks() {
+        let html = r#"<table>
+            <tr><th>Name</th><th>Items</th></tr>
+            <tr><td>Foo</td><td><ul><li>Item 1</li><li>Item 2</li></ul></td></tr>
+        </table>"#;
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Table(table) = &node.blocks[0] else {
+            panic!("expected table");
+        };
+        assert!(table.children[1].children[0].paragraph().is_some());
+        assert!(matches!(
+            table.children[1].children[1].children.as_slice(),
+            [BlockNode::List { .. }]
+        ));
+        assert_eq!(
+            node.to_markdown(),
+            indoc::indoc! {r#"
+            <table>
+            <tr>
+            <th>
+
+            Name
+
+            </th>
+            <th>
+
+            Items
+
+            </th>
+            </tr>
+            <tr>
+            <td>
+
+            Foo
+
+            </td>
+            <td>
+
+            - Item 1
+            - Item 2
+
+            </td>
+            </tr>
+            </table>
+            "#}
+            .trim()
+        );
+
+        let html = r#"<table><tr><th>Name</th></tr><tr><td>Foo</td></tr></table>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        assert!(!node.to_markdown().starts_with("<table>"));
+    }
+
+    #[test]
+    fn test_heading_image() {
+        let html = r#"<h2>Title <img class="emoji" src="https://example.com/smile.png" alt=":smile:" /></h2>"#;
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Heading {
+            level, children, ..
+        } = &node.blocks[0]
+        else {
+            panic!("expected heading");
+        };
+        assert_eq!(*level, 2);
+        assert_eq!(children.children[0].text, "Title ");
+        assert_eq!(
+            children.children[1].image,
+            Some(ImageNode {
+                url: "https://example.com/smile.png".to_string().into(),
+                alt: Some(":smile:".to_string().into()),
+                class: Some("emoji".into()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            node.to_markdown(),
+            "## Title ![:smile:](https://example.com/smile.png)"
+        );
+        // The emoji images are not clickable, e.g.: to open in the viewer.
+        assert!(children.children[1].image.as_ref().unwrap().is_emoji());
+        let image = ImageNode {
+            class: Some("big photo".into()),
+            ..Default::default()
+        };
+        assert!(!image.is_emoji());
+    }
+
+    #[test]
+    fn test_hard_break() {
+        let mut cx = NodeContext::default();
+        let html = r#"<p>Hello<br>World <em>foo<br>bar</em></p>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        assert_eq!(node.to_markdown(), "Hello  \nWorld *foo*  \n*bar*");
+
+        let html = r#"<p>Hello</p><br><p>World</p>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Root { children, .. } = &node.blocks[0] else {
+            panic!("expected root");
+        };
+        assert!(children.iter().any(|child| child.is_break()));
+        assert_eq!(node.to_markdown(), "Hello\n\n<br>\n\nWorld");
+        assert_eq!(node.to_html(), "<p>Hello</p>\n<br>\n<p>World</p>");
+
+        // The consecutive breaks are collapsed into one.
+        let html = r#"<p>Hello</p><br><br><br><p>World</p>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Root { children, .. } = &node.blocks[0] else {
+            panic!("expected root");
+        };
+        assert_eq!(children.iter().filter(|child| child.is_break()).count(), 1);
+
+        // The breaks at the end of the document are removed.
+        let html = r#"<p>Hello</p><br><br>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        assert!(matches!(node.blocks[0], BlockNode::Paragraph(_)));
+        assert_eq!(node.to_markdown(), "Hello");
+
+        // A text of a newline is not a hard break.
+        let mut paragraph = Paragraph::default();
+        paragraph.push(InlineNode::new("\n"));
+        paragraph.push_break();
+        assert!(!paragraph.children[0].is_hard_break());
+        assert!(paragraph.children[1].is_hard_break());
+    }
+
+    #[test]
+    fn test_white_space() {
+        let mut cx = NodeContext::default();
+        let html = "<div style=\"white-space: pre-wrap\">Hello   world\n  <b>foo</b>  bar</div>";
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        assert_eq!(paragraph.white_space, Some(WhiteSpace::PreWrap));
+        assert_eq!(paragraph.to_plain_text(), "Hello   world\n  foo  bar");
+
+        let html = "<p style=\"white-space:pre\">a  b</p><p>c  d</p>";
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Root { children, .. } = &node.blocks[0] else {
+            panic!("expected root");
+        };
+        let paragraphs = children
+            .iter()
+            .filter_map(|child| match child {
+                BlockNode::Paragraph(paragraph) => {
+                    Some((paragraph.white_space, paragraph.to_plain_text()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paragraphs,
+            vec![
+                (Some(WhiteSpace::Pre), "a  b".to_string()),
+                (None, "c d".to_string())
+            ]
+        );
+        assert_eq!(
+            node.to_html(),
+            "<p style=\"white-space: pre\">a  b</p>\n<p>c d</p>"
+        );
+    }
+
+    #[test]
+    fn test_collapse_whitespace() {
+        fn paragraph_text(html: &str) -> String {
+            let mut cx = NodeContext::default();
+            let node = super::parse(html, &mut cx).unwrap();
+            let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+                panic!("expected paragraph");
+            };
+            paragraph
+                .children
+                .iter()
+                .map(|node| {
+                    if node.image.is_some() {
+                        "[img]".to_string()
+                    } else {
+                        node.text.to_string()
+                    }
+                })
+                .collect()
+        }
+
+        assert_eq!(
+            paragraph_text("<p>and <em>x </em> <i>y</i> text</p>"),
+            "and x y text"
+        );
+        assert_eq!(
+            paragraph_text("<p>and <code>code</code>   text</p>"),
+            "and code text"
+        );
+        assert_eq!(
+            paragraph_text("<p>and <code>a  b</code>   text</p>"),
+            "and a  b text"
+        );
+        assert_eq!(
+            paragraph_text("<p>  Hello <span> </span> <b>world</b></p>"),
+            "Hello world"
+        );
+        assert_eq!(
+            paragraph_text(
+                r#"<p>Hello <img class="emoji" src="https://example.com/smile.png"> world</p>"#
+            ),
+            "Hello [img] world"
+        );
+        assert_eq!(
+            paragraph_text(
+                r#"<p>Hello<img class="emoji" src="https://example.com/smile.png">world</p>"#
+            ),
+            "Hello[img]world"
+        );
+        // The adjacent emoji are kept as the separate images, the spaces between them are
+        // collapsed to one, so they are evenly spaced.
+        let emoji = r#"<img class="emoji" src="https://example.com/smile.png">"#;
+        assert_eq!(
+            paragraph_text(&format!("<p>{}</p>", emoji.repeat(5))),
+            "[img][img][img][img][img]"
+        );
+        assert_eq!(
+            paragraph_text(&format!("<p>{}</p>", [emoji; 5].join(" \n "))),
+            "[img] [img] [img] [img] [img]"
+        );
+
+        let mut paragraph = Paragraph::default();
+        paragraph.push_str("Hello ");
+        paragraph.push(InlineNode::new("  world").marks(vec![(2..7, TextMark::default().bold())]));
+        super::collapse_whitespace(&mut paragraph);
+        assert_eq!(paragraph.children[1].text, "world");
+        assert_eq!(
+            paragraph.children[1].marks,
+            vec![(0..5, TextMark::default().bold())]
+        );
+    }
+
+    #[test]
+    fn test_pre_code_block() {
+        let html = indoc::indoc! {r#"
+            <p>Example:</p>
+            <pre><code class="language-rust">fn main() {
+                if a < b {
+                    println!("Hello");
+                }
+            }</code></pre>
+        "#};
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Root { children, .. } = &node.blocks[0] else {
+            panic!("expected root");
+        };
+        let Some(BlockNode::CodeBlock(cb)) = children.last() else {
+            panic!("expected code block");
+        };
+        assert_eq!(cb.lang(), Some("rust".into()));
+        assert_eq!(
+            cb.code(),
+            "fn main() {\n    if a < b {\n        println!(\"Hello\");\n    }\n}"
+        );
+        assert_eq!(cb.title(), None);
+
+        let html =
+            r#"<pre data-filename="main.rs"><code class="language-rust">fn main() {}</code></pre>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::CodeBlock(cb) = &node.blocks[0] else {
+            panic!("expected code block");
+        };
+        assert_eq!(cb.lang(), Some("rust".into()));
+        assert_eq!(cb.title(), Some("main.rs".into()));
+        assert_eq!(
+            node.to_markdown(),
+            "```rust title=\"main.rs\"\nfn main() {}\n```"
+        );
+    }
+
+    #[test]
+    fn test_textarea_and_xmp() {
+        let mut cx = NodeContext::default();
+        let code = |html: &str, cx: &mut NodeContext| {
+            let node = super::parse(html, cx).unwrap();
+            let BlockNode::CodeBlock(cb) = &node.blocks[0] else {
+                panic!("expected code block");
+            };
+            cb.code().to_string()
+        };
+
+        // The `<textarea>` decodes the entities, the `<xmp>` is literal.
+        assert_eq!(
+            code("<textarea>\n  <b>a</b> &amp;\n\n  b</textarea>", &mut cx),
+            "  <b>a</b> &\n\n  b"
+        );
+        assert_eq!(
+            code("<xmp>\n  <b>a</b> &amp;\n\n  b\n</xmp>", &mut cx),
+            "  <b>a</b> &amp;\n\n  b"
+        );
+
+        let node = super::parse("<p>Before</p><xmp>a < b</xmp><p>After</p>", &mut cx).unwrap();
+        let BlockNode::Root { children, .. } = &node.blocks[0] else {
+            panic!("expected root");
+        };
+        assert!(matches!(children[1], BlockNode::CodeBlock(_)));
+        assert_eq!(node.to_plain_text(), "Before\n\na < b\n\nAfter");
+    }
+
+    #[test]
+    fn test_trim_pre_text() {
+        let code = |html: &str| {
+            let mut cx = NodeContext::default();
+            let node = super::parse(html, &mut cx).unwrap();
+            let BlockNode::CodeBlock(cb) = &node.blocks[0] else {
+                panic!("expected code block");
+            };
+            cb.code().to_string()
+        };
+
+        assert_eq!(code("<pre>\ncode\n</pre>"), "code");
+        assert_eq!(code("<pre><code>\ncode\n</code></pre>"), "code");
+        assert_eq!(code("<pre><code>\r\ncode\r\n  </code></pre>"), "code");
+        // The internal blank lines and the indentation are kept.
+        assert_eq!(
+            code("<pre><code>\n  a\n\n    b\n\n</code></pre>"),
+            "  a\n\n    b\n"
+        );
+        // Only a single leading newline is trimmed.
+        assert_eq!(code("<pre><code>\n\ncode</code></pre>"), "\ncode");
+        // The parser has dropped the newline after `<pre>`, the blank line is kept.
+        assert_eq!(code("<pre>\n\ncode</pre>"), "\ncode");
+
+        assert_eq!(super::trim_pre_text("a\nb", true), "a\nb");
+        assert_eq!(super::trim_pre_text("\n", true), "");
+        assert_eq!(super::trim_pre_text("\ncode", false), "\ncode");
+    }
+
+    #[test]
+    fn test_linkify() {
+        let html = indoc::indoc! {r#"
+            <p>Visit https://example.com today (www.example.com).</p>
+            <p>Run <code>curl https://example.com</code> or <a href="https://a.com">https://b.com</a></p>
+        "#};
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        assert_eq!(
+            node.to_markdown(),
+            indoc::indoc! {r#"
+                Visit [https://example.com](https://example.com) today ([www.example.com](http://www.example.com)).
+
+                Run `curl https://example.com` or [https://b.com](https://a.com)"#}
+        );
+    }
+
+    #[test]
+    fn test_ordered_list_attrs() {
+        let mut cx = NodeContext::default();
+        let node =
+            super::parse(r#"<ol start="3"><li>Three</li><li>Four</li></ol>"#, &mut cx).unwrap();
+        assert_eq!(node.to_markdown(), "3. Three\n4. Four");
+
+        let node = super::parse(
+            r#"<ol type="a" reversed><li>B</li><li>A</li></ol>"#,
+            &mut cx,
+        )
+        .unwrap();
+        let [BlockNode::List { marker, start, .. }] = node.blocks.as_slice() else {
+            panic!("expected list");
+        };
+        assert_eq!(*marker, Some(ListMarker::LowerAlpha));
+        assert_eq!(*start, 2);
+        assert_eq!(node.blocks[0].list_item_numbers(), vec![2, 1]);
+        assert_eq!(
+            node.to_markdown(),
+            "<ol start=\"2\" type=\"a\" reversed>\n<li>B</li>\n<li>A</li>\n</ol>"
+        );
+
+        let node = super::parse(r#"<ul start="3" type="A"><li>Item</li></ul>"#, &mut cx).unwrap();
+        assert_eq!(node.to_markdown(), "- Item");
+    }
+
+    #[test]
+    fn test_loose_list() {
+        let mut cx = NodeContext::default();
+        let node = super::parse(
+            r#"<ul><li><p>One</p></li><li>Two<p>More</p></li><li>Three</li></ul>"#,
+            &mut cx,
+        )
+        .unwrap();
+        assert!(node.blocks[0].is_loose_list());
+        assert_eq!(node.to_markdown(), "- One\n\n- Two\n\n  More\n\n- Three");
+
+        // The nested list does not make the item loose.
+        let node = super::parse(r#"<ol><li>A<ul><li>B</li></ul></li></ol>"#, &mut cx).unwrap();
+        assert!(!node.blocks[0].is_loose_list());
+        assert_eq!(node.to_markdown(), "1. A\n   - B");
+    }
+
+    #[test]
+    fn test_list_item_value() {
+        let mut cx = NodeContext::default();
+        let node = super::parse(
+            r#"<ol><li>One</li><li value="5">Five</li><li>Six</li></ol>"#,
+            &mut cx,
+        )
+        .unwrap();
+        assert_eq!(node.blocks[0].list_item_numbers(), vec![1, 5, 6]);
+        assert_eq!(node.to_markdown(), "1. One\n5. Five\n6. Six");
+        assert_eq!(
+            node.to_html(),
+            "<ol>\n<li>One</li>\n<li value=\"5\">Five</li>\n<li>Six</li>\n</ol>"
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let html = indoc::indoc! {r#"
+            <p onclick="alert(1)">Hello <a href="javascript:alert(1)">click</a> and
+            <a href="https://example.com">link</a><script>alert(2)</script></p>
+            <iframe src="https://example.com"><p>frame</p></iframe>
+            <custom-tag>Custom <b>text</b></custom-tag>
+        "#};
+        let mut cx = NodeContext {
+            sanitize: true,
+            ..Default::default()
+        };
+        let node = super::parse(html, &mut cx).unwrap();
+        assert_eq!(
+            node.to_markdown(),
+            "Hello click and [link](https://example.com)\n\nCustom **text**"
+        );
+
+        let mut cx = NodeContext::default();
+        let node = super::parse(r#"<a href="javascript:alert(1)">click</a>"#, &mut cx).unwrap();
+        assert_eq!(node.to_markdown(), "[click](javascript:alert(1))");
+    }
+
+    #[test]
+    fn test_value_to_length() {
+        let mut cx = NodeContext::default();
+        assert_eq!(super::value_to_length("100px", &cx), Some(px(100.).into()));
+        assert_eq!(super::value_to_length("100%", &cx), Some(relative(1.)));
+        assert_eq!(super::value_to_length("56%", &cx), Some(relative(0.56)));
+        assert_eq!(super::value_to_length("240", &cx), Some(px(240.).into()));
+        // The `em` is the body text size out of the headings.
+        assert_eq!(super::value_to_length("2em", &cx), Some(px(28.).into()));
+        assert_eq!(
+            super::value_to_length("1.5rem", &cx),
+            Some(rems(1.5).into())
+        );
+        assert_eq!(super::value_to_length(" 12PX ", &cx), Some(px(12.).into()));
+        assert_eq!(super::value_to_length("0", &cx), None);
+        assert_eq!(super::value_to_length("0em", &cx), None);
+        assert_eq!(super::value_to_length("-10px", &cx), None);
+        assert_eq!(super::value_to_length("auto", &cx), None);
+        assert_eq!(super::value_to_length("em", &cx), None);
+        assert_eq!(super::value_to_length("12pt", &cx), Some(px(16.).into()));
+        assert_eq!(super::value_to_length("1.5in", &cx), None);
+        assert_eq!(super::value_to_length("1px solid", &cx), None);
+
+        // The viewport units are unknown without viewport size.
+        assert_eq!(super::value_to_length("50vw", &cx), None);
+        cx.viewport_size = Some(size(px(1200.), px(800.)));
+        assert_eq!(super::value_to_length("50vw", &cx), Some(px(600.).into()));
+        assert_eq!(super::value_to_length("25vh", &cx), Some(px(200.).into()));
+        assert_eq!(super::value_to_length("0vh", &cx), None);
+
+        // The `em` in the heading is the font size of the heading.
+        cx.heading_level = Some(1);
+        assert_eq!(super::value_to_length("2em", &cx), Some(px(56.).into()));
+    }
+
+    #[test]
+    fn test_uses_viewport() {
+        // The words containing the units (e.g.: "review") are not the viewport lengths.
+        let mut cx = NodeContext::default();
+        super::parse("<p>review the vw and vh, media=print</p>", &mut cx).unwrap();
+        assert!(!cx.is_viewport_dependent());
+
+        let mut cx = NodeContext::default();
+        super::parse(
+            r#"<p><img src="https://a.com/1.png" style="width: 50vw" /></p>"#,
+            &mut cx,
+        )
+        .unwrap();
+        assert!(cx.is_viewport_dependent());
+    }
+
+    #[test]
+    fn test_picture() {
+        let image_of = |html: &str, cx: &mut NodeContext| {
+            let node = super::parse(html, cx).unwrap();
+            let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+                panic!("expected paragraph");
+            };
+            paragraph.children[0].image.clone().unwrap()
+        };
+
+        let html = r#"<picture><source srcset="https://a.com/wide.avif" type="image/avif"><source srcset="https://a.com/wide.webp 1x, https://a.com/wide@2x.webp 2x" media="(min-width: 800px)"><img src="https://a.com/small.png" alt="Photo"></picture>"#;
+        let mut cx = NodeContext::default();
+        cx.viewport_size = Some(size(px(1200.), px(800.)));
+        let image = image_of(html, &mut cx);
+        assert_eq!(image.url.to_string(), "https://a.com/wide.webp");
+        assert_eq!(image.alt, Some("Photo".into()));
+
+        // The narrow viewport falls back to the `<img>`.
+        cx.viewport_size = Some(size(px(600.), px(800.)));
+        let image = image_of(html, &mut cx);
+        assert_eq!(image.url.to_string(), "https://a.com/small.png");
+
+        // No usable source, keep as a broken image.
+        let image = image_of(
+            r#"<picture><source srcset="https://a.com/a.avif" type="image/avif"></picture>"#,
+            &mut cx,
+        );
+        assert_eq!(image.url.to_string(), "");
+
+        assert!(super::media_matches(
+            "screen and (max-width: 40em)",
+            px(600.)
+        ));
+        assert!(super::media_matches("print, (min-width: 500px)", px(600.)));
+        assert!(!super::media_matches(
+            "(prefers-color-scheme: dark)",
+            px(600.)
+        ));
+        assert!(!super::media_matches("(min-width: 700px)", px(600.)));
+    }
+
+    #[test]
+    fn test_iframe_embed() {
+        let html = indoc::indoc! {r#"
+            <p>Watch</p>
+            <iframe src="//www.youtube.com/embed/abc?si=x" title="Demo"></iframe>
+            <iframe src="https://example.com/widget"></iframe>
+        "#};
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        let embeds = node
+            .blocks
+            .iter()
+            .filter_map(|block| match block {
+                BlockNode::Embed(embed) => Some(embed.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(embeds.len(), 2);
+        assert_eq!(
+            embeds[0].url.as_ref(),
+            "https://www.youtube.com/watch?v=abc"
+        );
+        assert_eq!(embeds[0].provider, Some("YouTube".into()));
+        assert_eq!(
+            embeds[0].thumbnail,
+            Some("https://img.youtube.com/vi/abc/hqdefault.jpg".into())
+        );
+        assert_eq!(embeds[0].title(), "Demo");
+        assert_eq!(embeds[1].url.as_ref(), "https://example.com/widget");
+        assert_eq!(embeds[1].provider, None);
+        assert_eq!(
+            node.to_markdown(),
+            "Watch\n\n[Demo](https://www.youtube.com/watch?v=abc)\n\n[Embedded content](https://example.com/widget)"
+        );
+
+        // The registered provider is matched by the host.
+        cx.embed_providers = std::sync::Arc::new(vec![crate::text::EmbedProvider::new(
+            "Widget",
+            ["example.com"],
+        )]);
+        let node = super::parse(html, &mut cx).unwrap();
+        let Some(BlockNode::Embed(embed)) = node.blocks.last() else {
+            panic!("expected embed");
+        };
+        assert_eq!(embed.provider, Some("Widget".into()));
+        assert_eq!(embed.title(), "Widget");
+    }
+
+    #[test]
+    fn test_image() {
+        let html = r#"<img src="https://example.com/image.png" alt="Example" width="100" height="200" title="Example Image" />"#;
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        assert_eq!(
+            node,
+            ParsedDocument {
+                source: html.to_string().into(),
+                blocks: vec![BlockNode::Paragraph(Paragraph {
+                    span: None,
+                    children: vec![InlineNode::image(ImageNode {
+                        url: "https://example.com/image.png".to_string().into(),
+                        alt: Some("Example".to_string().into()),
+                        width: Some(px(100.).into()),
+                        height: Some(px(200.).into()),
+                        title: Some("Example Image".to_string().into()),
+                        ..Default::default()
+                    })],
+                    ..Default::default()
+                })],
+                frontmatter: None,
+            }
+        );
+
+        let html = r#"<img src="https://example.com/image.png" alt="Example" style="width: 80%" title="Example Image" />"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        assert_eq!(
+            node,
+            ParsedDocument {
+                source: html.to_string().into(),
+                blocks: vec![BlockNode::Paragraph(Paragraph {
+                    span: None,
+                    children: vec![InlineNode::image(ImageNode {
+                        url: "https://example.com/image.png".to_string().into(),
+                        alt: Some("Example".to_string().into()),
+                        width: Some(relative(0.8)),
+                        height: None,
+                        title: Some("Example Image".to_string().into()),
+                        ..Default::default()
+                    })],
+                    ..Default::default()
+                })],
+                frontmatter: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_image_to_html() {
+        let mut cx = NodeContext::default();
+        let html =
+            r#"<img class="emoji" src="https://a.com/1.png" width="20" style="height: 2rem">"#;
+        let document = super::parse(html, &mut cx).unwrap();
+        assert_eq!(
+            document.to_html(),
+            r#"<p><img src="https://a.com/1.png" class="emoji" width="20" style="height: 2rem"></p>"#
+        );
+
+        // The relative width is not an attribute, which is read as pixels.
+        let html = r#"<img src="https://a.com/1.png" style="width: 80%; max-width: 100px">"#;
+        let document = super::parse(html, &mut cx).unwrap();
+        assert_eq!(
+            document.to_html(),
+            r#"<p><img src="https://a.com/1.png" style="width: 80%; max-width: 100px"></p>"#
+        );
+    }
+
+    #[test]
+    fn test_image_fit() {
+        let image = |html: &str| {
+            let mut cx = NodeContext::default();
+            let document = super::parse(html, &mut cx).unwrap();
+            let Some(BlockNode::Paragraph(paragraph)) = document.blocks.first() else {
+                panic!("expected paragraph");
+            };
+            paragraph.children[0].image.clone().unwrap()
+        };
+
+        // A 2000px image in a 400px column fills the column width at 400x200.
+        let fit = image(r#"<img src="https://a.com/1.png" width="2000" height="1000" />"#)
+            .fit(None)
+            .unwrap();
+        assert_eq!(fit.width, px(2000.));
+        assert_eq!(fit.aspect_ratio, 2.);
+        let fit = fit.clamp(Some(px(400.)), None);
+        assert_eq!(
+            (fit.width, fit.width / fit.aspect_ratio),
+            (px(400.), px(200.))
+        );
+
+        // The `max-height` of the style and the max size of the view both scale down the width.
+        let fit = image(
+            r#"<img src="https://a.com/1.png" width="2000" height="1000" style="max-height: 300px" />"#,
+        )
+        .fit(None)
+        .unwrap();
+        assert_eq!((fit.width, fit.aspect_ratio), (px(600.), 2.));
+        let fit = fit.clamp(Some(px(400.)), Some(px(100.)));
+        assert_eq!(
+            (fit.width, fit.width / fit.aspect_ratio),
+            (px(200.), px(100.))
+        );
+
+        let fit = image(r#"<img src="https://a.com/1.png" style="width: 300px; height: 400px" />"#)
+            .fit(None)
+            .unwrap();
+        assert_eq!(fit.width, px(300.));
+        assert_eq!(fit.aspect_ratio, 0.75);
+
+        // Only one dimension, follow the intrinsic ratio, or reserve a square box before loaded.
+        let image_400 = image(r#"<img src="https://a.com/1.png" width="400" />"#);
+        assert_eq!(
+            image_400.fit(None).map(|fit| (fit.width, fit.aspect_ratio)),
+            Some((px(400.), 1.))
+        );
+        assert_eq!(
+            image_400
+                .fit(Some(2.))
+                .map(|fit| (fit.width, fit.aspect_ratio)),
+            Some((px(400.), 2.))
+        );
+        let image_100 = image(r#"<img src="https://a.com/1.png" style="height: 100px" />"#);
+        assert_eq!(
+            image_100
+                .fit(Some(2.))
+                .map(|fit| (fit.width, fit.aspect_ratio)),
+            Some((px(200.), 2.))
+        );
+        assert_eq!(
+            image(r#"<img src="https://a.com/1.png" />"#).fit(Some(2.)),
+            None
+        );
+
+        // The rems are resolved on layout.
+        let image_rems =
+            image(r#"<img src="https://a.com/1.png" style="width: 10rem; height: 50%" />"#);
+        assert_eq!(image_rems.width, Some(rems(10.).into()));
+        assert_eq!(image_rems.height, Some(relative(0.5)));
+        assert_eq!(image_rems.fit(None), None);
+
+        let image_em =
+            image(r#"<img src="https://a.com/1.png" style="width: 10em; height: 50%" />"#);
+        assert_eq!(image_em.width, Some(px(140.).into()));
+        assert_eq!(image_em.height, Some(relative(0.5)));
+
+        // The `em` is relative to the font size of the heading.
+        let mut cx = NodeContext::default();
+        let document = super::parse(
+            r#"<h1><img src="https://a.com/1.png" style="width: 2em; height: 20px" /></h1>"#,
+            &mut cx,
+        )
+        .unwrap();
+        let Some(BlockNode::Heading { children, .. }) = document.blocks.first() else {
+            panic!("expected heading");
+        };
+        let image_em = children.children[0].image.clone().unwrap();
+        assert_eq!(image_em.width, Some(px(56.).into()));
+        assert_eq!(image_em.height, Some(px(20.).into()));
+
+        // The percentage width is resolved against the container.
+        assert_eq!(
+            image(r#"<img src="https://a.com/1.png" width="50%" height="100" />"#).fit(None),
+            None
+        );
+        // The invalid width is ignored.
+        assert_eq!(
+            image(r#"<img src="https://a.com/1.png" width="0" height="100" />"#)
+                .fit(None)
+                .map(|fit| (fit.width, fit.aspect_ratio)),
+            Some((px(100.), 1.))
+        );
+    }
+
+    #[test]
+    fn test_image_max_size() {
+        let html =
+            r#"<img src="https://a.com/1.png" width="1200" style="max-width:100%;height:auto" />"#;
+        let mut cx = NodeContext::default();
+        let document = super::parse(html, &mut cx).unwrap();
+        let Some(BlockNode::Paragraph(paragraph)) = document.blocks.first() else {
+            panic!("expected paragraph");
+        };
+        let image = paragraph.children[0].image.clone().unwrap();
+        assert_eq!(image.width, Some(px(1200.).into()));
+        assert_eq!(image.height, None);
+        assert_eq!(image.max_width, Some(relative(1.)));
+        assert_eq!(image.max_height, None);
+        assert_eq!(
+            document.to_html(),
+            r#"<p><img src="https://a.com/1.png" width="1200" style="max-width: 100%"></p>"#
+        );
+
+        // The `max-height` scales down the width with the aspect ratio.
+        let html = r#"<img src="https://a.com/1.png" width="2000" height="1000" style="max-height: 100px" />"#;
+        let document = super::parse(html, &mut cx).unwrap();
+        let Some(BlockNode::Paragraph(paragraph)) = document.blocks.first() else {
+            panic!("expected paragraph");
+        };
+        let image = paragraph.children[0].image.clone().unwrap();
+        assert_eq!(image.max_height, Some(px(100.).into()));
+        let fit = image.fit(None).unwrap();
+        assert_eq!((fit.width, fit.aspect_ratio), (px(200.), 2.));
+    }
+
+    #[test]
+    fn test_image_loading() {
+        let image = |html: &str| {
+            let mut cx = NodeContext::default();
+            let document = super::parse(html, &mut cx).unwrap();
+            let Some(BlockNode::Paragraph(paragraph)) = document.blocks.first() else {
+                panic!("expected paragraph");
+            };
+            (paragraph.children[0].image.clone().unwrap(), document)
+        };
+
+        let (lazy, document) = image(r#"<img src="https://a.com/1.png" loading="LAZY" />"#);
+        assert_eq!(lazy.loading, ImageLoading::Lazy);
+        assert_eq!(
+            document.to_html(),
+            r#"<p><img src="https://a.com/1.png" loading="lazy"></p>"#
+        );
+
+        for html in [
+            r#"<img src="https://a.com/1.png" loading="eager" />"#,
+            r#"<img src="https://a.com/1.png" loading="auto" />"#,
+            r#"<img src="https://a.com/1.png" />"#,
+        ] {
+            let (image, document) = image(html);
+            assert_eq!(image.loading, ImageLoading::Eager);
+            assert_eq!(
+                document.to_html(),
+                r#"<p><img src="https://a.com/1.png"></p>"#
+            );
+        }
+        assert_ne!(lazy, image(r#"<img src="https://a.com/1.png" />"#).0);
+    }
+
+    #[test]
+    fn test_image_fit_clamp() {
+        let mut cx = NodeContext::default();
+        let html = r#"<img src="https://a.com/1.png" width="2000" height="1000" />"#;
+        let document = super::parse(html, &mut cx).unwrap();
+        let Some(BlockNode::Paragraph(paragraph)) = document.blocks.first() else {
+            panic!("expected paragraph");
+        };
+        let fit = paragraph.children[0]
+            .image
+            .clone()
+            .unwrap()
+            .fit(None)
+            .unwrap();
+
+        // Clamped by the max width or height, whichever is smaller, with the aspect ratio.
+        let clamp = |max_width: Option<f32>, max_height: Option<f32>| {
+            let fit = fit.clamp(max_width.map(px), max_height.map(px));
+            (fit.width, fit.aspect_ratio)
+        };
+        assert_eq!(clamp(Some(800.), None), (px(800.), 2.));
+        assert_eq!(clamp(None, Some(300.)), (px(600.), 2.));
+        assert_eq!(clamp(Some(800.), Some(300.)), (px(600.), 2.));
+        assert_eq!(clamp(Some(500.), Some(300.)), (px(500.), 2.));
+        // The zero or None means unconstrained, the smaller image is not scaled up.
+        assert_eq!(clamp(None, None), (px(2000.), 2.));
+        assert_eq!(clamp(Some(0.), Some(0.)), (px(2000.), 2.));
+        assert_eq!(clamp(Some(4000.), None), (px(2000.), 2.));
+    }
+
+    #[test]
+    fn test_parse_style() {
+        let styles = super::parse_style(
+            r#"WIDTH: 100px; background: url(data:image/png;base64,iVBOR==) no-repeat;
+            font-family: "A; B", serif; /* color: red; */ height : 50% ; invalid; margin:"#,
+        );
+        assert_eq!(styles.len(), 5);
+        assert_eq!(styles["width"], "100px");
+        assert_eq!(
+            styles["background"],
+            "url(data:image/png;base64,iVBOR==) no-repeat"
+        );
+        assert_eq!(styles["font-family"], r#""A; B", serif"#);
+        assert_eq!(styles["height"], "50%");
+        assert_eq!(styles["margin"], "");
+        assert!(!styles.contains_key("color"));
+
+        let styles = super::parse_style("color: red; color: /* blue */ green");
+        assert_eq!(styles["color"], "green");
+    }
+
+    #[test]
+    fn test_horizontal_rule_style() {
+        let rule = |html: &str| {
+            let mut cx = NodeContext::default();
+            let document = super::parse(html, &mut cx).unwrap();
+            let Some(BlockNode::HorizontalRule { style, .. }) = document.blocks.first() else {
+                panic!("expected horizontal rule");
+            };
+            (*style, document.to_markdown(), document.to_html())
+        };
+
+        let (style, markdown, html) = rule(r#"<hr style="border-top: 2px dashed #ccc">"#);
+        assert_eq!(style.thickness, Some(px(2.)));
+        assert!(style.dashed);
+        assert_eq!(style.color, Some(Hsla::parse_hex("#cccccc").unwrap()));
+        assert_eq!(markdown, "---");
+        assert_eq!(html, r#"<hr style="border-top: 2px dashed #cccccc">"#);
+
+        // The longhands override the shorthand.
+        let (style, ..) = rule(
+            r#"<hr style="border: 1px dotted rgb(255, 0, 0); border-top-width: thick; border-style: solid">"#,
+        );
+        assert_eq!(style.thickness, Some(px(5.)));
+        assert!(!style.dashed);
+        assert_eq!(
+            style.color,
+            Some(
+                Rgba {
+                    r: 1.,
+                    g: 0.,
+                    b: 0.,
+                    a: 1.
+                }
+                .into()
+            )
+        );
+
+        // The unparseable styles fallback to the default divider.
+        let (style, markdown, html) = rule(r#"<hr style="border-top: 10% wavy tomato">"#);
+        assert_eq!(style, RuleStyle::default());
+        assert_eq!(markdown, "---");
+        assert_eq!(html, "<hr>");
+
+        let mut cx = NodeContext::default();
+        let document = super::parse("<p>Above</p><hr><p>Below</p>", &mut cx).unwrap();
+        assert_eq!(document.to_markdown(), "Above\n\n---\n\nBelow");
+    }
+
+    #[test]
+    fn test_parse_css_color() {
+        let color = |value: &str| {
+            super::parse_css_color(value).map(|color| {
+                let rgb = color.to_rgb();
+                [rgb.r, rgb.g, rgb.b, rgb.a].map(|value| (value * 255.).round() as u8)
+            })
+        };
+        assert_eq!(color("#ccc"), Some([204, 204, 204, 255]));
+        assert_eq!(color("#FF000080"), Some([255, 0, 0, 128]));
+        assert_eq!(color("rgb(0 128 255)"), Some([0, 128, 255, 255]));
+        assert_eq!(color("rgba(255, 0, 0, 50%)"), Some([255, 0, 0, 128]));
+        assert_eq!(color("#ggg"), None);
+        assert_eq!(color("red"), None);
+        assert_eq!(color("rgb(1, 2)"), None);
+    }
+
+    #[test]
+    fn test_semantic_tags() {
+        let html =
+            "<p><cite>A</cite>|<dfn>B</dfn>|<var>C</var>|<samp>D</samp></p><address>E</address>";
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        let marks = paragraph
+            .children
+            .iter()
+            .flat_map(|inline| inline.marks.iter().map(|(_, mark)| mark.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            marks,
+            vec![
+                TextMark::default().italic(),
+                TextMark::default().italic(),
+                TextMark::default().italic().code(),
+                TextMark::default().code(),
+            ]
+        );
+        assert_eq!(node.to_markdown(), "*A*|*B*|*`C`*|`D`\n\n*E*");
+
+        // Keep the tags as the inline HTML.
+        let mut cx = NodeContext {
+            keep_semantic_tags: true,
+            ..Default::default()
+        };
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        assert_eq!(
+            paragraph.children[0].marks[0].1,
+            TextMark {
+                italic: true,
+                semantic: Some(SemanticTag::Cite),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            node.to_markdown(),
+            "<cite>A</cite>|<dfn>B</dfn>|<var>C</var>|<samp>D</samp>\n\n<address>E</address>"
+        );
+        assert_eq!(
+            node.blocks[0].to_html(),
+            "<p><cite>A</cite>|<dfn>B</dfn>|<var>C</var>|<samp>D</samp></p>"
+        );
+
+        // One `<address>` around the paragraph of the multiple texts.
+        let html =
+            r#"<address>Written by <a href="mailto:jon@example.com">Jon</a><br>Box 564</address>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Root { children, .. } = &node.blocks[0] else {
+            panic!("expected root");
+        };
+        let BlockNode::Paragraph(paragraph) = &children[0] else {
+            panic!("expected paragraph");
+        };
+        assert_eq!(paragraph.semantic, Some(SemanticTag::Address));
+        assert!(paragraph.children[0].marks[0].1.italic);
+        let address =
+            r#"<address>Written by <a href="mailto:jon@example.com">Jon</a><br>Box 564</address>"#;
+        assert_eq!(node.to_markdown(), address);
+        assert_eq!(node.blocks[0].to_html(), address);
+    }
+
+    #[test]
+    fn test_time_formatter() {
+        let html = r#"<p>Posted <time datetime="2024-01-02T15:04Z">Jan 2</time>, <b>edited</b> <time datetime="2024-01-03">Jan 3</time>.</p>"#;
+        let mut cx = NodeContext::default();
+        let document = super::parse(html, &mut cx).unwrap();
+
+        // The original text and the datetime are kept in the document.
+        assert_eq!(
+            document.to_html(),
+            r#"<p>Posted <time datetime="2024-01-02T15:04Z">Jan 2</time>, <strong>edited</strong> <time datetime="2024-01-03">Jan 3</time>.</p>"#
+        );
+        let BlockNode::Paragraph(paragraph) = &document.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        let (text, display_map) = display_text(&paragraph.children[0], FirstLine::Normal, &cx);
+        assert_eq!(text, "Posted Jan 2, edited Jan 3.");
+        assert!(display_map.replaced.is_empty());
+
+        // Formatted on render, copied as displayed.
+        cx.time_formatter = Some(std::sync::Arc::new(|datetime: &str, _: &str| {
+            (datetime == "2024-01-02T15:04Z").then(|| "2 days ago".into())
+        }));
+        let (text, display_map) = display_text(&paragraph.children[0], FirstLine::Normal, &cx);
+        assert_eq!(text, "Posted 2 days ago, edited Jan 3.");
+        assert_eq!(
+            display_map.replaced,
+            vec![Replaced {
+                copy_displayed: true,
+                ..Replaced::new(7..17, "Jan 2")
+            }]
+        );
+        // The marks after the formatted time are shifted, e.g.: `edited`.
+        assert_eq!(display_map.display_range(&(14..20)), 19..25);
+    }
+
+    #[test]
+    fn test_style_marks() {
+        let html = r#"<p><span style="font-weight:bold; font-style:italic; text-decoration: underline line-through">ab</span>|<b style="font-style: italic">cd</b>|<span style="font-weight: 700">ef</span>|<span style="font-weight: 400; color: red">gh</span>|<u>ij</u></p>"#;
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Paragraph(paragraph) = &node.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        assert_eq!(paragraph.to_plain_text(), "ab|cd|ef|gh|ij");
+
+        let mark_at = |offset: usize| {
+            let mut mark = TextMark::default();
+            let mut start = 0;
+            for inline in paragraph.children.iter() {
+                for (range, m) in inline.marks.iter() {
+                    if range.contains(&(offset - start)) {
+                        mark.merge(m.clone());
+                    }
+                }
+                start += inline.text.len();
+                if start > offset {
+                    break;
+                }
+            }
+            mark
+        };
+
+        assert_eq!(
+            mark_at(0),
+            TextMark::default()
+                .bold()
+                .italic()
+                .underline()
+                .strikethrough()
+        );
+        assert_eq!(mark_at(3), TextMark::default().bold().italic());
+        assert_eq!(mark_at(6), TextMark::default().bold());
+        assert_eq!(mark_at(9), TextMark::default());
+        assert_eq!(mark_at(12), TextMark::default().underline());
+        assert_eq!(
+            node.to_html(),
+            "<p><strong><em><del><u>ab</u></del></em></strong>|<strong><em>cd</em></strong>|<strong>ef</strong>|gh|<u>ij</u></p>"
+        );
+    }
+
+    #[test]
+    fn test_text_align() {
+        use crate::text::{Visitor, node::TextAlign};
+
+        #[derive(Default)]
+        struct AlignCollector(Vec<(String, Option<TextAlign>)>);
+
+        impl Visitor for AlignCollector {
+            fn visit_block(&mut self, block: &BlockNode) {
+                if let BlockNode::Paragraph(paragraph)
+                | BlockNode::Heading {
+                    children: paragraph,
+                    ..
+                } = block
+                {
+                    self.0.push((paragraph.to_plain_text(), paragraph.align));
+                }
+            }
+        }
+
+        let html = r#"Before<p style="text-align: center">A</p><div align="right"><p>B</p><p align="left">C</p></div><h2 align="Center">T</h2><center>D</center><p style="text-align: justify">E</p>"#;
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+
+        let mut collector = AlignCollector::default();
+        node.walk(&mut collector);
+        assert_eq!(
+            collector.0,
+            vec![
+                ("Before".to_string(), None),
+                ("A".to_string(), Some(TextAlign::Center)),
+                ("B".to_string(), Some(TextAlign::Right)),
+                ("C".to_string(), Some(TextAlign::Left)),
+                ("T".to_string(), Some(TextAlign::Center)),
+                ("D".to_string(), Some(TextAlign::Center)),
+                ("E".to_string(), Some(TextAlign::Justify)),
+            ]
+        );
+
+        let node = super::parse(r#"<p align="center">A</p>"#, &mut cx).unwrap();
+        assert_eq!(node.to_html(), r#"<p style="text-align: center">A</p>"#);
+    }
+
+    #[test]
+    fn test_text_direction() {
+        use crate::text::{Visitor, node::TextDirection};
+
+        #[derive(Default)]
+        struct DirCollector(Vec<(String, Option<TextDirection>)>);
+
+        impl Visitor for DirCollector {
+            fn visit_block(&mut self, block: &BlockNode) {
+                if let BlockNode::Paragraph(paragraph)
+                | BlockNode::Heading {
+                    children: paragraph,
+                    ..
+                } = block
+                {
+                    self.0.push((paragraph.to_plain_text(), paragraph.dir));
+                }
+            }
+        }
+
+        let html = r#"<p dir="rtl">שלום</p><div dir="auto"><p>مرحبا</p><p>Hello</p><ul><li>עולם</li></ul></div><h1 style="direction: rtl">T</h1><div dir="rtl"><p dir="ltr">L</p></div>"#;
+        let mut cx = NodeContext::default();
+        let node = super::parse(html, &mut cx).unwrap();
+
+        let mut collector = DirCollector::default();
+        node.walk(&mut collector);
+        assert_eq!(
+            collector.0,
+            vec![
+                ("שלום".to_string(), Some(TextDirection::Rtl)),
+                ("مرحبا".to_string(), Some(TextDirection::Rtl)),
+                ("Hello".to_string(), Some(TextDirection::Ltr)),
+                ("עולם".to_string(), Some(TextDirection::Rtl)),
+                ("T".to_string(), Some(TextDirection::Rtl)),
+                ("L".to_string(), Some(TextDirection::Ltr)),
+            ]
+        );
+        assert_eq!(
+            TextDirection::detect("123 (שלום) abc"),
+            Some(TextDirection::Rtl)
+        );
+        assert_eq!(TextDirection::detect("123"), None);
+
+        let node = super::parse(r#"<p dir="rtl" align="center">A</p>"#, &mut cx).unwrap();
+        assert_eq!(
+            node.to_html(),
+            r#"<p dir="rtl" style="text-align: center">A</p>"#
+        );
+    }
+
+    #[test]
+    fn test_table_header_rows() {
+        let mut cx = NodeContext::default();
+        let html = r#"<table><thead><tr><td>A</td></tr></thead><tbody><tr><th>B</th></tr><tr><td>C</td></tr></tbody></table>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Table(table) = &node.blocks[0] else {
+            panic!("expected table");
+        };
+        assert_eq!(
+            table
+                .children
+                .iter()
+                .map(|row| row.is_header)
+                .collect::<Vec<_>>(),
+            vec![true, true, false]
+        );
+        assert_eq!(table.header_len(), 2);
+
+        // No header rows
+        let html = r#"<table><tr><td>A</td></tr><tr><td>B</td></tr></table>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        assert_eq!(
+            node.to_html(),
+            indoc::indoc! {r#"
+            <table>
+            <tbody>
+            <tr>
+            <td>A</td>
+            </tr>
+            <tr>
+            <td>B</td>
+            </tr>
+            </tbody>
+            </table>"#}
+        );
+    }
+
+    #[test]
+    fn test_table_footer_rows() {
+        let mut cx = NodeContext::default();
+        let html = r#"<table><tfoot><tr><td>Total</td></tr></tfoot><tbody><tr><td>A</td></tr><tr><td>B</td></tr></tbody><thead><tr><th>Name</th></tr></thead></table>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Table(table) = &node.blocks[0] else {
+            panic!("expected table");
+        };
+        assert_eq!(
+            table
+                .children
+                .iter()
+                .map(|row| (row.is_header, row.is_footer))
+                .collect::<Vec<_>>(),
+            vec![(true, false), (false, false), (false, false), (false, true)]
+        );
+        assert_eq!(table.header_len(), 1);
+        assert_eq!(table.footer_len(), 1);
+        assert_eq!(
+            node.to_html(),
+            indoc::indoc! {r#"
+            <table>
+            <thead>
+            <tr>
+            <th>Name</th>
+            </tr>
+            </thead>
+            <tbody>
+            <tr>
+            <td>A</td>
+            </tr>
+            <tr>
+            <td>B</td>
+            </tr>
+            </tbody>
+            <tfoot>
+            <tr>
+            <td>Total</td>
+            </tr>
+            </tfoot>
+            </table>"#}
+        );
+    }
+
+    #[test]
+    fn test_table_column_widths() {
+        let mut cx = NodeContext::default();
+        let html = r#"<table><colgroup><col style="width: 20%"><col span="2" width="100"></colgroup><tr><td>A</td><td>B</td></tr></table>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Table(table) = &node.blocks[0] else {
+            panic!("expected table");
+        };
+        assert_eq!(
+            table.column_widths,
+            vec![
+                Some(relative(0.2)),
+                Some(px(100.).into()),
+                Some(px(100.).into())
+            ]
+        );
+        assert_eq!(table.column_width(1), Some(px(100.).into()));
+        // The columns without `<col>` have no declared width.
+        assert_eq!(table.column_width(3), None);
+    }
+
+    #[test]
+    fn test_custom_block() {
+        let mut cx = NodeContext {
+            custom_block_renderer: Some(std::sync::Arc::new(
+                CustomBlockRenderer::new(|block, _, _| block.text.clone().into_any_element())
+                    .class("poll"),
+            )),
+            ..Default::default()
+        };
+        let html = r#"<p>Vote:</p><div class="poll large" data-id="42">Yes<br>No</div><div class="other">Text</div>"#;
+        let node = super::parse(html, &mut cx).unwrap();
+        let BlockNode::Root { children, .. } = &node.blocks[0] else {
+            panic!("expected root");
+        };
+        let BlockNode::Custom(block) = &children[1] else {
+            panic!("expected custom block");
+        };
+        assert_eq!(block.tag.as_ref(), "div");
+        assert_eq!(block.attr("data-id").map(|v| v.as_ref()), Some("42"));
+        assert_eq!(block.text.as_ref(), "Yes\nNo");
+        // The unmatched element falls through to the default handling.
+        assert!(matches!(children[2], BlockNode::Paragraph(_)));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut cx = NodeContext {
+            sanitize: true,
+            diagnostics: Some(Default::default()),
+            ..Default::default()
+        };
+        // The handled tags (e.g.: the `<html>`, the `<style>` and the `<script>`) are not reported.
+        let html = r#"<html><head><style>p { color: red }</style><script>alert(1)</script></head><body><p style="color red; font-weight: bold">A</p><img alt="x"><custom-tag>B</custom-tag><a href="javascript:alert(1)">C</a></body></html>"#;
+        super::parse(html, &mut cx).unwrap();
+        let diagnostics = cx
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.kind, diagnostic.message.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    DiagnosticKind::MalformedStyle,
+                    "color red; font-weight: bold".to_string()
+                ),
+                (DiagnosticKind::UnsupportedTag, "custom-tag".to_string()),
+                (
+                    DiagnosticKind::DangerousHrefStripped,
+                    "javascript:alert(1)".to_string()
+                ),
+                (DiagnosticKind::ImageMissingSrc, "<img>".to_string()),
+            ]
+        );
+        assert!(
+            cx.diagnostics()
+                .iter()
+                .all(|diagnostic| diagnostic.span.is_none())
         );
     }
 }
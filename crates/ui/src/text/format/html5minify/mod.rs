@@ -109,6 +109,14 @@ impl<'a> Context<'a> {
         })
     }
 
+    /// Whether the whitespace is preserved by the parent or any ancestor element.
+    fn preserve_whitespace(&self) -> bool {
+        preserve_whitespace(self.parent)
+            || self
+                .parent_context
+                .map_or(false, Context::preserve_whitespace)
+    }
+
     fn is_block_element(node: &Rc<Node>) -> Option<bool> {
         if let NodeData::Element { name, .. } = &node.data {
             Some(is_block_element_name(name.local.as_ref()))
@@ -197,14 +205,23 @@ where
                         if let NodeData::Element { name, .. } = &ctx.parent.data {
                             let name = name.local.as_ref();
 
-                            (preserve_whitespace(name), contains_code(name))
+                            (ctx.preserve_whitespace(), contains_code(name))
                         } else {
                             (false, false)
                         }
                     });
 
                 if skip_collapse_whitespace {
-                    return self.w.write_all(contents.as_bytes());
+                    self.preceding_whitespace = contents
+                        .as_bytes()
+                        .last()
+                        .map_or(false, u8::is_ascii_whitespace);
+                    // The `<xmp>` is the raw text, the entities are not decoded.
+                    if ctx.as_ref().is_some_and(|ctx| is_raw_text(ctx.parent)) {
+                        return self.w.write_all(contents.as_bytes());
+                    }
+                    // Escape the reserved characters, otherwise `<` in code may be parsed as a tag.
+                    return self.write(contents.as_bytes(), reserved_entity);
                 }
 
                 if contains_code {
@@ -261,6 +278,11 @@ where
                 let tag = name.local.as_ref();
 
                 if is_self_closing(tag) {
+                    // The inline void element (e.g. `<img>`) is a content,
+                    // the whitespace after it must be kept.
+                    if !is_block_element_name(tag) {
+                        self.preceding_whitespace = false;
+                    }
                     return self.write_start_tag(name, &attrs);
                 }
 
@@ -707,8 +729,35 @@ fn is_ascii_whitespace(c: char) -> bool {
     c.is_ascii_whitespace()
 }
 
-fn preserve_whitespace(name: &str) -> bool {
-    matches!(name, "pre" | "textarea")
+/// Whether the element keeps its literal whitespace, e.g. `<pre>`, `<code>`
+/// or an element with `white-space: pre` style.
+fn preserve_whitespace(node: &Node) -> bool {
+    let NodeData::Element { name, attrs, .. } = &node.data else {
+        return false;
+    };
+
+    if matches!(name.local.as_ref(), "pre" | "textarea" | "code" | "xmp") {
+        return true;
+    }
+
+    attrs.borrow().iter().any(|attr| {
+        attr.name.local.as_ref() == "style"
+            && attr.value.split(';').any(|decl| {
+                let mut parts = decl.splitn(2, ':');
+                matches!(
+                    (parts.next().map(str::trim), parts.next().map(str::trim)),
+                    (
+                        Some("white-space"),
+                        Some("pre" | "pre-wrap" | "pre-line" | "break-spaces")
+                    )
+                )
+            })
+    })
+}
+
+/// Whether the text of the element is not entity-decoded on parsing, e.g. `<xmp>`.
+fn is_raw_text(node: &Node) -> bool {
+    matches!(&node.data, NodeData::Element { name, .. } if name.local.as_ref() == "xmp")
 }
 
 fn contains_code(name: &str) -> bool {
@@ -793,6 +842,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_preserve_whitespace() {
+        for &(input, expected) in &[
+            (
+                "<pre>fn main() {\n    if a < b {}\n}</pre>",
+                "<pre>fn main() {\n    if a &lt; b {}\n}</pre>",
+            ),
+            (
+                "<pre><code>  let a = 1;\n  let b = 2;</code></pre>",
+                "<pre><code>  let a = 1;\n  let b = 2;</code></pre>",
+            ),
+            ("<p>a <code>x  y</code> b</p>", "<p>a <code>x  y</code> b"),
+            (
+                "<div style=\"white-space: pre\">a   b</div>",
+                "<div style=\"white-space: pre\">a   b</div>",
+            ),
+            ("<div>a   b</div>", "<div>a b</div>"),
+            (
+                "<textarea>a  <b> &amp;</textarea>",
+                "<textarea>a  &lt;b&gt; &#38;</textarea>",
+            ),
+            ("<xmp>a  <b> &amp;</xmp>", "<xmp>a  <b> &amp;</xmp>"),
+        ] {
+            let mut w = vec![];
+            let mut minifier = Minifier::new(&mut w);
+            minifier.omit_doctype(true);
+            minifier.minify(&mut input.as_bytes()).unwrap();
+
+            let s = str::from_utf8(&w).unwrap();
+
+            assert_eq!(expected, s);
+        }
+    }
+
     #[test]
     fn test_omit_tags() {
         for &(input, expected, collapse_whitespace, preserve_comments) in &[
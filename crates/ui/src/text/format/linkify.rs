@@ -0,0 +1,182 @@
+//! Linkify the bare URLs in the plain text.
+//!
+//! The Markdown (GFM) already has the autolink literal extension, so this is used for HTML.
+
+use std::ops::Range;
+
+use crate::text::node::{BlockNode, LinkMark, Paragraph, TextMark};
+
+const PREFIXES: [&str; 4] = ["https://", "http://", "www.", "mailto:"];
+
+/// The trailing punctuation will not be a part of the URL, e.g.: `See https://x.com.`
+const TRAILING_PUNCTUATION: [char; 11] = ['.', ',', ':', ';', '!', '?', '\'', '"', '*', '_', '~'];
+
+pub(super) fn linkify_block(node: &mut BlockNode) {
+    match node {
+        BlockNode::Root { children, .. }
+        | BlockNode::Blockquote { children, .. }
+        | BlockNode::Admonition { children, .. }
+        | BlockNode::List { children, .. }
+        | BlockNode::ListItem { children, .. } => {
+            children.iter_mut().for_each(linkify_block);
+        }
+        BlockNode::Paragraph(paragraph)
+        | BlockNode::Heading {
+            children: paragraph,
+            ..
+        } => linkify(paragraph),
+        BlockNode::Table(table) => {
+            for row in table.children.iter_mut() {
+                for cell in row.children.iter_mut() {
+                    cell.children.iter_mut().for_each(linkify_block);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Add the [`LinkMark`] to the bare URLs in the paragraph.
+///
+/// The URLs inside the code spans, the math or the existing links are skipped.
+fn linkify(paragraph: &mut Paragraph) {
+    for node in paragraph.children.iter_mut() {
+        if node.image.is_some() {
+            continue;
+        }
+
+        for range in find_urls(&node.text) {
+            let overlapped = node.marks.iter().any(|(mark_range, mark)| {
+                (mark.code || mark.math || mark.link.is_some())
+                    && mark_range.start < range.end
+                    && range.start < mark_range.end
+            });
+            if overlapped {
+                continue;
+            }
+
+            let url = &node.text[range.clone()];
+            let url = if url.get(..4).is_some_and(|s| s.eq_ignore_ascii_case("www.")) {
+                format!("http://{}", url)
+            } else {
+                url.to_string()
+            };
+
+            node.marks.push((
+                range,
+                TextMark {
+                    link: Some(LinkMark {
+                        url: url.into(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ));
+        }
+    }
+}
+
+/// Find the bare URLs in the text, return the byte ranges.
+fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut prev_char: Option<char> = None;
+    let mut skip_to = 0;
+
+    for (ix, c) in text.char_indices() {
+        let is_boundary = prev_char.is_none_or(|c| !c.is_alphanumeric() && c != '/' && c != '@');
+        prev_char = Some(c);
+        if ix < skip_to || !is_boundary {
+            continue;
+        }
+
+        let Some(prefix) = PREFIXES.iter().find(|prefix| {
+            text.get(ix..ix + prefix.len())
+                .is_some_and(|s| s.eq_ignore_ascii_case(prefix))
+        }) else {
+            continue;
+        };
+
+        let end = text[ix..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '`'))
+            .map_or(text.len(), |len| ix + len);
+        let end = trim_url_end(&text[ix..end]) + ix;
+
+        // Require some content after the prefix, e.g.: `https://` is not a link.
+        let rest = &text[ix + prefix.len()..end];
+        let valid = match *prefix {
+            "mailto:" => rest.contains('@'),
+            _ => rest.chars().next().is_some_and(|c| c.is_alphanumeric()),
+        };
+        if !valid {
+            continue;
+        }
+
+        ranges.push(ix..end);
+        skip_to = end;
+    }
+
+    ranges
+}
+
+/// Return the length of the URL without the trailing punctuation and unbalanced parentheses.
+fn trim_url_end(url: &str) -> usize {
+    let mut url = url;
+    loop {
+        let Some(last) = url.chars().last() else {
+            break;
+        };
+
+        if TRAILING_PUNCTUATION.contains(&last) {
+            url = &url[..url.len() - 1];
+            continue;
+        }
+
+        let open = match last {
+            ')' => '(',
+            ']' => '[',
+            _ => break,
+        };
+        if url.matches(last).count() > url.matches(open).count() {
+            url = &url[..url.len() - 1];
+            continue;
+        }
+
+        break;
+    }
+
+    url.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_urls;
+
+    fn urls(text: &str) -> Vec<&str> {
+        find_urls(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn test_find_urls() {
+        assert_eq!(
+            urls("Visit https://example.com today"),
+            vec!["https://example.com"]
+        );
+        assert_eq!(urls("(https://x.com)."), vec!["https://x.com"]);
+        assert_eq!(
+            urls("See https://en.wikipedia.org/wiki/Rust_(programming_language), ok?"),
+            vec!["https://en.wikipedia.org/wiki/Rust_(programming_language)"]
+        );
+        assert_eq!(
+            urls("www.example.com and mailto:foo@example.com."),
+            vec!["www.example.com", "mailto:foo@example.com"]
+        );
+        assert_eq!(urls("Not https:// or awww.example.com"), Vec::<&str>::new());
+        assert_eq!(
+            urls("你好 https://example.com/路径"),
+            vec!["https://example.com/路径"]
+        );
+    }
+}
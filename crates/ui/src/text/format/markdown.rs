@@ -1,3 +1,5 @@
+use std::collections::{HashMap, HashSet};
+
 use gpui::SharedString;
 use markdown::{
     ParseOptions,
@@ -9,12 +11,15 @@ use crate::{
     text::{
         document::ParsedDocument,
         node::{
-            self, BlockNode, CodeBlock, ImageNode, InlineNode, LinkMark, NodeContext, Paragraph,
-            Span, Table, TableRow, TextMark,
+            self, BlockNode, CodeBlock, DiagnosticKind, ImageNode, InlineNode, LinkMark,
+            NodeContext, Paragraph, Span, Table, TableRow, TextMark,
         },
+        visitor::{VisitorMut, walk_block_mut},
     },
 };
 
+use super::sanitize::{is_safe_image, is_safe_link};
+
 /// Parse Markdown into a tree of nodes.
 ///
 /// TODO: Remove `highlight_theme` option, this should in render stage.
@@ -23,13 +28,31 @@ pub(crate) fn parse(
     cx: &mut NodeContext,
     highlight_theme: &HighlightTheme,
 ) -> Result<ParsedDocument, SharedString> {
-    markdown::to_mdast(&source, &ParseOptions::gfm())
+    let mut options = ParseOptions::gfm();
+    // The frontmatter is only at the start of the document, not the re-parsed trailing block.
+    options.constructs.frontmatter = cx.offset == 0;
+    // The inline `$...$` and the display `$$...$$` math, the escaped `\$` is not a delimiter.
+    options.constructs.math_text = true;
+    options.constructs.math_flow = true;
+
+    let mut document = markdown::to_mdast(&source, &options)
         .map(|n| ast_to_document(source, n, cx, highlight_theme))
-        .map_err(|e| e.to_string().into())
+        .map_err(|e| SharedString::from(e.to_string()))?;
+    if let Some(resolver) = cx.mention_resolver.clone() {
+        super::mention::resolve_mentions(&mut document, &resolver);
+    }
+    if cx.emoji_shortcodes {
+        super::emoji::replace_shortcodes(&mut document);
+    }
+    Ok(document)
 }
 
 fn parse_table_row(table: &mut Table, node: &mdast::TableRow, cx: &mut NodeContext) {
-    let mut row = TableRow::default();
+    // The first row of the GFM table is always the header.
+    let mut row = TableRow {
+        is_header: table.children.is_empty(),
+        ..Default::default()
+    };
     node.children.iter().for_each(|c| {
         match c {
             Node::TableCell(cell) => {
@@ -47,12 +70,24 @@ fn parse_table_cell(row: &mut node::TableRow, node: &mdast::TableCell, cx: &mut
         parse_paragraph(&mut paragraph, c, cx);
     });
     let table_cell = node::TableCell {
-        children: paragraph,
+        children: vec![BlockNode::Paragraph(paragraph)],
         ..Default::default()
     };
     row.children.push(table_cell);
 }
 
+/// Push the children of `child_paragraph` into `paragraph` with an extra `mark`.
+///
+/// The inner marks are kept, so nested styles (e.g. a link inside bold) are composed.
+fn push_with_mark(paragraph: &mut Paragraph, child_paragraph: Paragraph, mark: TextMark) {
+    for mut child in child_paragraph.children {
+        if child.image.is_none() {
+            child.marks.push((0..child.text.len(), mark.clone()));
+        }
+        paragraph.push(child);
+    }
+}
+
 fn parse_paragraph(paragraph: &mut Paragraph, node: &mdast::Node, cx: &mut NodeContext) -> String {
     let span = node.position().map(|pos| Span {
         start: cx.offset + pos.start.offset,
@@ -79,27 +114,24 @@ fn parse_paragraph(paragraph: &mut Paragraph, node: &mdast::Node, cx: &mut NodeC
             for child in val.children.iter() {
                 text.push_str(&parse_paragraph(&mut child_paragraph, &child, cx));
             }
-            paragraph.push(
-                InlineNode::new(&text).marks(vec![(0..text.len(), TextMark::default().italic())]),
-            );
+            push_with_mark(paragraph, child_paragraph, TextMark::default().italic());
         }
         Node::Strong(val) => {
             let mut child_paragraph = Paragraph::default();
             for child in val.children.iter() {
                 text.push_str(&parse_paragraph(&mut child_paragraph, &child, cx));
             }
-            paragraph.push(
-                InlineNode::new(&text).marks(vec![(0..text.len(), TextMark::default().bold())]),
-            );
+            push_with_mark(paragraph, child_paragraph, TextMark::default().bold());
         }
         Node::Delete(val) => {
             let mut child_paragraph = Paragraph::default();
             for child in val.children.iter() {
                 text.push_str(&parse_paragraph(&mut child_paragraph, &child, cx));
             }
-            paragraph.push(
-                InlineNode::new(&text)
-                    .marks(vec![(0..text.len(), TextMark::default().strikethrough())]),
+            push_with_mark(
+                paragraph,
+                child_paragraph,
+                TextMark::default().strikethrough(),
             );
         }
         Node::InlineCode(val) => {
@@ -109,7 +141,16 @@ fn parse_paragraph(paragraph: &mut Paragraph, node: &mdast::Node, cx: &mut NodeC
             );
         }
         Node::Link(val) => {
-            let link_mark = Some(LinkMark {
+            // The link with disallowed scheme will be plain text.
+            let safe = !cx.sanitize || is_safe_link(&val.url);
+            if !safe {
+                cx.report(
+                    DiagnosticKind::DangerousHrefStripped,
+                    &val.url,
+                    new_span(val.position.clone(), cx),
+                );
+            }
+            let link_mark = safe.then(|| LinkMark {
                 url: val.url.clone().into(),
                 title: val.title.clone().map(|s| s.into()),
                 ..Default::default()
@@ -122,34 +163,54 @@ fn parse_paragraph(paragraph: &mut Paragraph, node: &mdast::Node, cx: &mut NodeC
 
             // FIXME: GPUI InteractiveText does not support inline images yet.
             // So here we push images to the paragraph directly.
-            for child in child_paragraph.children.iter_mut() {
-                if let Some(image) = child.image.as_mut() {
-                    image.link = link_mark.clone();
-                }
+            if link_mark.is_some() {
+                for child in child_paragraph.children.iter_mut() {
+                    if let Some(image) = child.image.as_mut() {
+                        image.link = link_mark.clone();
+                    }
 
-                child.marks.push((
-                    0..child.text.len(),
-                    TextMark {
-                        link: link_mark.clone(),
-                        ..Default::default()
-                    },
-                ));
+                    child.marks.push((
+                        0..child.text.len(),
+                        TextMark {
+                            link: link_mark.clone(),
+                            ..Default::default()
+                        },
+                    ));
+                }
             }
 
             paragraph.merge(child_paragraph);
         }
         Node::Image(raw) => {
-            paragraph.push_image(ImageNode {
-                url: raw.url.clone().into(),
-                title: raw.title.clone().map(|t| t.into()),
-                alt: Some(raw.alt.clone().into()),
-                ..Default::default()
-            });
+            // The image with disallowed scheme will be plain alt text.
+            if cx.sanitize && !is_safe_image(&raw.url) {
+                cx.report(
+                    DiagnosticKind::DangerousHrefStripped,
+                    &raw.url,
+                    new_span(raw.position.clone(), cx),
+                );
+                text = raw.alt.clone();
+                paragraph.push_str(&text);
+            } else {
+                if raw.url.is_empty() {
+                    cx.report(
+                        DiagnosticKind::ImageMissingSrc,
+                        &raw.alt,
+                        new_span(raw.position.clone(), cx),
+                    );
+                }
+                paragraph.push_image(ImageNode {
+                    url: raw.url.clone().into(),
+                    title: raw.title.clone().map(|t| t.into()),
+                    alt: Some(raw.alt.clone().into()),
+                    ..Default::default()
+                });
+            }
         }
         Node::InlineMath(raw) => {
             text = raw.value.clone();
             paragraph.push(
-                InlineNode::new(&text).marks(vec![(0..text.len(), TextMark::default().code())]),
+                InlineNode::new(&text).marks(vec![(0..text.len(), TextMark::default().math())]),
             );
         }
         Node::MdxTextExpression(raw) => {
@@ -157,7 +218,7 @@ fn parse_paragraph(paragraph: &mut Paragraph, node: &mdast::Node, cx: &mut NodeC
             paragraph
                 .push(InlineNode::new(&text).marks(vec![(0..text.len(), TextMark::default())]));
         }
-        Node::Html(val) => match super::html::parse(&val.value, cx) {
+        Node::Html(val) => match parse_html(&val.value, new_span(val.position.clone(), cx), cx) {
             Ok(el) => {
                 if el
                     .blocks
@@ -166,8 +227,13 @@ fn parse_paragraph(paragraph: &mut Paragraph, node: &mdast::Node, cx: &mut NodeC
                     .unwrap_or(false)
                 {
                     text = "\n".to_owned();
-                    paragraph.push(InlineNode::new(&text));
+                    paragraph.push_break();
                 } else {
+                    cx.report(
+                        DiagnosticKind::UnsupportedTag,
+                        &val.value,
+                        new_span(val.position.clone(), cx),
+                    );
                     if cfg!(debug_assertions) {
                         tracing::warn!("unsupported inline html tag: {:#?}", el);
                     }
@@ -181,14 +247,16 @@ fn parse_paragraph(paragraph: &mut Paragraph, node: &mdast::Node, cx: &mut NodeC
                 text.push_str(&val.value);
             }
         },
+        Node::Break(_) => {
+            text = "\n".to_owned();
+            paragraph.push_break();
+        }
         Node::FootnoteReference(foot) => {
-            let prefix = format!("[{}]", foot.identifier);
-            paragraph.push(InlineNode::new(&prefix).marks(vec![(
-                0..prefix.len(),
-                TextMark {
-                    italic: true,
-                    ..Default::default()
-                },
+            // The text is replaced by the number of the footnote, see `collect_footnotes`.
+            let identifier = foot.identifier.clone();
+            paragraph.push(InlineNode::new(&identifier).marks(vec![(
+                0..identifier.len(),
+                TextMark::default().footnote(identifier.clone()),
             )]));
         }
         Node::LinkReference(link) => {
@@ -233,15 +301,213 @@ fn ast_to_document(
         _ => panic!("expected root node"),
     };
 
-    let blocks = root
-        .children
+    let mut children = root.children;
+    children
+        .iter_mut()
+        .for_each(|child| demote_currency(child, source));
+    // The malformed frontmatter is kept in the body as a YAML code block.
+    let frontmatter = match children.first() {
+        Some(Node::Yaml(yaml)) => parse_frontmatter(&yaml.value),
+        _ => None,
+    };
+    if frontmatter.is_some() {
+        children.remove(0);
+    }
+
+    let mut blocks = children
         .into_iter()
         .map(|c| ast_to_node(c, cx, highlight_theme))
         .collect();
+    collect_footnotes(&mut blocks);
     ParsedDocument {
         source: source.to_string().into(),
         blocks,
+        frontmatter,
+    }
+}
+
+/// Convert the inline math like the currency back to the text, e.g.: `$5 and $10`.
+///
+/// The inline math has no whitespace just inside the `$` delimiters, and no digit right after
+/// the closing `$`.
+fn demote_currency(node: &mut Node, source: &str) {
+    let Some(children) = node.children_mut() else {
+        return;
+    };
+    for child in children.iter_mut() {
+        if let Node::InlineMath(math) = child
+            && let Some(position) = &math.position
+        {
+            let raw = &source[position.start.offset..position.end.offset];
+            let formula = raw.trim_matches('$');
+            if formula.starts_with(char::is_whitespace)
+                || formula.ends_with(char::is_whitespace)
+                || source[position.end.offset..].starts_with(|c: char| c.is_ascii_digit())
+            {
+                *child = Node::Text(mdast::Text {
+                    value: raw.to_string(),
+                    position: math.position.clone(),
+                });
+            }
+            continue;
+        }
+        demote_currency(child, source);
+    }
+}
+
+/// Move the footnote definitions to the [`BlockNode::Footnotes`] at the end of the document,
+/// numbered in the order of the first reference, the definitions without reference are removed.
+///
+/// The text of each reference is replaced by the number, the references without definition
+/// are kept as the plain text.
+fn collect_footnotes(blocks: &mut Vec<BlockNode>) {
+    struct NumberFootnotes {
+        defined: HashSet<SharedString>,
+        /// The identifiers in the order of the first reference.
+        order: Vec<SharedString>,
+        references: HashMap<SharedString, usize>,
+    }
+
+    impl VisitorMut for NumberFootnotes {
+        fn visit_inline_mut(&mut self, inline: &mut InlineNode) {
+            let Some(identifier) = inline
+                .marks
+                .iter()
+                .find_map(|(_, mark)| mark.footnote.clone())
+            else {
+                return;
+            };
+
+            if !self.defined.contains(&identifier) {
+                inline.text = format!("[^{}]", identifier).into();
+                inline.marks.clear();
+                return;
+            }
+
+            let number = match self.order.iter().position(|id| *id == identifier) {
+                Some(ix) => ix + 1,
+                None => {
+                    self.order.push(identifier.clone());
+                    self.order.len()
+                }
+            };
+            *self.references.entry(identifier).or_default() += 1;
+            inline.text = number.to_string().into();
+            for (range, _) in inline.marks.iter_mut() {
+                *range = 0..inline.text.len();
+            }
+        }
+    }
+
+    let mut definitions = HashMap::new();
+    take_footnote_definitions(blocks, &mut definitions);
+
+    let mut visitor = NumberFootnotes {
+        defined: definitions.keys().cloned().collect(),
+        order: vec![],
+        references: HashMap::new(),
+    };
+    for block in blocks.iter_mut() {
+        walk_block_mut(block, &mut visitor);
+    }
+
+    // The definitions may reference the other footnotes, which are appended to the order.
+    let mut footnotes = vec![];
+    let mut ix = 0;
+    while ix < visitor.order.len() {
+        if let Some(mut definition) = definitions.remove(&visitor.order[ix]) {
+            walk_block_mut(&mut definition, &mut visitor);
+            footnotes.push(definition);
+        }
+        ix += 1;
     }
+
+    for definition in footnotes.iter_mut() {
+        if let BlockNode::FootnoteDefinition {
+            identifier,
+            references,
+            ..
+        } = definition
+        {
+            *references = visitor.references.get(identifier).copied().unwrap_or(0);
+        }
+    }
+
+    if !footnotes.is_empty() {
+        blocks.push(BlockNode::Footnotes {
+            children: footnotes,
+        });
+    }
+}
+
+/// Remove the footnote definitions (including the nested ones) from the blocks, the first one is
+/// used for the duplicated identifiers.
+fn take_footnote_definitions(
+    blocks: &mut Vec<BlockNode>,
+    definitions: &mut HashMap<SharedString, BlockNode>,
+) {
+    let mut ix = 0;
+    while ix < blocks.len() {
+        match &mut blocks[ix] {
+            BlockNode::FootnoteDefinition { identifier, .. } => {
+                let identifier = identifier.clone();
+                let definition = blocks.remove(ix);
+                definitions.entry(identifier).or_insert(definition);
+                continue;
+            }
+            BlockNode::Root { children, .. }
+            | BlockNode::Blockquote { children, .. }
+            | BlockNode::Admonition { children, .. }
+            | BlockNode::List { children, .. }
+            | BlockNode::ListItem { children, .. } => {
+                take_footnote_definitions(children, definitions);
+            }
+            _ => {}
+        }
+        ix += 1;
+    }
+}
+
+/// Whether the `source` starts with a closed frontmatter, the `---` fences of the first line and a
+/// later line.
+pub(crate) fn has_frontmatter(source: &str) -> bool {
+    let mut lines = source.lines();
+    lines.next().is_some_and(|line| line.trim_end() == "---")
+        && lines.any(|line| line.trim_end() == "---")
+}
+
+/// Parse the flat `key: value` lines of the YAML frontmatter, the quotes of the values are
+/// removed, returns `None` for the other YAML (e.g.: lists, nested maps).
+fn parse_frontmatter(yaml: &str) -> Option<HashMap<String, String>> {
+    let mut frontmatter = HashMap::new();
+    for line in yaml.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            return None;
+        }
+
+        let (key, value) = line.split_once(':')?;
+        let key = key.trim();
+        if key.is_empty() || key.starts_with('-') {
+            return None;
+        }
+
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|quote| {
+                value
+                    .strip_prefix(*quote)
+                    .and_then(|value| value.strip_suffix(*quote))
+            })
+            .unwrap_or(value);
+        frontmatter.insert(key.to_string(), value.to_string());
+    }
+
+    Some(frontmatter)
 }
 
 fn new_span(pos: Option<markdown::unist::Position>, cx: &NodeContext) -> Option<Span> {
@@ -253,6 +519,25 @@ fn new_span(pos: Option<markdown::unist::Position>, cx: &NodeContext) -> Option<
     })
 }
 
+/// Parse the HTML in Markdown, the diagnostics of the HTML are located to the `span` of it.
+fn parse_html(
+    source: &str,
+    span: Option<Span>,
+    cx: &mut NodeContext,
+) -> Result<ParsedDocument, SharedString> {
+    let start = cx
+        .diagnostics
+        .as_ref()
+        .map_or(0, |diagnostics| diagnostics.lock().unwrap().len());
+    let result = super::html::parse(source, cx);
+    if let Some(diagnostics) = &cx.diagnostics {
+        for diagnostic in diagnostics.lock().unwrap()[start..].iter_mut() {
+            diagnostic.span = diagnostic.span.or(span);
+        }
+    }
+    result
+}
+
 fn ast_to_node(
     value: mdast::Node,
     cx: &mut NodeContext,
@@ -278,15 +563,26 @@ fn ast_to_node(
                 children,
                 span: new_span(val.position, cx),
             }
+            .into_admonition()
         }
         Node::List(list) => {
             let children = list
                 .children
                 .into_iter()
-                .map(|c| ast_to_node(c, cx, highlight_theme))
+                .map(|c| {
+                    let mut item = ast_to_node(c, cx, highlight_theme);
+                    // The items separated by blank lines make the whole list loose.
+                    if let BlockNode::ListItem { spread, .. } = &mut item {
+                        *spread |= list.spread;
+                    }
+                    item
+                })
                 .collect();
             BlockNode::List {
                 ordered: list.ordered,
+                start: list.start.unwrap_or(1) as usize,
+                marker: None,
+                reversed: false,
                 children,
                 span: new_span(list.position, cx),
             }
@@ -301,6 +597,7 @@ fn ast_to_node(
                 children,
                 spread: val.spread,
                 checked: val.checked,
+                value: None,
                 span: new_span(val.position, cx),
             }
         }
@@ -308,9 +605,18 @@ fn ast_to_node(
             html: false,
             span: new_span(val.position, cx),
         },
-        Node::Code(raw) => BlockNode::CodeBlock(CodeBlock::new(
+        // The fenced code block of the `math` language is the same as the `$$` display math.
+        Node::Code(raw) if raw.lang.as_deref() == Some("math") => BlockNode::Math {
+            formula: raw.value.into(),
+            span: new_span(raw.position, cx),
+        },
+        Node::Code(raw) => BlockNode::CodeBlock(CodeBlock::with_info(
             raw.value.into(),
-            raw.lang.map(|s| s.into()),
+            // The language is the first word of the info string, and the rest is the meta.
+            match (raw.lang, raw.meta) {
+                (Some(lang), Some(meta)) => Some(format!("{} {}", lang, meta).into()),
+                (lang, meta) => lang.or(meta).map(Into::into),
+            },
             highlight_theme,
             new_span(raw.position, cx),
         )),
@@ -323,16 +629,15 @@ fn ast_to_node(
             BlockNode::Heading {
                 level: val.depth,
                 children: paragraph,
+                id: None,
                 span: new_span(val.position, cx),
             }
         }
-        Node::Math(val) => BlockNode::CodeBlock(CodeBlock::new(
-            val.value.into(),
-            None,
-            highlight_theme,
-            new_span(val.position, cx),
-        )),
-        Node::Html(val) => match super::html::parse(&val.value, cx) {
+        Node::Math(val) => BlockNode::Math {
+            formula: val.value.into(),
+            span: new_span(val.position, cx),
+        },
+        Node::Html(val) => match parse_html(&val.value, new_span(val.position.clone(), cx), cx) {
             Ok(el) => BlockNode::Root {
                 children: el.blocks,
                 span: new_span(val.position, cx),
@@ -380,6 +685,7 @@ fn ast_to_node(
             BlockNode::Paragraph(paragraph)
         }
         Node::ThematicBreak(val) => BlockNode::HorizontalRule {
+            style: Default::default(),
             span: new_span(val.position, cx),
         },
         Node::Table(val) => {
@@ -399,32 +705,33 @@ fn ast_to_node(
 
             BlockNode::Table(table)
         }
-        Node::FootnoteDefinition(def) => {
-            let mut paragraph = Paragraph::default();
-            let prefix = format!("[{}]: ", def.identifier);
-            paragraph.push(InlineNode::new(&prefix).marks(vec![(
-                0..prefix.len(),
-                TextMark {
-                    italic: true,
-                    ..Default::default()
-                },
-            )]));
-
-            def.children.iter().for_each(|c| {
-                parse_paragraph(&mut paragraph, c, cx);
-            });
-            paragraph.span = new_span(def.position, cx);
-            BlockNode::Paragraph(paragraph)
-        }
+        Node::FootnoteDefinition(def) => BlockNode::FootnoteDefinition {
+            identifier: def.identifier.into(),
+            references: 0,
+            children: def
+                .children
+                .into_iter()
+                .map(|c| ast_to_node(c, cx, highlight_theme))
+                .collect(),
+            span: new_span(def.position, cx),
+        },
         Node::Definition(def) => {
-            cx.add_ref(
-                def.identifier.clone().into(),
-                LinkMark {
-                    url: def.url.clone().into(),
-                    identifier: Some(def.identifier.clone().into()),
-                    title: def.title.clone().map(Into::into),
-                },
-            );
+            if cx.sanitize && !is_safe_link(&def.url) {
+                cx.report(
+                    DiagnosticKind::DangerousHrefStripped,
+                    &def.url,
+                    new_span(def.position.clone(), cx),
+                );
+            } else {
+                cx.add_ref(
+                    def.identifier.clone().into(),
+                    LinkMark {
+                        url: def.url.clone().into(),
+                        identifier: Some(def.identifier.clone().into()),
+                        title: def.title.clone().map(Into::into),
+                    },
+                );
+            }
 
             BlockNode::Definition {
                 identifier: def.identifier.clone().into(),
@@ -441,3 +748,589 @@ fn ast_to_node(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use gpui::px;
+
+    use crate::{
+        highlighter::HighlightTheme,
+        text::{
+            TextViewStyle,
+            inline::Replaced,
+            node::{
+                AdmonitionKind, BlockNode, DiagnosticKind, FirstLine, ListMarker, NodeContext,
+                display_text, marker_gutter_width,
+            },
+        },
+    };
+
+    fn to_markdown(source: &str) -> String {
+        let mut cx = NodeContext::default();
+        super::parse(source, &mut cx, &HighlightTheme::default_light())
+            .unwrap()
+            .to_markdown()
+    }
+
+    #[test]
+    fn test_compose_marks() {
+        assert_eq!(
+            to_markdown("[**bold**](https://example.com)"),
+            "[**bold**](https://example.com)"
+        );
+        assert_eq!(
+            to_markdown("**[link](https://example.com)**"),
+            "**[link](https://example.com)**"
+        );
+    }
+
+    #[test]
+    fn test_loose_list() {
+        let source = indoc::indoc! {r#"
+            - One
+
+            - Two
+
+              Second paragraph
+
+            - Three
+
+            1. Tight
+            2. List
+               - Nested"#};
+
+        let mut cx = NodeContext::default();
+        let document = super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        assert!(document.blocks[0].is_loose_list());
+        assert!(!document.blocks[1].is_loose_list());
+        assert_eq!(document.to_markdown(), source);
+    }
+
+    #[test]
+    fn test_first_line() {
+        let mut cx = NodeContext {
+            style: TextViewStyle::default().first_line_indent(1.5),
+            ..Default::default()
+        };
+        let document = super::parse(
+            "Once **upon** a time",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+        let BlockNode::Paragraph(paragraph) = &document.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        let inline_node = &paragraph.children[0];
+
+        let (text, display_map) = display_text(inline_node, FirstLine::Normal, &cx);
+        assert_eq!(text, "Once ");
+        assert_eq!(display_map, Default::default());
+
+        // The indent is inserted before the text, the marks start after it.
+        let (text, display_map) = display_text(inline_node, FirstLine::Indent, &cx);
+        assert_eq!(text, "\u{2003}\u{2002}Once ");
+        assert_eq!(display_map.inserted, vec![0..6]);
+        assert_eq!(display_map.display_range(&(0..4)), 6..10);
+
+        // The first letter is removed for the drop cap.
+        let (text, display_map) = display_text(inline_node, FirstLine::DropCap, &cx);
+        assert_eq!(text, "nce ");
+        assert_eq!(display_map.removed.as_ref(), "O");
+        assert_eq!(display_map.display_range(&(1..4)), 0..3);
+    }
+
+    #[test]
+    fn test_list_item_markers() {
+        let source = (1..=12)
+            .map(|number| format!("{}. Item {}", number, number))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut cx = NodeContext::default();
+        let document = super::parse(&source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        let style = TextViewStyle::default();
+        let markers = document.blocks[0].list_item_markers(0, &style);
+        assert_eq!(markers[0], "1.");
+        assert_eq!(markers[9], "10.");
+        assert_eq!(markers.len(), 12);
+
+        // The gutter fits the widest marker, and the markers are right-aligned in it,
+        // so the text of all items starts at the same offset.
+        let measure = |marker: &str| px(marker.chars().count() as f32 * 8.);
+        let gutter = marker_gutter_width(&markers, measure);
+        assert_eq!(gutter, px(24.));
+        let offsets = markers
+            .iter()
+            .map(|marker| (gutter - measure(marker)).as_f32())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            offsets,
+            vec![8., 8., 8., 8., 8., 8., 8., 8., 8., 0., 0., 0.]
+        );
+
+        // The markers are cycled by the depth.
+        let ordered =
+            super::parse("1. One\n2. Two", &mut cx, &HighlightTheme::default_light()).unwrap();
+        let markers = (0..4)
+            .map(|depth| ordered.blocks[0].list_item_markers(depth, &style)[1].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(markers, vec!["2.", "b.", "ii.", "2."]);
+        let unordered = super::parse("- One", &mut cx, &HighlightTheme::default_light()).unwrap();
+        let markers = (0..4)
+            .map(|depth| unordered.blocks[0].list_item_markers(depth, &style)[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(markers, vec!["•", "◦", "▪", "•"]);
+
+        let style = TextViewStyle::default()
+            .list_bullets(["-", "*"])
+            .list_markers([ListMarker::UpperRoman]);
+        assert_eq!(
+            ordered.blocks[0].list_item_markers(1, &style),
+            vec!["I.", "II."]
+        );
+        assert_eq!(unordered.blocks[0].list_item_markers(1, &style), vec!["*"]);
+        // Fallback to decimal and no bullets without the markers.
+        let style = TextViewStyle::default()
+            .list_bullets(Vec::<&str>::new())
+            .list_markers([]);
+        assert_eq!(
+            ordered.blocks[0].list_item_markers(1, &style),
+            vec!["1.", "2."]
+        );
+        assert_eq!(unordered.blocks[0].list_item_markers(1, &style), vec![""]);
+    }
+
+    #[test]
+    fn test_nested_blockquote() {
+        let source = indoc::indoc! {r#"
+            > Level 1
+            >
+            >> Level 2
+            >>
+            >> - Item
+            >>
+            >> ```rust
+            >> fn main() {}
+            >> ```
+            >
+            > Back to level 1"#};
+
+        let mut cx = NodeContext::default();
+        let document = super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        let [BlockNode::Blockquote { children, .. }] = document.blocks.as_slice() else {
+            panic!("expected blockquote");
+        };
+        let BlockNode::Blockquote { children, .. } = &children[1] else {
+            panic!("expected nested blockquote");
+        };
+        assert!(matches!(children[1], BlockNode::List { .. }));
+        assert!(matches!(children[2], BlockNode::CodeBlock(_)));
+
+        assert_eq!(document.to_markdown(), source);
+        assert_eq!(to_markdown(&document.to_markdown()), source);
+    }
+
+    #[test]
+    fn test_admonition() {
+        let source = indoc::indoc! {r#"
+            > [!NOTE]
+            > Useful information.
+
+            > [!warning]
+            > Be **careful**.
+            >
+            > - Item
+
+            > [!FOO]
+            > Unknown marker.
+
+            > [!TIP] Not on its own line."#};
+
+        let mut cx = NodeContext::default();
+        let document = super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        let kinds = document
+            .blocks
+            .iter()
+            .map(|block| match block {
+                BlockNode::Admonition { kind, .. } => Some(*kind),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                Some(AdmonitionKind::Note),
+                Some(AdmonitionKind::Warning),
+                None,
+                None
+            ]
+        );
+
+        let BlockNode::Admonition { children, .. } = &document.blocks[1] else {
+            panic!("expected admonition");
+        };
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0].to_markdown(), "Be **careful**.");
+
+        assert_eq!(
+            document.to_markdown(),
+            indoc::indoc! {r#"
+                > [!NOTE]
+                > Useful information.
+
+                > [!WARNING]
+                > Be **careful**.
+                >
+                > - Item
+
+                > [!FOO]
+                > Unknown marker.
+
+                > [!TIP] Not on its own line."#}
+        );
+        assert_eq!(
+            document.blocks[0].to_html(),
+            indoc::indoc! {r#"
+                <div class="markdown-alert markdown-alert-note">
+                <p class="markdown-alert-title">Note</p>
+                <p>Useful information.</p>
+                </div>"#}
+        );
+    }
+
+    #[test]
+    fn test_code_info() {
+        let source = indoc::indoc! {r#"
+            ```rust,no_run title="example.rs" hl_lines="1 3-4" foo=bar
+            fn main() {}
+            ```
+
+            ```js
+            let a = 1;
+            ```"#};
+
+        let mut cx = NodeContext::default();
+        let document = super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        let [
+            BlockNode::CodeBlock(code_block),
+            BlockNode::CodeBlock(simple),
+        ] = document.blocks.as_slice()
+        else {
+            panic!("expected code blocks");
+        };
+
+        assert_eq!(code_block.lang(), Some("rust".into()));
+        assert_eq!(
+            code_block
+                .flags()
+                .iter()
+                .map(|flag| flag.as_ref())
+                .collect::<Vec<_>>(),
+            vec!["no_run"]
+        );
+        assert_eq!(code_block.title(), Some("example.rs".into()));
+        assert_eq!(code_block.highlight_lines(), vec![1..=1, 3..=4]);
+        assert_eq!(
+            code_block
+                .attributes()
+                .get("foo")
+                .map(|value| value.as_ref()),
+            Some("bar")
+        );
+
+        assert_eq!(simple.lang(), Some("js".into()));
+        assert!(simple.attributes().is_empty());
+        assert_eq!(simple.title(), None);
+
+        assert_eq!(document.to_markdown(), source);
+    }
+
+    #[test]
+    fn test_code_highlighted_runs() {
+        use crate::highlighter::SyntaxHighlighter;
+
+        let code = "fn main() {\n    let s = \"hello\"; // comment\n}\n".repeat(200);
+        let source = format!("```rust\n{}```", code);
+
+        let mut cx = NodeContext::default();
+        let document = super::parse(&source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        let BlockNode::CodeBlock(code_block) = &document.blocks[0] else {
+            panic!("expected code block");
+        };
+
+        let light = HighlightTheme::default_light();
+        let dark = HighlightTheme::default_dark();
+        let light_runs = code_block.highlighted_runs(&light);
+        let dark_runs = code_block.highlighted_runs(&dark);
+
+        // Same tokens, different colors.
+        assert_eq!(
+            light_runs
+                .iter()
+                .map(|(range, _)| range)
+                .collect::<Vec<_>>(),
+            dark_runs.iter().map(|(range, _)| range).collect::<Vec<_>>()
+        );
+        assert_ne!(light_runs, dark_runs);
+
+        // Same as highlighting from scratch.
+        let mut highlighter = SyntaxHighlighter::new("rust");
+        highlighter.update(None, &ropey::Rope::from_str(&code), None);
+        assert_eq!(light_runs, highlighter.styles(&(0..code.len()), &light));
+        assert_eq!(dark_runs, highlighter.styles(&(0..code.len()), &dark));
+    }
+
+    #[test]
+    fn test_setext_heading() {
+        let source = indoc::indoc! {r#"
+            Title
+            =====
+
+            Section
+            ---
+
+            Text
+
+            ---
+            Not a heading
+            ***"#};
+
+        let mut cx = NodeContext::default();
+        let document = super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        let levels = document
+            .blocks
+            .iter()
+            .map(|block| match block {
+                BlockNode::Heading { level, .. } => Some(*level),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // The `---` after a blank line is a thematic break, and `***` is never a heading.
+        assert_eq!(levels, vec![Some(1), Some(2), None, None, None, None]);
+        assert!(matches!(
+            document.blocks[3],
+            BlockNode::HorizontalRule { .. }
+        ));
+        assert!(matches!(
+            document.blocks[5],
+            BlockNode::HorizontalRule { .. }
+        ));
+        assert_eq!(
+            document.to_markdown(),
+            "# Title\n\n## Section\n\nText\n\n---\n\nNot a heading\n\n---"
+        );
+    }
+
+    #[test]
+    fn test_frontmatter() {
+        let source = indoc::indoc! {r#"
+            ---
+            title: "Hello: World"
+            date: 2024-01-01
+            ---
+
+            # Body"#};
+
+        let mut cx = NodeContext::default();
+        let document = super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        let frontmatter = document.frontmatter().unwrap();
+        assert_eq!(frontmatter["title"], "Hello: World");
+        assert_eq!(frontmatter["date"], "2024-01-01");
+        assert_eq!(document.to_markdown(), "# Body");
+
+        // The malformed frontmatter is kept in the body.
+        let source = "---\ntags:\n  - a\n---\n\nBody";
+        let document = super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        assert_eq!(document.frontmatter(), None);
+        assert!(matches!(document.blocks[0], BlockNode::CodeBlock(_)));
+
+        // Only at the start of the document.
+        let document = super::parse(
+            "Intro\n\n---\ntitle: Hello\n---",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+        assert_eq!(document.frontmatter(), None);
+        assert!(matches!(
+            document.blocks[1],
+            BlockNode::HorizontalRule { .. }
+        ));
+
+        assert!(super::has_frontmatter("---\ntitle: Hello\n---\n\nBody"));
+        assert!(!super::has_frontmatter("---\ntitle: Hello\n"));
+        assert!(!super::has_frontmatter("Intro\n\n---\ntitle: Hello\n---"));
+    }
+
+    #[test]
+    fn test_math() {
+        let source = indoc::indoc! {r#"
+            Energy $E = mc^2$, price \$5, code `$x$`, costs $5 and $10, or $ 1 $.
+
+            $$
+            \sum_{i=1}^n i
+            $$
+
+            ```math
+            a^2 + b^2 = c^2
+            ```"#};
+
+        let mut cx = NodeContext::default();
+        let document = super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        let BlockNode::Paragraph(paragraph) = &document.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        let math = paragraph
+            .children
+            .iter()
+            .filter(|node| node.marks.iter().any(|(_, mark)| mark.math))
+            .map(|node| node.text.to_string())
+            .collect::<Vec<_>>();
+        // The escaped `\$`, the math in the code span and the currency are not math.
+        assert_eq!(math, vec!["E = mc^2"]);
+        assert_eq!(
+            paragraph.to_plain_text(),
+            "Energy E = mc^2, price $5, code $x$, costs $5 and $10, or $ 1 $."
+        );
+
+        let formulas = document.blocks[1..]
+            .iter()
+            .map(|block| match block {
+                BlockNode::Math { formula, .. } => formula.to_string(),
+                _ => panic!("expected math"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(formulas, vec!["\\sum_{i=1}^n i", "a^2 + b^2 = c^2"]);
+
+        assert_eq!(
+            document.to_markdown(),
+            "Energy $E = mc^2$, price \\$5, code `$x$`, costs \\$5 and \\$10, or \\$ 1 \\$.\n\n$$\n\\sum_{i=1}^n i\n$$\n\n$$\na^2 + b^2 = c^2\n$$"
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let source = indoc::indoc! {r#"
+            Second[^b], first[^a] and again[^b], missing[^c].
+
+            [^a]: The first note.
+            [^b]: The second note.
+            [^unused]: Not referenced."#};
+
+        let mut cx = NodeContext::default();
+        let document = super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        assert_eq!(document.blocks.len(), 2);
+        // Numbered in the order of the first reference.
+        assert_eq!(
+            document.blocks[0].to_plain_text(),
+            "Second1, first2 and again1, missing[^c]."
+        );
+
+        let BlockNode::Footnotes { children } = &document.blocks[1] else {
+            panic!("expected footnotes");
+        };
+        let definitions = children
+            .iter()
+            .map(|child| match child {
+                BlockNode::FootnoteDefinition {
+                    identifier,
+                    references,
+                    ..
+                } => (identifier.to_string(), *references),
+                _ => panic!("expected footnote definition"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            definitions,
+            vec![("b".to_string(), 2), ("a".to_string(), 1)]
+        );
+
+        // The number of the reference is displayed as the superscript, copied as the number.
+        let BlockNode::Paragraph(paragraph) = &document.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        let (text, display_map) = display_text(&paragraph.children[1], FirstLine::Normal, &cx);
+        assert_eq!(text, "\u{b9}");
+        assert_eq!(display_map.replaced, vec![Replaced::new(0..2, "1")]);
+        assert_eq!(display_map.display_range(&(0..1)), 0..2);
+
+        assert_eq!(document.anchor_block_index("fn-a"), Some(1));
+        assert_eq!(document.anchor_block_index("fnref-b-2"), Some(0));
+        assert_eq!(document.anchor_block_index("fnref-b-3"), None);
+
+        assert_eq!(
+            document.to_markdown(),
+            indoc::indoc! {r#"
+                Second[^b], first[^a] and again[^b], missing[^c].
+
+                [^b]: The second note.
+
+                [^a]: The first note."#}
+        );
+    }
+
+    #[test]
+    fn test_hard_break() {
+        assert_eq!(to_markdown("Hello  \nWorld"), "Hello  \nWorld");
+        assert_eq!(to_markdown("Hello<br>World"), "Hello  \nWorld");
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let mut cx = NodeContext {
+            sanitize: true,
+            ..Default::default()
+        };
+        let source = "[click](javascript:alert(1)) [ok](https://example.com) ![x](data:image/png;base64,AAAA)";
+        let markdown = super::parse(source, &mut cx, &HighlightTheme::default_light())
+            .unwrap()
+            .to_markdown();
+        assert_eq!(markdown, "click [ok](https://example.com) x");
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let source = "[click](javascript:alert(1))\n\n<div><blink>x</blink></div>";
+
+        // No diagnostics by default.
+        let mut cx = NodeContext {
+            sanitize: true,
+            ..Default::default()
+        };
+        super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        assert!(cx.diagnostics().is_empty());
+
+        let mut cx = NodeContext {
+            sanitize: true,
+            diagnostics: Some(Default::default()),
+            ..Default::default()
+        };
+        super::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+        let diagnostics = cx
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.kind,
+                    diagnostic.message.to_string(),
+                    diagnostic.span.map(|span| span.start..span.end),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    DiagnosticKind::DangerousHrefStripped,
+                    "javascript:alert(1)".to_string(),
+                    Some(0..28)
+                ),
+                (
+                    DiagnosticKind::UnsupportedTag,
+                    "blink".to_string(),
+                    Some(30..57)
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Resolve the `@mentions` in the plain text, see [`TextViewState::set_mention_resolver`].
+//!
+//! [`TextViewState::set_mention_resolver`]: crate::text::TextViewState::set_mention_resolver
+
+use std::ops::Range;
+
+use crate::text::{
+    document::ParsedDocument,
+    node::{InlineNode, LinkMark, MentionResolveFn, TextMark},
+    visitor::VisitorMut,
+};
+
+/// Replace the resolved mentions with the display name and add the mention mark, the unresolved
+/// mentions are kept as the plain text.
+pub(super) fn resolve_mentions(document: &mut ParsedDocument, resolver: &MentionResolveFn) {
+    document.walk_mut(&mut ResolveMentions { resolver });
+}
+
+struct ResolveMentions<'a> {
+    resolver: &'a MentionResolveFn,
+}
+
+impl VisitorMut for ResolveMentions<'_> {
+    fn visit_inline_mut(&mut self, inline: &mut InlineNode) {
+        if inline.image.is_some() {
+            return;
+        }
+
+        let mut text = inline.text.to_string();
+        // From the end, so the ranges of the previous mentions are not shifted.
+        for range in find_mentions(&text).into_iter().rev() {
+            // The mentions inside the code spans, the math, the links or the resolved mentions
+            // (e.g.: the inline HTML in Markdown) are skipped.
+            let overlapped = inline.marks.iter().any(|(mark_range, mark)| {
+                (mark.code || mark.math || mark.link.is_some() || mark.mention.is_some())
+                    && mark_range.start < range.end
+                    && range.start < mark_range.end
+            });
+            if overlapped {
+                continue;
+            }
+
+            let handle = &text[range.start + 1..range.end];
+            let Some(mention) = (self.resolver)(handle) else {
+                continue;
+            };
+
+            let mark = TextMark {
+                link: mention.url.map(|url| LinkMark {
+                    url,
+                    ..Default::default()
+                }),
+                mention: Some(handle.to_string().into()),
+                ..Default::default()
+            };
+            let name = format!("@{}", mention.name);
+            text.replace_range(range.clone(), &name);
+
+            // Shift the marks after the mention, the marks around it are extended.
+            let shift = |offset: usize| {
+                if offset >= range.end {
+                    offset + name.len() - range.len()
+                } else {
+                    offset.min(range.start + name.len())
+                }
+            };
+            for (mark_range, _) in inline.marks.iter_mut() {
+                *mark_range = shift(mark_range.start)..shift(mark_range.end);
+            }
+            inline
+                .marks
+                .push((range.start..range.start + name.len(), mark));
+        }
+
+        if text != inline.text.as_ref() {
+            inline.text = text.into();
+        }
+    }
+}
+
+/// Find the `@handle` in the text, return the byte ranges including the `@`.
+///
+/// The handle contains the alphanumeric, `_`, `-` and `.` (not at the end), and the `@` must not
+/// follow a word, e.g.: the email `a@b.com` is not a mention.
+fn find_mentions(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut prev_char: Option<char> = None;
+
+    for (ix, c) in text.char_indices() {
+        let is_boundary = prev_char.is_none_or(|c| !c.is_alphanumeric() && !"_-.@/".contains(c));
+        prev_char = Some(c);
+        if c != '@' || !is_boundary {
+            continue;
+        }
+
+        let start = ix + 1;
+        let len = text[start..]
+            .find(|c: char| !c.is_alphanumeric() && !"_-.".contains(c))
+            .unwrap_or(text.len() - start);
+        let handle = text[start..start + len].trim_end_matches(['.', '-']);
+        if !handle.is_empty() {
+            ranges.push(ix..start + handle.len());
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        highlighter::HighlightTheme,
+        text::node::{BlockNode, Mention, NodeContext},
+    };
+
+    use super::find_mentions;
+
+    #[test]
+    fn test_find_mentions() {
+        let text = "@jason, cc @huacnlee. mail a@b.com @ @-";
+        let handles = find_mentions(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect::<Vec<_>>();
+        assert_eq!(handles, vec!["@jason", "@huacnlee"]);
+    }
+
+    #[test]
+    fn test_resolve_mentions() {
+        let mut cx = NodeContext {
+            mention_resolver: Some(std::sync::Arc::new(|handle: &str| {
+                (handle == "jason").then(|| Mention {
+                    name: "Jason Lee".into(),
+                    url: Some("https://github.com/jason".into()),
+                })
+            })),
+            ..Default::default()
+        };
+        let document = crate::text::format::markdown::parse(
+            "Hi **@jason** and @unknown, see `@jason`.",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+
+        let BlockNode::Paragraph(paragraph) = &document.blocks[0] else {
+            panic!("expected paragraph");
+        };
+        assert_eq!(
+            paragraph.to_plain_text(),
+            "Hi @Jason Lee and @unknown, see @jason."
+        );
+
+        let (range, mark) = paragraph
+            .children
+            .iter()
+            .flat_map(|node| node.marks.iter())
+            .find(|(_, mark)| mark.mention.is_some())
+            .unwrap();
+        assert_eq!(range.len(), "@Jason Lee".len());
+        assert_eq!(
+            mark.link.as_ref().map(|link| link.url.to_string()),
+            Some("https://github.com/jason".to_string())
+        );
+
+        // The handle is kept in the Markdown.
+        assert_eq!(
+            document.to_markdown(),
+            "Hi **@jason** and @unknown, see `@jason`."
+        );
+    }
+}
@@ -1,3 +1,7 @@
+mod emoji;
 pub(super) mod html;
 mod html5minify;
+mod linkify;
+mod mention;
 pub(super) mod markdown;
+mod sanitize;
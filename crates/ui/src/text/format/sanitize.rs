@@ -0,0 +1,219 @@
+//! Sanitize the untrusted HTML before building nodes.
+//!
+//! The allowlist is similar to the default profile of the common HTML sanitizers (e.g. `ammonia`).
+
+use std::rc::Rc;
+
+use markup5ever_rcdom::{Node, NodeData};
+
+use crate::text::node::{DiagnosticKind, NodeContext};
+
+/// The tags are allowed in sanitize mode, others will be unwrapped (keep the children).
+const ALLOWED_TAGS: [&str; 71] = [
+    "html",
+    "head",
+    "body",
+    "a",
+    "abbr",
+    "address",
+    "article",
+    "aside",
+    "b",
+    "bdi",
+    "bdo",
+    "big",
+    "blockquote",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "picture",
+    "pre",
+    "q",
+    "s",
+    "samp",
+    "section",
+    "small",
+    "source",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "u",
+    "ul",
+    "var",
+    "main",
+    "nav",
+];
+
+/// The tags will be removed with their content.
+const CLEAN_CONTENT_TAGS: [&str; 8] = [
+    "script", "style", "iframe", "object", "embed", "template", "noscript", "title",
+];
+
+/// Return true if the tag is in the allowlist of the sanitize mode.
+pub(super) fn is_allowed_tag(tag: &str) -> bool {
+    ALLOWED_TAGS.contains(&tag)
+}
+
+/// The allowed URL schemes for links.
+const LINK_SCHEMES: [&str; 4] = ["http", "https", "mailto", "tel"];
+
+/// The allowed URL schemes for images.
+const IMAGE_SCHEMES: [&str; 2] = ["http", "https"];
+
+/// Return true if the link url is allowed, relative urls are always allowed.
+pub(super) fn is_safe_link(url: &str) -> bool {
+    is_safe_url(url, &LINK_SCHEMES)
+}
+
+/// Return true if the image url is allowed, relative urls are always allowed.
+pub(super) fn is_safe_image(url: &str) -> bool {
+    is_safe_url(url, &IMAGE_SCHEMES)
+}
+
+fn is_safe_url(url: &str, schemes: &[&str]) -> bool {
+    // Browsers ignore the whitespace and control characters in the scheme, e.g.: `java\tscript:`
+    let url = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    let Some(colon) = url.find(':') else {
+        return true;
+    };
+
+    // The `:` after a path, query or fragment is not a scheme separator.
+    if url[..colon].contains(['/', '?', '#']) {
+        return true;
+    }
+
+    schemes.contains(&&url[..colon])
+}
+
+/// Sanitize the DOM node in place.
+///
+/// - Remove the [`CLEAN_CONTENT_TAGS`] with their content.
+/// - Unwrap the tags not in the allowlist.
+/// - Remove the event-handler attributes (e.g. `onclick`).
+/// - Remove the `href`, `cite`, `src` and `srcset` attributes with the disallowed schemes
+///   (e.g. `javascript:`), so the links become plain text, and report them to the diagnostics.
+pub(super) fn sanitize_node(node: &Rc<Node>, cx: &NodeContext) {
+    let children = std::mem::take(&mut *node.children.borrow_mut());
+    let mut new_children = Vec::with_capacity(children.len());
+
+    for child in children {
+        let NodeData::Element { name, attrs, .. } = &child.data else {
+            if matches!(child.data, NodeData::Text { .. }) {
+                new_children.push(child);
+            }
+            continue;
+        };
+
+        let tag = &*name.local;
+        if CLEAN_CONTENT_TAGS.contains(&tag) {
+            continue;
+        }
+        let allowed = ALLOWED_TAGS.contains(&tag);
+
+        attrs.borrow_mut().retain(|attr| {
+            let name = &*attr.name.local;
+            if name.starts_with("on") {
+                return false;
+            }
+
+            let safe = match name {
+                "href" | "cite" => is_safe_link(&attr.value),
+                "src" => is_safe_image(&attr.value),
+                // The `srcset` of `<source>`, e.g.: `a.png 1x, a@2x.png 2x`.
+                "srcset" => attr.value.split(',').all(|candidate| {
+                    candidate
+                        .split_whitespace()
+                        .next()
+                        .is_none_or(is_safe_image)
+                }),
+                _ => true,
+            };
+            if !safe {
+                cx.report(DiagnosticKind::DangerousHrefStripped, &attr.value, None);
+            }
+            safe
+        });
+
+        sanitize_node(&child, cx);
+
+        if allowed {
+            new_children.push(child);
+        } else {
+            for grand_child in child.children.borrow_mut().drain(..) {
+                grand_child.parent.set(Some(Rc::downgrade(node)));
+                new_children.push(grand_child);
+            }
+        }
+    }
+
+    *node.children.borrow_mut() = new_children;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_safe_image, is_safe_link};
+
+    #[test]
+    fn test_is_safe_url() {
+        assert!(is_safe_link("https://example.com"));
+        assert!(is_safe_link("HTTP://example.com"));
+        assert!(is_safe_link("mailto:foo@example.com"));
+        assert!(is_safe_link("/path/to:file"));
+        assert!(is_safe_link("#section"));
+        assert!(is_safe_link("page?a=b:c"));
+        assert!(!is_safe_link("javascript:alert(1)"));
+        assert!(!is_safe_link(" JavaScript:alert(1)"));
+        assert!(!is_safe_link("java\tscript:alert(1)"));
+        assert!(!is_safe_link("data:text/html;base64,PHNjcmlwdD4="));
+        assert!(!is_safe_link("vbscript:msgbox"));
+
+        assert!(is_safe_image("https://example.com/a.png"));
+        assert!(is_safe_image("images/a.png"));
+        assert!(!is_safe_image("data:image/png;base64,AAAA"));
+        assert!(!is_safe_image("javascript:alert(1)"));
+    }
+}
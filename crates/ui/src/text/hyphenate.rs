@@ -0,0 +1,101 @@
+//! Hyphenate the long words of the paragraphs on render, see [`TextViewStyle::hyphenation`].
+//!
+//! The soft hyphens are only inserted into the text to render, so the document, the search and
+//! the copied text are not changed, the offsets are mapped by the [`DisplayMap`].
+//!
+//! [`TextViewStyle::hyphenation`]: crate::text::TextViewStyle::hyphenation
+
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use hyphenation::{Hyphenator as _, Language, Load as _, Standard};
+use unicode_segmentation::UnicodeSegmentation as _;
+
+use super::inline::{DisplayMap, SOFT_HYPHEN};
+
+/// The min chars of the words to hyphenate.
+const MIN_WORD_CHARS: usize = 6;
+
+/// The loaded dictionaries by language, `None` if failed to load.
+static DICTIONARIES: LazyLock<Mutex<HashMap<Language, Option<Arc<Standard>>>>> =
+    LazyLock::new(Default::default);
+
+/// Get the embedded dictionary of the language code (e.g.: `en-us`), loaded once and shared by
+/// all the text views, `None` if the dictionary of the language is not embedded.
+pub(super) fn dictionary(lang: &str) -> Option<Arc<Standard>> {
+    let language = Language::try_from_code(&lang.to_ascii_lowercase())?;
+    DICTIONARIES
+        .lock()
+        .unwrap()
+        .entry(language)
+        .or_insert_with(|| Standard::from_embedded(language).ok().map(Arc::new))
+        .clone()
+}
+
+/// Insert the soft hyphens into the long words of the `text`, except the words in the `skip`
+/// ranges (e.g.: the inline code).
+///
+/// Returns the hyphenated text and the map of the soft hyphens inserted.
+pub(super) fn hyphenate(
+    text: &str,
+    skip: &[Range<usize>],
+    dictionary: &Standard,
+) -> (String, DisplayMap) {
+    let mut hyphenated = String::with_capacity(text.len());
+    let mut display_map = DisplayMap::default();
+    for (start, word) in text.split_word_bound_indices() {
+        let end = start + word.len();
+        let hyphenate = word.chars().count() >= MIN_WORD_CHARS
+            && word.chars().all(char::is_alphabetic)
+            && !skip
+                .iter()
+                .any(|range| range.start < end && start < range.end);
+        if !hyphenate {
+            hyphenated.push_str(word);
+            continue;
+        }
+
+        let mut last = 0;
+        for at in dictionary.hyphenate(word).breaks {
+            hyphenated.push_str(&word[last..at]);
+            let start = hyphenated.len();
+            hyphenated.push_str(SOFT_HYPHEN);
+            display_map.inserted.push(start..hyphenated.len());
+            last = at;
+        }
+        hyphenated.push_str(&word[last..]);
+    }
+
+    (hyphenated, display_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dictionary, hyphenate};
+
+    #[test]
+    fn test_hyphenate() {
+        assert!(dictionary("xx-unknown").is_none());
+        let dictionary = dictionary("en-US").unwrap();
+
+        let text = "The hyphenation of `hyphenation`, a word.";
+        let (hyphenated, display_map) = hyphenate(text, &[20..31], &dictionary);
+        assert!(!display_map.inserted.is_empty());
+        assert_eq!(hyphenated.replace('\u{ad}', ""), text);
+        for range in display_map.inserted.iter() {
+            assert_eq!(&hyphenated[range.clone()], "\u{ad}");
+            assert!(range.start > 4 && range.end < 22);
+        }
+
+        // The offsets of the text are mapped to the hyphenated text.
+        let source = text.find("a word").unwrap();
+        assert_eq!(
+            &hyphenated[display_map.display_offset(source, true)..],
+            "a word."
+        );
+        assert_eq!(display_map.display_offset(4, true), 4);
+    }
+}
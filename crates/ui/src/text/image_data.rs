@@ -0,0 +1,329 @@
+//! Load the raw bytes of the images (e.g.: to rasterize the SVG or to save the image), and copy
+//! the images to the clipboard.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context as _, anyhow};
+use futures::AsyncReadExt as _;
+use gpui::{App, ClipboardItem, Image, ImageFormat, RenderImage, SharedUri};
+
+/// The decoded image of the last render, shared by the clones of the image node, e.g.: to copy
+/// the image without loading it again.
+#[derive(Default, Clone)]
+pub(crate) struct LoadedImage(Arc<Mutex<Option<Arc<RenderImage>>>>);
+
+impl LoadedImage {
+    pub(super) fn set(&self, image: Arc<RenderImage>) {
+        *self.0.lock().unwrap() = Some(image);
+    }
+
+    pub(super) fn get(&self) -> Option<Arc<RenderImage>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Release the decoded image, e.g.: the inline emoji scrolled out of the view.
+    pub(super) fn clear(&self) {
+        self.0.lock().unwrap().take();
+    }
+}
+
+impl fmt::Debug for LoadedImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadedImage")
+            .field("loaded", &self.get().is_some())
+            .finish()
+    }
+}
+
+/// Load the raw bytes of the image url, the `data:` uri, the http(s) url or the file path.
+pub(super) fn load_bytes(
+    url: SharedUri,
+    cx: &App,
+) -> impl Future<Output = anyhow::Result<Vec<u8>>> + Send + 'static {
+    let client = cx.http_client();
+    async move {
+        let url: &str = &url;
+        if url.starts_with("data:") {
+            return decode_data_uri(url).ok_or_else(|| anyhow!("invalid data uri"));
+        }
+
+        if url.starts_with("http://") || url.starts_with("https://") {
+            let mut response = client.get(url, ().into(), true).await?;
+            if !response.status().is_success() {
+                return Err(anyhow!("failed to load {}: {}", url, response.status()));
+            }
+            let mut body = Vec::new();
+            response.body_mut().read_to_end(&mut body).await?;
+            return Ok(body);
+        }
+
+        std::fs::read(url).with_context(|| format!("failed to read {}", url))
+    }
+}
+
+/// Copy the image to the clipboard as an image.
+///
+/// The `data:` uri is decoded to copy the raw image, the other images are copied from the
+/// decoded `image` of the last render, the url is copied instead if the image is not loaded.
+pub(super) fn copy_image(url: &SharedUri, image: Option<&RenderImage>, cx: &mut App) {
+    let raw = url
+        .starts_with("data:")
+        .then(|| decode_data_uri(url))
+        .flatten()
+        .and_then(|bytes| Some(Image::from_bytes(image_format(&bytes)?, bytes)));
+    let image = raw.or_else(|| {
+        let image = image?;
+        let size = image.size(0);
+        let bytes = encode_bmp(
+            u32::try_from(size.width.0).ok()?,
+            u32::try_from(size.height.0).ok()?,
+            image.as_bytes(0)?,
+        )?;
+        Some(Image::from_bytes(ImageFormat::Bmp, bytes))
+    });
+    cx.write_to_clipboard(match image {
+        Some(image) => ClipboardItem::new_image(&image),
+        None => ClipboardItem::new_string(url.to_string()),
+    });
+}
+
+/// The size of the `BITMAPFILEHEADER` and the `BITMAPV4HEADER` of the BMP.
+const BMP_HEADER_SIZE: u32 = 14 + 108;
+
+/// Encode the decoded `pixels` (BGRA, top-down rows) as the 32-bit BMP, the alpha is kept by the
+/// bit masks of the `BITMAPV4HEADER`.
+///
+/// Returns None if the length of the `pixels` does not match the size.
+fn encode_bmp(width: u32, height: u32, pixels: &[u8]) -> Option<Vec<u8>> {
+    if width == 0 || height == 0 || pixels.len() as u64 != width as u64 * height as u64 * 4 {
+        return None;
+    }
+
+    let image_size = u32::try_from(pixels.len()).ok()?;
+    let mut bmp = Vec::with_capacity((BMP_HEADER_SIZE + image_size) as usize);
+    // BITMAPFILEHEADER
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&(BMP_HEADER_SIZE + image_size).to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+    bmp.extend_from_slice(&BMP_HEADER_SIZE.to_le_bytes());
+    // BITMAPV4HEADER, the negative height for the top-down rows.
+    bmp.extend_from_slice(&108u32.to_le_bytes());
+    bmp.extend_from_slice(&i32::try_from(width).ok()?.to_le_bytes());
+    bmp.extend_from_slice(&(-i32::try_from(height).ok()?).to_le_bytes());
+    bmp.extend_from_slice(&1u16.to_le_bytes());
+    bmp.extend_from_slice(&32u16.to_le_bytes());
+    // BI_BITFIELDS, the image size, and the 72 DPI resolution.
+    for value in [3, image_size, 2835, 2835, 0, 0] {
+        bmp.extend_from_slice(&value.to_le_bytes());
+    }
+    // The red, green, blue and alpha masks of the BGRA, then the sRGB color space.
+    for value in [
+        0x00ff0000u32,
+        0x0000ff00,
+        0x000000ff,
+        0xff000000,
+        0x73524742,
+    ] {
+        bmp.extend_from_slice(&value.to_le_bytes());
+    }
+    // The unused endpoints and gamma of the color space.
+    bmp.extend_from_slice(&[0; 48]);
+    bmp.extend_from_slice(pixels);
+    Some(bmp)
+}
+
+/// Save the image to the file chosen by the user, the file name is suggested by the url.
+///
+/// The image is loaded again after the file is chosen, the failure is logged.
+pub(super) fn save_image(url: &SharedUri, cx: &mut App) {
+    let directory = std::env::home_dir().unwrap_or_default();
+    let path = cx.prompt_for_new_path(&directory, Some(&file_name(url)));
+    let bytes = load_bytes(url.clone(), cx);
+    cx.background_spawn(async move {
+        let Some(path) = path.await.ok().into_iter().flatten().flatten().next() else {
+            return;
+        };
+        if let Err(err) = bytes
+            .await
+            .and_then(|bytes| std::fs::write(&path, bytes).map_err(Into::into))
+        {
+            tracing::warn!("failed to save image to {:?}: {}", path, err);
+        }
+    })
+    .detach();
+}
+
+/// The file name to save the image, the last segment of the url path, e.g.: `photo.png`, or
+/// `image` if the url has no file name, e.g.: the `data:` uri.
+fn file_name(url: &str) -> String {
+    if url.starts_with("data:") {
+        return "image".to_string();
+    }
+
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let path = match path.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map_or("", |(_, path)| path),
+        None => path,
+    };
+    path.rsplit(['/', '\\'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("image")
+        .to_string()
+}
+
+/// Detect the image format by the magic bytes.
+fn image_format(bytes: &[u8]) -> Option<ImageFormat> {
+    let format = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        ImageFormat::Png
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        ImageFormat::Jpeg
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        ImageFormat::Gif
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(&b"WEBP"[..]) {
+        ImageFormat::Webp
+    } else if bytes.starts_with(b"BM") {
+        ImageFormat::Bmp
+    } else if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        ImageFormat::Tiff
+    } else if String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).contains("<svg") {
+        ImageFormat::Svg
+    } else {
+        return None;
+    };
+    Some(format)
+}
+
+/// Decode the data of the `data:` uri, e.g.: `data:image/svg+xml;base64,PHN2Zz4=` or
+/// `data:image/svg+xml,%3Csvg%3E`.
+fn decode_data_uri(url: &str) -> Option<Vec<u8>> {
+    let (meta, data) = url.strip_prefix("data:")?.split_once(',')?;
+    let data = percent_decode(data);
+    if meta
+        .split(';')
+        .any(|param| param.trim().eq_ignore_ascii_case("base64"))
+    {
+        base64_decode(&data)
+    } else {
+        Some(data)
+    }
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut ix = 0;
+    while ix < bytes.len() {
+        let hex = bytes
+            .get(ix + 1..ix + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[ix], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                ix += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                ix += 1;
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for &byte in data {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ if byte.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+        buf = (buf << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buf >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use gpui::ImageFormat;
+
+    use super::{BMP_HEADER_SIZE, decode_data_uri, encode_bmp, file_name, image_format};
+
+    #[test]
+    fn test_decode_data_uri() {
+        assert_eq!(
+            decode_data_uri("data:image/svg+xml;base64,PHN2Zz48L3N2Zz4="),
+            Some(b"<svg></svg>".to_vec())
+        );
+        assert_eq!(
+            decode_data_uri("data:image/svg+xml;charset=utf-8,%3Csvg%3E%3C/svg%3E"),
+            Some(b"<svg></svg>".to_vec())
+        );
+        assert_eq!(decode_data_uri("data:image/svg+xml;base64,!!"), None);
+        assert_eq!(decode_data_uri("data:image/svg+xml"), None);
+    }
+
+    #[test]
+    fn test_image_format() {
+        let png = decode_data_uri("data:image/png;base64,iVBORw0KGgoAAAANSUhEUg==").unwrap();
+        assert_eq!(image_format(&png), Some(ImageFormat::Png));
+        assert_eq!(image_format(b"GIF89a..."), Some(ImageFormat::Gif));
+        assert_eq!(
+            image_format(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(ImageFormat::Webp)
+        );
+        assert_eq!(
+            image_format(b"<?xml version=\"1.0\"?><svg></svg>"),
+            Some(ImageFormat::Svg)
+        );
+        assert_eq!(image_format(b"hello"), None);
+    }
+
+    #[test]
+    fn test_encode_bmp() {
+        // The 2x1 pixels in BGRA: a red and a half transparent blue.
+        let pixels = [0, 0, 255, 255, 255, 0, 0, 128];
+        let bmp = encode_bmp(2, 1, &pixels).unwrap();
+        assert_eq!(image_format(&bmp), Some(ImageFormat::Bmp));
+        assert_eq!(bmp.len(), BMP_HEADER_SIZE as usize + pixels.len());
+        assert_eq!(&bmp[2..6], &(bmp.len() as u32).to_le_bytes());
+        assert_eq!(&bmp[10..14], &BMP_HEADER_SIZE.to_le_bytes());
+        // The width, and the negative height of the top-down rows.
+        assert_eq!(&bmp[18..22], &2i32.to_le_bytes());
+        assert_eq!(&bmp[22..26], &(-1i32).to_le_bytes());
+        assert_eq!(&bmp[BMP_HEADER_SIZE as usize..], &pixels);
+
+        assert_eq!(encode_bmp(2, 2, &pixels), None);
+        assert_eq!(encode_bmp(0, 0, &[]), None);
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(
+            file_name("https://a.com/images/photo.png?w=100#top"),
+            "photo.png"
+        );
+        assert_eq!(file_name("https://a.com"), "image");
+        assert_eq!(file_name("https://a.com/images/"), "image");
+        assert_eq!(file_name("data:image/png;base64,iVBORw0KGgo="), "image");
+        assert_eq!(file_name("/tmp/screenshot.jpg"), "screenshot.jpg");
+    }
+}
@@ -1,28 +1,67 @@
 use gpui::Corners;
 use std::{
+    cmp::Reverse,
+    collections::HashMap,
     ops::Range,
     rc::Rc,
     sync::{Arc, Mutex},
 };
 
 use gpui::{
-    App, BorderStyle, Bounds, CursorStyle, Edges, Element, ElementId, GlobalElementId, Half,
-    HighlightStyle, Hitbox, HitboxBehavior, InspectorElementId, IntoElement, LayoutId,
-    MouseMoveEvent, MouseUpEvent, Pixels, Point, SharedString, StyledText, TextLayout, Window,
-    point, px, quad,
+    AnyView, App, BorderStyle, Bounds, CursorStyle, Edges, Element, ElementId, GlobalElementId,
+    Half, HighlightStyle, Hitbox, HitboxBehavior, Hsla, ImgResourceLoader, InspectorElementId,
+    IntoElement, LayoutId, MouseButton, MouseDownEvent, MouseMoveEvent, MouseUpEvent,
+    ParentElement, Pixels, Point, RenderImage, Resource, ScrollWheelEvent, SharedString, SharedUri,
+    Styled, StyledText, TextLayout, TextRun, TextStyle, Window, div, point, prelude::FluentBuilder,
+    px, quad, size,
 };
 
-use crate::{ActiveTheme, global_state::GlobalState, input::Selection, text::node::LinkMark};
+use crate::{
+    ActiveTheme, Colorize as _,
+    global_state::GlobalState,
+    input::Selection,
+    root::Root,
+    text::{
+        AnchorClickFn, ContextMenuTarget, ContextMenuTargetFn, LinkClickEvent, LinkClickFn,
+        node::{ImageNode, LAZY_IMAGE_MARGIN, LinkMark},
+        svg_image,
+    },
+    tooltip::{Tooltip, TooltipContent, TooltipOverlay},
+    v_flex,
+};
 
 /// A inline element used to render a inline text and support selectable.
 ///
 /// All text in TextView (including the CodeBlock) used this for text rendering.
+///
+/// NOTE: The links and the inline images are not exposed to the accessibility tree, gpui has no
+/// accessibility API for the elements to publish the roles and labels yet.
 pub(super) struct Inline {
     id: ElementId,
     text: SharedString,
     links: Rc<Vec<(Range<usize>, LinkMark)>>,
     highlights: Vec<(Range<usize>, HighlightStyle)>,
+    code_font: Option<CodeFont>,
+    /// The abbreviations with their titles, e.g.: `<abbr title="...">`.
+    abbrs: Rc<Vec<(Range<usize>, SharedString)>>,
+    /// The ranges of the spoilers, hidden until clicked.
+    spoilers: Rc<Vec<Range<usize>>>,
+    /// Whether to include the hidden spoilers in the selected text.
+    copy_hidden_spoilers: bool,
+    /// The emoji ranges with their `:shortcode:`, copied as a whole in the selected text.
+    emoji_shortcodes: Rc<Vec<(Range<usize>, SharedString)>>,
+    /// Whether to copy the emoji as the shortcodes instead of the Unicode emoji.
+    copy_emoji_shortcodes: bool,
+    /// The inline emoji images painted over their placeholders in the text.
+    images: Rc<Vec<(Range<usize>, ImageNode)>>,
+    /// The text inserted or removed on render, e.g.: the soft hyphens of the hyphenation.
+    display_map: Rc<DisplayMap>,
+    /// The highlighted lines of the code block, e.g.: `hl_lines="1 3-5"`.
+    line_highlights: Vec<Range<usize>>,
     styled_text: StyledText,
+    on_anchor_click: Option<Arc<AnchorClickFn>>,
+    on_link_click: Option<Arc<LinkClickFn>>,
+    on_context_menu: Option<Arc<ContextMenuTargetFn>>,
 
     state: Arc<Mutex<InlineState>>,
 }
@@ -34,6 +73,27 @@ pub(crate) struct InlineState {
     /// The text that actually rendering, matched with selection.
     pub(super) text: SharedString,
     pub(super) selection: Option<Selection>,
+    /// The ranges of the search matches in the text, see [`TextViewState::find`].
+    ///
+    /// [`TextViewState::find`]: crate::text::TextViewState::find
+    pub(super) search_matches: Vec<Range<usize>>,
+    /// The active search match, highlighted in a distinct color.
+    pub(super) active_match: Option<Range<usize>>,
+    /// The range of the link focused by the keyboard, painted with a focus ring.
+    pub(super) focused_link: Option<Range<usize>>,
+    /// The start offsets of the spoilers revealed by clicking.
+    revealed_spoilers: Vec<usize>,
+    /// The ranges replaced in the selected text sorted by the start, e.g.: the hidden spoilers
+    /// are removed, and the emoji are replaced by the shortcodes.
+    copy_replacements: Vec<(Range<usize>, SharedString)>,
+    /// The text removed from the start on render (e.g.: the drop cap), copied if the selection
+    /// starts from the start.
+    copy_prefix: SharedString,
+    /// The painted bounds of the inline emoji images, in the order of the text.
+    pub(super) image_bounds: Vec<Bounds<Pixels>>,
+    /// The offsets of the soft hyphens that the lines are wrapped at, painted as the hyphens.
+    pub(super) hyphens: Vec<usize>,
+    runs: TextRunsCache,
 }
 
 impl InlineState {
@@ -41,6 +101,230 @@ impl InlineState {
     pub(crate) fn set_text(&mut self, text: SharedString) {
         self.text = text;
     }
+
+    /// Get the selected text with the copy replacements, e.g.: the hidden spoilers are excluded.
+    pub(super) fn selected_text(&self) -> Option<String> {
+        let selection = self.selection.as_ref()?;
+        let mut text = String::new();
+        if selection.start == 0 {
+            text.push_str(&self.copy_prefix);
+        }
+        let mut ix = selection.start;
+        for (range, replacement) in self.copy_replacements.iter() {
+            if range.end <= ix || range.start >= selection.end {
+                continue;
+            }
+            text.push_str(&self.text[ix..range.start.max(ix)]);
+            text.push_str(replacement);
+            ix = range.end.min(selection.end);
+        }
+        text.push_str(&self.text[ix..selection.end]);
+        Some(text)
+    }
+}
+
+/// The soft hyphen (U+00AD) inserted by the hyphenation, the line can break at it.
+pub(super) const SOFT_HYPHEN: &str = "\u{ad}";
+
+/// The text changed on render from the document text, e.g.: the soft hyphens inserted by the
+/// hyphenation, the first-line indent, the first letter removed for the drop cap, the
+/// superscript number of the footnote reference, or the formatted `<time>`.
+///
+/// The inserted text is excluded from the selected text, the removed text is included, and the
+/// replaced text is copied as the document text unless it is copied as displayed, e.g.: the
+/// formatted `<time>`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(super) struct DisplayMap {
+    /// The text removed from the start.
+    pub(super) removed: SharedString,
+    /// The ranges of the inserted text in the rendered text, sorted.
+    pub(super) inserted: Vec<Range<usize>>,
+    /// The text replaced in the rendered text, sorted.
+    pub(super) replaced: Vec<Replaced>,
+}
+
+/// The text replaced on render, see [`DisplayMap::replaced`].
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Replaced {
+    /// The range of the replacement in the rendered text.
+    pub(super) range: Range<usize>,
+    /// The document text replaced.
+    pub(super) text: SharedString,
+    /// Whether to copy the replacement as displayed instead of the document text.
+    pub(super) copy_displayed: bool,
+}
+
+impl Replaced {
+    pub(super) fn new(range: Range<usize>, text: impl Into<SharedString>) -> Self {
+        Self {
+            range,
+            text: text.into(),
+            copy_displayed: false,
+        }
+    }
+}
+
+impl DisplayMap {
+    /// Map the `offset` of the document text to the rendered text.
+    ///
+    /// The text inserted at the offset is before it if `is_start` (e.g.: a link after the
+    /// first-line indent), otherwise after it.
+    ///
+    /// The offset inside the replaced text is mapped to the start (or the end if not `is_start`)
+    /// of the replacement.
+    pub(super) fn display_offset(&self, offset: usize, is_start: bool) -> usize {
+        let mut offset = offset.saturating_sub(self.removed.len());
+        // The changes in the rendered order, with the length of the document text.
+        let mut changes = self
+            .inserted
+            .iter()
+            .map(|range| (range, 0))
+            .chain(
+                self.replaced
+                    .iter()
+                    .map(|replaced| (&replaced.range, replaced.text.len())),
+            )
+            .collect::<Vec<_>>();
+        changes.sort_by_key(|(range, _)| range.start);
+        for (range, len) in changes {
+            if len == 0 {
+                if range.start < offset || (range.start == offset && is_start) {
+                    offset += range.len();
+                } else {
+                    break;
+                }
+            } else if offset <= range.start {
+                break;
+            } else if offset >= range.start + len {
+                offset = offset + range.len() - len;
+            } else {
+                offset = if is_start { range.start } else { range.end };
+            }
+        }
+        offset
+    }
+
+    /// Map the `range` of the document text to the rendered text.
+    pub(super) fn display_range(&self, range: &Range<usize>) -> Range<usize> {
+        self.display_offset(range.start, true)..self.display_offset(range.end, false)
+    }
+
+    /// Append the map of the text rendered at the `offset`, e.g.: the next inline node.
+    pub(super) fn extend(&mut self, other: &DisplayMap, offset: usize) {
+        if offset == 0 && self.removed.is_empty() {
+            self.removed = other.removed.clone();
+        }
+        self.inserted.extend(
+            other
+                .inserted
+                .iter()
+                .map(|range| (offset + range.start)..(offset + range.end)),
+        );
+        self.replaced
+            .extend(other.replaced.iter().map(|replaced| Replaced {
+                range: (offset + replaced.range.start)..(offset + replaced.range.end),
+                ..replaced.clone()
+            }));
+    }
+}
+
+/// The text runs built from the highlights, cached across frames.
+///
+/// The runs are only rebuilt when the text, highlights or text style changed, so the selection
+/// changes (e.g.: drag to select) just repaint the selection with the existing runs.
+#[derive(Debug, Default, PartialEq)]
+struct TextRunsCache {
+    text: SharedString,
+    highlights: Vec<(Range<usize>, HighlightStyle)>,
+    code_font: Option<CodeFont>,
+    text_style: Option<TextStyle>,
+    runs: Vec<TextRun>,
+}
+
+/// The font family of the inline code ranges, see [`TextViewStyle::inline_code_font`].
+///
+/// [`TextViewStyle::inline_code_font`]: crate::text::TextViewStyle::inline_code_font
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct CodeFont {
+    pub(super) family: SharedString,
+    pub(super) ranges: Vec<Range<usize>>,
+}
+
+impl CodeFont {
+    /// Whether the `range` is inside the inline code.
+    fn contains(&self, range: &Range<usize>) -> bool {
+        self.ranges
+            .iter()
+            .any(|code| code.start <= range.start && range.end <= code.end)
+    }
+}
+
+impl TextRunsCache {
+    /// Update the runs for the given text, return `true` if the runs are rebuilt.
+    fn update(
+        &mut self,
+        text: &SharedString,
+        highlights: &[(Range<usize>, HighlightStyle)],
+        code_font: Option<&CodeFont>,
+        text_style: &TextStyle,
+    ) -> bool {
+        if &self.text == text
+            && self.highlights == highlights
+            && self.code_font.as_ref() == code_font
+            && self.text_style.as_ref() == Some(text_style)
+        {
+            return false;
+        }
+
+        // A byte range may carry multiple marks (e.g. bold + link), merge the
+        // overlapping highlights into one style per range before building runs.
+        let combined = gpui::combine_highlights(highlights.to_vec(), vec![]);
+
+        let mut runs = Vec::new();
+        let mut ix = 0;
+        for (range, highlight) in combined {
+            if ix < range.start {
+                push_run(&mut runs, text_style.clone().to_run(range.start - ix));
+            }
+            let mut run_style = text_style.clone().highlight(highlight);
+            // The inline code has a highlight, so its bounds are always the bounds of the ranges.
+            if let Some(code_font) = code_font.filter(|code_font| code_font.contains(&range)) {
+                run_style.font_family = code_font.family.clone();
+            }
+            push_run(&mut runs, run_style.to_run(range.len()));
+            ix = range.end;
+        }
+        if ix < text.len() {
+            push_run(&mut runs, text_style.clone().to_run(text.len() - ix));
+        }
+
+        self.text = text.clone();
+        self.highlights = highlights.to_vec();
+        self.code_font = code_font.cloned();
+        self.text_style = Some(text_style.clone());
+        self.runs = runs;
+        true
+    }
+}
+
+/// Push the run, or extend the last run if it has the same style, so the adjacent ranges of the
+/// same marks (e.g.: the words of a long bold text) are laid out as one run.
+fn push_run(runs: &mut Vec<TextRun>, run: TextRun) {
+    if run.len == 0 {
+        return;
+    }
+
+    if let Some(last) = runs.last_mut()
+        && last.font == run.font
+        && last.color == run.color
+        && last.background_color == run.background_color
+        && last.underline == run.underline
+        && last.strikethrough == run.strikethrough
+    {
+        last.len += run.len;
+        return;
+    }
+    runs.push(run);
 }
 
 impl Inline {
@@ -55,12 +339,142 @@ impl Inline {
             id: id.into(),
             links: Rc::new(links),
             highlights,
+            code_font: None,
+            abbrs: Rc::new(vec![]),
+            spoilers: Rc::new(vec![]),
+            copy_hidden_spoilers: false,
+            emoji_shortcodes: Rc::new(vec![]),
+            copy_emoji_shortcodes: false,
+            images: Rc::new(vec![]),
+            display_map: Rc::new(DisplayMap::default()),
+            line_highlights: vec![],
             text: text.clone(),
             styled_text: StyledText::new(text),
+            on_anchor_click: None,
+            on_link_click: None,
+            on_context_menu: None,
             state,
         }
     }
 
+    /// Set the handler for the internal anchor links, e.g.: `#section-2`.
+    pub(super) fn on_anchor_click(mut self, handler: Option<Arc<AnchorClickFn>>) -> Self {
+        self.on_anchor_click = handler;
+        self
+    }
+
+    /// Set the handler for the links, see [`TextView::on_link_click`].
+    ///
+    /// [`TextView::on_link_click`]: crate::text::TextView::on_link_click
+    pub(super) fn on_link_click(mut self, handler: Option<Arc<LinkClickFn>>) -> Self {
+        self.on_link_click = handler;
+        self
+    }
+
+    /// Set the handler to record the link under the mouse on right click for the context menu.
+    pub(super) fn on_context_menu(mut self, handler: Option<Arc<ContextMenuTargetFn>>) -> Self {
+        self.on_context_menu = handler;
+        self
+    }
+
+    /// Set the font family of the inline code `ranges`, `None` to use the text font.
+    pub(super) fn code_font(
+        mut self,
+        family: Option<SharedString>,
+        ranges: Vec<Range<usize>>,
+    ) -> Self {
+        self.code_font = family
+            .filter(|_| !ranges.is_empty())
+            .map(|family| CodeFont { family, ranges });
+        self
+    }
+
+    /// Set the abbreviation ranges with their titles, shown in the tooltip on hover.
+    pub(super) fn abbrs(mut self, abbrs: Vec<(Range<usize>, SharedString)>) -> Self {
+        self.abbrs = Rc::new(abbrs);
+        self
+    }
+
+    /// Set the ranges of the spoilers, the adjacent ranges are merged to reveal at once.
+    ///
+    /// If `copy_hidden` is false, the hidden spoilers are excluded from the selected text.
+    pub(super) fn spoilers(mut self, mut ranges: Vec<Range<usize>>, copy_hidden: bool) -> Self {
+        ranges.sort_by_key(|range| range.start);
+        let mut spoilers: Vec<Range<usize>> = vec![];
+        for range in ranges {
+            match spoilers.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => spoilers.push(range),
+            }
+        }
+        self.spoilers = Rc::new(spoilers);
+        self.copy_hidden_spoilers = copy_hidden;
+        self
+    }
+
+    /// Set the emoji ranges with their `:shortcode:`, the emoji partially selected (e.g.: the
+    /// skin tone modifier) are copied as a whole.
+    ///
+    /// If `copy_shortcodes` is true, the emoji are copied as the shortcodes.
+    pub(super) fn emoji_shortcodes(
+        mut self,
+        emojis: Vec<(Range<usize>, SharedString)>,
+        copy_shortcodes: bool,
+    ) -> Self {
+        self.emoji_shortcodes = Rc::new(emojis);
+        self.copy_emoji_shortcodes = copy_shortcodes;
+        self
+    }
+
+    /// Set the inline emoji images with the ranges of their placeholders, each painted over the
+    /// placeholder at the same line, so the adjacent emoji are laid out by the text.
+    pub(super) fn images(mut self, images: Vec<(Range<usize>, ImageNode)>) -> Self {
+        self.images = Rc::new(images);
+        self
+    }
+
+    /// Set the text inserted or removed on render, the ranges of the document text (e.g.: the
+    /// search matches) are mapped by it.
+    pub(super) fn display_map(mut self, display_map: DisplayMap) -> Self {
+        self.display_map = Rc::new(display_map);
+        self
+    }
+
+    /// Get the hidden spoiler that contains the text `offset`.
+    fn hidden_spoiler_at(
+        spoilers: &[Range<usize>],
+        state: &InlineState,
+        offset: usize,
+    ) -> Option<Range<usize>> {
+        spoilers
+            .iter()
+            .find(|range| {
+                range.contains(&offset) && !state.revealed_spoilers.contains(&range.start)
+            })
+            .cloned()
+    }
+
+    /// Set the ranges of the highlighted lines, the background fills the whole width.
+    pub(super) fn line_highlights(mut self, ranges: Vec<Range<usize>>) -> Self {
+        self.line_highlights = ranges;
+        self
+    }
+
+    /// Get the index of the abbreviation that contains the text `offset`.
+    fn abbr_index_at(
+        abbrs: &[(Range<usize>, SharedString)],
+        offset: Option<usize>,
+    ) -> Option<usize> {
+        let offset = offset?;
+        abbrs.iter().position(|(range, _)| range.contains(&offset))
+    }
+
+    /// Get the index of the link that contains the text `offset`.
+    fn link_index_at(links: &[(Range<usize>, LinkMark)], offset: Option<usize>) -> Option<usize> {
+        let offset = offset?;
+        links.iter().position(|(range, _)| range.contains(&offset))
+    }
+
     /// Get link at given mouse position.
     fn link_for_position(
         layout: &TextLayout,
@@ -93,6 +507,7 @@ impl Inline {
     fn layout_selections(
         &self,
         text_layout: &TextLayout,
+        bounds: &Bounds<Pixels>,
         window: &mut Window,
         cx: &mut App,
     ) -> (bool, bool, Option<Selection>) {
@@ -111,6 +526,13 @@ impl Inline {
         };
         let line_height = window.line_height();
 
+        // Skip measuring each char if the whole text is out of the selection vertical range.
+        let top = selection_start.y.min(selection_end.y);
+        let bottom = selection_start.y.max(selection_end.y);
+        if bounds.bottom() <= top || bounds.top() > bottom {
+            return (true, true, None);
+        }
+
         // Use for debug selection bounds
         // self.paint_selected_bounds(Bounds::from_corners(selection_start, selection_end), window, cx);
 
@@ -123,15 +545,24 @@ impl Inline {
                 continue;
             };
 
+            // The next char may be on the left of this one in a right-to-left run, so the char
+            // box starts from the smaller x.
+            let mut char_pos = pos;
             let mut char_width = line_height.half();
-            if let Some(next_pos) = text_layout.position_for_index(offset + 1) {
+            if let Some(next_pos) = text_layout.position_for_index(offset + c.len_utf8()) {
                 if next_pos.y == pos.y {
-                    char_width = next_pos.x - pos.x;
+                    char_pos.x = pos.x.min(next_pos.x);
+                    char_width = (next_pos.x - pos.x).abs();
                 }
             }
 
-            if point_in_text_selection(pos, char_width, selection_start, selection_end, line_height)
-            {
+            if point_in_text_selection(
+                char_pos,
+                char_width,
+                selection_start,
+                selection_end,
+                line_height,
+            ) {
                 if selection.is_none() {
                     selection = Some((offset..offset).into());
                 }
@@ -159,63 +590,277 @@ impl Inline {
         if end < start {
             std::mem::swap(&mut start, &mut end);
         }
-        let Some(start_position) = text_layout.position_for_index(start) else {
+
+        Self::paint_range_background(
+            start..end,
+            text_layout,
+            bounds,
+            cx.theme().selection,
+            px(0.),
+            window,
+        );
+    }
+
+    /// Paint the background of the text `range`, see [`Inline::paint_range`].
+    fn paint_range_background(
+        range: Range<usize>,
+        text_layout: &TextLayout,
+        bounds: &Bounds<Pixels>,
+        background: Hsla,
+        corner_radius: Pixels,
+        window: &mut Window,
+    ) {
+        Self::paint_range(range, text_layout, bounds, window, |bounds, window| {
+            window.paint_quad(quad(
+                bounds,
+                corner_radius,
+                background,
+                Edges::default(),
+                gpui::transparent_black(),
+                BorderStyle::default(),
+            ));
+        });
+    }
+
+    /// Paint the focus ring around the link focused by the keyboard, see [`Inline::paint_range`].
+    fn paint_focus_ring(
+        range: Range<usize>,
+        text_layout: &TextLayout,
+        bounds: &Bounds<Pixels>,
+        window: &mut Window,
+        cx: &App,
+    ) {
+        let ring = cx.theme().ring;
+        Self::paint_range(range, text_layout, bounds, window, |bounds, window| {
+            window.paint_quad(quad(
+                bounds,
+                px(2.),
+                gpui::transparent_black(),
+                Edges::all(px(1.)),
+                ring,
+                BorderStyle::default(),
+            ));
+        });
+    }
+
+    /// Paint the dotted underline of the abbreviation, see [`Inline::paint_range`].
+    fn paint_dotted_underline(
+        range: Range<usize>,
+        text_layout: &TextLayout,
+        bounds: &Bounds<Pixels>,
+        window: &mut Window,
+        cx: &App,
+    ) {
+        let color = cx.theme().muted_foreground;
+        let line_height = text_layout.line_height();
+        Self::paint_range(range, text_layout, bounds, window, |bounds, window| {
+            // The middle lines of the range are painted at once, underline each of them.
+            let mut y = bounds.top() + line_height - px(2.);
+            while y < bounds.bottom() {
+                let mut x = bounds.left();
+                while x + px(1.) <= bounds.right() {
+                    window.paint_quad(gpui::fill(
+                        Bounds::new(point(x, y), size(px(1.), px(1.))),
+                        color,
+                    ));
+                    x += px(3.);
+                }
+                y += line_height;
+            }
+        });
+    }
+
+    /// Layout the inline images over their text ranges, each fits in the square of the
+    /// placeholder width at the middle of the line, the image not laid out is skipped.
+    ///
+    /// The images of the same url are loaded once, e.g.: the repeated emoji. The images far from
+    /// the visible area are not loaded, and their decoded images are released, the placeholders
+    /// keep the layout, so they are loaded again once scrolled back.
+    fn layout_images(
+        &self,
+        text_layout: &TextLayout,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> Vec<(Bounds<Pixels>, Option<Arc<RenderImage>>)> {
+        let mut loaded: HashMap<SharedUri, Option<Arc<RenderImage>>> = HashMap::new();
+        let visible_bounds = window.content_mask().bounds.dilate(LAZY_IMAGE_MARGIN);
+        self.images
+            .iter()
+            .filter_map(|(range, image)| {
+                let square = Self::image_square(range.clone(), text_layout, window)?;
+                if !visible_bounds.intersects(&square) {
+                    image.loaded.clear();
+                    return Some((square, None));
+                }
+                let data = loaded
+                    .entry(image.url.clone())
+                    .or_insert_with(|| {
+                        if svg_image::is_svg(&image.url) {
+                            svg_image::load_svg(
+                                &image.url,
+                                Some(square.size.width.into()),
+                                window,
+                                cx,
+                            )
+                        } else {
+                            window.use_asset::<ImgResourceLoader>(
+                                &Resource::Uri(image.url.clone()),
+                                cx,
+                            )
+                        }
+                        .and_then(|data| data.ok())
+                    })
+                    .clone();
+                let Some(data) = data else {
+                    return Some((square, None));
+                };
+                image.loaded.set(data.clone());
+
+                // Keep the aspect ratio of the image, e.g.: the wide custom emoji.
+                let side = square.size.width;
+                let image_size = data.size(0);
+                let ratio = image_size.width.0 as f32 / image_size.height.0.max(1) as f32;
+                let fit = if ratio > 1. {
+                    size(side, side / ratio)
+                } else {
+                    size(side * ratio, side)
+                };
+                let bounds = Bounds::new(
+                    point(
+                        square.left() + (side - fit.width).half(),
+                        square.top() + (side - fit.height).half(),
+                    ),
+                    fit,
+                );
+                Some((bounds, Some(data)))
+            })
+            .collect()
+    }
+
+    /// The square of the placeholder width over the text `range` at the middle of the line,
+    /// `None` if the range is not laid out.
+    fn image_square(
+        range: Range<usize>,
+        text_layout: &TextLayout,
+        window: &Window,
+    ) -> Option<Bounds<Pixels>> {
+        let start = text_layout.position_for_index(range.start)?;
+        let line_height = text_layout.line_height();
+        // The placeholder at the end of the line may be wrapped after it, use the em width.
+        let (x, side) = match text_layout.position_for_index(range.end) {
+            Some(end) if end.y == start.y => (start.x.min(end.x), (end.x - start.x).abs()),
+            _ => (
+                start.x,
+                window.text_style().font_size.to_pixels(window.rem_size()),
+            ),
+        };
+        Some(Bounds::new(
+            point(x, start.y + (line_height - side).half()),
+            size(side, side),
+        ))
+    }
+
+    /// Paint a hyphen at the end of the lines wrapped at the soft hyphens of the hyphenation, the
+    /// soft hyphen itself is invisible.
+    ///
+    /// Returns the offsets of the soft hyphens painted.
+    fn paint_hyphens(
+        &self,
+        text_layout: &TextLayout,
+        runs: &[TextRun],
+        window: &mut Window,
+        cx: &mut App,
+    ) -> Vec<usize> {
+        let mut hyphens = vec![];
+        for range in self.display_map.inserted.iter() {
+            if self.text.get(range.clone()) != Some(SOFT_HYPHEN) {
+                continue;
+            }
+            let (Some(start), Some(end)) = (
+                text_layout.position_for_index(range.start),
+                text_layout.position_for_index(range.end),
+            ) else {
+                continue;
+            };
+            // The text after the soft hyphen is wrapped to the next line.
+            if end.y <= start.y {
+                continue;
+            }
+
+            // The style of the text at the soft hyphen, e.g.: in the bold text.
+            let mut run_end = 0;
+            let Some(run) = runs.iter().find(|run| {
+                run_end += run.len;
+                range.start < run_end
+            }) else {
+                continue;
+            };
+            let font_size = window.text_style().font_size.to_pixels(window.rem_size());
+            let hyphen = window.text_system().shape_line(
+                "-".into(),
+                font_size,
+                &[TextRun {
+                    len: 1,
+                    ..run.clone()
+                }],
+                None,
+            );
+            _ = hyphen.paint(
+                start,
+                text_layout.line_height(),
+                gpui::TextAlign::Left,
+                None,
+                window,
+                cx,
+            );
+            hyphens.push(range.start);
+        }
+        hyphens
+    }
+
+    /// Paint each line of the text `range` by `paint_line`, the middle lines of the multi-line
+    /// range fill the whole width of the `bounds`.
+    fn paint_range(
+        range: Range<usize>,
+        text_layout: &TextLayout,
+        bounds: &Bounds<Pixels>,
+        window: &mut Window,
+        mut paint_line: impl FnMut(Bounds<Pixels>, &mut Window),
+    ) {
+        let Some(start_position) = text_layout.position_for_index(range.start) else {
             return;
         };
-        let Some(end_position) = text_layout.position_for_index(end) else {
+        let Some(end_position) = text_layout.position_for_index(range.end) else {
             return;
         };
 
         let line_height = text_layout.line_height();
+        let mut paint_line = |bounds: Bounds<Pixels>| paint_line(bounds, window);
+
         if start_position.y == end_position.y {
-            window.paint_quad(quad(
-                Bounds::from_corners(
-                    start_position,
-                    point(end_position.x, end_position.y + line_height),
+            paint_line(Bounds::from_corners(
+                point(start_position.x.min(end_position.x), start_position.y),
+                point(
+                    start_position.x.max(end_position.x),
+                    end_position.y + line_height,
                 ),
-                px(0.),
-                cx.theme().selection,
-                Edges::default(),
-                gpui::transparent_black(),
-                BorderStyle::default(),
             ));
         } else {
-            window.paint_quad(quad(
-                Bounds::from_corners(
-                    start_position,
-                    point(bounds.right(), start_position.y + line_height),
-                ),
-                px(0.),
-                cx.theme().selection,
-                Edges::default(),
-                gpui::transparent_black(),
-                BorderStyle::default(),
+            paint_line(Bounds::from_corners(
+                start_position,
+                point(bounds.right(), start_position.y + line_height),
             ));
 
             if end_position.y > start_position.y + line_height {
-                window.paint_quad(quad(
-                    Bounds::from_corners(
-                        point(bounds.left(), start_position.y + line_height),
-                        point(bounds.right(), end_position.y),
-                    ),
-                    px(0.),
-                    cx.theme().selection,
-                    Edges::default(),
-                    gpui::transparent_black(),
-                    BorderStyle::default(),
+                paint_line(Bounds::from_corners(
+                    point(bounds.left(), start_position.y + line_height),
+                    point(bounds.right(), end_position.y),
                 ));
             }
 
-            window.paint_quad(quad(
-                Bounds::from_corners(
-                    point(bounds.left(), end_position.y),
-                    point(end_position.x, end_position.y + line_height),
-                ),
-                px(0.),
-                cx.theme().selection,
-                Edges::default(),
-                gpui::transparent_black(),
-                BorderStyle::default(),
+            paint_line(Bounds::from_corners(
+                point(bounds.left(), end_position.y),
+                point(end_position.x, end_position.y + line_height),
             ));
         }
     }
@@ -229,9 +874,15 @@ impl IntoElement for Inline {
     }
 }
 
+pub(super) struct PrepaintState {
+    hitbox: Hitbox,
+    /// The bounds of the inline images with the loaded images, in the order of the text.
+    images: Vec<(Bounds<Pixels>, Option<Arc<RenderImage>>)>,
+}
+
 impl Element for Inline {
     type RequestLayoutState = ();
-    type PrepaintState = Hitbox;
+    type PrepaintState = PrepaintState;
 
     fn id(&self) -> Option<ElementId> {
         Some(self.id.clone())
@@ -249,19 +900,57 @@ impl Element for Inline {
         cx: &mut App,
     ) -> (LayoutId, Self::RequestLayoutState) {
         let text_style = window.text_style();
+        let runs = {
+            let mut state = self.state.lock().unwrap();
+            let hovered_link = Self::link_index_at(&self.links, state.hovered_index);
+            if hovered_link.is_none() && state.search_matches.is_empty() {
+                state.runs.update(
+                    &self.text,
+                    &self.highlights,
+                    self.code_font.as_ref(),
+                    &text_style,
+                );
+            } else {
+                let mut highlights = self.highlights.clone();
+                // Brighten the hovered link, the other marks (e.g. bold) on it are kept.
+                if let Some(ix) = hovered_link {
+                    highlights.push((
+                        self.links[ix].0.clone(),
+                        HighlightStyle {
+                            color: Some(cx.theme().link_hover),
+                            ..Default::default()
+                        },
+                    ));
+                }
+                // Highlight the search matches, the same colors as the search in Input.
+                for range in state.search_matches.iter() {
+                    let display_range = self.display_map.display_range(range);
+                    if display_range.end > self.text.len() {
+                        continue;
+                    }
 
-        let mut runs = Vec::new();
-        let mut ix = 0;
-        for (range, highlight) in self.highlights.iter() {
-            if ix < range.start {
-                runs.push(text_style.clone().to_run(range.start - ix));
+                    let background = if state.active_match.as_ref() == Some(range) {
+                        cx.theme().selection
+                    } else {
+                        cx.theme().mark
+                    };
+                    highlights.push((
+                        display_range,
+                        HighlightStyle {
+                            background_color: Some(background),
+                            ..Default::default()
+                        },
+                    ));
+                }
+                state.runs.update(
+                    &self.text,
+                    &highlights,
+                    self.code_font.as_ref(),
+                    &text_style,
+                );
             }
-            runs.push(text_style.clone().highlight(*highlight).to_run(range.len()));
-            ix = range.end;
-        }
-        if ix < self.text.len() {
-            runs.push(text_style.to_run(self.text.len() - ix));
-        }
+            state.runs.runs.clone()
+        };
 
         self.styled_text = StyledText::new(self.text.clone()).with_runs(runs);
         let (layout_id, _) =
@@ -284,7 +973,10 @@ impl Element for Inline {
             .prepaint(id, inspector_id, bounds, &mut (), window, cx);
 
         let hitbox = window.insert_hitbox(bounds, HitboxBehavior::Normal);
-        hitbox
+        // The bounds are not changed on paint, so the images are painted in a batch.
+        let text_layout = self.styled_text.layout().clone();
+        let images = self.layout_images(&text_layout, window, cx);
+        PrepaintState { hitbox, images }
     }
 
     fn paint(
@@ -298,16 +990,118 @@ impl Element for Inline {
         cx: &mut App,
     ) {
         let current_view = window.current_view();
-        let hitbox = prepaint;
+        let hitbox = &prepaint.hitbox;
         let mut state = self.state.lock().unwrap();
 
         let text_layout = self.styled_text.layout().clone();
+
+        for range in self.line_highlights.iter() {
+            Self::paint_range_background(
+                range.clone(),
+                &text_layout,
+                &bounds,
+                cx.theme().accent.opacity(0.5),
+                px(0.),
+                window,
+            );
+        }
+
+        // Highlight the hovered link behind the text.
+        if let Some(ix) = Self::link_index_at(&self.links, state.hovered_index) {
+            Self::paint_range_background(
+                self.links[ix].0.clone(),
+                &text_layout,
+                &bounds,
+                cx.theme().secondary_hover,
+                px(2.),
+                window,
+            );
+        }
+
         self.styled_text
             .paint(global_id, None, bounds, &mut (), &mut (), window, cx);
 
+        state.hyphens = self.paint_hyphens(&text_layout, &state.runs.runs, window, cx);
+        for (bounds, data) in prepaint.images.iter() {
+            if let Some(data) = data {
+                _ = window.paint_image(*bounds, Corners::default(), data.clone(), 0, false);
+            }
+        }
+        state.image_bounds = prepaint.images.iter().map(|(bounds, _)| *bounds).collect();
+
+        for (range, _) in self.abbrs.iter() {
+            Self::paint_dotted_underline(range.clone(), &text_layout, &bounds, window, cx);
+        }
+
+        // Black out the hidden spoilers over the text, so the layout size is kept.
+        let hidden_spoilers = self
+            .spoilers
+            .iter()
+            .filter(|range| !state.revealed_spoilers.contains(&range.start))
+            .cloned()
+            .collect::<Vec<_>>();
+        for range in hidden_spoilers.iter() {
+            Self::paint_range_background(
+                range.clone(),
+                &text_layout,
+                &bounds,
+                cx.theme().foreground,
+                px(2.),
+                window,
+            );
+        }
+
+        // The emoji are copied as a whole (or the shortcodes), and the hidden spoilers are removed.
+        let mut copy_replacements = self
+            .emoji_shortcodes
+            .iter()
+            .map(|(range, shortcode)| {
+                if self.copy_emoji_shortcodes {
+                    (range.clone(), shortcode.clone())
+                } else {
+                    (range.clone(), self.text[range.clone()].to_string().into())
+                }
+            })
+            .collect::<Vec<_>>();
+        if !self.copy_hidden_spoilers {
+            copy_replacements.extend(
+                hidden_spoilers
+                    .into_iter()
+                    .map(|range| (range, SharedString::default())),
+            );
+        }
+        // The placeholders of the images are copied as the alt text.
+        copy_replacements.extend(self.display_map.inserted.iter().map(|range| {
+            let alt = self
+                .images
+                .iter()
+                .find(|(image_range, _)| image_range == range)
+                .and_then(|(_, image)| image.alt.clone());
+            (range.clone(), alt.unwrap_or_default())
+        }));
+        copy_replacements.extend(
+            self.display_map
+                .replaced
+                .iter()
+                .filter(|replaced| !replaced.copy_displayed)
+                .map(|replaced| (replaced.range.clone(), replaced.text.clone())),
+        );
+        // The longer range first if they start at the same offset, e.g.: the emoji in a spoiler.
+        copy_replacements.sort_by_key(|(range, _)| (range.start, Reverse(range.end)));
+        state.copy_replacements = copy_replacements;
+        state.copy_prefix = self.display_map.removed.clone();
+
+        if let Some(range) = state
+            .focused_link
+            .as_ref()
+            .map(|range| self.display_map.display_range(range))
+        {
+            Self::paint_focus_ring(range, &text_layout, &bounds, window, cx);
+        }
+
         // layout selections
         let (is_selectable, is_selection, selection) =
-            self.layout_selections(&text_layout, window, cx);
+            self.layout_selections(&text_layout, &bounds, window, cx);
 
         state.selection = selection;
 
@@ -320,6 +1114,11 @@ impl Element for Inline {
         if let Some(_) = Self::link_for_position(&text_layout, &self.links, mouse_position) {
             window.set_cursor_style(CursorStyle::PointingHand, &hitbox);
         }
+        if let Ok(ix) = text_layout.index_for_position(mouse_position)
+            && Self::hidden_spoiler_at(&self.spoilers, &state, ix).is_some()
+        {
+            window.set_cursor_style(CursorStyle::PointingHand, &hitbox);
+        }
 
         if let Some(selection) = &state.selection {
             Self::paint_selection(selection, &text_layout, &bounds, window, cx);
@@ -329,46 +1128,307 @@ impl Element for Inline {
         window.on_mouse_event({
             let hitbox = hitbox.clone();
             let text_layout = text_layout.clone();
-            let mut hovered_index = state.hovered_index;
+            let links = self.links.clone();
+            let abbrs = self.abbrs.clone();
+            let state = self.state.clone();
             move |event: &MouseMoveEvent, phase, window, cx| {
-                if !phase.bubble() || !hitbox.is_hovered(window) {
+                if !phase.bubble() {
+                    return;
+                }
+
+                let updated = if hitbox.is_hovered(window) {
+                    text_layout.index_for_position(event.position).ok()
+                } else {
+                    None
+                };
+                let mut state = state.lock().unwrap();
+                let current = state.hovered_index;
+                state.hovered_index = updated;
+
+                //  notify update when hovering over different links or abbreviations
+                let hovered_link = Self::link_index_at(&links, updated);
+                let hovered_abbr = Self::abbr_index_at(&abbrs, updated);
+                if Self::link_index_at(&links, current) != hovered_link
+                    || Self::abbr_index_at(&abbrs, current) != hovered_abbr
+                {
+                    // Show the link destination near the cursor, on the hovered line.
+                    let trigger_bounds = updated
+                        .and_then(|ix| text_layout.position_for_index(ix))
+                        .map(|pos| {
+                            Bounds::new(
+                                point(event.position.x, pos.y),
+                                size(px(1.), text_layout.line_height()),
+                            )
+                        });
+                    // The link is preferred, e.g.: an abbreviation in the link text.
+                    match (hovered_link, hovered_abbr, trigger_bounds) {
+                        (Some(ix), _, Some(trigger_bounds)) => {
+                            show_link_tooltip(&links[ix].1, trigger_bounds, window, cx)
+                        }
+                        (None, Some(ix), Some(trigger_bounds)) => {
+                            show_abbr_tooltip(&abbrs[ix].1, trigger_bounds, window, cx)
+                        }
+                        _ => hide_link_tooltip(window, cx),
+                    }
+                    cx.notify(current_view);
+                }
+            }
+        });
+
+        // scroll, dismiss the tooltip, the link is moved away from the cursor.
+        window.on_mouse_event({
+            let links = self.links.clone();
+            let abbrs = self.abbrs.clone();
+            let state = self.state.clone();
+            move |_: &ScrollWheelEvent, phase, window, cx| {
+                if !phase.bubble() {
                     return;
                 }
 
-                let current = hovered_index;
-                let updated = text_layout.index_for_position(event.position).ok();
-                //  notify update when hovering over different links
-                if current != updated {
-                    hovered_index = updated;
+                let mut state = state.lock().unwrap();
+                if Self::link_index_at(&links, state.hovered_index).is_some()
+                    || Self::abbr_index_at(&abbrs, state.hovered_index).is_some()
+                {
+                    state.hovered_index = None;
+                    hide_link_tooltip(window, cx);
                     cx.notify(current_view);
                 }
             }
         });
 
+        // right click on link, record it for the context menu
+        if let Some(on_context_menu) = self.on_context_menu.clone() {
+            window.on_mouse_event({
+                let links = self.links.clone();
+                let text_layout = text_layout.clone();
+                let hitbox = hitbox.clone();
+                move |event: &MouseDownEvent, phase, window, cx| {
+                    if !phase.bubble()
+                        || event.button != MouseButton::Right
+                        || !hitbox.is_hovered(window)
+                    {
+                        return;
+                    }
+
+                    if let Some(link) =
+                        Self::link_for_position(&text_layout, &links, event.position)
+                    {
+                        on_context_menu(ContextMenuTarget::Link(link), cx);
+                    }
+                }
+            });
+        }
+
         if !is_selection {
             // click to open link
             window.on_mouse_event({
                 let links = self.links.clone();
                 let text_layout = text_layout.clone();
                 let hitbox = hitbox.clone();
+                let on_anchor_click = self.on_anchor_click.clone();
+                let on_link_click = self.on_link_click.clone();
+                let spoilers = self.spoilers.clone();
+                let state = self.state.clone();
 
                 move |event: &MouseUpEvent, phase, window, cx| {
                     if !phase.bubble() || !hitbox.is_hovered(window) {
                         return;
                     }
+                    // The middle button click is treated as opening in the background.
+                    if !matches!(event.button, MouseButton::Left | MouseButton::Middle) {
+                        return;
+                    }
+                    // The link in the hidden spoiler is not opened, the click reveals it.
+                    if let Ok(ix) = text_layout.index_for_position(event.position)
+                        && Self::hidden_spoiler_at(&spoilers, &state.lock().unwrap(), ix).is_some()
+                    {
+                        return;
+                    }
 
                     if let Some(link) =
                         Self::link_for_position(&text_layout, &links, event.position)
                     {
                         cx.stop_propagation();
-                        cx.open_url(&link.url);
+                        open_link(
+                            LinkClickEvent {
+                                url: link.url,
+                                modifiers: event.modifiers,
+                                background: event.button == MouseButton::Middle
+                                    || event.modifiers.secondary(),
+                            },
+                            on_anchor_click.as_ref(),
+                            on_link_click.as_ref(),
+                            window,
+                            cx,
+                        );
                     }
                 }
             });
+
+            // click to reveal the spoiler
+            if !self.spoilers.is_empty() {
+                window.on_mouse_event({
+                    let spoilers = self.spoilers.clone();
+                    let text_layout = text_layout.clone();
+                    let hitbox = hitbox.clone();
+                    let state = self.state.clone();
+
+                    move |event: &MouseUpEvent, phase, window, cx| {
+                        if !phase.bubble()
+                            || event.button != MouseButton::Left
+                            || !hitbox.is_hovered(window)
+                        {
+                            return;
+                        }
+
+                        let Ok(ix) = text_layout.index_for_position(event.position) else {
+                            return;
+                        };
+                        let mut state = state.lock().unwrap();
+                        if let Some(range) = Self::hidden_spoiler_at(&spoilers, &state, ix) {
+                            state.revealed_spoilers.push(range.start);
+                            cx.stop_propagation();
+                            cx.notify(current_view);
+                        }
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Open the link url, the internal anchor links (e.g.: `#section-2`) are sent to the `on_anchor_click`,
+/// the others are sent to the `on_link_click` if any, or opened with the system browser.
+///
+/// Do nothing for the anchor links if no `on_anchor_click`.
+pub(super) fn open_link(
+    event: LinkClickEvent,
+    on_anchor_click: Option<&Arc<AnchorClickFn>>,
+    on_link_click: Option<&Arc<LinkClickFn>>,
+    window: &mut Window,
+    cx: &mut App,
+) {
+    if let Some(id) = event.url.strip_prefix('#') {
+        if let Some(on_anchor_click) = on_anchor_click {
+            on_anchor_click(id, window, cx);
         }
+        return;
+    }
+
+    match on_link_click {
+        Some(on_link_click) => on_link_click(&event, window, cx),
+        None => cx.open_url(&event.url),
+    }
+}
+
+/// The max chars of the URL displayed in the link tooltip.
+const LINK_TOOLTIP_MAX_CHARS: usize = 60;
+
+/// Show the destination (and title) of the hovered `link` after the tooltip delay.
+fn show_link_tooltip(
+    link: &LinkMark,
+    trigger_bounds: Bounds<Pixels>,
+    window: &mut Window,
+    cx: &mut App,
+) {
+    let url: SharedString = truncate_url(&link.url, LINK_TOOLTIP_MAX_CHARS).into();
+    let title = link.title.clone().filter(|title| !title.is_empty());
+    let build = Rc::new(move |window: &mut Window, cx: &mut App| {
+        let url = url.clone();
+        let title = title.clone();
+        Tooltip::element(move |_, cx| {
+            v_flex()
+                .when_some(title.clone(), |this, title| this.child(title))
+                .child(
+                    div()
+                        .when(title.is_some(), |this| {
+                            this.text_color(cx.theme().muted_foreground)
+                        })
+                        .child(url.clone()),
+                )
+        })
+        .build(window, cx)
+    });
+
+    request_tooltip(build, trigger_bounds, window, cx);
+}
+
+/// Show the `title` (expansion) of the hovered abbreviation after the tooltip delay.
+fn show_abbr_tooltip(
+    title: &SharedString,
+    trigger_bounds: Bounds<Pixels>,
+    window: &mut Window,
+    cx: &mut App,
+) {
+    let title = title.clone();
+    let build = Rc::new(move |window: &mut Window, cx: &mut App| {
+        Tooltip::new(title.clone()).build(window, cx)
+    });
+
+    request_tooltip(build, trigger_bounds, window, cx);
+}
+
+fn request_tooltip(
+    build: Rc<dyn Fn(&mut Window, &mut App) -> AnyView>,
+    trigger_bounds: Bounds<Pixels>,
+    window: &mut Window,
+    cx: &mut App,
+) {
+    let Some(overlay) = Root::tooltip_overlay(window, cx) else {
+        return;
+    };
+
+    overlay.update(cx, |overlay: &mut TooltipOverlay, cx| {
+        overlay.request_show(
+            TooltipContent {
+                build,
+                trigger_bounds,
+            },
+            window,
+            cx,
+        );
+    });
+}
+
+fn hide_link_tooltip(window: &mut Window, cx: &mut App) {
+    if let Some(overlay) = Root::tooltip_overlay(window, cx) {
+        overlay.update(cx, |overlay: &mut TooltipOverlay, cx| {
+            overlay.request_hide(window, cx);
+        });
     }
 }
 
+/// Truncate the `url` to `max_chars` with an ellipsis in the middle, the scheme and host are
+/// always kept, e.g.: `https://example.com/…/page.html`.
+///
+/// The `url` without the path (e.g.: `mailto:` or a bare long host) is truncated at the end.
+fn truncate_url(url: &str, max_chars: usize) -> String {
+    let char_count = url.chars().count();
+    if char_count <= max_chars {
+        return url.to_string();
+    }
+
+    let host_start = url.find("://").map(|ix| ix + 3).unwrap_or(0);
+    let host_end = url[host_start..]
+        .find(['/', '?', '#'])
+        .map(|ix| host_start + ix)
+        .unwrap_or(url.len());
+    let head = &url[..host_end];
+
+    let rest = &url[host_end..];
+    if rest.is_empty() {
+        let head: String = url.chars().take(max_chars.saturating_sub(1)).collect();
+        return format!("{}…", head);
+    }
+    let tail_chars = max_chars.saturating_sub(head.chars().count() + 1);
+    let tail: String = rest
+        .chars()
+        .skip(rest.chars().count().saturating_sub(tail_chars))
+        .collect();
+
+    format!("{}…{}", head, tail)
+}
+
 /// Check if a `pos` is within a `bounds`, considering multi-line selections.
 fn point_in_text_selection(
     pos: Point<Pixels>,
@@ -414,8 +1474,282 @@ fn point_in_text_selection(
 
 #[cfg(test)]
 mod tests {
-    use super::point_in_text_selection;
-    use gpui::{point, px};
+    use super::{
+        CodeFont, DisplayMap, Inline, InlineState, Replaced, TextRunsCache,
+        point_in_text_selection, truncate_url,
+    };
+    use crate::{input::Selection, text::node::LinkMark};
+    use gpui::{FontWeight, HighlightStyle, SharedString, TextStyle, point, px};
+
+    #[test]
+    fn test_selected_text_hidden_spoilers() {
+        let mut state = InlineState::default();
+        state.set_text("The killer is the butler, and the cook.".into());
+        assert_eq!(state.selected_text(), None);
+
+        state.selection = Some(Selection::new(4, 39));
+        state.copy_replacements = vec![(14..24, "".into()), (34..38, "".into())];
+        assert_eq!(state.selected_text().unwrap(), "killer is , and the .");
+
+        // The selection starts or ends inside the hidden spoiler.
+        state.selection = Some(Selection::new(20, 36));
+        assert_eq!(state.selected_text().unwrap(), ", and the ");
+
+        // Revealed.
+        state.copy_replacements.clear();
+        assert_eq!(state.selected_text().unwrap(), "tler, and the co");
+    }
+
+    #[test]
+    fn test_selected_text_emoji_shortcodes() {
+        let mut state = InlineState::default();
+        state.set_text("Ship it 🚀 now".into());
+        state.copy_replacements = vec![(8..12, ":rocket:".into())];
+
+        state.selection = Some(Selection::new(0, 16));
+        assert_eq!(state.selected_text().unwrap(), "Ship it :rocket: now");
+        state.selection = Some(Selection::new(5, 8));
+        assert_eq!(state.selected_text().unwrap(), "it ");
+
+        // The selection starts or ends inside the emoji, e.g.: the skin tone modifier.
+        state.set_text("Hi 👋🏼 there".into());
+        state.copy_replacements = vec![(3..11, "👋🏼".into())];
+        state.selection = Some(Selection::new(7, 17));
+        assert_eq!(state.selected_text().unwrap(), "👋🏼 there");
+        state.selection = Some(Selection::new(0, 7));
+        assert_eq!(state.selected_text().unwrap(), "Hi 👋🏼");
+        state.copy_replacements = vec![(3..11, ":wave:t3:".into())];
+        assert_eq!(state.selected_text().unwrap(), "Hi :wave:t3:");
+
+        // The adjacent emoji are copied once each.
+        state.set_text("👋🏼👋🚀".into());
+        state.copy_replacements = vec![
+            (0..8, ":wave:t3:".into()),
+            (8..12, ":wave:".into()),
+            (12..16, ":rocket:".into()),
+        ];
+        state.selection = Some(Selection::new(4, 16));
+        assert_eq!(state.selected_text().unwrap(), ":wave:t3::wave::rocket:");
+        state.selection = Some(Selection::new(8, 12));
+        assert_eq!(state.selected_text().unwrap(), ":wave:");
+
+        // The emoji in the hidden spoiler is removed with the spoiler.
+        state.set_text("Ship it 🚀 now".into());
+        state.copy_replacements = vec![(5..12, "".into()), (8..12, ":rocket:".into())];
+        state.selection = Some(Selection::new(0, 16));
+        assert_eq!(state.selected_text().unwrap(), "Ship  now");
+    }
+
+    #[test]
+    fn test_display_map() {
+        // "hy-phen-ation is" with the soft hyphens.
+        let text = "hy\u{ad}phen\u{ad}ation is";
+        let map = DisplayMap {
+            inserted: vec![2..4, 8..10],
+            ..Default::default()
+        };
+        assert_eq!(map.display_offset(0, true), 0);
+        assert_eq!(map.display_offset(2, false), 2);
+        assert_eq!(map.display_offset(2, true), 4);
+        assert_eq!(map.display_offset(3, true), 5);
+        assert_eq!(map.display_range(&(2..6)), 4..8);
+        assert_eq!(&text[map.display_offset(12, true)..], "is");
+        assert_eq!(DisplayMap::default().display_offset(12, true), 12);
+
+        // The inserted text is excluded from the selected text.
+        let mut state = InlineState::default();
+        state.set_text(text.into());
+        state.copy_replacements = map
+            .inserted
+            .iter()
+            .map(|range| (range.clone(), "".into()))
+            .collect();
+        state.selection = Some(Selection::new(0, text.len()));
+        assert_eq!(state.selected_text().unwrap(), "hyphenation is");
+
+        // The first letter removed for the drop cap, then the next inline node.
+        let mut map = DisplayMap::default();
+        map.extend(
+            &DisplayMap {
+                removed: "O".into(),
+                ..Default::default()
+            },
+            0,
+        );
+        map.extend(
+            &DisplayMap {
+                removed: "x".into(),
+                inserted: vec![1..3],
+                ..Default::default()
+            },
+            10,
+        );
+        assert_eq!(map.removed.as_ref(), "O");
+        assert_eq!(map.inserted, vec![11..13]);
+        assert_eq!(map.display_range(&(1..5)), 0..4);
+
+        state.set_text("nce upon".into());
+        state.copy_replacements.clear();
+        state.copy_prefix = "O".into();
+        state.selection = Some(Selection::new(0, 3));
+        assert_eq!(state.selected_text().unwrap(), "Once");
+        state.selection = Some(Selection::new(4, 8));
+        assert_eq!(state.selected_text().unwrap(), "upon");
+
+        // "Note¹², again¹" of the footnote references "12" and "1" with the soft hyphen.
+        let text = "Note\u{b9}\u{b2}, ag\u{ad}ain\u{b9}";
+        let map = DisplayMap {
+            inserted: vec![12..14],
+            replaced: vec![Replaced::new(4..8, "12"), Replaced::new(17..19, "1")],
+            ..Default::default()
+        };
+        assert_eq!(map.display_range(&(4..6)), 4..8);
+        assert_eq!(map.display_offset(5, true), 4);
+        assert_eq!(map.display_offset(5, false), 8);
+        assert_eq!(map.display_offset(10, true), 14);
+        assert_eq!(map.display_offset(10, false), 12);
+        assert_eq!(&text[map.display_range(&(13..14))], "\u{b9}");
+
+        // The replaced text is copied as the document text.
+        state.set_text(text.into());
+        state.copy_prefix = "".into();
+        state.copy_replacements = map
+            .inserted
+            .iter()
+            .map(|range| (range.clone(), "".into()))
+            .chain(
+                map.replaced
+                    .iter()
+                    .map(|replaced| (replaced.range.clone(), replaced.text.clone())),
+            )
+            .collect();
+        state
+            .copy_replacements
+            .sort_by_key(|(range, _)| range.start);
+        state.selection = Some(Selection::new(0, text.len()));
+        assert_eq!(state.selected_text().unwrap(), "Note12, again1");
+    }
+
+    #[test]
+    fn test_truncate_url() {
+        assert_eq!(truncate_url("https://a.com/b", 30), "https://a.com/b");
+        assert_eq!(
+            truncate_url("https://example.com/a/very/long/path/to/the/page.html", 30),
+            "https://example.com…/page.html"
+        );
+        assert_eq!(
+            truncate_url("https://example.com/a/very/long/path?q=1", 24),
+            "https://example.com…?q=1"
+        );
+        // The scheme and host are always kept.
+        assert_eq!(
+            truncate_url("https://a-long-subdomain.example.com/path", 20),
+            "https://a-long-subdomain.example.com…"
+        );
+        // No path to cut in the middle.
+        assert_eq!(
+            truncate_url("mailto:someone.with.a.long.name@example.com", 20),
+            "mailto:someone.with…"
+        );
+        assert_eq!(
+            truncate_url("https://a-long-subdomain.example.com", 20),
+            "https://a-long-subd…"
+        );
+    }
+
+    #[test]
+    fn test_link_index_at() {
+        let links = vec![(0..5, LinkMark::default()), (10..15, LinkMark::default())];
+        assert_eq!(Inline::link_index_at(&links, None), None);
+        assert_eq!(Inline::link_index_at(&links, Some(0)), Some(0));
+        assert_eq!(Inline::link_index_at(&links, Some(4)), Some(0));
+        assert_eq!(Inline::link_index_at(&links, Some(5)), None);
+        assert_eq!(Inline::link_index_at(&links, Some(12)), Some(1));
+        assert_eq!(Inline::link_index_at(&links, Some(15)), None);
+    }
+
+    #[test]
+    fn test_text_runs_cache() {
+        let text = SharedString::from("Hello world, this is a long paragraph. ".repeat(100));
+        let highlights = vec![(
+            6..11,
+            HighlightStyle {
+                font_weight: Some(FontWeight::BOLD),
+                ..Default::default()
+            },
+        )];
+        let text_style = TextStyle::default();
+
+        let mut cache = TextRunsCache::default();
+        assert!(cache.update(&text, &highlights, None, &text_style));
+        assert_eq!(cache.runs.len(), 3);
+        assert_eq!(
+            cache.runs.iter().map(|run| run.len).sum::<usize>(),
+            text.len()
+        );
+        assert_eq!(cache.runs[1].font.weight, FontWeight::BOLD);
+
+        // Drag to select repaints each frame, the runs must be reused.
+        for _ in 0..10 {
+            assert!(!cache.update(&text, &highlights, None, &text_style));
+        }
+
+        // Changed highlights
+        assert!(cache.update(&text, &[], None, &text_style));
+        assert_eq!(cache.runs.len(), 1);
+
+        // Changed text style
+        let text_style = TextStyle {
+            font_weight: FontWeight::BOLD,
+            ..Default::default()
+        };
+        assert!(cache.update(&text, &[], None, &text_style));
+        assert!(!cache.update(&text, &[], None, &text_style));
+
+        // Changed text
+        assert!(cache.update(&"Hello".into(), &[], None, &text_style));
+        assert_eq!(cache.runs[0].len, 5);
+
+        // The inline code font, only the runs in the code ranges are changed.
+        let code_font = CodeFont {
+            family: "Menlo".into(),
+            ranges: vec![0..2],
+        };
+        let highlights = vec![(0..2, HighlightStyle::default())];
+        assert!(cache.update(&"Hello".into(), &highlights, Some(&code_font), &text_style));
+        assert_eq!(cache.runs.len(), 2);
+        assert_eq!(cache.runs[0].font.family.as_ref(), "Menlo");
+        assert_eq!(cache.runs[1].font.family, text_style.font_family);
+        assert!(!cache.update(&"Hello".into(), &highlights, Some(&code_font), &text_style));
+        assert!(cache.update(&"Hello".into(), &highlights, None, &text_style));
+    }
+
+    #[test]
+    fn test_text_runs_coalesce() {
+        let text = SharedString::from("a".repeat(30));
+        let bold = HighlightStyle {
+            font_weight: Some(FontWeight::BOLD),
+            ..Default::default()
+        };
+        let link = HighlightStyle {
+            color: Some(gpui::red()),
+            ..Default::default()
+        };
+        // The overlapping bold and link, and the adjacent bold ranges.
+        let highlights = vec![(0..10, bold), (5..15, link), (15..20, bold), (20..25, bold)];
+
+        let mut cache = TextRunsCache::default();
+        cache.update(&text, &highlights, None, &TextStyle::default());
+        assert_eq!(
+            cache.runs.iter().map(|run| run.len).collect::<Vec<_>>(),
+            vec![5, 5, 5, 10, 5]
+        );
+        assert_eq!(cache.runs[1].font.weight, FontWeight::BOLD);
+        assert_eq!(cache.runs[1].color, gpui::red());
+        assert_eq!(cache.runs[2].font.weight, FontWeight::NORMAL);
+        assert_eq!(cache.runs[2].color, gpui::red());
+        assert_eq!(cache.runs[3].font.weight, FontWeight::BOLD);
+    }
 
     #[test]
     fn test_point_in_text_selection() {
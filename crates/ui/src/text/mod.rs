@@ -1,16 +1,35 @@
+mod animated_image;
+mod builder;
 mod document;
 mod format;
+#[cfg(feature = "hyphenation")]
+mod hyphenate;
+mod image_data;
 mod inline;
 mod node;
+mod search;
+#[cfg(feature = "serde")]
+mod serde_helpers;
 mod state;
 mod style;
+mod svg_image;
 mod text_view;
 mod utils;
+mod visitor;
 
 use gpui::{App, ElementId, IntoElement, RenderOnce, SharedString, Window};
+
+pub use builder::{DocumentBuilder, ListBuilder, ParagraphBuilder};
+pub use document::{ParsedDocument, TocEntry};
+pub use node::{
+    AdmonitionKind, BlockNode, CodeBlock, CustomBlock, CustomBlockRenderer, Diagnostic,
+    DiagnosticKind, EmbedNode, EmbedProvider, ImageLoading, ImageNode, InlineNode, LinkMark,
+    ListMarker, Mention, Paragraph, RuleStyle, SemanticTag, Span, Table, TextMark,
+};
 pub use state::*;
 pub use style::*;
 pub use text_view::*;
+pub use visitor::{Visitor, VisitorMut};
 
 pub(crate) fn init(cx: &mut App) {
     state::init(cx);
@@ -1,35 +1,56 @@
 use std::{
+    borrow::Cow,
+    cell::RefCell,
     collections::HashMap,
-    ops::Range,
-    sync::{Arc, Mutex},
+    ops::{Range, RangeInclusive},
+    rc::Rc,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
 use gpui::{
-    AnyElement, App, DefiniteLength, Div, ElementId, FontStyle, FontWeight, Half, HighlightStyle,
-    InteractiveElement as _, IntoElement, Length, ObjectFit, ParentElement, SharedString,
-    SharedUri, StatefulInteractiveElement, Styled, StyledImage as _, Window, div, img,
-    prelude::FluentBuilder as _, px, relative, rems,
+    AbsoluteLength, AnyElement, App, Bounds, DefiniteLength, Div, Element, ElementId, Font,
+    FontStyle, FontWeight, GlobalElementId, Half, HighlightStyle, Hsla, ImageSource,
+    ImgResourceLoader, InspectorElementId, InteractiveElement as _, IntoElement, LayoutId,
+    MouseButton, ObjectFit, ParentElement, Pixels, Rems, Resource, SharedString, SharedUri, Size,
+    StatefulInteractiveElement, Styled, StyledImage as _, Window, canvas, div, img,
+    prelude::FluentBuilder as _, px, relative, rems, transparent_white,
 };
 use markdown::mdast;
 use ropey::Rope;
+use rust_i18n::t;
+use unicode_segmentation::UnicodeSegmentation as _;
 
 use crate::{
-    ActiveTheme as _, Icon, IconName, StyledExt, h_flex,
-    highlighter::{HighlightTheme, SyntaxHighlighter},
+    ActiveTheme as _, Colorize as _, Icon, IconName, StyledExt, h_flex,
+    highlighter::{HighlightTheme, SyntaxHighlighter, token_styles},
+    scroll::ScrollableElement as _,
     text::{
-        CodeBlockActionsFn,
-        document::NodeRenderOptions,
-        inline::{Inline, InlineState},
+        AnchorClickFn, CheckboxToggleFn, CodeBlockActionsFn, ContextMenuTarget,
+        ContextMenuTargetFn, ImageClickFn, LinkClickEvent, LinkClickFn, LinkVisitedFn,
+        MathRenderFn,
+        animated_image::AnimatedImage,
+        document::{NodeRenderOptions, for_each_block},
+        image_data,
+        inline::{DisplayMap, Inline, InlineState, Replaced, open_link},
+        svg_image,
     },
     tooltip::Tooltip,
     v_flex,
 };
 
-use super::{TextViewStyle, utils::list_item_prefix};
+use super::{
+    TextViewStyle,
+    utils::{alpha_number, escape_html, roman_number},
+};
 
 /// The block-level nodes.
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum BlockNode {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum BlockNode {
     /// Something like a Div container in HTML.
     Root {
         children: Vec<BlockNode>,
@@ -39,16 +60,32 @@ pub(crate) enum BlockNode {
     Heading {
         level: u8,
         children: Paragraph,
+        /// The anchor id of the heading, e.g.: `getting-started`.
+        id: Option<SharedString>,
         span: Option<Span>,
     },
     Blockquote {
         children: Vec<BlockNode>,
         span: Option<Span>,
     },
+    /// The GitHub-style alert, a blockquote starts with the `[!NOTE]` marker.
+    Admonition {
+        kind: AdmonitionKind,
+        children: Vec<BlockNode>,
+        span: Option<Span>,
+    },
     List {
         /// Only contains ListItem, others will be ignored
         children: Vec<BlockNode>,
         ordered: bool,
+        /// The number of the first item in the ordered list, default is 1.
+        start: usize,
+        /// The marker type of the ordered list, if None, the marker is decided by the depth.
+        #[cfg_attr(feature = "serde", serde(default))]
+        marker: Option<ListMarker>,
+        /// Whether the ordered list is numbered in descending order.
+        #[cfg_attr(feature = "serde", serde(default))]
+        reversed: bool,
         span: Option<Span>,
     },
     ListItem {
@@ -56,15 +93,47 @@ pub(crate) enum BlockNode {
         spread: bool,
         /// Whether the list item is checked, if None, it's not a checkbox
         checked: Option<bool>,
+        /// The explicit number of the item in the ordered list, the following items continue from it.
+        #[cfg_attr(feature = "serde", serde(default))]
+        value: Option<usize>,
         span: Option<Span>,
     },
     CodeBlock(CodeBlock),
+    /// The display math, e.g.: `$$ ... $$` or the fenced code block of the `math` language.
+    Math {
+        /// The raw formula, e.g.: the TeX source.
+        formula: SharedString,
+        span: Option<Span>,
+    },
     Table(Table),
+    /// The `<br>` between the blocks, rendered as a blank line.
     Break {
         html: bool,
         span: Option<Span>,
     },
     HorizontalRule {
+        /// The style from the `border` styles of `<hr>`, the theme divider by default.
+        #[cfg_attr(feature = "serde", serde(default))]
+        style: RuleStyle,
+        span: Option<Span>,
+    },
+    /// The embedded content, e.g.: `<iframe>`, rendered as a link card.
+    Embed(EmbedNode),
+    /// The HTML element rendered by the app, see [`CustomBlockRenderer`].
+    Custom(CustomBlock),
+    /// The footnote definitions collected at the end of the document, numbered in the order of
+    /// the first reference.
+    ///
+    /// It has no span, so the whole source is re-parsed when appending the text.
+    Footnotes {
+        /// Only contains FootnoteDefinition, others will be ignored
+        children: Vec<BlockNode>,
+    },
+    FootnoteDefinition {
+        identifier: SharedString,
+        /// The number of the references to the footnote, each has a back-reference link.
+        references: usize,
+        children: Vec<BlockNode>,
         span: Option<Span>,
     },
     /// Use for to_markdown get raw definition
@@ -86,6 +155,140 @@ impl BlockNode {
         matches!(self, Self::Break { .. })
     }
 
+    /// Whether the list is loose, that any item is `spread` (e.g.: separated by blank lines),
+    /// the loose list has the paragraph spacing between the items.
+    pub(super) fn is_loose_list(&self) -> bool {
+        let BlockNode::List { children, .. } = self else {
+            return false;
+        };
+
+        children
+            .iter()
+            .any(|child| matches!(child, BlockNode::ListItem { spread: true, .. }))
+    }
+
+    /// The number of the task list items (with checkbox) in the block, including the nested
+    /// blocks and table cells.
+    pub(crate) fn checkbox_count(&self) -> usize {
+        let mut count = 0;
+        for_each_block(std::slice::from_ref(self), &mut |block| {
+            if matches!(
+                block,
+                BlockNode::ListItem {
+                    checked: Some(_),
+                    ..
+                }
+            ) {
+                count += 1;
+            }
+        });
+        count
+    }
+
+    /// Convert the blockquote that starts with an alert marker (e.g.: `[!NOTE]`) into [`BlockNode::Admonition`].
+    ///
+    /// The marker must be on its own line, the unknown markers are kept as a plain blockquote.
+    pub(super) fn into_admonition(self) -> BlockNode {
+        let BlockNode::Blockquote {
+            mut children, span, ..
+        } = self
+        else {
+            return self;
+        };
+
+        let Some(BlockNode::Paragraph(paragraph)) = children.first_mut() else {
+            return BlockNode::Blockquote { children, span };
+        };
+        let Some((kind, marker_len)) = AdmonitionKind::parse_marker(&paragraph.leading_text())
+        else {
+            return BlockNode::Blockquote { children, span };
+        };
+
+        paragraph.trim_start_bytes(marker_len);
+        if paragraph.is_empty() {
+            children.remove(0);
+        }
+
+        BlockNode::Admonition {
+            kind,
+            children,
+            span,
+        }
+    }
+
+    /// Returns the number of each child in the ordered list, the non-item children
+    /// take the number of the next item.
+    ///
+    /// The item with an explicit `value` restarts the numbering from it.
+    pub(super) fn list_item_numbers(&self) -> Vec<usize> {
+        let BlockNode::List {
+            children,
+            start,
+            reversed,
+            ..
+        } = self
+        else {
+            return vec![];
+        };
+
+        let mut number = *start;
+        children
+            .iter()
+            .map(|child| {
+                if let BlockNode::ListItem {
+                    value: Some(value), ..
+                } = child
+                {
+                    number = *value;
+                }
+
+                let current = number;
+                if child.is_list_item() {
+                    number = if *reversed {
+                        number.saturating_sub(1)
+                    } else {
+                        number + 1
+                    };
+                }
+                current
+            })
+            .collect()
+    }
+
+    /// Returns the marker of each child in the list at the `depth`, e.g.: `1.`, `a.` or `•`.
+    ///
+    /// The markers are cycled by the depth, see [`TextViewStyle::list_bullets`] and
+    /// [`TextViewStyle::list_markers`].
+    pub(super) fn list_item_markers(&self, depth: usize, style: &TextViewStyle) -> Vec<String> {
+        let BlockNode::List {
+            ordered, marker, ..
+        } = self
+        else {
+            return vec![];
+        };
+
+        let numbers = self.list_item_numbers();
+        if !*ordered {
+            let bullet = depth
+                .checked_rem(style.list_bullets.len())
+                .map(|ix| style.list_bullets[ix].to_string())
+                .unwrap_or_default();
+            return vec![bullet; numbers.len()];
+        }
+
+        let marker = marker
+            .or_else(|| {
+                depth
+                    .checked_rem(style.list_markers.len())
+                    .map(|ix| style.list_markers[ix])
+            })
+            .unwrap_or(ListMarker::Decimal);
+        numbers
+            .into_iter()
+            .map(|number| format!("{}.", marker.format(number)))
+            .collect()
+    }
+
     /// Combine all children, omitting the empt parent nodes.
     pub(super) fn compact(self) -> BlockNode {
         match self {
@@ -101,17 +304,84 @@ impl BlockNode {
             BlockNode::Paragraph(paragraph) => paragraph.span,
             BlockNode::Heading { span, .. } => *span,
             BlockNode::Blockquote { span, .. } => *span,
+            BlockNode::Admonition { span, .. } => *span,
             BlockNode::List { span, .. } => *span,
             BlockNode::ListItem { span, .. } => *span,
             BlockNode::CodeBlock(code_block) => code_block.span,
+            BlockNode::Math { span, .. } => *span,
             BlockNode::Table(table) => table.span,
             BlockNode::Break { span, .. } => *span,
             BlockNode::HorizontalRule { span, .. } => *span,
+            BlockNode::Embed(embed) => embed.span,
+            BlockNode::Custom(block) => block.span,
+            BlockNode::Footnotes { .. } => None,
+            BlockNode::FootnoteDefinition { span, .. } => *span,
             BlockNode::Definition { span, .. } => *span,
             BlockNode::Unknown { .. } => None,
         }
     }
 
+    /// Whether the block is right-to-left, by the direction of its first paragraph.
+    pub(super) fn is_rtl(&self) -> bool {
+        match self {
+            BlockNode::Paragraph(paragraph) => paragraph.is_rtl(),
+            BlockNode::Heading { children, .. } => children.is_rtl(),
+            BlockNode::Root { children, .. }
+            | BlockNode::Blockquote { children, .. }
+            | BlockNode::Admonition { children, .. }
+            | BlockNode::List { children, .. }
+            | BlockNode::ListItem { children, .. } => {
+                children.first().is_some_and(|child| child.is_rtl())
+            }
+            _ => false,
+        }
+    }
+
+    /// Return length of the widest line of the node text.
+    pub(super) fn text_len(&self) -> usize {
+        match self {
+            BlockNode::Root { children, .. }
+            | BlockNode::Blockquote { children, .. }
+            | BlockNode::Admonition { children, .. }
+            | BlockNode::List { children, .. }
+            | BlockNode::ListItem { children, .. }
+            | BlockNode::Footnotes { children }
+            | BlockNode::FootnoteDefinition { children, .. } => children
+                .iter()
+                .map(|child| child.text_len())
+                .max()
+                .unwrap_or(0),
+            BlockNode::Paragraph(paragraph) => paragraph.text_len(),
+            BlockNode::Heading { children, .. } => children.text_len(),
+            BlockNode::CodeBlock(code_block) => code_block
+                .code()
+                .lines()
+                .map(|line| line.len())
+                .max()
+                .unwrap_or(0),
+            BlockNode::Math { formula, .. } => {
+                formula.lines().map(|line| line.len()).max().unwrap_or(0)
+            }
+            BlockNode::Table(table) => table
+                .children
+                .iter()
+                .map(|row| {
+                    row.children
+                        .iter()
+                        .map(|cell| cell.text_len())
+                        .sum::<usize>()
+                })
+                .max()
+                .unwrap_or(0),
+            BlockNode::Break { .. }
+            | BlockNode::HorizontalRule { .. }
+            | BlockNode::Embed(_)
+            | BlockNode::Custom(_)
+            | BlockNode::Definition { .. }
+            | BlockNode::Unknown => 0,
+        }
+    }
+
     pub(super) fn selected_text(&self) -> String {
         let mut text = String::new();
         match self {
@@ -146,12 +416,14 @@ impl BlockNode {
                     text.push_str(&c.selected_text());
                 }
             }
-            BlockNode::ListItem { children, .. } => {
+            BlockNode::ListItem { children, .. }
+            | BlockNode::Footnotes { children }
+            | BlockNode::FootnoteDefinition { children, .. } => {
                 for c in children.iter() {
                     text.push_str(&c.selected_text());
                 }
             }
-            BlockNode::Blockquote { children, .. } => {
+            BlockNode::Blockquote { children, .. } | BlockNode::Admonition { children, .. } => {
                 let mut block_text = String::new();
                 for c in children.iter() {
                     block_text.push_str(&c.selected_text());
@@ -163,18 +435,7 @@ impl BlockNode {
                 }
             }
             BlockNode::Table(table) => {
-                let mut block_text = String::new();
-                for row in table.children.iter() {
-                    let mut row_texts = vec![];
-                    for cell in row.children.iter() {
-                        row_texts.push(cell.children.selected_text());
-                    }
-                    if !row_texts.is_empty() {
-                        block_text.push_str(&row_texts.join(" "));
-                        block_text.push('\n');
-                    }
-                }
-
+                let block_text = table.selected_tsv();
                 if !block_text.is_empty() {
                     text.push_str(&block_text);
                     text.push('\n');
@@ -190,15 +451,110 @@ impl BlockNode {
             BlockNode::Definition { .. }
             | BlockNode::Break { .. }
             | BlockNode::HorizontalRule { .. }
+            | BlockNode::Math { .. }
+            | BlockNode::Embed(_)
+            | BlockNode::Custom(_)
             | BlockNode::Unknown { .. } => {}
         }
 
         text
     }
+
+    /// Collect the text of each [`Inline`] element with its state in render order, for searching,
+    /// grouped by the paragraphs.
+    pub(super) fn text_segments(
+        &self,
+        segments: &mut Vec<Vec<(Arc<Mutex<InlineState>>, SharedString)>>,
+    ) {
+        match self {
+            BlockNode::Paragraph(paragraph)
+            | BlockNode::Heading {
+                children: paragraph,
+                ..
+            } => paragraph.text_segments(segments),
+            BlockNode::CodeBlock(code_block) => {
+                segments.push(vec![(code_block.state.clone(), code_block.code())]);
+            }
+            BlockNode::Root { children, .. }
+            | BlockNode::Blockquote { children, .. }
+            | BlockNode::Admonition { children, .. }
+            | BlockNode::List { children, .. }
+            | BlockNode::ListItem { children, .. }
+            | BlockNode::Footnotes { children }
+            | BlockNode::FootnoteDefinition { children, .. } => {
+                for child in children.iter() {
+                    child.text_segments(segments);
+                }
+            }
+            BlockNode::Table(table) => {
+                for row in table.children.iter() {
+                    for cell in row.children.iter() {
+                        for child in cell.children.iter() {
+                            child.text_segments(segments);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Collect the links of each [`Inline`] element with its state in render order, for the
+    /// keyboard focus, the ranges are in the inline text.
+    pub(super) fn link_segments(
+        &self,
+        node_cx: &NodeContext,
+        segments: &mut Vec<(Arc<Mutex<InlineState>>, Range<usize>, LinkMark)>,
+    ) {
+        match self {
+            BlockNode::Paragraph(paragraph)
+            | BlockNode::Heading {
+                children: paragraph,
+                ..
+            } => paragraph.link_segments(node_cx, segments),
+            BlockNode::Root { children, .. }
+            | BlockNode::Blockquote { children, .. }
+            | BlockNode::Admonition { children, .. }
+            | BlockNode::List { children, .. }
+            | BlockNode::ListItem { children, .. }
+            | BlockNode::Footnotes { children }
+            | BlockNode::FootnoteDefinition { children, .. } => {
+                for child in children.iter() {
+                    child.link_segments(node_cx, segments);
+                }
+            }
+            BlockNode::Table(table) => {
+                for row in table.children.iter() {
+                    for cell in row.children.iter() {
+                        for child in cell.children.iter() {
+                            child.link_segments(node_cx, segments);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the selection in markdown, the blocks covered by the selection are fully included.
+    pub(super) fn selected_markdown(&self) -> String {
+        match self {
+            BlockNode::Root { children, .. } => children
+                .iter()
+                .map(|child| child.selected_markdown())
+                .filter(|markdown| !markdown.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            BlockNode::Table(table) => table.selected_markdown(),
+            _ if self.selected_text().trim().is_empty() => String::new(),
+            _ => self.to_markdown(),
+        }
+    }
 }
 
 #[allow(unused)]
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LinkMark {
     pub url: SharedString,
     /// Optional identifier for footnotes.
@@ -207,12 +563,100 @@ pub struct LinkMark {
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TextMark {
     pub bold: bool,
     pub italic: bool,
     pub strikethrough: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub underline: bool,
     pub code: bool,
+    /// The inline math formula, e.g.: `$E = mc^2$`, rendered by the math renderer, or as the raw
+    /// formula like the code.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub math: bool,
     pub link: Option<LinkMark>,
+    /// The expansion of the abbreviation, e.g.: `<abbr title="World Health Organization">`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub abbr: Option<SharedString>,
+    /// The identifier of the footnote reference, e.g.: `[^note]`, the text is the number of the
+    /// footnote, and the `link` goes to the footnote definition.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub footnote: Option<SharedString>,
+    /// The handle of the resolved `@mention`, e.g.: `jason`, the text is the display name.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mention: Option<SharedString>,
+    /// The spoiler, e.g.: `<span class="spoiler">`, the text is hidden until clicked.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub spoiler: bool,
+    /// The shortcode of the emoji, e.g.: `smile`, the text is the Unicode emoji.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub emoji: Option<SharedString>,
+    /// The semantic HTML tag kept in the Markdown, e.g.: `<cite>`, the styles of the tag are set
+    /// by the other marks, see [`SemanticTag::mark`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub semantic: Option<SemanticTag>,
+    /// The machine-readable datetime of `<time datetime="...">`, e.g.: `2024-01-02T15:04Z`,
+    /// the text may be re-formatted, see [`TextViewState::set_time_formatter`].
+    ///
+    /// [`TextViewState::set_time_formatter`]: crate::text::TextViewState::set_time_formatter
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time: Option<SharedString>,
+}
+
+/// The semantic HTML tags styled by the other marks, e.g.: `<cite>` is italic.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SemanticTag {
+    /// `<cite>`, italic.
+    Cite,
+    /// `<dfn>`, italic.
+    Dfn,
+    /// `<var>`, italic and monospace.
+    Var,
+    /// `<samp>`, monospace.
+    Samp,
+    /// `<address>`, italic.
+    Address,
+}
+
+impl SemanticTag {
+    /// Returns the tag name, e.g.: `cite`.
+    pub(crate) fn tag(&self) -> &'static str {
+        match self {
+            Self::Cite => "cite",
+            Self::Dfn => "dfn",
+            Self::Var => "var",
+            Self::Samp => "samp",
+            Self::Address => "address",
+        }
+    }
+
+    fn italic(&self) -> bool {
+        !matches!(self, Self::Samp)
+    }
+
+    /// Whether the tag is kept around the paragraph instead of the text, see
+    /// [`Paragraph::semantic`].
+    fn is_block(&self) -> bool {
+        matches!(self, Self::Address)
+    }
+
+    fn code(&self) -> bool {
+        matches!(self, Self::Var | Self::Samp)
+    }
+
+    /// Returns the mark with the styles of the tag, the tag is kept in the Markdown and HTML if
+    /// `keep` is true, otherwise the Markdown uses the italic and the backticks.
+    pub(crate) fn mark(self, keep: bool) -> TextMark {
+        TextMark {
+            italic: self.italic(),
+            code: self.code(),
+            semantic: keep.then_some(self),
+            ..Default::default()
+        }
+    }
 }
 
 impl TextMark {
@@ -231,29 +675,213 @@ impl TextMark {
         self
     }
 
+    pub fn underline(mut self) -> Self {
+        self.underline = true;
+        self
+    }
+
     pub fn code(mut self) -> Self {
         self.code = true;
         self
     }
 
+    pub fn math(mut self) -> Self {
+        self.math = true;
+        self
+    }
+
     pub fn link(mut self, link: impl Into<LinkMark>) -> Self {
         self.link = Some(link.into());
         self
     }
 
+    pub fn abbr(mut self, title: impl Into<SharedString>) -> Self {
+        self.abbr = Some(title.into());
+        self
+    }
+
+    pub fn spoiler(mut self) -> Self {
+        self.spoiler = true;
+        self
+    }
+
+    pub fn time(mut self, datetime: impl Into<SharedString>) -> Self {
+        self.time = Some(datetime.into());
+        self
+    }
+
+    /// Set the footnote reference, links to the definition with the `#fn-{identifier}` anchor.
+    pub fn footnote(mut self, identifier: impl Into<SharedString>) -> Self {
+        let identifier = identifier.into();
+        self.link = Some(LinkMark {
+            url: format!("#fn-{}", identifier).into(),
+            ..Default::default()
+        });
+        self.footnote = Some(identifier);
+        self
+    }
+
     pub fn merge(&mut self, other: TextMark) {
         self.bold |= other.bold;
         self.italic |= other.italic;
         self.strikethrough |= other.strikethrough;
+        self.underline |= other.underline;
         self.code |= other.code;
+        self.math |= other.math;
+        self.spoiler |= other.spoiler;
         if let Some(link) = other.link {
             self.link = Some(link);
         }
+        if let Some(abbr) = other.abbr {
+            self.abbr = Some(abbr);
+        }
+        if let Some(footnote) = other.footnote {
+            self.footnote = Some(footnote);
+        }
+        if let Some(mention) = other.mention {
+            self.mention = Some(mention);
+        }
+        if let Some(emoji) = other.emoji {
+            self.emoji = Some(emoji);
+        }
+        if let Some(semantic) = other.semantic {
+            self.semantic = Some(semantic);
+        }
+        if let Some(time) = other.time {
+            self.time = Some(time);
+        }
+    }
+
+    /// Wrap the text with the markdown syntax of all the styles in this mark.
+    ///
+    /// The code span (or math) is the innermost, the link is the outermost.
+    fn wrap_markdown(&self, text: &str) -> String {
+        // The text of the footnote reference is the generated number.
+        if let Some(identifier) = &self.footnote {
+            return format!("[^{}]", identifier);
+        }
+        // The text of the mention is the display name.
+        if let Some(handle) = &self.mention {
+            return format!("@{}", handle);
+        }
+        // The text of the emoji is the Unicode emoji.
+        if let Some(shortcode) = &self.emoji {
+            return format!(":{}:", shortcode);
+        }
+
+        // The styles of the kept semantic tag are not repeated.
+        let semantic_code = self.semantic.is_some_and(|tag| tag.code());
+        let semantic_italic = self.semantic.is_some_and(|tag| tag.italic());
+
+        let mut text = text.to_string();
+        if self.math {
+            text = format!("${}$", text);
+        } else if self.code && !semantic_code {
+            text = format!("`{}`", text);
+        }
+        // No underline syntax in Markdown, use the inline HTML.
+        if self.underline {
+            text = format!("<u>{}</u>", text);
+        }
+        if self.strikethrough {
+            text = format!("~~{}~~", text);
+        }
+        if self.italic && !semantic_italic {
+            text = format!("*{}*", text);
+        }
+        // No semantic tags in Markdown, use the inline HTML.
+        if let Some(tag) = self.semantic.filter(|tag| !tag.is_block()) {
+            text = format!("<{0}>{1}</{0}>", tag.tag(), text);
+        }
+        if let Some(datetime) = &self.time {
+            text = format!(
+                r#"<time datetime="{}">{}</time>"#,
+                escape_html(datetime),
+                text
+            );
+        }
+        if self.bold {
+            text = format!("**{}**", text);
+        }
+        // No abbreviation syntax in Markdown, use the inline HTML.
+        if let Some(abbr) = &self.abbr {
+            text = format!(r#"<abbr title="{}">{}</abbr>"#, escape_html(abbr), text);
+        }
+        // No spoiler syntax in Markdown, use the inline HTML.
+        if self.spoiler {
+            text = format!(r#"<span class="spoiler">{}</span>"#, text);
+        }
+        if let Some(link) = &self.link {
+            text = format!("[{}]({})", text, link.url);
+        }
+        text
+    }
+
+    /// Wrap the HTML with the tags of all the styles in this mark.
+    ///
+    /// The `<code>` is the innermost, the `<a>` is the outermost.
+    fn wrap_html(&self, html: &str) -> String {
+        let semantic_code = self.semantic.is_some_and(|tag| tag.code());
+        let semantic_italic = self.semantic.is_some_and(|tag| tag.italic());
+
+        let mut html = html.to_string();
+        if self.math {
+            html = format!(r#"<code class="language-math math-inline">{}</code>"#, html);
+        } else if self.code && !semantic_code {
+            html = format!("<code>{}</code>", html);
+        }
+        if self.footnote.is_some() {
+            html = format!("<sup>{}</sup>", html);
+        }
+        if self.mention.is_some() {
+            html = format!(r#"<span class="mention">{}</span>"#, html);
+        }
+        if self.underline {
+            html = format!("<u>{}</u>", html);
+        }
+        if self.strikethrough {
+            html = format!("<del>{}</del>", html);
+        }
+        if self.italic && !semantic_italic {
+            html = format!("<em>{}</em>", html);
+        }
+        if let Some(tag) = self.semantic.filter(|tag| !tag.is_block()) {
+            html = format!("<{0}>{1}</{0}>", tag.tag(), html);
+        }
+        if let Some(datetime) = &self.time {
+            html = format!(
+                r#"<time datetime="{}">{}</time>"#,
+                escape_html(datetime),
+                html
+            );
+        }
+        if self.bold {
+            html = format!("<strong>{}</strong>", html);
+        }
+        if let Some(abbr) = &self.abbr {
+            html = format!(r#"<abbr title="{}">{}</abbr>"#, escape_html(abbr), html);
+        }
+        if self.spoiler {
+            html = format!(r#"<span class="spoiler">{}</span>"#, html);
+        }
+        if let Some(link) = &self.link {
+            let title = link.title.as_ref().map_or(String::new(), |title| {
+                format!(r#" title="{}""#, escape_html(title))
+            });
+            html = format!(
+                r#"<a href="{}"{}>{}</a>"#,
+                escape_html(&link.url),
+                title,
+                html
+            );
+        }
+        html
     }
 }
 
 /// The bytes
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
     pub start: usize,
     pub end: usize,
@@ -267,13 +895,70 @@ impl From<Span> for ElementId {
 
 #[allow(unused)]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImageNode {
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::text::serde_helpers::shared_uri")
+    )]
     pub url: SharedUri,
     pub link: Option<LinkMark>,
     pub title: Option<SharedString>,
     pub alt: Option<SharedString>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::text::serde_helpers::option_length")
+    )]
     pub width: Option<DefiniteLength>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::text::serde_helpers::option_length")
+    )]
     pub height: Option<DefiniteLength>,
+    /// The `max-width` style constraint, e.g.: `max-width: 100%`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::text::serde_helpers::option_length")
+    )]
+    pub max_width: Option<DefiniteLength>,
+    /// The `max-height` style constraint.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::text::serde_helpers::option_length")
+    )]
+    pub max_height: Option<DefiniteLength>,
+    /// The `class` attribute, e.g.: `emoji` of the inline emoji images.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub class: Option<SharedString>,
+    /// The `loading` hint, the lazy image is not loaded until it is near the visible area.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub loading: ImageLoading,
+    /// The decoded image of the last render, to copy the image.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) loaded: image_data::LoadedImage,
+}
+
+/// The `loading` attribute of the `<img>`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ImageLoading {
+    /// Load the image immediately, the default.
+    #[default]
+    Eager,
+    /// Defer loading the image until it is near the visible area, e.g.: scrolled into the view.
+    Lazy,
+}
+
+impl ImageLoading {
+    /// Parse the value of the `loading` attribute, the invalid value is [`ImageLoading::Eager`].
+    pub(crate) fn parse(value: &str) -> Self {
+        if value.trim().eq_ignore_ascii_case("lazy") {
+            Self::Lazy
+        } else {
+            Self::Eager
+        }
+    }
 }
 
 impl ImageNode {
@@ -283,362 +968,2125 @@ impl ImageNode {
             .unwrap_or_else(|| self.alt.clone().unwrap_or_default())
             .to_string()
     }
-}
 
-impl PartialEq for ImageNode {
-    fn eq(&self, other: &Self) -> bool {
-        self.url == other.url
-            && self.link == other.link
-            && self.title == other.title
-            && self.alt == other.alt
-            && self.width == other.width
-            && self.height == other.height
+    /// Whether the image is an inline emoji, by the `emoji` class.
+    pub(crate) fn is_emoji(&self) -> bool {
+        self.class
+            .as_ref()
+            .is_some_and(|class| class.split_whitespace().any(|name| name == "emoji"))
     }
-}
-
-#[derive(Default, Clone, Debug)]
-pub(crate) struct InlineNode {
-    /// The text content.
-    pub(crate) text: SharedString,
-    pub(crate) image: Option<ImageNode>,
-    /// The text styles, each tuple contains the range of the text and the style.
-    pub(crate) marks: Vec<(Range<usize>, TextMark)>,
-
-    state: Arc<Mutex<InlineState>>,
-}
 
-impl PartialEq for InlineNode {
-    fn eq(&self, other: &Self) -> bool {
-        self.text == other.text && self.image == other.image && self.marks == other.marks
+    /// Copy the image to the clipboard as an image, e.g.: for the "Copy Image" of the context menu.
+    ///
+    /// The image displayed is copied without loading it again, the `url` is copied instead if
+    /// the image has not finished loading. The `data:` uri is decoded to copy the raw image.
+    pub fn copy_image(&self, cx: &mut App) {
+        image_data::copy_image(&self.url, self.loaded.get().as_deref(), cx);
     }
-}
 
-impl InlineNode {
-    pub(crate) fn new(text: impl Into<SharedString>) -> Self {
-        Self {
-            text: text.into(),
-            image: None,
-            marks: vec![],
-            state: Arc::new(Mutex::new(InlineState::default())),
-        }
+    /// Save the image to the file chosen by the user, e.g.: for the "Save Image" of the menu.
+    pub fn save_image(&self, cx: &mut App) {
+        image_data::save_image(&self.url, cx);
     }
 
-    pub(crate) fn image(image: ImageNode) -> Self {
-        let mut this = Self::new("");
-        this.image = Some(image);
-        this
-    }
+    /// Returns the [`ImageFit`] to scale down the image to the container width,
+    /// `None` if neither `width` nor `height` is absolute pixels.
+    ///
+    /// If only one dimension is given, the other one is computed from the `intrinsic_ratio`
+    /// (width / height) of the loaded image, or reserve a square box before it is loaded.
+    pub(crate) fn fit(&self, intrinsic_ratio: Option<f32>) -> Option<ImageFit> {
+        let absolute = |length: Option<DefiniteLength>| match length {
+            Some(DefiniteLength::Absolute(AbsoluteLength::Pixels(value))) if value > px(0.) => {
+                Some(value)
+            }
+            _ => None,
+        };
+        let aspect_ratio = intrinsic_ratio
+            .filter(|ratio| ratio.is_finite() && *ratio > 0.)
+            .unwrap_or(1.);
 
-    pub(crate) fn marks(mut self, marks: Vec<(Range<usize>, TextMark)>) -> Self {
-        self.marks = marks;
-        self
+        let mut fit = match (absolute(self.width), absolute(self.height)) {
+            (Some(width), Some(height)) => ImageFit {
+                width,
+                aspect_ratio: width / height,
+            },
+            (Some(width), None) => ImageFit {
+                width,
+                aspect_ratio,
+            },
+            // Keep the relative width to be resolved against the container.
+            (None, Some(height)) if self.width.is_none() => ImageFit {
+                width: height * aspect_ratio,
+                aspect_ratio,
+            },
+            _ => return None,
+        };
+
+        // Scale down the width to keep the aspect ratio within the `max-height`,
+        // the `max-width` is applied by the wrapper of the image.
+        if let Some(max_height) = absolute(self.max_height) {
+            let max_width = max_height * fit.aspect_ratio;
+            if max_width < fit.width {
+                fit.width = max_width;
+            }
+        }
+
+        Some(fit)
     }
 }
 
-/// The paragraph element, contains multiple text nodes.
-///
-/// Unlike other Element, this is cloneable, because it is used in the Node AST.
-/// We are keep the selection state inside this AST Nodes.
-#[derive(Debug, Clone, Default)]
-pub(crate) struct Paragraph {
-    pub(super) span: Option<Span>,
-    pub(super) children: Vec<InlineNode>,
-    /// The link references in this paragraph, used for reference links.
-    ///
-    /// The key is the identifier, the value is the url.
-    pub(super) link_refs: HashMap<SharedString, SharedString>,
-
-    pub(crate) state: Arc<Mutex<InlineState>>,
+/// The embedded content (e.g.: the `<iframe>` of a video), rendered as a link card.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmbedNode {
+    /// The url opened on click, e.g.: the video page.
+    pub url: SharedString,
+    /// The name of the recognized provider, e.g.: `YouTube`.
+    pub provider: Option<SharedString>,
+    /// The `title` of the `<iframe>`.
+    pub title: Option<SharedString>,
+    /// The thumbnail image url, e.g.: the video cover.
+    pub thumbnail: Option<SharedString>,
+    pub span: Option<Span>,
 }
 
-impl PartialEq for Paragraph {
-    fn eq(&self, other: &Self) -> bool {
-        self.span == other.span
-            && self.children == other.children
-            && self.link_refs == other.link_refs
+impl EmbedNode {
+    /// Returns the title, or the provider name, or the generic "Embedded content".
+    pub fn title(&self) -> SharedString {
+        self.title
+            .clone()
+            .or_else(|| self.provider.clone())
+            .unwrap_or_else(|| t!("TextView.Embedded content").into())
     }
-}
 
-impl Paragraph {
-    pub(crate) fn new(text: String) -> Self {
-        Self {
-            span: None,
-            children: vec![InlineNode::new(&text)],
-            link_refs: HashMap::new(),
-            state: Arc::new(Mutex::new(InlineState::default())),
-        }
+    fn to_markdown(&self) -> String {
+        format!("[{}]({})", self.title(), self.url)
     }
 
-    pub(super) fn selected_text(&self) -> String {
-        let mut text = String::new();
-
-        for c in self.children.iter() {
-            let state = c.state.lock().unwrap();
-            if let Some(selection) = &state.selection {
-                let part_text = state.text.clone();
-                text.push_str(&part_text[selection.start..selection.end]);
-            }
-        }
+    fn to_html(&self) -> String {
+        format!(
+            r#"<p><a href="{}">{}</a></p>"#,
+            escape_html(&self.url),
+            escape_html(&self.title())
+        )
+    }
 
-        let state = self.state.lock().unwrap();
-        if let Some(selection) = &state.selection {
-            let all_text = state.text.clone();
-            text.push_str(&all_text[selection.start..selection.end]);
-        }
+    fn render(&self, ix: usize, node_cx: &NodeContext, cx: &App) -> impl IntoElement {
+        let url = self.url.clone();
+        let on_anchor_click = node_cx.on_anchor_click.clone();
+        let on_link_click = node_cx.on_link_click.clone();
+        let hover_bg = cx.theme().secondary_hover;
 
-        text
+        h_flex()
+            .id(("embed", ix))
+            .max_w(px(480.))
+            .gap_3()
+            .p_2()
+            .border_1()
+            .border_color(cx.theme().border)
+            .rounded(cx.theme().radius)
+            .cursor_pointer()
+            .hover(|this| this.bg(hover_bg))
+            .when_some(self.thumbnail.clone(), |this, thumbnail| {
+                this.child(
+                    img(thumbnail)
+                        .flex_shrink_0()
+                        .w(px(120.))
+                        .h(px(68.))
+                        .rounded(cx.theme().radius)
+                        .object_fit(ObjectFit::Cover),
+                )
+            })
+            .child(
+                v_flex()
+                    .overflow_hidden()
+                    .child(div().font_semibold().truncate().child(self.title()))
+                    .child(
+                        div()
+                            .text_sm()
+                            .text_color(cx.theme().muted_foreground)
+                            .truncate()
+                            .child(self.url.clone()),
+                    ),
+            )
+            .on_click(move |event, window, cx| {
+                open_link(
+                    LinkClickEvent {
+                        url: url.clone(),
+                        modifiers: event.modifiers(),
+                        background: event.modifiers().secondary(),
+                    },
+                    on_anchor_click.as_ref(),
+                    on_link_click.as_ref(),
+                    window,
+                    cx,
+                )
+            })
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub(crate) struct Table {
-    pub(crate) children: Vec<TableRow>,
-    pub(crate) column_aligns: Vec<ColumnumnAlign>,
-    pub(crate) span: Option<Span>,
+/// The HTML element matched by the [`CustomBlockRenderer`], rendered by the app.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CustomBlock {
+    /// The tag name, e.g.: `div`.
+    pub tag: SharedString,
+    /// The attributes of the element in the source order, e.g.: `("class", "poll")`.
+    pub attrs: Vec<(SharedString, SharedString)>,
+    /// The inner text of the element, the `<br>` is converted to a newline.
+    pub text: SharedString,
+    pub span: Option<Span>,
 }
 
-impl Table {
-    pub(crate) fn column_align(&self, index: usize) -> ColumnumnAlign {
-        self.column_aligns.get(index).copied().unwrap_or_default()
+impl CustomBlock {
+    /// Returns the value of the attribute by the name.
+    pub fn attr(&self, name: &str) -> Option<&SharedString> {
+        self.attrs
+            .iter()
+            .find_map(|(key, value)| (key.as_ref() == name).then_some(value))
     }
-}
-
-#[derive(Debug, Default, Copy, Clone, PartialEq)]
-pub(crate) enum ColumnumnAlign {
-    #[default]
-    Left,
-    Center,
-    Right,
-}
 
-impl From<mdast::AlignKind> for ColumnumnAlign {
-    fn from(value: mdast::AlignKind) -> Self {
-        match value {
-            mdast::AlignKind::None => ColumnumnAlign::Left,
-            mdast::AlignKind::Left => ColumnumnAlign::Left,
-            mdast::AlignKind::Center => ColumnumnAlign::Center,
-            mdast::AlignKind::Right => ColumnumnAlign::Right,
-        }
+    fn to_html(&self) -> String {
+        let attrs = self
+            .attrs
+            .iter()
+            .map(|(key, value)| format!(r#" {}="{}""#, key, escape_html(value)))
+            .collect::<String>();
+        format!(
+            "<{}{}>{}</{}>",
+            self.tag,
+            attrs,
+            escape_html(&self.text),
+            self.tag
+        )
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub(crate) struct TableRow {
-    pub children: Vec<TableCell>,
+/// Render the HTML elements matched by the tag or class as the [`CustomBlock`]s by the app,
+/// e.g.: a poll widget from `<div class="poll">`, see [`TextViewState::set_custom_block_renderer`].
+///
+/// The unmatched elements are parsed as usual.
+///
+/// [`TextViewState::set_custom_block_renderer`]: crate::text::TextViewState::set_custom_block_renderer
+#[derive(Clone)]
+pub struct CustomBlockRenderer {
+    tags: Vec<SharedString>,
+    classes: Vec<SharedString>,
+    render: Arc<CustomBlockRenderFn>,
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub(crate) struct TableCell {
-    pub children: Paragraph,
-    pub width: Option<DefiniteLength>,
-}
+/// Render the [`CustomBlock`] to the element.
+type CustomBlockRenderFn = dyn Fn(&CustomBlock, &mut Window, &mut App) -> AnyElement + Send + Sync;
 
-impl Paragraph {
-    pub(crate) fn take(&mut self) -> Paragraph {
-        std::mem::replace(
-            self,
-            Paragraph {
-                span: None,
-                children: vec![],
-                link_refs: Default::default(),
-                state: Arc::new(Mutex::new(InlineState::default())),
-            },
-        )
+impl CustomBlockRenderer {
+    /// Create a renderer without matchers, add them by [`Self::tag`] or [`Self::class`].
+    pub fn new(
+        render: impl Fn(&CustomBlock, &mut Window, &mut App) -> AnyElement + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            tags: vec![],
+            classes: vec![],
+            render: Arc::new(render),
+        }
     }
 
-    pub(crate) fn is_image(&self) -> bool {
-        false
+    /// Match the elements by the tag name, e.g.: `poll` for `<poll>`.
+    ///
+    /// In sanitize mode, the tags not in the allowlist are unwrapped before matching.
+    pub fn tag(mut self, tag: impl Into<SharedString>) -> Self {
+        self.tags.push(tag.into());
+        self
     }
 
-    pub(crate) fn set_span(&mut self, span: Span) {
-        self.span = Some(span);
+    /// Match the elements by the class, e.g.: `poll` for `<div class="poll">`.
+    pub fn class(mut self, class: impl Into<SharedString>) -> Self {
+        self.classes.push(class.into());
+        self
     }
 
-    pub(crate) fn push_str(&mut self, text: &str) {
-        self.children.push(
-            InlineNode::new(text.to_string()).marks(vec![(0..text.len(), TextMark::default())]),
-        );
+    /// Whether the element of the `tag` and the `class` attribute is matched.
+    pub(crate) fn matches(&self, tag: &str, class: Option<&str>) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+            || class.is_some_and(|class| {
+                class
+                    .split_ascii_whitespace()
+                    .any(|class| self.classes.iter().any(|c| c.as_ref() == class))
+            })
     }
+}
 
-    pub(crate) fn push(&mut self, text: InlineNode) {
-        self.children.push(text);
-    }
+/// The resolved `@mention`, see [`TextViewState::set_mention_resolver`].
+///
+/// [`TextViewState::set_mention_resolver`]: crate::text::TextViewState::set_mention_resolver
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mention {
+    /// The display name, e.g.: `Jason Lee`, rendered as `@Jason Lee`.
+    pub name: SharedString,
+    /// The url to open on click, e.g.: the profile page.
+    pub url: Option<SharedString>,
+}
 
-    pub(crate) fn push_image(&mut self, image: ImageNode) {
-        self.children.push(InlineNode::image(image));
-    }
+/// Resolve the handle (without `@`) of the mention, returns `None` for the unknown users.
+pub(crate) type MentionResolveFn = dyn Fn(&str) -> Option<Mention> + Send + Sync;
 
-    pub(crate) fn is_empty(&self) -> bool {
-        self.children.is_empty()
-            || self
-                .children
-                .iter()
-                .all(|node| node.text.is_empty() && node.image.is_none())
-    }
+/// Format the datetime of `<time datetime="...">` (the first argument) for display, the second
+/// argument is the original text, returns `None` to keep the original text.
+pub(crate) type TimeFormatFn = dyn Fn(&str, &str) -> Option<SharedString> + Send + Sync;
 
-    /// Return length of children text.
-    pub(crate) fn text_len(&self) -> usize {
-        self.children
-            .iter()
-            .map(|node| node.text.len())
-            .sum::<usize>()
-    }
+/// A structured warning recorded on parsing, see [`TextViewState::set_collect_diagnostics`].
+///
+/// [`TextViewState::set_collect_diagnostics`]: crate::text::TextViewState::set_collect_diagnostics
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// The detail of the warning, e.g.: the tag name or the stripped url.
+    pub message: SharedString,
+    /// The byte range in the source, `None` if unknown (e.g.: the HTML has no source positions).
+    pub span: Option<Span>,
+}
 
-    pub(crate) fn merge(&mut self, other: Self) {
-        self.children.extend(other.children);
-    }
+/// The kind of the [`Diagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// The tag is not supported, the children are kept as the inline content.
+    UnsupportedTag,
+    /// The `<img>` has no `src` attribute, it is skipped.
+    ImageMissingSrc,
+    /// The `style` attribute has the invalid declarations, they are skipped.
+    MalformedStyle,
+    /// The link or image url with the disallowed scheme (e.g. `javascript:`) is stripped in
+    /// sanitize mode.
+    DangerousHrefStripped,
 }
 
-#[derive(Debug, Clone)]
-pub struct CodeBlock {
-    lang: Option<SharedString>,
-    styles: Vec<(Range<usize>, HighlightStyle)>,
-    state: Arc<Mutex<InlineState>>,
-    pub span: Option<Span>,
+/// A provider of the embedded content, recognized by the host of the `<iframe>` src, see
+/// [`TextViewState::register_embed_provider`].
+///
+/// [`TextViewState::register_embed_provider`]: crate::text::TextViewState::register_embed_provider
+#[derive(Clone)]
+pub struct EmbedProvider {
+    name: SharedString,
+    hosts: Vec<SharedString>,
+    resolve: Option<Arc<EmbedResolveFn>>,
 }
 
-impl PartialEq for CodeBlock {
-    fn eq(&self, other: &Self) -> bool {
-        self.lang == other.lang && self.styles == other.styles
+/// Resolve the embed url to the page url and the thumbnail url.
+type EmbedResolveFn = dyn Fn(&str) -> Option<(String, Option<String>)> + Send + Sync;
+
+impl EmbedProvider {
+    /// Create a provider with the `hosts`, e.g.: `youtube.com`, the subdomains
+    /// (e.g.: `www.youtube.com`) are matched too.
+    pub fn new(
+        name: impl Into<SharedString>,
+        hosts: impl IntoIterator<Item = impl Into<SharedString>>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            hosts: hosts.into_iter().map(Into::into).collect(),
+            resolve: None,
+        }
     }
-}
 
-impl CodeBlock {
-    /// Get the language of the code block.
-    pub fn lang(&self) -> Option<SharedString> {
-        self.lang.clone()
+    /// Set the function to resolve the embed url to the page url and the thumbnail url,
+    /// by default the embed url is opened without thumbnail.
+    pub fn resolve(
+        mut self,
+        f: impl Fn(&str) -> Option<(String, Option<String>)> + Send + Sync + 'static,
+    ) -> Self {
+        self.resolve = Some(Arc::new(f));
+        self
     }
 
-    /// Get the code content of the code block.
-    pub fn code(&self) -> SharedString {
-        self.state.lock().unwrap().text.clone()
+    /// The built-in providers: YouTube and Vimeo.
+    pub(crate) fn builtin() -> Vec<Self> {
+        vec![
+            Self::new("YouTube", ["youtube.com", "youtube-nocookie.com"]).resolve(|url| {
+                let id = embed_path(url)?
+                    .strip_prefix("/embed/")?
+                    .split('/')
+                    .next()?;
+                Some((
+                    format!("https://www.youtube.com/watch?v={}", id),
+                    Some(format!("https://img.youtube.com/vi/{}/hqdefault.jpg", id)),
+                ))
+            }),
+            Self::new("Vimeo", ["vimeo.com"]).resolve(|url| {
+                let id = embed_path(url)?
+                    .strip_prefix("/video/")?
+                    .split('/')
+                    .next()?;
+                Some((format!("https://vimeo.com/{}", id), None))
+            }),
+        ]
     }
 
-    pub(crate) fn new(
-        code: SharedString,
-        lang: Option<SharedString>,
-        highlight_theme: &HighlightTheme,
-        span: Option<impl Into<Span>>,
-    ) -> Self {
-        let mut styles = vec![];
-        if let Some(lang) = &lang {
-            let mut highlighter = SyntaxHighlighter::new(&lang);
-            highlighter.update(None, &Rope::from_str(code.as_str()), None);
-            styles = highlighter.styles(&(0..code.len()), highlight_theme);
+    fn matches(&self, url: &str) -> bool {
+        let Some(host) = embed_host(url) else {
+            return false;
         };
+        self.hosts.iter().any(|h| {
+            host == h.as_str()
+                || host
+                    .strip_suffix(h.as_str())
+                    .is_some_and(|sub| sub.ends_with('.'))
+        })
+    }
+}
 
-        let state = Arc::new(Mutex::new(InlineState::default()));
-        state.lock().unwrap().set_text(code);
+/// Convert the `<iframe>` src to an [`EmbedNode`] with the first matched provider, the unknown
+/// src is kept as a generic embedded content.
+pub(crate) fn embed_node(
+    src: &str,
+    title: Option<SharedString>,
+    providers: &[EmbedProvider],
+) -> EmbedNode {
+    // The protocol-relative url, e.g.: `//www.youtube.com/embed/id`.
+    let src = match src.strip_prefix("//") {
+        Some(rest) => format!("https://{}", rest),
+        None => src.to_string(),
+    };
 
-        Self {
-            lang,
-            styles,
-            state,
-            span: span.map(|s| s.into()),
-        }
-    }
+    let provider = providers.iter().find(|provider| provider.matches(&src));
+    let (url, thumbnail) = provider
+        .and_then(|provider| provider.resolve.as_ref())
+        .and_then(|resolve| resolve(&src))
+        .unwrap_or_else(|| (src.clone(), None));
 
-    pub(super) fn selected_text(&self) -> String {
-        let mut text = String::new();
-        let state = self.state.lock().unwrap();
-        if let Some(selection) = &state.selection {
-            let part_text = state.text.clone();
-            text.push_str(&part_text[selection.start..selection.end]);
-        }
-        text
+    EmbedNode {
+        url: url.into(),
+        provider: provider.map(|provider| provider.name.clone()),
+        title: title.filter(|title| !title.trim().is_empty()),
+        thumbnail: thumbnail.map(Into::into),
+        span: None,
     }
+}
 
-    fn render(
-        &self,
-        options: &NodeRenderOptions,
-        node_cx: &NodeContext,
-        window: &mut Window,
-        cx: &mut App,
-    ) -> AnyElement {
-        let style = &node_cx.style;
+/// Returns the lowercase host of the `url` without port.
+fn embed_host(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit('@').next()?.split(':').next()?;
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
 
-        div()
-            .when(!options.is_last, |this| this.pb(style.paragraph_gap))
-            .child(
-                div()
-                    .id(("codeblock", options.ix))
-                    .p_3()
-                    .rounded(cx.theme().radius)
-                    .bg(cx.theme().muted)
-                    .font_family(cx.theme().mono_font_family.clone())
-                    .text_size(cx.theme().mono_font_size)
-                    .relative()
-                    .refine_style(&style.code_block)
-                    .child(Inline::new(
-                        "code",
-                        self.state.clone(),
-                        vec![],
-                        self.styles.clone(),
-                    ))
-                    .when_some(node_cx.code_block_actions.clone(), |this, actions| {
-                        this.child(
-                            div()
-                                .id("actions")
-                                .absolute()
-                                .top_2()
-                                .right_2()
-                                .bg(cx.theme().muted)
-                                .rounded(cx.theme().radius)
-                                .child(actions(&self, window, cx)),
-                        )
-                    }),
-            )
-            .into_any_element()
-    }
+/// Returns the path of the `url` without query and fragment.
+fn embed_path(url: &str) -> Option<&str> {
+    let rest = url.split_once("://")?.1;
+    let path = &rest[rest.find('/')?..];
+    path.split(['?', '#']).next()
 }
 
-/// A context for rendering nodes, contains link references.
-#[derive(Default, Clone)]
-pub(crate) struct NodeContext {
-    /// The byte offset of the node in the original markdown text.
-    /// Used for incremental updates.
-    pub(crate) offset: usize,
-    pub(crate) link_refs: HashMap<SharedString, LinkMark>,
-    pub(crate) style: TextViewStyle,
-    pub(crate) code_block_actions: Option<Arc<CodeBlockActionsFn>>,
+/// The layout of an image with absolute width or height.
+///
+/// The image fills the container width up to its own `width`, and the height follows the
+/// `aspect_ratio`, e.g.: a 2000x1000 image is shown at 400x200 in a 400px column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ImageFit {
+    /// The max width of the image.
+    pub(crate) width: Pixels,
+    /// The width / height of the image.
+    pub(crate) aspect_ratio: f32,
 }
 
-impl NodeContext {
-    pub(super) fn add_ref(&mut self, identifier: SharedString, link: LinkMark) {
-        self.link_refs.insert(identifier, link);
+impl ImageFit {
+    /// Scale down the image to fit in the `max_width` and `max_height` with the aspect ratio,
+    /// the zero or None means unconstrained.
+    pub(crate) fn clamp(mut self, max_width: Option<Pixels>, max_height: Option<Pixels>) -> Self {
+        if let Some(max_width) = clamped_width(self.aspect_ratio, max_width, max_height) {
+            self.width = self.width.min(max_width);
+        }
+        self
     }
 }
 
-impl PartialEq for NodeContext {
-    fn eq(&self, other: &Self) -> bool {
-        self.link_refs == other.link_refs && self.style == other.style
-        // Note: code_block_buttons is intentionally not compared (closures can't be compared)
+/// The max width of an image with the `aspect_ratio` to fit in the `max_width` and `max_height`,
+/// None if unconstrained.
+fn clamped_width(
+    aspect_ratio: f32,
+    max_width: Option<Pixels>,
+    max_height: Option<Pixels>,
+) -> Option<Pixels> {
+    let max_width = max_width.filter(|width| *width > px(0.));
+    let max_height = max_height
+        .filter(|height| *height > px(0.))
+        .map(|height| height * aspect_ratio);
+    match (max_width, max_height) {
+        (Some(width), Some(height)) => Some(width.min(height)),
+        (width, height) => width.or(height),
     }
 }
 
-impl Paragraph {
-    fn render(
-        &self,
-        node_cx: &NodeContext,
-        _window: &mut Window,
-        cx: &mut App,
-    ) -> impl IntoElement {
-        let span = self.span;
-        let children = &self.children;
-
-        let mut child_nodes: Vec<AnyElement> = vec![];
-
-        let mut text = String::new();
-        let mut highlights: Vec<(Range<usize>, HighlightStyle)> = vec![];
-        let mut links: Vec<(Range<usize>, LinkMark)> = vec![];
+impl ImageNode {
+    /// Converts the image to `<img>` tag, wrapped with `<a>` if it has a link.
+    fn to_html(&self) -> String {
+        let mut attrs = format!(r#"src="{}""#, escape_html(&self.url));
+        if let Some(class) = &self.class {
+            attrs.push_str(&format!(r#" class="{}""#, escape_html(class)));
+        }
+        if let Some(alt) = &self.alt {
+            attrs.push_str(&format!(r#" alt="{}""#, escape_html(alt)));
+        }
+        if let Some(title) = &self.title {
+            attrs.push_str(&format!(r#" title="{}""#, escape_html(title)));
+        }
+        let length_value = |length: Option<DefiniteLength>| match length {
+            Some(DefiniteLength::Absolute(AbsoluteLength::Pixels(value))) => {
+                Some(format!("{}px", value.as_f32()))
+            }
+            Some(DefiniteLength::Absolute(AbsoluteLength::Rems(value))) => {
+                Some(format!("{}rem", value.0))
+            }
+            Some(DefiniteLength::Fraction(value)) => Some(format!("{}%", value * 100.)),
+            None => None,
+        };
+        // The `width` and `height` attributes are in pixels, other units are kept in the style.
+        let mut styles = vec![];
+        for (name, length) in [("width", self.width), ("height", self.height)] {
+            if let Some(DefiniteLength::Absolute(AbsoluteLength::Pixels(value))) = length {
+                attrs.push_str(&format!(r#" {}="{}""#, name, value.as_f32()));
+            } else if let Some(value) = length_value(length) {
+                styles.push(format!("{}: {}", name, value));
+            }
+        }
+        for (name, length) in [
+            ("max-width", self.max_width),
+            ("max-height", self.max_height),
+        ] {
+            if let Some(value) = length_value(length) {
+                styles.push(format!("{}: {}", name, value));
+            }
+        }
+        if !styles.is_empty() {
+            attrs.push_str(&format!(r#" style="{}""#, styles.join("; ")));
+        }
+        if self.loading == ImageLoading::Lazy {
+            attrs.push_str(r#" loading="lazy""#);
+        }
+
+        let html = format!("<img {}>", attrs);
+        match &self.link {
+            Some(link) => TextMark::default().link(link.clone()).wrap_html(&html),
+            None => html,
+        }
+    }
+}
+
+impl PartialEq for ImageNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url
+            && self.link == other.link
+            && self.title == other.title
+            && self.alt == other.alt
+            && self.width == other.width
+            && self.height == other.height
+            && self.max_width == other.max_width
+            && self.max_height == other.max_height
+            && self.class == other.class
+            && self.loading == other.loading
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InlineNode {
+    /// The text content.
+    pub text: SharedString,
+    /// The inline image, the `text` is empty if this is an image.
+    pub image: Option<ImageNode>,
+    /// The text styles, each tuple contains the range of the text and the style.
+    pub marks: Vec<(Range<usize>, TextMark)>,
+    /// Whether this node is a hard line break (e.g.: `<br>`), not a text of a newline.
+    #[cfg_attr(feature = "serde", serde(default))]
+    hard_break: bool,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    state: Arc<Mutex<InlineState>>,
+}
+
+impl PartialEq for InlineNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+            && self.image == other.image
+            && self.marks == other.marks
+            && self.hard_break == other.hard_break
+    }
+}
+
+/// The text of a hard line break inside a paragraph.
+const HARD_BREAK: &str = "\n";
+
+impl InlineNode {
+    pub(crate) fn new(text: impl Into<SharedString>) -> Self {
+        Self {
+            text: text.into(),
+            image: None,
+            marks: vec![],
+            hard_break: false,
+            state: Arc::new(Mutex::new(InlineState::default())),
+        }
+    }
+
+    pub(crate) fn image(image: ImageNode) -> Self {
+        let mut this = Self::new("");
+        this.image = Some(image);
+        this
+    }
+
+    pub(crate) fn marks(mut self, marks: Vec<(Range<usize>, TextMark)>) -> Self {
+        self.marks = marks;
+        self
+    }
+
+    /// Return the non-empty marks sorted for composing, the outer marks come first.
+    fn sorted_marks(&self) -> Vec<&(Range<usize>, TextMark)> {
+        let mut marks = self
+            .marks
+            .iter()
+            .filter(|(range, mark)| !range.is_empty() && *mark != TextMark::default())
+            .collect::<Vec<_>>();
+        // Outer marks first, so the nested marks are composed inside them.
+        // For the same range, the later pushed mark comes from the outer node.
+        marks.reverse();
+        marks.sort_by(|(a, _), (b, _)| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+        marks
+    }
+
+    /// Return true if this node is the inline math, e.g.: `$E = mc^2$`.
+    pub(crate) fn is_math(&self) -> bool {
+        !self.text.is_empty()
+            && self
+                .marks
+                .iter()
+                .any(|(range, mark)| mark.math && range.len() == self.text.len())
+    }
+
+    /// Return true if this node is a hard line break, see [`Paragraph::push_break`].
+    pub(crate) fn is_hard_break(&self) -> bool {
+        self.hard_break
+    }
+}
+
+/// The paragraph element, contains multiple text nodes.
+///
+/// Unlike other Element, this is cloneable, because it is used in the Node AST.
+/// We are keep the selection state inside this AST Nodes.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Paragraph {
+    pub(super) span: Option<Span>,
+    pub(super) children: Vec<InlineNode>,
+    /// The link references in this paragraph, used for reference links.
+    ///
+    /// The key is the identifier, the value is the url.
+    pub(super) link_refs: HashMap<SharedString, SharedString>,
+    /// The text alignment, `None` is left aligned.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) align: Option<TextAlign>,
+    /// The text direction, `None` is left-to-right.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) dir: Option<TextDirection>,
+    /// The whitespace handling, `None` is collapsed.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) white_space: Option<WhiteSpace>,
+    /// The semantic block tag kept around the paragraph, e.g.: `<address>`, the text is styled by
+    /// the marks of the tag.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) semantic: Option<SemanticTag>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) state: Arc<Mutex<InlineState>>,
+}
+
+impl PartialEq for Paragraph {
+    fn eq(&self, other: &Self) -> bool {
+        self.span == other.span
+            && self.children == other.children
+            && self.link_refs == other.link_refs
+            && self.align == other.align
+            && self.dir == other.dir
+            && self.white_space == other.white_space
+            && self.semantic == other.semantic
+    }
+}
+
+impl Paragraph {
+    pub(crate) fn new(text: String) -> Self {
+        Self {
+            span: None,
+            children: vec![InlineNode::new(&text)],
+            link_refs: HashMap::new(),
+            align: None,
+            dir: None,
+            white_space: None,
+            semantic: None,
+            state: Arc::new(Mutex::new(InlineState::default())),
+        }
+    }
+
+    /// The `dir` and `style` attributes of the text direction, alignment and whitespace in HTML.
+    fn html_attrs(&self) -> String {
+        let mut attrs = String::new();
+        if let Some(dir) = self.dir {
+            attrs.push_str(&format!(r#" dir="{}""#, dir.as_str()));
+        }
+
+        let mut styles = vec![];
+        if let Some(align) = self.align {
+            styles.push(format!("text-align: {}", align.as_str()));
+        }
+        if let Some(white_space) = self.white_space {
+            styles.push(format!("white-space: {}", white_space.as_str()));
+        }
+        if !styles.is_empty() {
+            attrs.push_str(&format!(r#" style="{}""#, styles.join("; ")));
+        }
+        attrs
+    }
+
+    /// Whether the paragraph is right-to-left.
+    pub(super) fn is_rtl(&self) -> bool {
+        self.dir == Some(TextDirection::Rtl)
+    }
+
+    pub(super) fn selected_text(&self) -> String {
+        let mut text = String::new();
+
+        for c in self.children.iter() {
+            if let Some(part_text) = c.state.lock().unwrap().selected_text() {
+                text.push_str(&part_text);
+            }
+        }
+
+        if let Some(all_text) = self.state.lock().unwrap().selected_text() {
+            text.push_str(&all_text);
+        }
+
+        text
+    }
+
+    /// Collect the text split by the images, the same as the [`Inline`] elements on render, as
+    /// one group.
+    fn text_segments(&self, segments: &mut Vec<Vec<(Arc<Mutex<InlineState>>, SharedString)>>) {
+        let mut paragraph_segments = vec![];
+        let mut text = String::new();
+        for inline_node in self.children.iter() {
+            text.push_str(&inline_node.text);
+            if inline_node.image.is_some() {
+                if !text.is_empty() {
+                    paragraph_segments
+                        .push((inline_node.state.clone(), std::mem::take(&mut text).into()));
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            paragraph_segments.push((self.state.clone(), text.into()));
+        }
+        if !paragraph_segments.is_empty() {
+            segments.push(paragraph_segments);
+        }
+    }
+
+    /// Collect the links split by the images, the same as the links of the [`Inline`] elements
+    /// on render, the adjacent ranges of the same link (e.g.: partly bold) are merged.
+    fn link_segments(
+        &self,
+        node_cx: &NodeContext,
+        segments: &mut Vec<(Arc<Mutex<InlineState>>, Range<usize>, LinkMark)>,
+    ) {
+        let mut links: Vec<(Range<usize>, LinkMark)> = vec![];
+        let mut offset = 0;
+        for inline_node in self.children.iter() {
+            if inline_node.image.is_some() {
+                for (range, link) in links.drain(..) {
+                    segments.push((inline_node.state.clone(), range, link));
+                }
+                offset = 0;
+                continue;
+            }
+
+            for (range, mark) in inline_node.sorted_marks() {
+                // Resolve the link references the same as on render.
+                let Some(link) = mark
+                    .link
+                    .clone()
+                    .and_then(|link| node_cx.resolve_link(link))
+                else {
+                    continue;
+                };
+
+                let range = (offset + range.start)..(offset + range.end);
+                match links.last_mut() {
+                    Some((last, last_link)) if last.end == range.start && *last_link == link => {
+                        last.end = range.end;
+                    }
+                    _ => links.push((range, link)),
+                }
+            }
+            offset += inline_node.text.len();
+        }
+
+        for (range, link) in links {
+            segments.push((self.state.clone(), range, link));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Table {
+    pub(crate) children: Vec<TableRow>,
+    pub(crate) column_aligns: Vec<ColumnumnAlign>,
+    /// The column widths from the `<colgroup>` and `<col>` of HTML, the width of the cells
+    /// overrides it.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::text::serde_helpers::option_lengths")
+    )]
+    pub(crate) column_widths: Vec<Option<DefiniteLength>>,
+    pub(crate) span: Option<Span>,
+}
+
+impl Table {
+    pub(crate) fn column_align(&self, index: usize) -> ColumnumnAlign {
+        self.column_aligns.get(index).copied().unwrap_or_default()
+    }
+
+    /// The declared width of the column, `None` if it is not declared by `<col>`.
+    pub(crate) fn column_width(&self, index: usize) -> Option<DefiniteLength> {
+        self.column_widths.get(index).copied().flatten()
+    }
+
+    /// The number of the leading header rows.
+    pub(crate) fn header_len(&self) -> usize {
+        self.children.iter().take_while(|row| row.is_header).count()
+    }
+
+    /// The number of the trailing footer rows.
+    pub(crate) fn footer_len(&self) -> usize {
+        self.children
+            .iter()
+            .rev()
+            .take_while(|row| row.is_footer)
+            .count()
+    }
+
+    /// Converts the table to HTML, the header rows are in `<thead>` and the footer rows are in
+    /// `<tfoot>`.
+    fn to_html(&self) -> String {
+        let header_len = self.header_len();
+        let footer_start = self.children.len() - self.footer_len();
+        let mut out = String::from("<table>\n");
+        for (row_ix, row) in self.children.iter().enumerate() {
+            let tag = if row_ix < header_len { "th" } else { "td" };
+            if row_ix == 0 && header_len > 0 {
+                out.push_str("<thead>\n");
+            }
+            if row_ix == header_len && row_ix < footer_start {
+                out.push_str("<tbody>\n");
+            }
+            if row_ix == footer_start {
+                if footer_start > header_len {
+                    out.push_str("</tbody>\n");
+                }
+                out.push_str("<tfoot>\n");
+            }
+
+            out.push_str("<tr>\n");
+            for (ix, cell) in row.children.iter().enumerate() {
+                let align = match self.column_align(ix) {
+                    ColumnumnAlign::Left => "",
+                    ColumnumnAlign::Center => r#" align="center""#,
+                    ColumnumnAlign::Right => r#" align="right""#,
+                };
+                out.push_str(&format!("<{tag}{align}>{}</{tag}>\n", cell.to_html()));
+            }
+            out.push_str("</tr>\n");
+
+            if row_ix + 1 == header_len {
+                out.push_str("</thead>\n");
+            }
+        }
+        if footer_start < self.children.len() {
+            out.push_str("</tfoot>\n");
+        } else if self.children.len() > header_len {
+            out.push_str("</tbody>\n");
+        }
+        out.push_str("</table>");
+        out
+    }
+
+    /// Converts the table to a HTML table with Markdown cells.
+    ///
+    /// This is used when some cells contain block nodes (e.g. List, CodeBlock).
+    fn to_html_markdown(&self) -> String {
+        let mut out = String::from("<table>\n");
+        for (row_ix, row) in self.children.iter().enumerate() {
+            let tag = if row_ix == 0 { "th" } else { "td" };
+            out.push_str("<tr>\n");
+            for cell in row.children.iter() {
+                out.push_str(&format!("<{tag}>\n\n{}\n\n</{tag}>\n", cell.to_markdown()));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>");
+        out
+    }
+
+    /// The selected text of the cells in the rows with a selection, each row is padded to the
+    /// columns covered by the selection, so the partial rows keep the columns aligned.
+    ///
+    /// Returns the columns range and the rows.
+    fn selected_cells(&self) -> (Range<usize>, Vec<Vec<String>>) {
+        let rows = self
+            .children
+            .iter()
+            .map(|row| {
+                row.children
+                    .iter()
+                    .map(|cell| cell.selected_text().trim().replace(['\t', '\n'], " "))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cells| cells.iter().any(|text| !text.is_empty()))
+            .collect::<Vec<_>>();
+
+        let start = rows
+            .iter()
+            .filter_map(|cells| cells.iter().position(|text| !text.is_empty()))
+            .min()
+            .unwrap_or(0);
+        let end = rows
+            .iter()
+            .filter_map(|cells| cells.iter().rposition(|text| !text.is_empty()))
+            .max()
+            .map_or(start, |ix| ix + 1);
+
+        let rows = rows
+            .into_iter()
+            .map(|cells| {
+                (start..end)
+                    .map(|ix| cells.get(ix).cloned().unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+        (start..end, rows)
+    }
+
+    /// The selected cells as tab-separated values, one line per row.
+    fn selected_tsv(&self) -> String {
+        self.selected_cells()
+            .1
+            .iter()
+            .map(|cells| cells.join("\t"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The selected cells as a Markdown table, the first selected row is the header.
+    fn selected_markdown(&self) -> String {
+        let (cols, rows) = self.selected_cells();
+        if rows.is_empty() {
+            return String::new();
+        }
+
+        // With the leading and trailing pipes, the empty cells of the partial rows are kept.
+        let row_markdown = |cells: &Vec<String>| {
+            let cells = cells
+                .iter()
+                .map(|text| text.replace('|', "\\|"))
+                .collect::<Vec<_>>();
+            format!("| {} |", cells.join(" | "))
+        };
+        let alignments = cols
+            .map(|ix| match self.column_align(ix) {
+                ColumnumnAlign::Left => ":--",
+                ColumnumnAlign::Center => ":-:",
+                ColumnumnAlign::Right => "--:",
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+
+        let mut lines = vec![row_markdown(&rows[0]), format!("| {} |", alignments)];
+        lines.extend(rows[1..].iter().map(row_markdown));
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) enum ColumnumnAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// The text alignment of the paragraph or heading, from the `align` attribute or the `text-align`
+/// style of HTML.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub(crate) enum TextAlign {
+    Left,
+    Center,
+    Right,
+    /// Rendered as [`TextAlign::Left`], the text layout does not support distributing the space
+    /// between words.
+    Justify,
+}
+
+impl TextAlign {
+    /// Parse the `align` attribute or the `text-align` style, e.g.: `center`, `right`.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "left" | "start" => Some(Self::Left),
+            "center" => Some(Self::Center),
+            "right" | "end" => Some(Self::Right),
+            "justify" => Some(Self::Justify),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Left => "left",
+            Self::Center => "center",
+            Self::Right => "right",
+            Self::Justify => "justify",
+        }
+    }
+}
+
+/// The text direction of the paragraph or heading, from the `dir` attribute or the `direction`
+/// style of HTML.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub(crate) enum TextDirection {
+    Ltr,
+    Rtl,
+}
+
+impl TextDirection {
+    /// Parse the `dir` attribute or the `direction` style, `auto` is detected by the `text`.
+    pub(crate) fn parse(value: &str, text: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ltr" => Some(Self::Ltr),
+            "rtl" => Some(Self::Rtl),
+            "auto" => Self::detect(text),
+            _ => None,
+        }
+    }
+
+    /// Detect the direction by the first strong directional char of the `text`.
+    pub(crate) fn detect(text: &str) -> Option<Self> {
+        text.chars().find_map(|c| {
+            if is_rtl_char(c) {
+                Some(Self::Rtl)
+            } else if c.is_alphabetic() {
+                Some(Self::Ltr)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ltr => "ltr",
+            Self::Rtl => "rtl",
+        }
+    }
+}
+
+/// The whitespace handling of the paragraph, from the `white-space` style of HTML, `None` is
+/// `normal` that the whitespace is collapsed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub(crate) enum WhiteSpace {
+    /// Keep the newlines and spaces, the lines are not wrapped and scrolled horizontally.
+    Pre,
+    /// Keep the newlines and spaces, the lines are wrapped at the box edge.
+    PreWrap,
+}
+
+impl WhiteSpace {
+    /// Parse the `white-space` style, returns `None` for the collapsed values, e.g.: `normal`.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "pre" => Some(Self::Pre),
+            "pre-wrap" | "break-spaces" => Some(Self::PreWrap),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pre => "pre",
+            Self::PreWrap => "pre-wrap",
+        }
+    }
+}
+
+/// Whether the char is in the right-to-left scripts, e.g.: Hebrew, Arabic, Syriac, Thaana.
+fn is_rtl_char(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0590}'..='\u{08FF}'
+            | '\u{FB1D}'..='\u{FDFF}'
+            | '\u{FE70}'..='\u{FEFF}'
+            | '\u{10800}'..='\u{10FFF}'
+            | '\u{1E800}'..='\u{1EFFF}'
+    )
+}
+
+/// The marker type of the ordered list, from the `type` attribute of `<ol>`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ListMarker {
+    /// `1`, `2`, `3`
+    Decimal,
+    /// `a`, `b`, `c`
+    LowerAlpha,
+    /// `A`, `B`, `C`
+    UpperAlpha,
+    /// `i`, `ii`, `iii`
+    LowerRoman,
+    /// `I`, `II`, `III`
+    UpperRoman,
+}
+
+impl ListMarker {
+    /// Parse the `type` attribute of `<ol>`, e.g.: `1`, `a`, `A`, `i`, `I`.
+    pub(crate) fn from_type_attr(value: &str) -> Option<Self> {
+        match value.trim() {
+            "1" => Some(Self::Decimal),
+            "a" => Some(Self::LowerAlpha),
+            "A" => Some(Self::UpperAlpha),
+            "i" => Some(Self::LowerRoman),
+            "I" => Some(Self::UpperRoman),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the `type` attribute of `<ol>`.
+    pub(crate) fn type_attr(&self) -> &'static str {
+        match self {
+            Self::Decimal => "1",
+            Self::LowerAlpha => "a",
+            Self::UpperAlpha => "A",
+            Self::LowerRoman => "i",
+            Self::UpperRoman => "I",
+        }
+    }
+
+    /// Format the item number with the marker type, e.g.: `3` -> `c`.
+    pub(crate) fn format(&self, number: usize) -> String {
+        match self {
+            Self::LowerAlpha if number > 0 => alpha_number(number, false),
+            Self::UpperAlpha if number > 0 => alpha_number(number, true),
+            Self::LowerRoman => roman_number(number, false),
+            Self::UpperRoman => roman_number(number, true),
+            _ => number.to_string(),
+        }
+    }
+}
+
+/// The style of [`BlockNode::HorizontalRule`], from the `border` styles of `<hr>`, e.g.:
+/// `border-top: 2px dashed #ccc`.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuleStyle {
+    /// The thickness of the line, the theme divider thickness if None.
+    pub thickness: Option<Pixels>,
+    /// Whether the line is dashed (or dotted), default is solid.
+    pub dashed: bool,
+    /// The color of the line, the theme `border` color if None.
+    pub color: Option<Hsla>,
+}
+
+impl RuleStyle {
+    /// Returns the `style` attribute of `<hr>`, None for the default style.
+    fn to_css(&self) -> Option<String> {
+        if *self == Self::default() {
+            return None;
+        }
+
+        let mut values = vec![];
+        if let Some(thickness) = self.thickness {
+            values.push(format!("{}px", thickness.as_f32()));
+        }
+        values.push(if self.dashed { "dashed" } else { "solid" }.to_string());
+        if let Some(color) = self.color {
+            let rgb = color.to_rgb();
+            let hex = |value: f32| format!("{:02x}", (value * 255.).round() as u8);
+            values.push(format!("#{}{}{}", hex(rgb.r), hex(rgb.g), hex(rgb.b)));
+            if rgb.a < 1. {
+                values.last_mut().unwrap().push_str(&hex(rgb.a));
+            }
+        }
+        Some(format!("border-top: {}", values.join(" ")))
+    }
+}
+
+/// The kind of [`BlockNode::Admonition`], see GitHub's alert syntax.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AdmonitionKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl AdmonitionKind {
+    /// Parse the alert marker at the start of the text, e.g.: `[!NOTE]`, case-insensitive.
+    ///
+    /// Returns the kind and the byte length of the marker line (including the newline).
+    fn parse_marker(text: &str) -> Option<(Self, usize)> {
+        let offset = text.len() - text.trim_start().len();
+        let rest = text[offset..].strip_prefix("[!")?;
+        let close = rest.find(']')?;
+        let kind = match rest[..close].to_ascii_uppercase().as_str() {
+            "NOTE" => Self::Note,
+            "TIP" => Self::Tip,
+            "IMPORTANT" => Self::Important,
+            "WARNING" => Self::Warning,
+            "CAUTION" => Self::Caution,
+            _ => return None,
+        };
+
+        // The marker must be on its own line.
+        let after = &rest[close + 1..];
+        let line_len = after.find('\n').map_or(after.len(), |ix| ix + 1);
+        if !after[..line_len].trim().is_empty() {
+            return None;
+        }
+
+        Some((kind, offset + "[!".len() + close + 1 + line_len))
+    }
+
+    /// Returns the marker name, e.g.: `NOTE`.
+    pub(crate) fn marker(&self) -> &'static str {
+        match self {
+            Self::Note => "NOTE",
+            Self::Tip => "TIP",
+            Self::Important => "IMPORTANT",
+            Self::Warning => "WARNING",
+            Self::Caution => "CAUTION",
+        }
+    }
+
+    /// Returns the title to display, e.g.: `Note`.
+    pub(crate) fn title(&self) -> &'static str {
+        match self {
+            Self::Note => "Note",
+            Self::Tip => "Tip",
+            Self::Important => "Important",
+            Self::Warning => "Warning",
+            Self::Caution => "Caution",
+        }
+    }
+
+    fn icon(&self) -> IconName {
+        match self {
+            Self::Note => IconName::Info,
+            Self::Tip => IconName::Star,
+            Self::Important => IconName::Bell,
+            Self::Warning => IconName::TriangleAlert,
+            Self::Caution => IconName::CircleX,
+        }
+    }
+
+    fn color(&self, cx: &App) -> Hsla {
+        match self {
+            Self::Note => cx.theme().info,
+            Self::Tip => cx.theme().success,
+            Self::Important => cx.theme().primary,
+            Self::Warning => cx.theme().warning,
+            Self::Caution => cx.theme().danger,
+        }
+    }
+}
+
+impl From<mdast::AlignKind> for ColumnumnAlign {
+    fn from(value: mdast::AlignKind) -> Self {
+        match value {
+            mdast::AlignKind::None => ColumnumnAlign::Left,
+            mdast::AlignKind::Left => ColumnumnAlign::Left,
+            mdast::AlignKind::Center => ColumnumnAlign::Center,
+            mdast::AlignKind::Right => ColumnumnAlign::Right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct TableRow {
+    pub children: Vec<TableCell>,
+    /// Whether the row is a header row, from `<thead>` or the first row of the Markdown table.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub is_header: bool,
+    /// Whether the row is a footer row from `<tfoot>`, the footer rows are at the end.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub is_footer: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct TableCell {
+    /// The block nodes in the cell, most of the time it is a single paragraph.
+    pub children: Vec<BlockNode>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::text::serde_helpers::option_length")
+    )]
+    pub width: Option<DefiniteLength>,
+}
+
+impl TableCell {
+    /// Return the paragraph if the cell only contains a single paragraph.
+    pub(crate) fn paragraph(&self) -> Option<&Paragraph> {
+        match self.children.as_slice() {
+            [BlockNode::Paragraph(paragraph)] => Some(paragraph),
+            _ => None,
+        }
+    }
+
+    /// Return true if the cell can be represented as inline content (e.g. in a Markdown table).
+    pub(crate) fn is_inline(&self) -> bool {
+        self.children.is_empty() || self.paragraph().is_some()
+    }
+
+    /// Return length of the widest line in the cell.
+    pub(crate) fn text_len(&self) -> usize {
+        self.children
+            .iter()
+            .map(|child| child.text_len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn selected_text(&self) -> String {
+        if let Some(paragraph) = self.paragraph() {
+            return paragraph.selected_text();
+        }
+
+        self.children
+            .iter()
+            .map(|child| child.selected_text())
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+
+    fn to_markdown(&self) -> String {
+        self.children
+            .iter()
+            .map(|child| child.to_markdown())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn to_html(&self) -> String {
+        if let Some(paragraph) = self.paragraph() {
+            return paragraph.to_html();
+        }
+
+        self.children
+            .iter()
+            .map(|child| child.to_html())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn render(
+        &self,
+        checkbox_ix: usize,
+        node_cx: &NodeContext,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> AnyElement {
+        if let Some(paragraph) = self.paragraph() {
+            return paragraph.render(node_cx, window, cx).into_any_element();
+        }
+
+        let children_len = self.children.len();
+        let mut checkbox_ix = checkbox_ix;
+        v_flex()
+            .children(self.children.iter().enumerate().map(|(ix, child)| {
+                let options = NodeRenderOptions {
+                    ix,
+                    is_last: ix + 1 == children_len,
+                    checkbox_ix,
+                    ..Default::default()
+                };
+                checkbox_ix += child.checkbox_count();
+                child.render_block(options, node_cx, window, cx)
+            }))
+            .into_any_element()
+    }
+}
+
+impl Paragraph {
+    pub(crate) fn take(&mut self) -> Paragraph {
+        std::mem::replace(
+            self,
+            Paragraph {
+                span: None,
+                children: vec![],
+                link_refs: Default::default(),
+                align: None,
+                dir: None,
+                // Keep the whitespace handling for the following content of the same element.
+                white_space: self.white_space,
+                semantic: None,
+                state: Arc::new(Mutex::new(InlineState::default())),
+            },
+        )
+    }
+
+    pub(crate) fn is_image(&self) -> bool {
+        false
+    }
+
+    pub(crate) fn set_span(&mut self, span: Span) {
+        self.span = Some(span);
+    }
+
+    pub(crate) fn push_str(&mut self, text: &str) {
+        self.children.push(
+            InlineNode::new(text.to_string()).marks(vec![(0..text.len(), TextMark::default())]),
+        );
+    }
+
+    /// Push a hard line break, rendered as a newline in the paragraph.
+    pub(crate) fn push_break(&mut self) {
+        let mut node = InlineNode::new(HARD_BREAK);
+        node.hard_break = true;
+        self.children.push(node);
+    }
+
+    pub(crate) fn push(&mut self, text: InlineNode) {
+        self.children.push(text);
+    }
+
+    pub(crate) fn push_image(&mut self, image: ImageNode) {
+        self.children.push(InlineNode::image(image));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.children.is_empty()
+            || self
+                .children
+                .iter()
+                .all(|node| node.text.is_empty() && node.image.is_none())
+    }
+
+    /// Return length of children text.
+    pub(crate) fn text_len(&self) -> usize {
+        self.children
+            .iter()
+            .map(|node| node.text.len())
+            .sum::<usize>()
+    }
+
+    pub(crate) fn merge(&mut self, other: Self) {
+        self.children.extend(other.children);
+    }
+
+    /// Return the inline nodes of the paragraph.
+    pub fn children(&self) -> &[InlineNode] {
+        &self.children
+    }
+
+    /// Return the mutable inline nodes, e.g.: to remove the images in [`crate::text::VisitorMut`].
+    pub fn children_mut(&mut self) -> &mut Vec<InlineNode> {
+        &mut self.children
+    }
+
+    /// Return the text before the first image.
+    pub(super) fn leading_text(&self) -> String {
+        self.children
+            .iter()
+            .take_while(|node| node.image.is_none())
+            .map(|node| node.text.as_ref())
+            .collect()
+    }
+
+    /// Remove the first `len` bytes of the text, the marks are shifted.
+    pub(super) fn trim_start_bytes(&mut self, len: usize) {
+        let mut len = len;
+        while len > 0 && !self.children.is_empty() {
+            let node = &mut self.children[0];
+            if node.image.is_some() {
+                break;
+            }
+
+            if node.text.len() <= len {
+                len -= node.text.len();
+                self.children.remove(0);
+                continue;
+            }
+
+            node.text = node.text[len..].to_string().into();
+            node.marks = node
+                .marks
+                .drain(..)
+                .filter(|(range, _)| range.end > len)
+                .map(|(range, mark)| (range.start.saturating_sub(len)..range.end - len, mark))
+                .collect();
+            break;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        from = "crate::text::serde_helpers::CodeBlockData",
+        into = "crate::text::serde_helpers::CodeBlockData"
+    )
+)]
+pub struct CodeBlock {
+    lang: Option<SharedString>,
+    /// The full info string of the fence, e.g.: `rust,no_run title="example.rs"`.
+    info: Option<SharedString>,
+    /// The `key=value` attributes of the info string, e.g.: `title`, `hl_lines`.
+    attributes: HashMap<SharedString, SharedString>,
+    /// The flag words of the info string, e.g.: `no_run`.
+    flags: Vec<SharedString>,
+    /// The highlight tokens of the code, independent of the theme.
+    tokens: Arc<Vec<(Range<usize>, SharedString)>>,
+    /// The highlight styles mapped from the tokens, cached for the last rendered theme.
+    styles: Arc<Mutex<(Arc<HighlightTheme>, Vec<(Range<usize>, HighlightStyle)>)>>,
+    state: Arc<Mutex<InlineState>>,
+    pub span: Option<Span>,
+}
+
+impl PartialEq for CodeBlock {
+    fn eq(&self, other: &Self) -> bool {
+        self.lang == other.lang
+            && self.attributes == other.attributes
+            && self.flags == other.flags
+            && self.tokens == other.tokens
+    }
+}
+
+impl CodeBlock {
+    /// Get the language of the code block.
+    pub fn lang(&self) -> Option<SharedString> {
+        self.lang.clone()
+    }
+
+    /// Get the code content of the code block.
+    pub fn code(&self) -> SharedString {
+        self.state.lock().unwrap().text.clone()
+    }
+
+    /// Get the full info string of the fence, e.g.: `rust,no_run title="example.rs"`.
+    pub fn info(&self) -> Option<SharedString> {
+        self.info.clone().or_else(|| self.lang.clone())
+    }
+
+    /// Get the `key=value` attributes of the info string, the unknown attributes are kept.
+    pub fn attributes(&self) -> &HashMap<SharedString, SharedString> {
+        &self.attributes
+    }
+
+    /// Get the flag words of the info string, e.g.: `no_run`.
+    pub fn flags(&self) -> &[SharedString] {
+        &self.flags
+    }
+
+    /// Get the `title` attribute, e.g.: the file name, shown in the header.
+    pub fn title(&self) -> Option<SharedString> {
+        self.attributes
+            .get("title")
+            .filter(|title| !title.trim().is_empty())
+            .cloned()
+    }
+
+    /// Get the 1-based line numbers of the `hl_lines` attribute, e.g.: `hl_lines="1 3-5"`.
+    pub fn highlight_lines(&self) -> Vec<RangeInclusive<usize>> {
+        let Some(value) = self.attributes.get("hl_lines") else {
+            return vec![];
+        };
+
+        value
+            .split([' ', ','])
+            .filter_map(|part| {
+                let (start, end) = part.split_once('-').unwrap_or((part, part));
+                let start = start.trim().parse::<usize>().ok()?;
+                let end = end.trim().parse::<usize>().ok()?;
+                (start > 0 && start <= end).then_some(start..=end)
+            })
+            .collect()
+    }
+
+    /// Create the code block with the info string of the fence, e.g.: `rust,no_run title="example.rs"`.
+    pub(crate) fn with_info(
+        code: SharedString,
+        info: Option<SharedString>,
+        highlight_theme: &HighlightTheme,
+        span: Option<impl Into<Span>>,
+    ) -> Self {
+        let Some(info) = info.filter(|info| !info.trim().is_empty()) else {
+            return Self::new(code, None, highlight_theme, span);
+        };
+
+        let (lang, attributes, flags) = parse_code_info(&info);
+        let mut code_block = Self::new(code, lang, highlight_theme, span);
+        code_block.info = Some(info);
+        code_block.attributes = attributes;
+        code_block.flags = flags;
+        code_block
+    }
+
+    /// Set the `title` attribute, e.g.: from the `data-filename` of the HTML `<pre>`.
+    pub(crate) fn with_title(mut self, title: SharedString) -> Self {
+        // Quote the title in the info string to keep it for the Markdown output.
+        let quote = if title.contains('"') { '\'' } else { '"' };
+        let info = match self.info() {
+            Some(info) => format!("{} title={}{}{}", info, quote, title, quote),
+            None => format!("title={}{}{}", quote, title, quote),
+        };
+        self.info = Some(info.into());
+        self.attributes.insert("title".into(), title);
+        self
+    }
+
+    /// The byte ranges of the `hl_lines` in the code, each range includes the line break.
+    fn highlight_line_ranges(&self) -> Vec<Range<usize>> {
+        let lines = self.highlight_lines();
+        if lines.is_empty() {
+            return vec![];
+        }
+
+        let code = self.code();
+        let mut ranges: Vec<Range<usize>> = vec![];
+        let mut start = 0;
+        for (ix, line) in code.split_inclusive('\n').enumerate() {
+            let end = start + line.len();
+            if lines.iter().any(|lines| lines.contains(&(ix + 1))) {
+                match ranges.last_mut() {
+                    Some(last) if last.end == start => last.end = end,
+                    _ => ranges.push(start..end),
+                }
+            }
+            start = end;
+        }
+        ranges
+    }
+
+    pub(crate) fn new(
+        code: SharedString,
+        lang: Option<SharedString>,
+        highlight_theme: &HighlightTheme,
+        span: Option<impl Into<Span>>,
+    ) -> Self {
+        let mut tokens = vec![];
+        if let Some(lang) = &lang {
+            let mut highlighter = SyntaxHighlighter::new(&lang);
+            highlighter.update(None, &Rope::from_str(code.as_str()), None);
+            tokens = highlighter.tokens(&(0..code.len()));
+        };
+        let styles = if lang.is_some() {
+            token_styles(&(0..code.len()), &tokens, highlight_theme)
+        } else {
+            vec![]
+        };
+
+        let state = Arc::new(Mutex::new(InlineState::default()));
+        state.lock().unwrap().set_text(code);
+
+        Self {
+            lang,
+            info: None,
+            attributes: HashMap::new(),
+            flags: vec![],
+            tokens: Arc::new(tokens),
+            styles: Arc::new(Mutex::new((Arc::new(highlight_theme.clone()), styles))),
+            state,
+            span: span.map(|s| s.into()),
+        }
+    }
+
+    /// Get the highlighted runs of the code for the theme.
+    ///
+    /// The code is tokenized once when the code block is created, this only maps the
+    /// tokens to the styles of the theme, so it is cheap to switch the theme.
+    pub fn highlighted_runs(&self, theme: &HighlightTheme) -> Vec<(Range<usize>, HighlightStyle)> {
+        if self.lang.is_none() {
+            return vec![];
+        }
+
+        let len = self.state.lock().unwrap().text.len();
+        token_styles(&(0..len), &self.tokens, theme)
+    }
+
+    /// Get the highlighted runs for the theme, re-mapped only when the theme is changed.
+    fn themed_runs(&self, theme: &Arc<HighlightTheme>) -> Vec<(Range<usize>, HighlightStyle)> {
+        let mut styles = self.styles.lock().unwrap();
+        if !Arc::ptr_eq(&styles.0, theme) {
+            if styles.0 != *theme {
+                styles.1 = self.highlighted_runs(theme);
+            }
+            styles.0 = theme.clone();
+        }
+        styles.1.clone()
+    }
+
+    pub(super) fn selected_text(&self) -> String {
+        let mut text = String::new();
+        let state = self.state.lock().unwrap();
+        if let Some(selection) = &state.selection {
+            let part_text = state.text.clone();
+            text.push_str(&part_text[selection.start..selection.end]);
+        }
+        text
+    }
+
+    fn render(
+        &self,
+        options: &NodeRenderOptions,
+        node_cx: &NodeContext,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> AnyElement {
+        let style = &node_cx.style;
+        let title = self.title();
+        let actions = node_cx.code_block_actions.clone();
+        // The negative margin of the header to cover the padding, which may be refined by the style.
+        let (base_size, rem_size) = (window.text_style().font_size, window.rem_size());
+        let header_margin = |padding: Option<DefiniteLength>| {
+            -padding
+                .unwrap_or(CODE_BLOCK_PADDING.into())
+                .to_pixels(base_size, rem_size)
+        };
+
+        div()
+            .when(!options.is_last, |this| this.pb(style.paragraph_gap))
+            .child(
+                div()
+                    .id(("codeblock", options.ix))
+                    .p(CODE_BLOCK_PADDING)
+                    .rounded(cx.theme().radius)
+                    .bg(cx.theme().code_block)
+                    .text_color(cx.theme().code_block_foreground)
+                    .font_family(
+                        style
+                            .code_font_family
+                            .clone()
+                            .unwrap_or_else(|| cx.theme().mono_font_family.clone()),
+                    )
+                    .text_size(style.code_font_size.unwrap_or(cx.theme().mono_font_size))
+                    .relative()
+                    .refine_style(&style.code_block)
+                    .when_some(title.clone(), |this, title| {
+                        // The header is flush with the top of the block, over the padding.
+                        this.child(
+                            h_flex()
+                                .id("header")
+                                .ml(header_margin(style.code_block.padding.left))
+                                .mr(header_margin(style.code_block.padding.right))
+                                .mt(header_margin(style.code_block.padding.top))
+                                .mb_3()
+                                .px_3()
+                                .py_1p5()
+                                .gap_2()
+                                .justify_between()
+                                .rounded_t(cx.theme().radius)
+                                .border_b_1()
+                                .border_color(cx.theme().border)
+                                .bg(cx.theme().muted)
+                                .text_sm()
+                                .child(
+                                    h_flex()
+                                        .gap_2()
+                                        .min_w_0()
+                                        .child(div().truncate().child(title))
+                                        .when_some(self.lang.clone(), |this, lang| {
+                                            this.child(
+                                                div()
+                                                    .flex_shrink_0()
+                                                    .px_1p5()
+                                                    .rounded(cx.theme().radius.half())
+                                                    .bg(cx.theme().secondary)
+                                                    .text_xs()
+                                                    .text_color(cx.theme().muted_foreground)
+                                                    .child(lang),
+                                            )
+                                        }),
+                                )
+                                .when_some(actions.clone(), |this, actions| {
+                                    this.child(
+                                        div()
+                                            .id("actions")
+                                            .flex_shrink_0()
+                                            .child(actions(&self, window, cx)),
+                                    )
+                                }),
+                        )
+                    })
+                    .child(
+                        Inline::new(
+                            "code",
+                            self.state.clone(),
+                            vec![],
+                            self.themed_runs(&cx.theme().highlight_theme),
+                        )
+                        .line_highlights(self.highlight_line_ranges()),
+                    )
+                    .when_some(actions.filter(|_| title.is_none()), |this, actions| {
+                        this.child(
+                            div()
+                                .id("actions")
+                                .absolute()
+                                .top_2()
+                                .right_2()
+                                .bg(cx.theme().code_block)
+                                .rounded(cx.theme().radius)
+                                .child(actions(&self, window, cx)),
+                        )
+                    }),
+            )
+            .into_any_element()
+    }
+}
+
+/// Parse the info string of the fenced code block, e.g.: `rust,no_run title="example.rs"`.
+///
+/// The words are separated by the whitespace or comma, the first word is the language unless it
+/// is an attribute, the others are the `key=value` attributes (the value can be quoted) or flags.
+fn parse_code_info(
+    info: &str,
+) -> (
+    Option<SharedString>,
+    HashMap<SharedString, SharedString>,
+    Vec<SharedString>,
+) {
+    let mut words: Vec<String> = vec![];
+    let mut word = String::new();
+    let mut quote = None;
+    for c in info.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c.is_whitespace() || c == ',' => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            None => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    let mut lang = None;
+    let mut attributes = HashMap::new();
+    let mut flags = vec![];
+    for (ix, word) in words.into_iter().enumerate() {
+        match word.split_once('=') {
+            Some((key, value)) => {
+                attributes.insert(key.trim().to_string().into(), value.to_string().into());
+            }
+            None if ix == 0 => lang = Some(word.into()),
+            None => flags.push(word.into()),
+        }
+    }
+
+    (lang, attributes, flags)
+}
+
+/// The default reading speed for [`NodeContext::words_per_minute`].
+pub(crate) const DEFAULT_WORDS_PER_MINUTE: usize = 200;
+
+/// A context for rendering nodes, contains link references.
+#[derive(Clone)]
+pub(crate) struct NodeContext {
+    /// The byte offset of the node in the original markdown text.
+    /// Used for incremental updates.
+    pub(crate) offset: usize,
+    pub(crate) link_refs: HashMap<SharedString, LinkMark>,
+    pub(crate) style: TextViewStyle,
+    pub(crate) code_block_actions: Option<Arc<CodeBlockActionsFn>>,
+    /// The renderer of the display math, see [`TextView::math_renderer`].
+    ///
+    /// [`TextView::math_renderer`]: crate::text::TextView::math_renderer
+    pub(crate) math_renderer: Option<Arc<MathRenderFn>>,
+    /// The handler for the internal anchor links, set by [`TextViewState`] on render.
+    ///
+    /// [`TextViewState`]: crate::text::TextViewState
+    pub(crate) on_anchor_click: Option<Arc<AnchorClickFn>>,
+    /// The handler for clicking the images, see [`TextView::on_image_click`].
+    ///
+    /// [`TextView::on_image_click`]: crate::text::TextView::on_image_click
+    pub(crate) on_image_click: Option<Arc<ImageClickFn>>,
+    /// The handler for clicking the links, see [`TextView::on_link_click`].
+    ///
+    /// [`TextView::on_link_click`]: crate::text::TextView::on_link_click
+    pub(crate) on_link_click: Option<Arc<LinkClickFn>>,
+    /// The predicate to check if a link has been visited, see [`TextView::link_visited`].
+    ///
+    /// [`TextView::link_visited`]: crate::text::TextView::link_visited
+    pub(crate) link_visited: Option<Arc<LinkVisitedFn>>,
+    /// The handler for clicking the checkboxes of the task list items, set by [`TextViewState`]
+    /// on render if [`TextView::on_checkbox_toggle`] is set.
+    ///
+    /// [`TextViewState`]: crate::text::TextViewState
+    /// [`TextView::on_checkbox_toggle`]: crate::text::TextView::on_checkbox_toggle
+    pub(crate) on_checkbox_toggle: Option<Arc<CheckboxToggleFn>>,
+    /// The handler to record the link or image under the mouse on right click for the context menu,
+    /// set by [`TextViewState`] on render.
+    ///
+    /// [`TextViewState`]: crate::text::TextViewState
+    pub(crate) on_context_menu: Option<Arc<ContextMenuTargetFn>>,
+    /// Whether to sanitize the untrusted content, see [`TextViewState::set_sanitize`].
+    ///
+    /// [`TextViewState::set_sanitize`]: crate::text::TextViewState::set_sanitize
+    pub(crate) sanitize: bool,
+    /// The reading speed used for the reading time estimate, default is 200.
+    pub(crate) words_per_minute: usize,
+    /// The window viewport size to resolve the `vw` and `vh` lengths on parsing.
+    pub(crate) viewport_size: Option<Size<Pixels>>,
+    /// The providers to recognize the `<iframe>` on parsing, see [`EmbedProvider`].
+    pub(crate) embed_providers: Arc<Vec<EmbedProvider>>,
+    /// The resolver of the `@mentions` on parsing, the mentions are plain text if None.
+    pub(crate) mention_resolver: Option<Arc<MentionResolveFn>>,
+    /// The formatter of the `<time>` on render, the original text is kept if None, set by
+    /// [`TextViewState`] on render, see [`TextViewState::set_time_formatter`].
+    ///
+    /// [`TextViewState`]: crate::text::TextViewState
+    /// [`TextViewState::set_time_formatter`]: crate::text::TextViewState::set_time_formatter
+    pub(crate) time_formatter: Option<Arc<TimeFormatFn>>,
+    /// The renderer of the custom blocks, matches the HTML elements on parsing and renders them.
+    pub(crate) custom_block_renderer: Option<Arc<CustomBlockRenderer>>,
+    /// The collector of the [`Diagnostic`]s on parsing, None (the default) to skip.
+    pub(crate) diagnostics: Option<Arc<Mutex<Vec<Diagnostic>>>>,
+    /// Whether to play the animated images (e.g.: GIF), see [`TextView::animate_images`].
+    ///
+    /// [`TextView::animate_images`]: crate::text::TextView::animate_images
+    pub(crate) animate_images: bool,
+    /// Whether to include the hidden spoilers in the selected text, see
+    /// [`TextView::copy_hidden_spoilers`].
+    ///
+    /// [`TextView::copy_hidden_spoilers`]: crate::text::TextView::copy_hidden_spoilers
+    pub(crate) copy_hidden_spoilers: bool,
+    /// Whether to replace the `:shortcode:` with the Unicode emoji on parsing, see
+    /// [`TextViewState::set_emoji_shortcodes`].
+    ///
+    /// [`TextViewState::set_emoji_shortcodes`]: crate::text::TextViewState::set_emoji_shortcodes
+    pub(crate) emoji_shortcodes: bool,
+    /// Whether to copy the emoji as the `:shortcode:` in the selected text, see
+    /// [`TextView::copy_emoji_shortcodes`].
+    ///
+    /// [`TextView::copy_emoji_shortcodes`]: crate::text::TextView::copy_emoji_shortcodes
+    pub(crate) copy_emoji_shortcodes: bool,
+    /// Whether to keep the semantic tags (e.g.: `<cite>`) as the inline HTML in the Markdown on
+    /// parsing, see [`TextViewState::set_keep_semantic_tags`].
+    ///
+    /// [`TextViewState::set_keep_semantic_tags`]: crate::text::TextViewState::set_keep_semantic_tags
+    pub(crate) keep_semantic_tags: bool,
+    /// The max width of the rendered images, see [`TextView::max_image_width`].
+    ///
+    /// [`TextView::max_image_width`]: crate::text::TextView::max_image_width
+    pub(crate) max_image_width: Option<Pixels>,
+    /// The max height of the rendered images, see [`TextView::max_image_height`].
+    ///
+    /// [`TextView::max_image_height`]: crate::text::TextView::max_image_height
+    pub(crate) max_image_height: Option<Pixels>,
+    /// The state of the first paragraph of the document to render with the drop cap, see
+    /// [`TextViewStyle::drop_cap_lines`].
+    pub(crate) drop_cap_paragraph: Option<Arc<Mutex<InlineState>>>,
+    /// The level of the heading being parsed, the `em` lengths are resolved against its font
+    /// size, see [`NodeContext::em_size`].
+    pub(crate) heading_level: Option<u8>,
+    /// Whether a `vw` or `vh` length or a `<picture>` media query is resolved on parsing, so the
+    /// text is parsed again when the viewport is resized.
+    pub(crate) uses_viewport: Arc<AtomicBool>,
+}
+
+impl Default for NodeContext {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            link_refs: HashMap::new(),
+            style: TextViewStyle::default(),
+            code_block_actions: None,
+            math_renderer: None,
+            on_anchor_click: None,
+            on_image_click: None,
+            on_link_click: None,
+            link_visited: None,
+            on_checkbox_toggle: None,
+            on_context_menu: None,
+            sanitize: false,
+            words_per_minute: DEFAULT_WORDS_PER_MINUTE,
+            viewport_size: None,
+            embed_providers: Arc::new(EmbedProvider::builtin()),
+            mention_resolver: None,
+            time_formatter: None,
+            custom_block_renderer: None,
+            diagnostics: None,
+            animate_images: true,
+            copy_hidden_spoilers: false,
+            emoji_shortcodes: false,
+            copy_emoji_shortcodes: false,
+            keep_semantic_tags: false,
+            max_image_width: None,
+            max_image_height: None,
+            drop_cap_paragraph: None,
+            heading_level: None,
+            uses_viewport: Arc::default(),
+        }
+    }
+}
+
+impl NodeContext {
+    pub(super) fn add_ref(&mut self, identifier: SharedString, link: LinkMark) {
+        self.link_refs.insert(identifier, link);
+    }
+
+    /// Replace the link reference with its definition, the unresolved reference is kept as is,
+    /// `None` if it is sanitized, e.g.: the definition is removed for the disallowed scheme.
+    pub(super) fn resolve_link(&self, link: LinkMark) -> Option<LinkMark> {
+        let Some(identifier) = link.identifier.as_ref() else {
+            return Some(link);
+        };
+        match self.link_refs.get(identifier) {
+            Some(definition) => Some(definition.clone()),
+            None => (!self.sanitize).then_some(link),
+        }
+    }
+
+    /// The font size to resolve the `em` lengths on parsing, the font size of the heading being
+    /// parsed, or the [`TextViewStyle::heading_base_font_size`] as the size of the body text.
+    pub(crate) fn em_size(&self) -> Pixels {
+        match self.heading_level {
+            Some(level) => self.style.heading_size(level),
+            None => self.style.heading_base_font_size,
+        }
+    }
+
+    /// Mark the parsed text to depend on the viewport size, see [`NodeContext::uses_viewport`].
+    pub(crate) fn use_viewport(&self) {
+        self.uses_viewport.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the parsed text depends on the viewport size.
+    pub(crate) fn is_viewport_dependent(&self) -> bool {
+        self.uses_viewport.load(Ordering::Relaxed)
+    }
+
+    /// Record a [`Diagnostic`], do nothing if the diagnostics are not collected.
+    pub(super) fn report(&self, kind: DiagnosticKind, message: &str, span: Option<Span>) {
+        if let Some(diagnostics) = &self.diagnostics {
+            diagnostics.lock().unwrap().push(Diagnostic {
+                kind,
+                message: message.to_string().into(),
+                span,
+            });
+        }
+    }
+
+    /// Return the collected [`Diagnostic`]s, empty if they are not collected.
+    pub(crate) fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics
+            .as_ref()
+            .map(|diagnostics| diagnostics.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+}
+
+impl PartialEq for NodeContext {
+    fn eq(&self, other: &Self) -> bool {
+        self.link_refs == other.link_refs
+            && self.style == other.style
+            && self.sanitize == other.sanitize
+            && self.words_per_minute == other.words_per_minute
+            && self.viewport_size == other.viewport_size
+            && self.emoji_shortcodes == other.emoji_shortcodes
+            && self.keep_semantic_tags == other.keep_semantic_tags
+        // Note: code_block_buttons, embed_providers, mention_resolver, time_formatter,
+        // custom_block_renderer and diagnostics are intentionally not compared (closures can't be
+        // compared)
+    }
+}
+
+impl Paragraph {
+    /// The element id only by the span start, so it is kept when the text is appended to the
+    /// paragraph (e.g. streaming), and the element state of the inline images is reused.
+    pub(super) fn element_id(&self) -> ElementId {
+        ("paragraph", self.span.map_or(0, |span| span.start)).into()
+    }
+
+    fn render(&self, node_cx: &NodeContext, window: &mut Window, cx: &mut App) -> AnyElement {
+        self.render_with(FirstLine::Normal, node_cx, window, cx)
+    }
+
+    fn render_with(
+        &self,
+        first_line: FirstLine,
+        node_cx: &NodeContext,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> AnyElement {
+        let children = &self.children;
+
+        let mut child_nodes: Vec<AnyElement> = vec![];
+
+        let mut text = String::new();
+        let mut highlights: Vec<(Range<usize>, HighlightStyle)> = vec![];
+        let mut links: Vec<(Range<usize>, LinkMark)> = vec![];
+        let mut code_ranges: Vec<Range<usize>> = vec![];
+        let mut abbrs: Vec<(Range<usize>, SharedString)> = vec![];
+        let mut spoilers: Vec<Range<usize>> = vec![];
+        let mut emojis: Vec<(Range<usize>, SharedString)> = vec![];
+        let mut images: Vec<(Range<usize>, ImageNode)> = vec![];
+        let code_font_family = node_cx.style.inline_code_font.then(|| {
+            node_cx
+                .style
+                .code_font_family
+                .clone()
+                .unwrap_or_else(|| cx.theme().mono_font_family.clone())
+        });
+        let mut display_map = DisplayMap::default();
+        // The first letter removed from the text, enlarged to span the lines at the start.
+        let mut drop_cap = SharedString::default();
         let mut offset = 0;
 
         let mut ix = 0;
         for inline_node in children {
-            let text_len = inline_node.text.len();
-            text.push_str(&inline_node.text);
+            let first_line = if ix == 0 {
+                first_line
+            } else {
+                FirstLine::Normal
+            };
+            let (node_text, node_map) = display_text(inline_node, first_line, node_cx);
+            let text_len = node_text.len();
+            text.push_str(&node_text);
+            display_map.extend(&node_map, offset);
+            if ix == 0 {
+                drop_cap = node_map.removed.clone();
+            }
 
-            if let Some(image) = &inline_node.image {
+            // The inline math is rendered by the math renderer like the inline image.
+            let math_renderer = node_cx
+                .math_renderer
+                .as_ref()
+                .filter(|_| inline_node.is_math());
+            let block_image = inline_node.image.as_ref().filter(|image| !image.is_emoji());
+            if block_image.is_some() || math_renderer.is_some() {
                 if text.len() > 0 {
                     inline_node
                         .state
@@ -652,134 +3100,1004 @@ impl Paragraph {
                             links.clone(),
                             highlights.clone(),
                         )
+                        .code_font(code_font_family.clone(), code_ranges.clone())
+                        .abbrs(abbrs.clone())
+                        .spoilers(spoilers.clone(), node_cx.copy_hidden_spoilers)
+                        .emoji_shortcodes(emojis.clone(), node_cx.copy_emoji_shortcodes)
+                        .images(images.clone())
+                        .display_map(display_map.clone())
+                        .on_anchor_click(node_cx.on_anchor_click.clone())
+                        .on_link_click(node_cx.on_link_click.clone())
+                        .on_context_menu(node_cx.on_context_menu.clone())
                         .into_any_element(),
                     );
                 }
-                child_nodes.push(
-                    img(image.url.clone())
-                        .id(ix)
-                        .object_fit(ObjectFit::Contain)
-                        .max_w(relative(1.))
-                        .when_some(image.width, |this, width| this.w(width))
-                        .when_some(image.link.clone(), |this, link| {
-                            let title = image.title();
-                            this.cursor_pointer()
-                                .tooltip(move |window, cx| {
-                                    Tooltip::new(title.clone()).build(window, cx)
-                                })
-                                .on_click(move |_, _, cx| {
-                                    cx.stop_propagation();
-                                    cx.open_url(&link.url);
-                                })
-                        })
-                        .into_any_element(),
-                );
+                if let Some(math_renderer) = math_renderer {
+                    child_nodes.push(math_renderer(&inline_node.text, false, window, cx));
+                } else if let Some(image) = block_image {
+                    // The id is keyed by url, so the element state (e.g. the frame of the animated
+                    // image) is kept on re-render, and reset if the image is changed.
+                    let id = ElementId::NamedInteger(image.url.clone(), ix as u64);
+                    // The lazy image is not requested until it is near the visible area.
+                    let placeholder =
+                        lazy_image_placeholder(id.clone(), image, node_cx, window, cx);
+                    // The image is loaded by url with the asset cache, so the repeated images in
+                    // the document (and the inline emoji of the same url) are only fetched and
+                    // decoded once, it is shared with the `img`, and re-render once loaded.
+                    //
+                    // The SVG is rasterized for the display size and the scale factor.
+                    let is_svg = svg_image::is_svg(&image.url);
+                    // The SVG is rasterized at the display scale, so it is sized by the intrinsic
+                    // size at 1x instead of the rasterized size, e.g.: not doubled on HiDPI.
+                    let intrinsic_size = (is_svg
+                        && placeholder.is_none()
+                        && image.width.is_none()
+                        && image.height.is_none())
+                    .then(|| svg_image::intrinsic_size(&image.url, window, cx))
+                    .flatten();
+                    let sized_image;
+                    let image = match intrinsic_size {
+                        Some(size) => {
+                            sized_image = ImageNode {
+                                width: Some(size.width.into()),
+                                height: Some(size.height.into()),
+                                ..image.clone()
+                            };
+                            &sized_image
+                        }
+                        None => image,
+                    };
+                    let data = if placeholder.is_some() {
+                        None
+                    } else if is_svg {
+                        svg_image::load_svg(&image.url, image.width, window, cx)
+                    } else {
+                        window.use_asset::<ImgResourceLoader>(&Resource::Uri(image.url.clone()), cx)
+                    }
+                    .and_then(|data| data.ok());
+                    if let Some(data) = &data {
+                        image.loaded.set(data.clone());
+                    }
+                    // Only use the intrinsic size if the image misses a dimension.
+                    let intrinsic_ratio = if image.width.is_some() && image.height.is_some() {
+                        None
+                    } else {
+                        data.as_ref().map(|data| {
+                            let size = data.size(0);
+                            size.width.0 as f32 / size.height.0 as f32
+                        })
+                    };
+                    let frames = data.filter(|data| data.frame_count() > 1);
+                    child_nodes.push(match (placeholder, frames) {
+                        (Some(placeholder), _) => placeholder,
+                        (None, Some(data)) => layout_image(
+                            bind_image_events(
+                                AnimatedImage::new(id, data).animate(node_cx.animate_images),
+                                image,
+                                node_cx,
+                            ),
+                            image,
+                            intrinsic_ratio,
+                            node_cx,
+                        ),
+                        (None, None) => layout_image(
+                            bind_image_events(
+                                img(if is_svg {
+                                    let url = image.url.clone();
+                                    let width = image.width;
+                                    ImageSource::from(move |window: &mut Window, cx: &mut App| {
+                                        svg_image::load_svg(&url, width, window, cx)
+                                    })
+                                } else {
+                                    ImageSource::from(image.url.clone())
+                                })
+                                .id(id)
+                                .object_fit(ObjectFit::Contain)
+                                .with_fallback({
+                                    let alt = image.alt.clone();
+                                    let color = cx.theme().muted_foreground;
+                                    move || broken_image(alt.clone(), color)
+                                }),
+                                image,
+                                node_cx,
+                            ),
+                            image,
+                            intrinsic_ratio,
+                            node_cx,
+                        ),
+                    });
+                    // Clamp the image by the `max-width`, which may be relative to the container.
+                    if let Some(max_width) = image.max_width {
+                        let image_el = child_nodes.pop().unwrap();
+                        child_nodes.push(
+                            div()
+                                .w_full()
+                                .max_w(max_width)
+                                .child(image_el)
+                                .into_any_element(),
+                        );
+                    }
+                }
+
+                text.clear();
+                links.clear();
+                highlights.clear();
+                code_ranges.clear();
+                abbrs.clear();
+                spoilers.clear();
+                emojis.clear();
+                images.clear();
+                display_map = DisplayMap::default();
+                offset = 0;
+            } else {
+                if let Some(image) = &inline_node.image {
+                    let range = offset..(offset + text_len);
+                    if let Some(link) = &image.link {
+                        links.push((range.clone(), link.clone()));
+                    }
+                    images.push((range, image.clone()));
+                }
+
+                let mut node_highlights = vec![];
+                for (range, style) in &inline_node.marks {
+                    let inner_range = node_map.display_range(range);
+                    let inner_range = (offset + inner_range.start)..(offset + inner_range.end);
+
+                    let mut highlight = HighlightStyle::default();
+                    if style.bold {
+                        highlight.font_weight = Some(FontWeight::BOLD);
+                    }
+                    if style.italic {
+                        highlight.font_style = Some(FontStyle::Italic);
+                    }
+                    if style.strikethrough {
+                        highlight.strikethrough = Some(gpui::StrikethroughStyle {
+                            thickness: gpui::px(1.),
+                            ..Default::default()
+                        });
+                    }
+                    if style.underline {
+                        highlight.underline = Some(gpui::UnderlineStyle {
+                            thickness: gpui::px(1.),
+                            ..Default::default()
+                        });
+                    }
+                    if style.code || style.math {
+                        highlight.background_color = Some(cx.theme().inline_code);
+                        code_ranges.push(inner_range.clone());
+                    }
+                    if let Some(title) = &style.abbr {
+                        abbrs.push((inner_range.clone(), title.clone()));
+                    }
+                    if style.spoiler {
+                        spoilers.push(inner_range.clone());
+                    }
+                    if let Some(shortcode) = &style.emoji {
+                        emojis.push((inner_range.clone(), format!(":{}:", shortcode).into()));
+                    }
+
+                    let link_mark = style
+                        .link
+                        .clone()
+                        .and_then(|link_mark| node_cx.resolve_link(link_mark));
+
+                    if let Some(link_mark) = link_mark {
+                        // The hovered link is painted with the `link_hover` over this.
+                        let visited = node_cx
+                            .link_visited
+                            .as_ref()
+                            .is_some_and(|link_visited| link_visited(link_mark.url.as_ref()));
+                        highlight.color = Some(if visited {
+                            cx.theme().link.mix_oklab(cx.theme().muted_foreground, 0.5)
+                        } else {
+                            cx.theme().link
+                        });
+                        highlight.underline = Some(gpui::UnderlineStyle {
+                            thickness: gpui::px(1.),
+                            ..Default::default()
+                        });
+
+                        links.push((inner_range.clone(), link_mark));
+                    }
+
+                    // The mention is a chip without the link underline.
+                    if style.mention.is_some() {
+                        highlight.background_color = Some(cx.theme().accent);
+                        highlight.color = Some(cx.theme().accent_foreground);
+                        highlight.underline = None;
+                    }
+
+                    node_highlights.push((inner_range, highlight));
+                }
+
+                highlights = gpui::combine_highlights(highlights, node_highlights).collect();
+                offset += text_len;
+            }
+            ix += 1;
+        }
+
+        // Add the last text node
+        if text.len() > 0 {
+            self.state.lock().unwrap().set_text(text.into());
+            child_nodes.push(
+                Inline::new(ix, self.state.clone(), links, highlights)
+                    .code_font(code_font_family, code_ranges)
+                    .abbrs(abbrs)
+                    .spoilers(spoilers, node_cx.copy_hidden_spoilers)
+                    .emoji_shortcodes(emojis, node_cx.copy_emoji_shortcodes)
+                    .images(images)
+                    .display_map(display_map)
+                    .on_anchor_click(node_cx.on_anchor_click.clone())
+                    .on_link_click(node_cx.on_link_click.clone())
+                    .on_context_menu(node_cx.on_context_menu.clone())
+                    .into_any_element(),
+            );
+        }
+
+        let paragraph = div()
+            .id(self.element_id())
+            .map(|this| match (self.align, self.dir) {
+                (Some(TextAlign::Center), _) => this.text_center(),
+                (Some(TextAlign::Right), _) => this.text_right(),
+                // The right-to-left text starts from the right without an explicit alignment.
+                (None | Some(TextAlign::Justify), Some(TextDirection::Rtl)) => this.text_right(),
+                _ => this,
+            })
+            .when(self.white_space == Some(WhiteSpace::Pre), |this| {
+                this.whitespace_nowrap().overflow_x_scroll()
+            })
+            .children(child_nodes);
+
+        if drop_cap.is_empty() {
+            return paragraph.into_any_element();
+        }
+
+        // The text layout has no float, so the paragraph is beside the drop cap as a column, the
+        // lines below the drop cap keep the same indent.
+        let height = window.line_height() * node_cx.style.drop_cap_lines as f32;
+        h_flex()
+            .items_start()
+            .when(self.is_rtl(), |this| this.flex_row_reverse())
+            .child(
+                div()
+                    .flex_shrink_0()
+                    .h(height)
+                    .px(rems(0.25))
+                    .text_size(height)
+                    .line_height(height)
+                    .child(drop_cap),
+            )
+            .child(paragraph.flex_1().min_w_0())
+            .into_any_element()
+    }
+}
+
+impl Paragraph {
+    fn to_markdown(&self) -> String {
+        // No semantic block tags in Markdown, the paragraph is kept as the HTML.
+        if let Some(tag) = self.semantic {
+            return format!("<{0}>{1}</{0}>\n\n", tag.tag(), self.to_html());
+        }
+
+        let mut text = self
+            .children
+            .iter()
+            .map(|text_node| {
+                if text_node.is_hard_break() {
+                    return "  \n".to_string();
+                }
+
+                let mut text = compose_marks(
+                    &text_node.text,
+                    0..text_node.text.len(),
+                    &text_node.sorted_marks(),
+                    &TextMark::default(),
+                    &|mark, text| mark.wrap_markdown(text),
+                    // The literal `$` is escaped, so it does not start the math.
+                    &|active, text| {
+                        if active.code || active.math {
+                            text.to_string()
+                        } else {
+                            text.replace('$', "\\$")
+                        }
+                    },
+                );
+
+                if let Some(image) = &text_node.image {
+                    let alt = image.alt.clone().unwrap_or_default();
+                    let title = image
+                        .title
+                        .clone()
+                        .map_or(String::new(), |t| format!(" \"{}\"", t));
+                    text.push_str(&format!("![{}]({}{})", alt, image.url, title))
+                }
+
+                text
+            })
+            .collect::<Vec<_>>()
+            .join("");
+
+        text.push_str("\n\n");
+        text
+    }
+
+    /// Converts the paragraph to the inline HTML, without the `<p>` tag.
+    fn to_html(&self) -> String {
+        self.children
+            .iter()
+            .map(|node| {
+                if node.is_hard_break() {
+                    return "<br>".to_string();
+                }
+
+                if let Some(image) = &node.image {
+                    return image.to_html();
+                }
+
+                compose_marks(
+                    &node.text,
+                    0..node.text.len(),
+                    &node.sorted_marks(),
+                    &TextMark::default(),
+                    &|mark, html| mark.wrap_html(html),
+                    &|_, text| escape_html(text),
+                )
+            })
+            .collect()
+    }
+
+    /// Converts the paragraph to plain text, images are represented by their alt text.
+    pub(crate) fn to_plain_text(&self) -> String {
+        self.children
+            .iter()
+            .map(|node| match &node.image {
+                Some(image) => image.alt.clone().unwrap_or_default(),
+                None => node.text.clone(),
+            })
+            .collect()
+    }
+}
+
+/// The style of the first line of a paragraph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum FirstLine {
+    Normal,
+    /// Indented by the [`TextViewStyle::first_line_indent`].
+    Indent,
+    /// Starts with the drop cap beside the text, see [`TextViewStyle::drop_cap_lines`].
+    DropCap,
+}
+
+/// The text of the inline node to render, with the map of the text changed on render, e.g.: the
+/// long words are hyphenated if the [`TextViewStyle::hyphenation`] is set, the number of the
+/// footnote reference is the superscript, and the `<time>` is formatted.
+///
+/// [`TextViewStyle::hyphenation`]: crate::text::TextViewStyle
+pub(super) fn display_text<'a>(
+    inline_node: &'a InlineNode,
+    first_line: FirstLine,
+    node_cx: &NodeContext,
+) -> (Cow<'a, str>, DisplayMap) {
+    // The emoji image is painted over the placeholder in the text line, so the adjacent emoji
+    // each take a distinct range, see [`Inline::images`].
+    if inline_node.image.as_ref().is_some_and(ImageNode::is_emoji) {
+        return (
+            Cow::Borrowed(EMOJI_PLACEHOLDER),
+            DisplayMap {
+                inserted: vec![0..EMOJI_PLACEHOLDER.len()],
+                ..Default::default()
+            },
+        );
+    }
+    // The inline math rendered by the math renderer is an element like the image, not the text.
+    if inline_node.image.is_some() || (node_cx.math_renderer.is_some() && inline_node.is_math()) {
+        return (Cow::Borrowed(""), DisplayMap::default());
+    }
+
+    let (mut text, mut display_map) = hyphenated_text(inline_node, node_cx);
+    let replacements = display_replacements(inline_node, node_cx);
+    if !replacements.is_empty() {
+        text = replace_display_text(&text, &mut display_map, &replacements).into();
+    }
+    match first_line {
+        FirstLine::Normal => {}
+        FirstLine::Indent => {
+            let indent = first_line_indent(node_cx.style.first_line_indent);
+            if !indent.is_empty() {
+                for range in display_map.inserted.iter_mut() {
+                    *range = (range.start + indent.len())..(range.end + indent.len());
+                }
+                for replaced in display_map.replaced.iter_mut() {
+                    let range = &mut replaced.range;
+                    *range = (range.start + indent.len())..(range.end + indent.len());
+                }
+                display_map.inserted.insert(0, 0..indent.len());
+                text = format!("{}{}", indent, text).into();
+            }
+        }
+        FirstLine::DropCap => {
+            if let Some(cap) = text
+                .graphemes(true)
+                .next()
+                .filter(|cap| cap.len() < text.len())
+                // The replaced text is not the first letter of the document text.
+                .filter(|_| {
+                    display_map
+                        .replaced
+                        .first()
+                        .is_none_or(|replaced| replaced.range.start > 0)
+                })
+                .map(str::to_string)
+            {
+                // The soft hyphens are never inserted into the first letter.
+                for range in display_map.inserted.iter_mut() {
+                    *range = (range.start - cap.len())..(range.end - cap.len());
+                }
+                for replaced in display_map.replaced.iter_mut() {
+                    let range = &mut replaced.range;
+                    *range = (range.start - cap.len())..(range.end - cap.len());
+                }
+                text = text[cap.len()..].to_string().into();
+                display_map.removed = cap.into();
+            }
+        }
+    }
+
+    (text, display_map)
+}
+
+/// The placeholder of the inline emoji image in the text, an em space as wide as the emoji.
+const EMOJI_PLACEHOLDER: &str = "\u{2003}";
+
+/// The em spaces to indent the first line by `em`, rounded to the half em.
+fn first_line_indent(em: f32) -> String {
+    let halves = (em.max(0.) * 2.).round() as usize;
+    let mut indent = "\u{2003}".repeat(halves / 2);
+    if halves % 2 == 1 {
+        indent.push('\u{2002}');
+    }
+    indent
+}
+
+/// The long words of the text are hyphenated if the [`TextViewStyle::hyphenation`] is set.
+///
+/// [`TextViewStyle::hyphenation`]: crate::text::TextViewStyle
+#[cfg_attr(not(feature = "hyphenation"), allow(unused_variables))]
+fn hyphenated_text<'a>(
+    inline_node: &'a InlineNode,
+    node_cx: &NodeContext,
+) -> (Cow<'a, str>, DisplayMap) {
+    #[cfg(feature = "hyphenation")]
+    if let Some(dictionary) = node_cx
+        .style
+        .hyphenation
+        .as_deref()
+        .and_then(super::hyphenate::dictionary)
+    {
+        // The code, the links (often the urls) and the replaced text are kept as is.
+        let skip = inline_node
+            .marks
+            .iter()
+            .filter(|(_, mark)| {
+                mark.code
+                    || mark.math
+                    || mark.link.is_some()
+                    || mark.footnote.is_some()
+                    || mark.time.is_some()
+            })
+            .map(|(range, _)| range.clone())
+            .collect::<Vec<_>>();
+        let (text, display_map) =
+            super::hyphenate::hyphenate(&inline_node.text, &skip, &dictionary);
+        return (text.into(), display_map);
+    }
+
+    (
+        Cow::Borrowed(inline_node.text.as_ref()),
+        DisplayMap::default(),
+    )
+}
+
+/// The ranges of the document text replaced on render, sorted, e.g.: the number of the footnote
+/// reference is displayed as the superscript, in the smaller size above the baseline, and the
+/// `<time>` is formatted by the [`NodeContext::time_formatter`].
+///
+/// Returns the ranges with the replacements, and whether to copy them as displayed, e.g.: the
+/// formatted time, the footnote number is copied as the document text.
+fn display_replacements(
+    inline_node: &InlineNode,
+    node_cx: &NodeContext,
+) -> Vec<(Range<usize>, String, bool)> {
+    let mut replacements = inline_node
+        .marks
+        .iter()
+        .filter(|(range, _)| !range.is_empty())
+        .filter_map(|(range, mark)| {
+            let text = &inline_node.text[range.clone()];
+            let replacement = if mark.footnote.is_some() {
+                superscript(text)
+            } else if let Some(datetime) = &mark.time {
+                let time_formatter = node_cx.time_formatter.as_ref()?;
+                time_formatter(datetime, text).map(|formatted| formatted.to_string())
+            } else {
+                None
+            };
+            replacement.map(|replacement| (range.clone(), replacement, mark.time.is_some()))
+        })
+        .collect::<Vec<_>>();
+    replacements.sort_by_key(|(range, ..)| range.start);
+    replacements.dedup_by(|(next, ..), (prev, ..)| next.start < prev.end);
+    replacements
+}
+
+/// The superscript of the digits, e.g.: `12` to `¹²`, `None` if any char is not a digit.
+fn superscript(text: &str) -> Option<String> {
+    const DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+    text.chars()
+        .map(|c| c.to_digit(10).map(|digit| DIGITS[digit as usize]))
+        .collect()
+}
+
+/// Replace the `replacements` of the document text in the rendered `text`, the replaced ranges
+/// are added to the `display_map`, and the inserted text after them is moved.
+fn replace_display_text(
+    text: &str,
+    display_map: &mut DisplayMap,
+    replacements: &[(Range<usize>, String, bool)],
+) -> String {
+    let ranges = replacements
+        .iter()
+        .map(|(range, ..)| display_map.display_range(range))
+        .collect::<Vec<_>>();
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+    for (range, (_, replacement, copy_displayed)) in ranges.iter().zip(replacements) {
+        replaced.push_str(&text[last..range.start]);
+        let start = replaced.len();
+        replaced.push_str(replacement);
+        display_map.replaced.push(Replaced {
+            range: start..replaced.len(),
+            text: text[range.clone()].to_string().into(),
+            copy_displayed: *copy_displayed,
+        });
+        last = range.end;
+    }
+    replaced.push_str(&text[last..]);
+
+    for inserted in display_map.inserted.iter_mut() {
+        let (added, removed) = ranges
+            .iter()
+            .zip(replacements)
+            .filter(|(range, _)| range.end <= inserted.start)
+            .fold((0, 0), |(added, removed), (range, (_, replacement, _))| {
+                (added + replacement.len(), removed + range.len())
+            });
+        *inserted = (inserted.start + added - removed)..(inserted.end + added - removed);
+    }
+    replaced
+}
+
+/// Bind the context menu, the tooltip and the click events of the image.
+fn bind_image_events<E>(el: E, image: &ImageNode, node_cx: &NodeContext) -> E
+where
+    E: StatefulInteractiveElement + Styled,
+{
+    el.when_some(node_cx.on_context_menu.clone(), |this, on_context_menu| {
+        let image = image.clone();
+        this.on_mouse_down(MouseButton::Right, move |_, _, cx| {
+            on_context_menu(ContextMenuTarget::Image(image.clone()), cx)
+        })
+    })
+    .when_some(image.link.as_ref(), |this, _| {
+        let title = image.title();
+        this.tooltip(move |window, cx| Tooltip::new(title.clone()).build(window, cx))
+    })
+    .when(
+        image.link.is_some() || (node_cx.on_image_click.is_some() && !image.is_emoji()),
+        |this| {
+            let image = image.clone();
+            let on_anchor_click = node_cx.on_anchor_click.clone();
+            // The emoji images are part of the text, not opened in the viewer.
+            let on_image_click = node_cx.on_image_click.clone().filter(|_| !image.is_emoji());
+            let on_link_click = node_cx.on_link_click.clone();
+            this.cursor_pointer().on_click(move |event, window, cx| {
+                cx.stop_propagation();
+                // Prefer to open the link, unless the secondary modifier is held.
+                match (&image.link, &on_image_click) {
+                    (Some(_), Some(on_image_click)) if event.modifiers().secondary() => {
+                        on_image_click(&image, window, cx)
+                    }
+                    (Some(link), _) => open_link(
+                        LinkClickEvent {
+                            url: link.url.clone(),
+                            modifiers: event.modifiers(),
+                            background: event.modifiers().secondary(),
+                        },
+                        on_anchor_click.as_ref(),
+                        on_link_click.as_ref(),
+                        window,
+                        cx,
+                    ),
+                    (None, Some(on_image_click)) => on_image_click(&image, window, cx),
+                    (None, None) => {}
+                }
+            })
+        },
+    )
+}
+
+/// Size the image by the dimensions and the aspect ratio.
+fn layout_image<E>(
+    el: E,
+    image: &ImageNode,
+    intrinsic_ratio: Option<f32>,
+    node_cx: &NodeContext,
+) -> AnyElement
+where
+    E: Styled + IntoElement,
+{
+    let (max_width, max_height) = (node_cx.max_image_width, node_cx.max_image_height);
+    match image.fit(intrinsic_ratio) {
+        // The `img` computes the auto height from the unclamped width,
+        // so let the wrapper scale down the image with the aspect ratio.
+        Some(fit) => {
+            let fit = fit.clamp(max_width, max_height);
+            div()
+                .w_full()
+                .max_w(fit.width)
+                .max_h(fit.width / fit.aspect_ratio)
+                .aspect_ratio(fit.aspect_ratio)
+                .child(el.size_full())
+                .into_any_element()
+        }
+        None => {
+            // The relative width is resolved against the container, so clamp it by the wrapper,
+            // the max height is converted to the max width once the intrinsic size is known.
+            let ratio = intrinsic_ratio.filter(|ratio| ratio.is_finite() && *ratio > 0.);
+            let max_width = match ratio {
+                Some(ratio) => clamped_width(ratio, max_width, max_height),
+                None => max_width.filter(|width| *width > px(0.)),
+            };
+            let max_height = match (image.max_height, max_height.filter(|h| *h > px(0.))) {
+                (Some(DefiniteLength::Absolute(AbsoluteLength::Pixels(height))), Some(max)) => {
+                    Some(height.min(max).into())
+                }
+                (height, None) => height,
+                (_, Some(max)) => Some(max.into()),
+            };
+            let el = el
+                .max_w(relative(1.))
+                .when_some(image.width, |this, width| this.w(width))
+                .when_some(max_height, |this, max_height| this.max_h(max_height))
+                .into_any_element();
+            match max_width {
+                Some(max_width) => div().w_full().max_w(max_width).child(el).into_any_element(),
+                None => el,
+            }
+        }
+    }
+}
+
+/// The gap between the list marker and the text of the item.
+const MARKER_GAP: Rems = Rems(0.25);
+
+/// The padding of the code block, unless refined by [`TextViewStyle::code_block`].
+///
+/// [`TextViewStyle::code_block`]: crate::text::TextViewStyle::code_block
+const CODE_BLOCK_PADDING: Rems = Rems(0.75);
+
+/// The width of the gutter to right-align the markers of the ordered list, fits the widest marker.
+pub(super) fn marker_gutter_width(markers: &[String], measure: impl Fn(&str) -> Pixels) -> Pixels {
+    markers
+        .iter()
+        .map(|marker| measure(marker))
+        .fold(px(0.), |width, marker_width| width.max(marker_width))
+}
+
+/// The gutter of the ordered list markers, measured on layout in the font of the list items, and
+/// cached for the list, so the markers are shaped once for all items.
+#[derive(Clone)]
+pub(super) struct MarkerGutter {
+    markers: Rc<Vec<String>>,
+    /// The font and the font size measured with, and the measured width.
+    width: Rc<RefCell<Option<(Font, Pixels, Pixels)>>>,
+}
+
+impl MarkerGutter {
+    pub(super) fn new(markers: Vec<String>) -> Self {
+        Self {
+            markers: Rc::new(markers),
+            width: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// The width of the gutter in the current text style, see [`marker_gutter_width`].
+    pub(super) fn width(&self, window: &mut Window) -> Pixels {
+        let text_style = window.text_style();
+        let font = text_style.font();
+        let font_size = text_style.font_size.to_pixels(window.rem_size());
+        if let Some((cached_font, cached_size, width)) = self.width.borrow().as_ref()
+            && *cached_font == font
+            && *cached_size == font_size
+        {
+            return *width;
+        }
+
+        let width = marker_gutter_width(&self.markers, |marker| {
+            window
+                .text_system()
+                .shape_line(
+                    marker.to_string().into(),
+                    font_size,
+                    &[text_style.to_run(marker.len())],
+                    None,
+                )
+                .width
+        });
+        *self.width.borrow_mut() = Some((font, font_size, width));
+        width
+    }
+}
+
+/// The marker of the ordered list item, right-aligned in the [`MarkerGutter`], so the text of
+/// the items is aligned, e.g.: `9.` and `10.`.
+///
+/// The marker inherits the text style of the item on layout, e.g.: in a blockquote.
+struct ListMarker {
+    marker: SharedString,
+    gutter: MarkerGutter,
+    is_rtl: bool,
+}
+
+impl IntoElement for ListMarker {
+    type Element = Self;
+
+    fn into_element(self) -> Self::Element {
+        self
+    }
+}
+
+impl Element for ListMarker {
+    type RequestLayoutState = AnyElement;
+    type PrepaintState = ();
+
+    fn id(&self) -> Option<ElementId> {
+        None
+    }
+
+    fn source_location(&self) -> Option<&'static std::panic::Location<'static>> {
+        None
+    }
+
+    fn request_layout(
+        &mut self,
+        _: Option<&GlobalElementId>,
+        _: Option<&InspectorElementId>,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> (LayoutId, Self::RequestLayoutState) {
+        let mut el = div()
+            .flex_none()
+            .flex()
+            .w(self.gutter.width(window))
+            .map(|this| {
+                if self.is_rtl {
+                    this.justify_start().pl(MARKER_GAP)
+                } else {
+                    this.justify_end().pr(MARKER_GAP)
+                }
+            })
+            .child(self.marker.clone())
+            .into_any_element();
+        let layout_id = el.request_layout(window, cx);
+        (layout_id, el)
+    }
 
-                text.clear();
-                links.clear();
-                highlights.clear();
-                offset = 0;
-            } else {
-                let mut node_highlights = vec![];
-                for (range, style) in &inline_node.marks {
-                    let inner_range = (offset + range.start)..(offset + range.end);
+    fn prepaint(
+        &mut self,
+        _: Option<&GlobalElementId>,
+        _: Option<&InspectorElementId>,
+        _: Bounds<Pixels>,
+        el: &mut Self::RequestLayoutState,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> Self::PrepaintState {
+        el.prepaint(window, cx);
+    }
 
-                    let mut highlight = HighlightStyle::default();
-                    if style.bold {
-                        highlight.font_weight = Some(FontWeight::BOLD);
-                    }
-                    if style.italic {
-                        highlight.font_style = Some(FontStyle::Italic);
-                    }
-                    if style.strikethrough {
-                        highlight.strikethrough = Some(gpui::StrikethroughStyle {
-                            thickness: gpui::px(1.),
-                            ..Default::default()
-                        });
-                    }
-                    if style.code {
-                        highlight.background_color = Some(cx.theme().accent);
-                    }
+    fn paint(
+        &mut self,
+        _: Option<&GlobalElementId>,
+        _: Option<&InspectorElementId>,
+        _: Bounds<Pixels>,
+        el: &mut Self::RequestLayoutState,
+        _: &mut Self::PrepaintState,
+        window: &mut Window,
+        cx: &mut App,
+    ) {
+        el.paint(window, cx);
+    }
+}
 
-                    if let Some(mut link_mark) = style.link.clone() {
-                        highlight.color = Some(cx.theme().link);
-                        highlight.underline = Some(gpui::UnderlineStyle {
-                            thickness: gpui::px(1.),
-                            ..Default::default()
-                        });
+/// The margin around the visible area to start loading the lazy images, so they are mostly
+/// loaded before scrolled into the view.
+pub(super) const LAZY_IMAGE_MARGIN: Pixels = px(500.);
 
-                        // convert link references, replace link
-                        if let Some(identifier) = link_mark.identifier.as_ref() {
-                            if let Some(mark) = node_cx.link_refs.get(identifier) {
-                                link_mark = mark.clone();
-                            }
-                        }
+/// Returns the placeholder of the lazy image until it is painted near the visible area, the image
+/// is not requested before that, `None` to load the image.
+///
+/// The state is keyed by url, the image of the same url is only fetched once by the asset cache.
+///
+/// The placeholder reserves the box of the image by the `width` and `height` attributes, so the
+/// content below is not moved once the image is loaded. Without both of them the size is unknown
+/// before loading, the placeholder is a small box and the content below jumps when it is loaded.
+fn lazy_image_placeholder(
+    id: ElementId,
+    image: &ImageNode,
+    node_cx: &NodeContext,
+    window: &mut Window,
+    cx: &mut App,
+) -> Option<AnyElement> {
+    if image.loading != ImageLoading::Lazy {
+        return None;
+    }
+    let near_visible = window.use_keyed_state(
+        SharedString::from(format!("{}/lazy", image.url)),
+        cx,
+        |_, _| false,
+    );
+    if *near_visible.read(cx) {
+        return None;
+    }
 
-                        links.push((inner_range.clone(), link_mark));
+    let reserved = image.width.is_some() && image.height.is_some();
+    let placeholder = div()
+        .id(id)
+        .min_w(rems(1.))
+        .min_h(rems(1.))
+        .when(reserved, |this| {
+            this.when_some(image.height, |this, height| this.h(height))
+        })
+        .child(
+            canvas(
+                |_, _, _| {},
+                move |bounds, _, window, cx| {
+                    let visible_bounds = window.content_mask().bounds.dilate(LAZY_IMAGE_MARGIN);
+                    if visible_bounds.intersects(&bounds) {
+                        near_visible.update(cx, |near_visible, cx| {
+                            *near_visible = true;
+                            cx.notify();
+                        });
                     }
+                },
+            )
+            .size_full(),
+        );
+    let placeholder = bind_image_events(placeholder, image, node_cx);
+    if !reserved {
+        // Not to guess the aspect ratio from only one dimension, e.g.: a square box.
+        return Some(
+            placeholder
+                .max_w(relative(1.))
+                .when_some(image.width, |this, width| this.w(width))
+                .into_any_element(),
+        );
+    }
+    Some(layout_image(placeholder, image, None, node_cx))
+}
 
-                    node_highlights.push((inner_range, highlight));
-                }
+/// The placeholder of the image failed to load (e.g. a `<picture>` without usable source),
+/// with the alt text if any.
+fn broken_image(alt: Option<SharedString>, color: Hsla) -> AnyElement {
+    h_flex()
+        .gap_1()
+        .text_color(color)
+        .child(Icon::new(IconName::TriangleAlert))
+        .when_some(alt, |this, alt| this.child(alt))
+        .into_any_element()
+}
 
-                highlights = gpui::combine_highlights(highlights, node_highlights).collect();
-                offset += text_len;
-            }
-            ix += 1;
+/// Render the `range` of `text` with the nested `marks` (sorted by range start).
+///
+/// The `wrap` wraps the inner content with the styles of a mark, and the `plain`
+/// converts the unmarked text inside the `active` marks. The styles already applied by the
+/// outer marks (`active`) are not repeated.
+fn compose_marks(
+    text: &str,
+    range: Range<usize>,
+    marks: &[&(Range<usize>, TextMark)],
+    active: &TextMark,
+    wrap: &dyn Fn(&TextMark, &str) -> String,
+    plain: &dyn Fn(&TextMark, &str) -> String,
+) -> String {
+    let mut out = String::new();
+    let mut pos = range.start;
+    let mut ix = 0;
+    while ix < marks.len() {
+        let (mark_range, mark) = marks[ix];
+        let mut end_ix = ix + 1;
+        while end_ix < marks.len()
+            && marks[end_ix].0.start >= mark_range.start
+            && marks[end_ix].0.end <= mark_range.end
+        {
+            end_ix += 1;
         }
 
-        // Add the last text node
-        if text.len() > 0 {
-            self.state.lock().unwrap().set_text(text.into());
-            child_nodes
-                .push(Inline::new(ix, self.state.clone(), links, highlights).into_any_element());
+        // Skip the marks that partially overlap with the previous one.
+        if mark_range.start >= pos && mark_range.end <= range.end {
+            let new_mark = TextMark {
+                bold: mark.bold && !active.bold,
+                italic: mark.italic && !active.italic,
+                strikethrough: mark.strikethrough && !active.strikethrough,
+                underline: mark.underline && !active.underline,
+                code: mark.code && !active.code,
+                math: mark.math && !active.math,
+                link: mark.link.clone().filter(|_| active.link.is_none()),
+                abbr: mark.abbr.clone().filter(|_| active.abbr.is_none()),
+                footnote: mark.footnote.clone().filter(|_| active.footnote.is_none()),
+                mention: mark.mention.clone().filter(|_| active.mention.is_none()),
+                spoiler: mark.spoiler && !active.spoiler,
+                emoji: mark.emoji.clone().filter(|_| active.emoji.is_none()),
+                semantic: mark.semantic.filter(|_| active.semantic.is_none()),
+                time: mark.time.clone().filter(|_| active.time.is_none()),
+            };
+            let mut inner_active = active.clone();
+            inner_active.merge(mark.clone());
+
+            out.push_str(&plain(active, &text[pos..mark_range.start]));
+            let inner = compose_marks(
+                text,
+                mark_range.clone(),
+                &marks[ix + 1..end_ix],
+                &inner_active,
+                wrap,
+                plain,
+            );
+            out.push_str(&wrap(&new_mark, &inner));
+            pos = mark_range.end;
         }
+        ix = end_ix;
+    }
+    out.push_str(&plain(active, &text[pos..range.end]));
+    out
+}
 
-        div().id(span.unwrap_or_default()).children(child_nodes)
+/// The anchor id of the `n`-th (from 1) reference to the footnote, e.g.: `fnref-note`, `fnref-note-2`.
+pub(super) fn footnote_reference_anchor(identifier: &str, n: usize) -> String {
+    if n == 1 {
+        format!("fnref-{}", identifier)
+    } else {
+        format!("fnref-{}-{}", identifier, n)
     }
 }
 
-impl Paragraph {
-    fn to_markdown(&self) -> String {
-        let mut text = self
-            .children
-            .iter()
-            .map(|text_node| {
-                let mut text = text_node.text.to_string();
-                for (range, style) in &text_node.marks {
-                    if style.bold {
-                        text = format!("**{}**", &text_node.text[range.clone()]);
-                    }
-                    if style.italic {
-                        text = format!("*{}*", &text_node.text[range.clone()]);
-                    }
-                    if style.strikethrough {
-                        text = format!("~~{}~~", &text_node.text[range.clone()]);
-                    }
-                    if style.code {
-                        text = format!("`{}`", &text_node.text[range.clone()]);
-                    }
-                    if let Some(link) = &style.link {
-                        text = format!("[{}]({})", &text_node.text[range.clone()], link.url);
+/// Converts the blocks to the Markdown blockquote lines.
+fn quote_markdown(children: &[BlockNode]) -> String {
+    children
+        .iter()
+        .map(|child| {
+            // The nested blockquote stacks the markers, e.g.: `>> ` for level two.
+            let nested = matches!(
+                child,
+                BlockNode::Blockquote { .. } | BlockNode::Admonition { .. }
+            );
+            child
+                .to_markdown()
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        ">".to_string()
+                    } else if nested {
+                        format!(">{}", line)
+                    } else {
+                        format!("> {}", line)
                     }
-                }
-
-                if let Some(image) = &text_node.image {
-                    let alt = image.alt.clone().unwrap_or_default();
-                    let title = image
-                        .title
-                        .clone()
-                        .map_or(String::new(), |t| format!(" \"{}\"", t));
-                    text.push_str(&format!("![{}]({}{})", alt, image.url, title))
-                }
-
-                text
-            })
-            .collect::<Vec<_>>()
-            .join("");
-
-        text.push_str("\n\n");
-        text
-    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n>\n")
 }
 
 impl BlockNode {
@@ -801,36 +4119,64 @@ impl BlockNode {
                 let hashes = "#".repeat(*level as usize);
                 format!("{} {}", hashes, children.to_markdown())
             }
-            BlockNode::Blockquote { children, .. } => {
-                let content = children
-                    .iter()
-                    .map(|child| child.to_markdown())
-                    .collect::<Vec<_>>()
-                    .join("\n\n");
+            BlockNode::Blockquote { children, .. } => quote_markdown(children),
+            BlockNode::Admonition { kind, children, .. } => {
+                let marker = format!("> [!{}]", kind.marker());
+                if children.is_empty() {
+                    marker
+                } else {
+                    format!("{}\n{}", marker, quote_markdown(children))
+                }
+            }
+            BlockNode::List {
+                children,
+                ordered,
+                marker,
+                reversed,
+                ..
+            } => {
+                // Markdown can only express the decimal ascending list, fallback to HTML list.
+                if *ordered
+                    && (*reversed || marker.is_some_and(|marker| marker != ListMarker::Decimal))
+                {
+                    return self.to_html();
+                }
 
-                content
-                    .lines()
-                    .map(|line| format!("> {}", line))
+                let separator = if self.is_loose_list() { "\n\n" } else { "\n" };
+                children
+                    .iter()
+                    .zip(self.list_item_numbers())
+                    .map(|(child, number)| {
+                        let prefix = if *ordered {
+                            format!("{}. ", number)
+                        } else {
+                            "- ".to_string()
+                        };
+                        // Indent the following lines to keep the blocks in the item.
+                        let indent = " ".repeat(prefix.len());
+                        let content = child
+                            .to_markdown()
+                            .lines()
+                            .enumerate()
+                            .map(|(ix, line)| {
+                                if ix == 0 || line.is_empty() {
+                                    line.to_string()
+                                } else {
+                                    format!("{}{}", indent, line)
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        format!("{}{}", prefix, content)
+                    })
                     .collect::<Vec<_>>()
-                    .join("\n")
+                    .join(separator)
             }
-            BlockNode::List {
-                children, ordered, ..
-            } => children
-                .iter()
-                .enumerate()
-                .map(|(i, child)| {
-                    let prefix = if *ordered {
-                        format!("{}. ", i + 1)
-                    } else {
-                        "- ".to_string()
-                    };
-                    format!("{}{}", prefix, child.to_markdown())
-                })
-                .collect::<Vec<_>>()
-                .join("\n"),
             BlockNode::ListItem {
-                children, checked, ..
+                children,
+                spread,
+                checked,
+                ..
             } => {
                 let checkbox = if let Some(checked) = checked {
                     if *checked { "[x] " } else { "[ ] " }
@@ -844,87 +4190,344 @@ impl BlockNode {
                         .iter()
                         .map(|child| child.to_markdown())
                         .collect::<Vec<_>>()
-                        .join("\n")
+                        .join(if *spread { "\n\n" } else { "\n" })
+                )
+            }
+            BlockNode::CodeBlock(code_block) => {
+                format!(
+                    "```{}\n{}\n```",
+                    code_block.info().unwrap_or_default(),
+                    code_block.code()
+                )
+            }
+            BlockNode::Table(table) => {
+                // Markdown table can only contain inline content, fallback to HTML table.
+                if !table
+                    .children
+                    .iter()
+                    .all(|row| row.children.iter().all(|cell| cell.is_inline()))
+                {
+                    return table.to_html_markdown();
+                }
+
+                let header = table
+                    .children
+                    .first()
+                    .map(|row| {
+                        row.children
+                            .iter()
+                            .map(|cell| cell.to_markdown())
+                            .collect::<Vec<_>>()
+                            .join(" | ")
+                    })
+                    .unwrap_or_default();
+                let alignments = table
+                    .column_aligns
+                    .iter()
+                    .map(|align| {
+                        match align {
+                            ColumnumnAlign::Left => ":--",
+                            ColumnumnAlign::Center => ":-:",
+                            ColumnumnAlign::Right => "--:",
+                        }
+                        .to_string()
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" | ");
+                let rows = table
+                    .children
+                    .iter()
+                    .skip(1)
+                    .map(|row| {
+                        row.children
+                            .iter()
+                            .map(|cell| cell.to_markdown())
+                            .collect::<Vec<_>>()
+                            .join(" | ")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("{}\n{}\n{}", header, alignments, rows)
+            }
+            // The blank lines are collapsed in Markdown, so the `<br>` is kept as the raw HTML.
+            BlockNode::Break { html: true, .. } => "<br>".to_string(),
+            // A blank line, joined with the sibling blocks by the blank lines.
+            BlockNode::Break { .. } => String::new(),
+            BlockNode::HorizontalRule { .. } => "---".to_string(),
+            BlockNode::Math { formula, .. } => format!("$$\n{}\n$$", formula),
+            BlockNode::Embed(embed) => embed.to_markdown(),
+            // The raw HTML is kept in Markdown.
+            BlockNode::Custom(block) => block.to_html(),
+            BlockNode::Footnotes { children } => children
+                .iter()
+                .map(|child| child.to_markdown())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            BlockNode::FootnoteDefinition {
+                identifier,
+                children,
+                ..
+            } => {
+                let content = children
+                    .iter()
+                    .map(|child| child.to_markdown().trim_end().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                // The continuation lines are indented to be a part of the definition.
+                let content = content
+                    .lines()
+                    .enumerate()
+                    .map(|(ix, line)| {
+                        if ix == 0 || line.is_empty() {
+                            line.to_string()
+                        } else {
+                            format!("    {}", line)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("[^{}]: {}", identifier, content)
+            }
+            BlockNode::Definition {
+                identifier,
+                url,
+                title,
+                ..
+            } => {
+                if let Some(title) = title {
+                    format!("[{}]: {} \"{}\"", identifier, url, title)
+                } else {
+                    format!("[{}]: {}", identifier, url)
+                }
+            }
+            BlockNode::Unknown { .. } => "".to_string(),
+        }
+        // Keep the non-breaking spaces, e.g.: `&nbsp;` at the start of the paragraph.
+        .trim_matches(|c: char| c.is_ascii_whitespace())
+        .to_string()
+    }
+
+    /// Converts the node to semantic HTML.
+    pub(crate) fn to_html(&self) -> String {
+        fn join(children: &[BlockNode]) -> String {
+            children
+                .iter()
+                .map(|child| child.to_html())
+                .filter(|html| !html.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        match self {
+            BlockNode::Root { children, .. } => join(children),
+            BlockNode::Paragraph(paragraph) => {
+                let tag = paragraph.semantic.map_or("p", |tag| tag.tag());
+                format!(
+                    "<{0}{1}>{2}</{0}>",
+                    tag,
+                    paragraph.html_attrs(),
+                    paragraph.to_html()
+                )
+            }
+            BlockNode::Heading {
+                level,
+                children,
+                id,
+                ..
+            } => {
+                let id = id
+                    .as_ref()
+                    .map_or(String::new(), |id| format!(r#" id="{}""#, escape_html(id)));
+                let attrs = children.html_attrs();
+                format!("<h{level}{id}{attrs}>{}</h{level}>", children.to_html())
+            }
+            BlockNode::Blockquote { children, .. } => {
+                format!("<blockquote>\n{}\n</blockquote>", join(children))
+            }
+            BlockNode::Admonition { kind, children, .. } => {
+                let name = kind.marker().to_lowercase();
+                format!(
+                    "<div class=\"markdown-alert markdown-alert-{}\">\n<p class=\"markdown-alert-title\">{}</p>\n{}\n</div>",
+                    name,
+                    kind.title(),
+                    join(children)
                 )
             }
+            BlockNode::List {
+                children,
+                ordered,
+                start,
+                marker,
+                reversed,
+                ..
+            } => {
+                let tag = if *ordered { "ol" } else { "ul" };
+                let mut attrs = String::new();
+                if *ordered {
+                    if *start != 1 || *reversed {
+                        attrs.push_str(&format!(r#" start="{}""#, start));
+                    }
+                    if let Some(marker) = marker {
+                        attrs.push_str(&format!(r#" type="{}""#, marker.type_attr()));
+                    }
+                    if *reversed {
+                        attrs.push_str(" reversed");
+                    }
+                }
+                format!("<{tag}{attrs}>\n{}\n</{tag}>", join(children))
+            }
+            BlockNode::ListItem {
+                children,
+                spread,
+                checked,
+                value,
+                ..
+            } => {
+                let value = value.map_or(String::new(), |value| format!(r#" value="{}""#, value));
+                let checkbox = match checked {
+                    Some(true) => r#"<input type="checkbox" disabled checked> "#,
+                    Some(false) => r#"<input type="checkbox" disabled> "#,
+                    None => "",
+                };
+
+                // The tight list item renders the paragraphs without `<p>`.
+                let content = children
+                    .iter()
+                    .map(|child| match child {
+                        BlockNode::Paragraph(paragraph) if !*spread => paragraph.to_html(),
+                        _ => child.to_html(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("<li{}>{}{}</li>", value, checkbox, content)
+            }
             BlockNode::CodeBlock(code_block) => {
+                let class = code_block.lang().map_or(String::new(), |lang| {
+                    format!(r#" class="language-{}""#, escape_html(&lang))
+                });
                 format!(
-                    "```{}\n{}\n```",
-                    code_block.lang.clone().unwrap_or_default(),
-                    code_block.code()
+                    "<pre><code{}>{}</code></pre>",
+                    class,
+                    escape_html(&code_block.code())
                 )
             }
-            BlockNode::Table(table) => {
-                let header = table
-                    .children
-                    .first()
-                    .map(|row| {
-                        row.children
-                            .iter()
-                            .map(|cell| cell.children.to_markdown())
-                            .collect::<Vec<_>>()
-                            .join(" | ")
-                    })
-                    .unwrap_or_default();
-                let alignments = table
-                    .column_aligns
+            BlockNode::Table(table) => table.to_html(),
+            BlockNode::Break { .. } => "<br>".to_string(),
+            BlockNode::HorizontalRule { style, .. } => match style.to_css() {
+                Some(css) => format!(r#"<hr style="{}">"#, escape_html(&css)),
+                None => "<hr>".to_string(),
+            },
+            BlockNode::Math { formula, .. } => format!(
+                r#"<pre><code class="language-math math-display">{}</code></pre>"#,
+                escape_html(formula)
+            ),
+            BlockNode::Embed(embed) => embed.to_html(),
+            BlockNode::Custom(block) => block.to_html(),
+            BlockNode::Footnotes { children } => format!(
+                r#"<section class="footnotes"><ol>{}</ol></section>"#,
+                children
                     .iter()
-                    .map(|align| {
-                        match align {
-                            ColumnumnAlign::Left => ":--",
-                            ColumnumnAlign::Center => ":-:",
-                            ColumnumnAlign::Right => "--:",
-                        }
-                        .to_string()
+                    .map(|child| child.to_html())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+            BlockNode::FootnoteDefinition {
+                identifier,
+                references,
+                children,
+                ..
+            } => {
+                let identifier = escape_html(identifier);
+                let back_links = (1..=*references)
+                    .map(|n| {
+                        format!(
+                            r##"<a href="#{}">↩</a>"##,
+                            footnote_reference_anchor(&identifier, n)
+                        )
                     })
                     .collect::<Vec<_>>()
-                    .join(" | ");
-                let rows = table
-                    .children
+                    .join(" ");
+                let content = children
                     .iter()
-                    .skip(1)
-                    .map(|row| {
-                        row.children
-                            .iter()
-                            .map(|cell| cell.children.to_markdown())
-                            .collect::<Vec<_>>()
-                            .join(" | ")
-                    })
+                    .map(|child| child.to_html())
                     .collect::<Vec<_>>()
                     .join("\n");
-                format!("{}\n{}\n{}", header, alignments, rows)
+                format!(
+                    r#"<li id="fn-{}">{} {}</li>"#,
+                    identifier, content, back_links
+                )
             }
-            BlockNode::Break { html, .. } => {
-                if *html {
-                    "<br>".to_string()
-                } else {
-                    "\n".to_string()
-                }
+            BlockNode::Definition { .. } | BlockNode::Unknown { .. } => String::new(),
+        }
+    }
+
+    /// Converts the node to plain text without marks.
+    ///
+    /// The blocks are separated by a blank line, the list items and table rows by a newline,
+    /// and the table cells by a tab.
+    pub(crate) fn to_plain_text(&self) -> String {
+        fn join(children: &[BlockNode], separator: &str) -> String {
+            children
+                .iter()
+                .map(|child| child.to_plain_text())
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join(separator)
+        }
+
+        match self {
+            BlockNode::Root { children, .. } | BlockNode::Blockquote { children, .. } => {
+                join(children, "\n\n")
             }
-            BlockNode::HorizontalRule { .. } => "---".to_string(),
-            BlockNode::Definition {
-                identifier,
-                url,
-                title,
-                ..
-            } => {
-                if let Some(title) = title {
-                    format!("[{}]: {} \"{}\"", identifier, url, title)
+            BlockNode::Admonition { kind, children, .. } => {
+                let text = join(children, "\n\n");
+                if text.is_empty() {
+                    kind.title().to_string()
                 } else {
-                    format!("[{}]: {}", identifier, url)
+                    format!("{}\n\n{}", kind.title(), text)
                 }
             }
-            BlockNode::Unknown { .. } => "".to_string(),
+            BlockNode::Paragraph(paragraph) => paragraph.to_plain_text(),
+            BlockNode::Heading { children, .. } => children.to_plain_text(),
+            BlockNode::List { children, .. } | BlockNode::ListItem { children, .. } => {
+                join(children, "\n")
+            }
+            BlockNode::CodeBlock(code_block) => code_block.code().to_string(),
+            BlockNode::Math { formula, .. } => formula.to_string(),
+            BlockNode::Table(table) => table
+                .children
+                .iter()
+                .map(|row| {
+                    row.children
+                        .iter()
+                        .map(|cell| join(&cell.children, " "))
+                        .collect::<Vec<_>>()
+                        .join("\t")
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            BlockNode::Embed(embed) => embed.title().to_string(),
+            BlockNode::Custom(block) => block.text.to_string(),
+            BlockNode::Footnotes { children } => children
+                .iter()
+                .enumerate()
+                .map(|(ix, child)| format!("{}. {}", ix + 1, child.to_plain_text()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            BlockNode::FootnoteDefinition { children, .. } => join(children, "\n\n"),
+            BlockNode::Break { .. }
+            | BlockNode::HorizontalRule { .. }
+            | BlockNode::Definition { .. }
+            | BlockNode::Unknown { .. } => String::new(),
         }
-        .trim()
-        .to_string()
     }
 }
 
 impl BlockNode {
     fn render_list_item(
         item: &BlockNode,
-        ix: usize,
+        marker: &str,
+        gutter: Option<&MarkerGutter>,
         options: NodeRenderOptions,
         node_cx: &NodeContext,
         window: &mut Window,
@@ -940,13 +4543,19 @@ impl BlockNode {
                 .id(("li", options.ix))
                 .w_full()
                 .min_w_0()
-                .when(*spread, |this| this.child(div()))
+                .when(*spread, |this| this.gap(node_cx.style.paragraph_gap))
                 .children({
                     let mut items: Vec<Div> = Vec::with_capacity(children.len());
+                    // The checkboxes of the nested items follow the checkbox of this item.
+                    let mut checkbox_ix = options.checkbox_ix + checked.is_some() as usize;
 
                     for (child_ix, child) in children.iter().enumerate() {
+                        let child_checkbox_ix = checkbox_ix;
+                        checkbox_ix += child.checkbox_count();
+
                         match child {
-                            BlockNode::Paragraph { .. } => {
+                            BlockNode::Paragraph(paragraph) => {
+                                let is_rtl = paragraph.is_rtl();
                                 let last_not_list = child_ix > 0
                                     && !matches!(children[child_ix - 1], BlockNode::List { .. });
 
@@ -968,13 +4577,24 @@ impl BlockNode {
                                 if last_not_list {
                                     if let Some(preceding_row) = items.pop() {
                                         items.push(
-                                            v_flex().child(preceding_row).child(
-                                                div()
-                                                    .w_full()
-                                                    .pl(rems(0.75))
-                                                    .overflow_hidden()
-                                                    .child(text),
-                                            ),
+                                            v_flex()
+                                                .when(*spread, |this| {
+                                                    this.gap(node_cx.style.paragraph_gap)
+                                                })
+                                                .child(preceding_row)
+                                                .child(
+                                                    div()
+                                                        .w_full()
+                                                        .map(|this| {
+                                                            if is_rtl {
+                                                                this.pr(rems(0.75))
+                                                            } else {
+                                                                this.pl(rems(0.75))
+                                                            }
+                                                        })
+                                                        .overflow_hidden()
+                                                        .child(text),
+                                                ),
                                         );
                                         continue;
                                     }
@@ -988,20 +4608,46 @@ impl BlockNode {
                                         .relative()
                                         .items_start()
                                         .content_start()
+                                        // Mirror the marker to the right of the text.
+                                        .when(is_rtl, |this| this.flex_row_reverse())
                                         .when(!options.todo && checked.is_none(), |this| {
-                                            this.child(list_item_prefix(
-                                                ix,
-                                                options.ordered,
-                                                options.depth,
-                                            ))
+                                            // Right-align the ordered markers in the gutter.
+                                            match gutter {
+                                                Some(gutter) => this.child(ListMarker {
+                                                    marker: marker.to_string().into(),
+                                                    gutter: gutter.clone(),
+                                                    is_rtl,
+                                                }),
+                                                None => this.child(
+                                                    div()
+                                                        .flex_none()
+                                                        .flex()
+                                                        .map(|this| {
+                                                            if is_rtl {
+                                                                this.pl(MARKER_GAP)
+                                                            } else {
+                                                                this.pr(MARKER_GAP)
+                                                            }
+                                                        })
+                                                        .child(marker.to_string()),
+                                                ),
+                                            }
                                         })
                                         .when_some(*checked, |this, checked| {
                                             // Todo list checkbox
+                                            let index = options.checkbox_ix;
                                             this.child(
                                                 div()
+                                                    .id(("checkbox", index))
                                                     .flex()
                                                     .mt(rems(0.4))
-                                                    .mr_1p5()
+                                                    .map(|this| {
+                                                        if is_rtl {
+                                                            this.ml_1p5()
+                                                        } else {
+                                                            this.mr_1p5()
+                                                        }
+                                                    })
                                                     .size(rems(0.875))
                                                     .items_center()
                                                     .justify_center()
@@ -1015,7 +4661,20 @@ impl BlockNode {
                                                                 .size_2()
                                                                 .text_xs(),
                                                         )
-                                                    }),
+                                                    })
+                                                    .when_some(
+                                                        node_cx.on_checkbox_toggle.clone(),
+                                                        |this, on_toggle| {
+                                                            this.cursor_pointer().on_click(
+                                                                move |_, window, cx| {
+                                                                    cx.stop_propagation();
+                                                                    on_toggle(
+                                                                        index, !checked, window, cx,
+                                                                    )
+                                                                },
+                                                            )
+                                                        },
+                                                    ),
                                             )
                                         })
                                         .child(
@@ -1024,17 +4683,25 @@ impl BlockNode {
                                 );
                             }
                             BlockNode::List { .. } => {
-                                items.push(div().ml(rems(1.)).child(child.render_block(
-                                    NodeRenderOptions {
-                                        depth: options.depth + 1,
-                                        todo: checked.is_some(),
-                                        is_last: true,
-                                        ..options
-                                    },
-                                    node_cx,
-                                    window,
-                                    cx,
-                                )));
+                                // Stop indenting after the max depth, the deeper lists continue
+                                // at the same indent.
+                                let indent = options.depth < node_cx.style.list_max_indent_depth;
+                                items.push(
+                                    div()
+                                        .when(indent, |this| this.ml(node_cx.style.list_indent))
+                                        .child(child.render_block(
+                                            NodeRenderOptions {
+                                                depth: options.depth + 1,
+                                                todo: checked.is_some(),
+                                                is_last: true,
+                                                checkbox_ix: child_checkbox_ix,
+                                                ..options
+                                            },
+                                            node_cx,
+                                            window,
+                                            cx,
+                                        )),
+                                );
                             }
                             _ => {}
                         }
@@ -1046,6 +4713,109 @@ impl BlockNode {
         }
     }
 
+    fn render_footnotes(
+        footnotes: &BlockNode,
+        options: NodeRenderOptions,
+        node_cx: &NodeContext,
+        window: &mut Window,
+        cx: &mut App,
+    ) -> AnyElement {
+        let BlockNode::Footnotes { children } = footnotes else {
+            return div().into_any_element();
+        };
+
+        let mut items = Vec::with_capacity(children.len());
+        let mut checkbox_ix = options.checkbox_ix;
+        for (ix, item) in children.iter().enumerate() {
+            let BlockNode::FootnoteDefinition {
+                identifier,
+                references,
+                children,
+                ..
+            } = item
+            else {
+                continue;
+            };
+
+            let mut blocks = Vec::with_capacity(children.len());
+            for (child_ix, child) in children.iter().enumerate() {
+                blocks.push(child.render_block(
+                    NodeRenderOptions {
+                        ix: child_ix,
+                        is_last: child_ix + 1 == children.len(),
+                        checkbox_ix,
+                        ..options
+                    },
+                    node_cx,
+                    window,
+                    cx,
+                ));
+                checkbox_ix += child.checkbox_count();
+            }
+
+            // The back-reference links return to each reference of the footnote.
+            let link_color = cx.theme().link;
+            let back_links = (1..=*references).map(|n| {
+                let url: SharedString =
+                    format!("#{}", footnote_reference_anchor(identifier, n)).into();
+                let on_anchor_click = node_cx.on_anchor_click.clone();
+                div()
+                    .id(("back-link", n))
+                    .flex_shrink_0()
+                    .text_color(link_color)
+                    .cursor_pointer()
+                    .child(if n == 1 {
+                        "↩".to_string()
+                    } else {
+                        format!("↩{}", n)
+                    })
+                    .on_click(move |event, window, cx| {
+                        cx.stop_propagation();
+                        open_link(
+                            LinkClickEvent {
+                                url: url.clone(),
+                                modifiers: event.modifiers(),
+                                background: false,
+                            },
+                            on_anchor_click.as_ref(),
+                            None,
+                            window,
+                            cx,
+                        );
+                    })
+            });
+
+            items.push(
+                h_flex()
+                    .id(("footnote", ix))
+                    .w_full()
+                    .items_start()
+                    .gap_2()
+                    .child(div().flex_shrink_0().child(format!("{}.", ix + 1)))
+                    .child(
+                        v_flex()
+                            .flex_1()
+                            .min_w_0()
+                            .gap(node_cx.style.paragraph_gap)
+                            .children(blocks),
+                    )
+                    .children(back_links),
+            );
+        }
+
+        v_flex()
+            .id("footnotes")
+            .w_full()
+            .pt_3()
+            .gap(node_cx.style.list_item_gap)
+            .border_t_1()
+            .border_color(cx.theme().border)
+            .text_sm()
+            .text_color(cx.theme().muted_foreground)
+            .children(items)
+            .into_any_element()
+    }
+
     fn render_table(
         item: &BlockNode,
         options: &NodeRenderOptions,
@@ -1053,99 +4823,170 @@ impl BlockNode {
         window: &mut Window,
         cx: &mut App,
     ) -> impl IntoElement {
-        const DEFAULT_LENGTH: usize = 5;
-        const MAX_LENGTH: usize = 150;
-        let col_lens = match item {
-            BlockNode::Table(table) => {
-                let mut col_lens = vec![];
-                for row in table.children.iter() {
-                    for (ix, cell) in row.children.iter().enumerate() {
-                        if col_lens.len() <= ix {
-                            col_lens.push(DEFAULT_LENGTH);
-                        }
+        /// The estimated width of a char in rems, for the natural width of the columns.
+        const CHAR_WIDTH: f32 = 0.5;
+        const MIN_LENGTH: usize = 8;
+        const MAX_LENGTH: usize = 40;
 
-                        let len = cell.children.text_len();
-                        if len > col_lens[ix] {
-                            col_lens[ix] = len;
-                        }
-                    }
+        let BlockNode::Table(table) = item else {
+            return div().into_any_element();
+        };
+
+        // The columns with the `width` of the cells keep the width, the other columns start
+        // from the estimated width of the text, and share the remaining space.
+        let rem_size = window.rem_size();
+        let mut col_widths: Vec<Option<DefiniteLength>> = vec![];
+        let mut col_bases: Vec<Pixels> = vec![];
+        for row in table.children.iter() {
+            for (ix, cell) in row.children.iter().enumerate() {
+                if col_bases.len() <= ix {
+                    col_widths.push(None);
+                    col_bases.push(px(0.));
+                }
+
+                let len = cell.text_len().clamp(MIN_LENGTH, MAX_LENGTH);
+                // Including the horizontal padding of the cell.
+                let basis = rems(len as f32 * CHAR_WIDTH + 1.).to_pixels(rem_size);
+                col_bases[ix] = col_bases[ix].max(basis);
+                if col_widths[ix].is_none() {
+                    col_widths[ix] = cell.width;
                 }
-                col_lens
             }
-            _ => vec![],
-        };
+        }
+        // The declared widths of `<col>` are used if no cell in the column has a width, the
+        // extra `<col>` without the cells are ignored.
+        for (ix, width) in col_widths.iter_mut().enumerate() {
+            if width.is_none() {
+                *width = table.column_width(ix);
+            }
+        }
 
-        match item {
-            BlockNode::Table(table) => div()
-                .pb(rems(1.))
+        // Scroll horizontally if the natural width is wider than the container.
+        let min_width =
+            col_widths
+                .iter()
+                .zip(col_bases.iter())
+                .fold(px(0.), |total, (width, basis)| {
+                    total
+                        + match width {
+                            Some(DefiniteLength::Absolute(width)) => width.to_pixels(rem_size),
+                            Some(DefiniteLength::Fraction(_)) => px(0.),
+                            None => *basis,
+                        }
+                });
+
+        let rows_len = table.children.len();
+        let header_len = table.header_len();
+        let style = &node_cx.style;
+        let render_row = |row_ix: usize,
+                          row: &TableRow,
+                          checkbox_ix: &mut usize,
+                          window: &mut Window,
+                          cx: &mut App| {
+            let is_header = row_ix < header_len;
+            // Stripe the even rows of the body, counting from 1.
+            let is_even = !is_header && !row.is_footer && (row_ix - header_len) % 2 == 1;
+
+            div()
+                .id(("row", row_ix))
                 .w_full()
-                .child(
-                    div()
-                        .id(("table", options.ix))
-                        .w_full()
-                        .border_1()
-                        .border_color(cx.theme().border)
-                        .rounded(cx.theme().radius)
-                        .children({
-                            let mut rows = Vec::with_capacity(table.children.len());
-                            for (row_ix, row) in table.children.iter().enumerate() {
-                                rows.push(
-                                    div()
-                                        .id("row")
-                                        .w_full()
-                                        .when(row_ix < table.children.len() - 1, |this| {
-                                            this.border_b_1()
-                                        })
-                                        .border_color(cx.theme().border)
-                                        .flex()
-                                        .flex_row()
-                                        .children({
-                                            let mut cells = Vec::with_capacity(row.children.len());
-                                            for (ix, cell) in row.children.iter().enumerate() {
-                                                let align = table.column_align(ix);
-                                                let is_last_col = ix == row.children.len() - 1;
-                                                let len = col_lens
-                                                    .get(ix)
-                                                    .copied()
-                                                    .unwrap_or(MAX_LENGTH)
-                                                    .min(MAX_LENGTH);
-
-                                                cells.push(
-                                                    div()
-                                                        .id(("cell", ix))
-                                                        .overflow_hidden()
-                                                        .when(
-                                                            align == ColumnumnAlign::Center,
-                                                            |this| this.text_center(),
-                                                        )
-                                                        .when(
-                                                            align == ColumnumnAlign::Right,
-                                                            |this| this.text_right(),
-                                                        )
-                                                        .min_w_16()
-                                                        .w(Length::Definite(relative(len as f32)))
-                                                        .px_2()
-                                                        .py_1()
-                                                        .when(!is_last_col, |this| {
-                                                            this.border_r_1()
-                                                                .border_color(cx.theme().border)
-                                                        })
-                                                        .child(
-                                                            cell.children
-                                                                .render(node_cx, window, cx),
-                                                        ),
-                                                )
-                                            }
-                                            cells
-                                        }),
-                                )
-                            }
-                            rows
-                        }),
-                )
-                .into_any_element(),
-            _ => div().into_any_element(),
-        }
+                .when(row_ix < rows_len - 1, |this| this.border_b_1())
+                .border_color(cx.theme().table_border)
+                .when(is_header && style.table_header, |this| {
+                    this.bg(cx.theme().table_head)
+                        .text_color(cx.theme().table_head_foreground)
+                        .font_semibold()
+                })
+                .when(is_even && style.table_striped, |this| {
+                    this.bg(cx.theme().table_even)
+                })
+                .when(row.is_footer, |this| {
+                    this.bg(cx.theme().table_foot)
+                        .text_color(cx.theme().table_foot_foreground)
+                        .font_semibold()
+                })
+                .flex()
+                .flex_row()
+                .children({
+                    let mut cells = Vec::with_capacity(row.children.len());
+                    for (ix, cell) in row.children.iter().enumerate() {
+                        let align = table.column_align(ix);
+                        let is_last_col = ix == row.children.len() - 1;
+
+                        cells.push(
+                            div()
+                                .id(("cell", ix))
+                                .overflow_hidden()
+                                .when(align == ColumnumnAlign::Center, |this| this.text_center())
+                                .when(align == ColumnumnAlign::Right, |this| this.text_right())
+                                .min_w_16()
+                                .map(|this| match col_widths.get(ix).copied().flatten() {
+                                    Some(width) => this.flex_none().w(width),
+                                    None => this
+                                        .flex_grow()
+                                        .flex_shrink_0()
+                                        .flex_basis(col_bases.get(ix).copied().unwrap_or_default()),
+                                })
+                                .px_2()
+                                .py_1()
+                                .when(!is_last_col, |this| {
+                                    this.border_r_1().border_color(cx.theme().table_border)
+                                })
+                                .child(cell.render(*checkbox_ix, node_cx, window, cx)),
+                        );
+                        *checkbox_ix += cell
+                            .children
+                            .iter()
+                            .map(BlockNode::checkbox_count)
+                            .sum::<usize>();
+                    }
+                    cells
+                })
+        };
+
+        // The header rows are pinned, only the body scrolls vertically.
+        let (header, body) = table.children.split_at(header_len);
+        let mut checkbox_ix = options.checkbox_ix;
+        let header = header
+            .iter()
+            .enumerate()
+            .map(|(row_ix, row)| render_row(row_ix, row, &mut checkbox_ix, window, cx))
+            .collect::<Vec<_>>();
+        let body = body
+            .iter()
+            .enumerate()
+            .map(|(ix, row)| render_row(header.len() + ix, row, &mut checkbox_ix, window, cx))
+            .collect::<Vec<_>>();
+
+        div()
+            .pb(rems(1.))
+            .w_full()
+            .child(
+                div()
+                    .id(("table", options.ix))
+                    .w_full()
+                    .border_1()
+                    .border_color(cx.theme().table_border)
+                    .rounded(cx.theme().radius)
+                    .overflow_hidden()
+                    .child(
+                        v_flex()
+                            .w_full()
+                            .min_w(min_width)
+                            .children(header)
+                            .child(
+                                div()
+                                    .id("body")
+                                    .w_full()
+                                    .when_some(node_cx.style.table_max_height, |this, height| {
+                                        this.max_h(height).overflow_y_scroll()
+                                    })
+                                    .children(body),
+                            )
+                            .overflow_x_scrollbar(),
+                    ),
+            )
+            .into_any_element()
     }
 
     pub(crate) fn render_block(
@@ -1163,34 +5004,49 @@ impl BlockNode {
         };
 
         match self {
-            BlockNode::Root { children, .. } => div()
-                .id(("div", ix))
-                .children(children.into_iter().enumerate().map(move |(ix, node)| {
-                    node.render_block(NodeRenderOptions { ix, ..options }, node_cx, window, cx)
-                }))
-                .into_any_element(),
-            BlockNode::Paragraph(paragraph) => div()
-                .id(("p", ix))
-                .pb(mb)
-                .child(paragraph.render(node_cx, window, cx))
-                .into_any_element(),
+            BlockNode::Root { children, .. } => {
+                let mut checkbox_ix = options.checkbox_ix;
+                div()
+                    .id(("div", ix))
+                    .children(children.into_iter().enumerate().map(move |(ix, node)| {
+                        let options = NodeRenderOptions {
+                            ix,
+                            checkbox_ix,
+                            ..options
+                        };
+                        checkbox_ix += node.checkbox_count();
+                        node.render_block(options, node_cx, window, cx)
+                    }))
+                    .into_any_element()
+            }
+            BlockNode::Paragraph(paragraph) => {
+                let drop_cap = node_cx
+                    .drop_cap_paragraph
+                    .as_ref()
+                    .is_some_and(|state| Arc::ptr_eq(state, &paragraph.state));
+                let first_line = if drop_cap {
+                    FirstLine::DropCap
+                } else if node_cx.style.first_line_indent > 0. && options.depth == 0 {
+                    FirstLine::Indent
+                } else {
+                    FirstLine::Normal
+                };
+                div()
+                    .id(("p", ix))
+                    .pb(mb)
+                    .child(paragraph.render_with(first_line, node_cx, window, cx))
+                    .into_any_element()
+            }
             BlockNode::Heading {
                 level, children, ..
             } => {
-                let (text_size, font_weight) = match level {
-                    1 => (rems(2.), FontWeight::BOLD),
-                    2 => (rems(1.5), FontWeight::SEMIBOLD),
-                    3 => (rems(1.25), FontWeight::SEMIBOLD),
-                    4 => (rems(1.125), FontWeight::SEMIBOLD),
-                    5 => (rems(1.), FontWeight::SEMIBOLD),
-                    6 => (rems(1.), FontWeight::MEDIUM),
-                    _ => (rems(1.), FontWeight::NORMAL),
+                let font_weight = match level {
+                    1 => FontWeight::BOLD,
+                    2..=5 => FontWeight::SEMIBOLD,
+                    6 => FontWeight::MEDIUM,
+                    _ => FontWeight::NORMAL,
                 };
-
-                let mut text_size = text_size.to_pixels(node_cx.style.heading_base_font_size);
-                if let Some(f) = node_cx.style.heading_font_size.as_ref() {
-                    text_size = (f)(*level, node_cx.style.heading_base_font_size);
-                }
+                let text_size = node_cx.style.heading_size(*level);
 
                 h_flex()
                     .id(SharedString::from(format!("h{}-{}", level, ix)))
@@ -1198,69 +5054,214 @@ impl BlockNode {
                     .whitespace_normal()
                     .text_size(text_size)
                     .font_weight(font_weight)
-                    .child(children.render(node_cx, window, cx))
+                    // Let the inline images in heading (e.g. emoji) be constrained by heading width.
+                    .child(
+                        div()
+                            .flex_1()
+                            .min_w_0()
+                            .child(children.render(node_cx, window, cx)),
+                    )
                     .into_any_element()
             }
-            BlockNode::Blockquote { children, .. } => div()
-                .w_full()
-                .pb(mb)
-                .child(
-                    div()
-                        .id(("blockquote", ix))
-                        .w_full()
-                        .text_color(cx.theme().muted_foreground)
-                        .border_l_3()
-                        .border_color(cx.theme().secondary_active)
-                        .px_4()
-                        .children({
-                            let children_len = children.len();
-                            children.into_iter().enumerate().map(move |(index, c)| {
-                                let is_last = index == children_len - 1;
-                                c.render_block(options.is_last(is_last), node_cx, window, cx)
+            BlockNode::Blockquote { children, .. } => {
+                // Each nested level draws its own left border bar inside the parent,
+                // the bar is on the right for the right-to-left text.
+                let nested = options.quote_depth > 0;
+                let is_rtl = self.is_rtl();
+                let options = NodeRenderOptions {
+                    quote_depth: options.quote_depth + 1,
+                    ..options
+                };
+
+                div()
+                    .w_full()
+                    .pb(mb)
+                    .child(
+                        div()
+                            .id(("blockquote", ix))
+                            .w_full()
+                            .text_color(cx.theme().muted_foreground)
+                            .map(|this| {
+                                if is_rtl {
+                                    this.border_r_3()
+                                } else {
+                                    this.border_l_3()
+                                }
                             })
-                        }),
-                )
-                .into_any_element(),
+                            .border_color(cx.theme().blockquote_border)
+                            .map(|this| {
+                                let padding = node_cx.style.blockquote_padding;
+                                if nested && is_rtl {
+                                    this.pr(rems(padding.0 * 0.75))
+                                } else if nested {
+                                    this.pl(rems(padding.0 * 0.75))
+                                } else {
+                                    this.px(padding)
+                                }
+                            })
+                            .children({
+                                let children_len = children.len();
+                                let mut checkbox_ix = options.checkbox_ix;
+                                children.into_iter().enumerate().map(move |(index, c)| {
+                                    let is_last = index == children_len - 1;
+                                    let options = NodeRenderOptions {
+                                        checkbox_ix,
+                                        ..options.is_last(is_last)
+                                    };
+                                    checkbox_ix += c.checkbox_count();
+                                    c.render_block(options, node_cx, window, cx)
+                                })
+                            }),
+                    )
+                    .into_any_element()
+            }
+            BlockNode::Admonition { kind, children, .. } => {
+                let color = kind.color(cx);
+                let is_rtl = self.is_rtl();
+
+                div()
+                    .w_full()
+                    .pb(mb)
+                    .child(
+                        v_flex()
+                            .id(("admonition", ix))
+                            .w_full()
+                            .gap_1()
+                            .map(|this| {
+                                if is_rtl {
+                                    this.border_r_3()
+                                } else {
+                                    this.border_l_3()
+                                }
+                            })
+                            .border_color(color)
+                            .bg(color.mix_oklab(transparent_white(), 0.04))
+                            .px_4()
+                            .py_2()
+                            .child(
+                                h_flex()
+                                    .gap_2()
+                                    .when(is_rtl, |this| this.flex_row_reverse())
+                                    .text_color(color)
+                                    .font_semibold()
+                                    .child(Icon::new(kind.icon()).size_4())
+                                    .child(kind.title()),
+                            )
+                            .children({
+                                let children_len = children.len();
+                                let mut checkbox_ix = options.checkbox_ix;
+                                children.into_iter().enumerate().map(move |(index, c)| {
+                                    let is_last = index == children_len - 1;
+                                    let options = NodeRenderOptions {
+                                        checkbox_ix,
+                                        ..options.is_last(is_last)
+                                    };
+                                    checkbox_ix += c.checkbox_count();
+                                    c.render_block(options, node_cx, window, cx)
+                                })
+                            }),
+                    )
+                    .into_any_element()
+            }
             BlockNode::List {
                 children, ordered, ..
             } => v_flex()
                 .id((if *ordered { "ol" } else { "ul" }, ix))
                 .pb(mb)
+                .gap(if self.is_loose_list() {
+                    node_cx.style.paragraph_gap
+                } else {
+                    node_cx.style.list_item_gap
+                })
                 .children({
                     let mut items = Vec::with_capacity(children.len());
-                    let mut item_index = 0;
-                    for (ix, item) in children.into_iter().enumerate() {
-                        let is_item = item.is_list_item();
-
+                    let markers = self.list_item_markers(options.depth, &node_cx.style);
+                    let gutter = ordered.then(|| MarkerGutter::new(markers.clone()));
+                    let mut checkbox_ix = options.checkbox_ix;
+                    for (ix, (item, item_marker)) in children.iter().zip(&markers).enumerate() {
                         items.push(Self::render_list_item(
                             item,
-                            item_index,
+                            item_marker,
+                            gutter.as_ref(),
                             NodeRenderOptions {
                                 ix,
                                 ordered: *ordered,
+                                checkbox_ix,
                                 ..options
                             },
                             node_cx,
                             window,
                             cx,
                         ));
-
-                        if is_item {
-                            item_index += 1;
-                        }
+                        checkbox_ix += item.checkbox_count();
                     }
                     items
                 })
                 .into_any_element(),
             BlockNode::CodeBlock(code_block) => code_block.render(&options, node_cx, window, cx),
+            BlockNode::Math { formula, .. } => {
+                div()
+                    .pb(mb)
+                    .child(
+                        div().id(("math", ix)).w_full().overflow_x_scroll().map(
+                            |this| match node_cx.math_renderer.as_ref() {
+                                Some(render) => this.child(render(formula, true, window, cx)),
+                                // Fallback to the raw formula in monospace.
+                                None => {
+                                    this.py_2()
+                                        .flex()
+                                        .justify_center()
+                                        .font_family(
+                                            node_cx.style.code_font_family.clone().unwrap_or_else(
+                                                || cx.theme().mono_font_family.clone(),
+                                            ),
+                                        )
+                                        .text_size(
+                                            node_cx
+                                                .style
+                                                .code_font_size
+                                                .unwrap_or(cx.theme().mono_font_size),
+                                        )
+                                        .whitespace_nowrap()
+                                        .child(formula.clone())
+                                }
+                            },
+                        ),
+                    )
+                    .into_any_element()
+            }
             BlockNode::Table { .. } => {
                 Self::render_table(self, &options, node_cx, window, cx).into_any_element()
             }
-            BlockNode::HorizontalRule { .. } => div()
+            BlockNode::HorizontalRule { style, .. } => div()
+                .pb(mb)
+                .child(div().id("horizontal-rule").map(|this| {
+                    if *style == RuleStyle::default() {
+                        this.bg(cx.theme().border).h(px(2.))
+                    } else {
+                        this.border_t(style.thickness.unwrap_or(px(2.)))
+                            .border_color(style.color.unwrap_or(cx.theme().border))
+                            .when(style.dashed, |this| this.border_dashed())
+                    }
+                }))
+                .into_any_element(),
+            // A blank line between the blocks, the consecutive breaks are collapsed on parsing.
+            BlockNode::Break { .. } => div().id("break").h(window.line_height()).into_any_element(),
+            BlockNode::Embed(embed) => div()
                 .pb(mb)
-                .child(div().id("horizontal-rule").bg(cx.theme().border).h(px(2.)))
+                .child(embed.render(ix, node_cx, cx))
                 .into_any_element(),
-            BlockNode::Break { .. } => div().id("break").into_any_element(),
+            // The inner text is rendered if the renderer is not set, e.g.: the deserialized nodes.
+            BlockNode::Custom(block) => div()
+                .pb(mb)
+                .map(|this| match node_cx.custom_block_renderer.as_ref() {
+                    Some(renderer) => this.child((renderer.render)(block, window, cx)),
+                    None => this.child(block.text.clone()),
+                })
+                .into_any_element(),
+            BlockNode::Footnotes { .. } => {
+                Self::render_footnotes(self, options, node_cx, window, cx)
+            }
             BlockNode::Unknown { .. } | BlockNode::Definition { .. } => div().into_any_element(),
             _ => {
                 if cfg!(debug_assertions) {
@@ -0,0 +1,275 @@
+//! The in-text search of the [`TextViewState`], see [`TextViewState::find`].
+//!
+//! The document offsets are the byte offsets in the text of all [`Inline`] elements joined in
+//! document order, the images (e.g.: emoji) split the text of a paragraph but have no text.
+//! The text of a paragraph is searched as a whole, so a match may span the [`Inline`] elements.
+//!
+//! [`TextViewState`]: crate::text::TextViewState
+//! [`TextViewState::find`]: crate::text::TextViewState::find
+//! [`Inline`]: crate::text::inline::Inline
+
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use gpui::SharedString;
+
+use crate::text::{document::ParsedDocument, inline::InlineState};
+
+/// A match of the search.
+#[derive(Clone)]
+pub(super) struct SearchMatch {
+    /// The range in the document offsets.
+    pub(super) range: Range<usize>,
+    /// The index of the top-level block containing the match.
+    pub(super) block_ix: usize,
+    /// The states of the inline texts containing the match, with the range in each inline text,
+    /// the highlight is saved into them.
+    parts: Vec<(Arc<Mutex<InlineState>>, Range<usize>)>,
+}
+
+/// The search query and the matches of a document.
+#[derive(Clone)]
+pub(super) struct SearchState {
+    pub(super) query: SharedString,
+    pub(super) case_sensitive: bool,
+    pub(super) matches: Vec<SearchMatch>,
+    /// The index of the active match in `matches`.
+    pub(super) active: Option<usize>,
+}
+
+impl SearchState {
+    /// Find the `query` in the `document`, and highlight the matches.
+    pub(super) fn new(query: &str, case_sensitive: bool, document: &ParsedDocument) -> Self {
+        let mut matches = vec![];
+        let mut offset = 0;
+        for (block_ix, block) in document.blocks.iter().enumerate() {
+            let mut segments = vec![];
+            block.text_segments(&mut segments);
+
+            for paragraph_segments in segments {
+                let mut text = String::new();
+                for (state, segment_text) in paragraph_segments.iter() {
+                    let mut state = state.lock().unwrap();
+                    state.search_matches.clear();
+                    state.active_match = None;
+                    text.push_str(segment_text);
+                }
+
+                for range in find_matches(&text, query, case_sensitive) {
+                    // Split the match by the inline texts, e.g.: around an image.
+                    let mut parts = vec![];
+                    let mut segment_start = 0;
+                    for (state, segment_text) in paragraph_segments.iter() {
+                        let segment_end = segment_start + segment_text.len();
+                        let start = range.start.max(segment_start);
+                        let end = range.end.min(segment_end);
+                        if start < end {
+                            let local_range = (start - segment_start)..(end - segment_start);
+                            state
+                                .lock()
+                                .unwrap()
+                                .search_matches
+                                .push(local_range.clone());
+                            parts.push((state.clone(), local_range));
+                        }
+                        segment_start = segment_end;
+                    }
+
+                    matches.push(SearchMatch {
+                        range: (offset + range.start)..(offset + range.end),
+                        block_ix,
+                        parts,
+                    });
+                }
+
+                offset += text.len();
+            }
+        }
+
+        Self {
+            query: query.to_string().into(),
+            case_sensitive,
+            matches,
+            active: None,
+        }
+    }
+
+    /// Set the active match, it is highlighted in a distinct color.
+    pub(super) fn set_active(&mut self, active: Option<usize>) {
+        if let Some(item) = self.active.and_then(|ix| self.matches.get(ix)) {
+            for (state, _) in item.parts.iter() {
+                state.lock().unwrap().active_match = None;
+            }
+        }
+
+        self.active = active.filter(|ix| *ix < self.matches.len());
+        if let Some(item) = self.active.and_then(|ix| self.matches.get(ix)) {
+            for (state, local_range) in item.parts.iter() {
+                state.lock().unwrap().active_match = Some(local_range.clone());
+            }
+        }
+    }
+
+    /// Remove the highlights of the matches.
+    pub(super) fn clear(&self) {
+        for (state, _) in self.matches.iter().flat_map(|item| item.parts.iter()) {
+            let mut state = state.lock().unwrap();
+            state.search_matches.clear();
+            state.active_match = None;
+        }
+    }
+
+    pub(super) fn ranges(&self) -> Vec<Range<usize>> {
+        self.matches.iter().map(|item| item.range.clone()).collect()
+    }
+}
+
+/// Find the non-overlapping `query` in the `text`, returns the byte ranges.
+///
+/// The case-insensitive search compares the lowercase chars, so the ranges are always in the
+/// original `text` even the lowercase has a different length.
+pub(super) fn find_matches(text: &str, query: &str, case_sensitive: bool) -> Vec<Range<usize>> {
+    if query.is_empty() {
+        return vec![];
+    }
+
+    if case_sensitive {
+        return text
+            .match_indices(query)
+            .map(|(ix, matched)| ix..ix + matched.len())
+            .collect();
+    }
+
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let mut matches = vec![];
+    let mut next_start = 0;
+    for (start, _) in text.char_indices() {
+        if start < next_start {
+            continue;
+        }
+
+        let mut chars = text[start..].char_indices().flat_map(|(ix, c)| {
+            let end = start + ix + c.len_utf8();
+            c.to_lowercase().map(move |c| (end, c))
+        });
+
+        let mut end = None;
+        for expected in query.iter() {
+            match chars.next() {
+                Some((char_end, c)) if c == *expected => end = Some(char_end),
+                _ => {
+                    end = None;
+                    break;
+                }
+            }
+        }
+
+        if let Some(end) = end {
+            matches.push(start..end);
+            next_start = end;
+        }
+    }
+
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SearchState, find_matches};
+    use crate::{
+        highlighter::HighlightTheme,
+        text::{format, node::NodeContext},
+    };
+
+    #[test]
+    fn test_find_matches() {
+        assert!(find_matches("Hello world", "", false).is_empty());
+        assert_eq!(find_matches("Hello hello", "hello", true), vec![6..11]);
+        assert_eq!(
+            find_matches("Hello hello", "HELLO", false),
+            vec![0..5, 6..11]
+        );
+        assert_eq!(find_matches("aaaa", "aa", false), vec![0..2, 2..4]);
+        assert_eq!(find_matches("Straße STRASSE", "straße", false), vec![0..7]);
+        assert_eq!(
+            find_matches("你好世界，世界", "世界", false),
+            vec![6..12, 15..21]
+        );
+    }
+
+    #[test]
+    fn test_search_state() {
+        let mut cx = NodeContext::default();
+        let document = format::markdown::parse(
+            "Hello **wor**ld ![img](https://a.com/1.png) world\n\n- The World",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+
+        let mut search = SearchState::new("world", false, &document);
+        // The match across the bold mark is found, the image splits the paragraph text.
+        assert_eq!(search.ranges(), vec![6..11, 13..18, 22..27]);
+        assert_eq!(
+            search
+                .matches
+                .iter()
+                .map(|item| item.block_ix)
+                .collect::<Vec<_>>(),
+            vec![0, 0, 1]
+        );
+
+        let state = |item: &super::SearchMatch, ix: usize| item.parts[ix].0.clone();
+        search.set_active(Some(1));
+        assert_eq!(
+            state(&search.matches[1], 0).lock().unwrap().active_match,
+            Some(1..6)
+        );
+        search.set_active(Some(2));
+        assert_eq!(
+            state(&search.matches[1], 0).lock().unwrap().active_match,
+            None
+        );
+        assert_eq!(
+            state(&search.matches[2], 0).lock().unwrap().search_matches,
+            vec![4..9]
+        );
+
+        search.clear();
+        assert!(
+            state(&search.matches[2], 0)
+                .lock()
+                .unwrap()
+                .search_matches
+                .is_empty()
+        );
+
+        // The match across the image is split into the inline texts around it.
+        let document = format::markdown::parse(
+            "Hel![img](https://a.com/1.png)lo world",
+            &mut cx,
+            &HighlightTheme::default_light(),
+        )
+        .unwrap();
+        let mut search = SearchState::new("hello", false, &document);
+        assert_eq!(search.ranges(), vec![0..5]);
+        let item = search.matches[0].clone();
+        assert_eq!(item.parts.len(), 2);
+        assert_eq!(item.parts[0].1, 0..3);
+        assert_eq!(item.parts[1].1, 0..2);
+        search.set_active(Some(0));
+        assert_eq!(state(&item, 0).lock().unwrap().active_match, Some(0..3));
+        assert_eq!(state(&item, 1).lock().unwrap().active_match, Some(0..2));
+        // The texts of the different paragraphs are not joined.
+        let document =
+            format::markdown::parse("Hel\n\nlo", &mut cx, &HighlightTheme::default_light())
+                .unwrap();
+        assert!(
+            SearchState::new("hello", false, &document)
+                .matches
+                .is_empty()
+        );
+    }
+}
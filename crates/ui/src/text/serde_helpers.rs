@@ -0,0 +1,128 @@
+//! The serde helpers for the AST fields that the GPUI types do not support serde.
+
+use gpui::{AbsoluteLength, DefiniteLength, SharedString, SharedUri, px, rems};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    highlighter::HighlightTheme,
+    text::node::{CodeBlock, Span},
+};
+
+/// Serialize [`SharedUri`] as a string.
+pub(super) mod shared_uri {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        uri: &SharedUri,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(uri)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SharedUri, D::Error> {
+        Ok(SharedUri::from(String::deserialize(deserializer)?))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Length {
+    Px(f32),
+    Rems(f32),
+    Fraction(f32),
+}
+
+/// Serialize `Option<DefiniteLength>` as `{ "px": 100.0 }`, `{ "rems": 1.0 }` or `{ "fraction": 0.5 }`.
+pub(super) mod option_length {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        length: &Option<DefiniteLength>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        length
+            .map(|length| match length {
+                DefiniteLength::Absolute(AbsoluteLength::Pixels(value)) => {
+                    Length::Px(value.as_f32())
+                }
+                DefiniteLength::Absolute(AbsoluteLength::Rems(value)) => Length::Rems(value.0),
+                DefiniteLength::Fraction(value) => Length::Fraction(value),
+            })
+            .serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DefiniteLength>, D::Error> {
+        Ok(
+            Option::<Length>::deserialize(deserializer)?.map(|length| match length {
+                Length::Px(value) => px(value).into(),
+                Length::Rems(value) => rems(value).into(),
+                Length::Fraction(value) => DefiniteLength::Fraction(value),
+            }),
+        )
+    }
+}
+
+/// Serialize `Vec<Option<DefiniteLength>>` as a list of [`option_length`].
+pub(super) mod option_lengths {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(transparent)]
+    struct OptionLength(#[serde(with = "option_length")] Option<DefiniteLength>);
+
+    pub(crate) fn serialize<S: Serializer>(
+        lengths: &[Option<DefiniteLength>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(lengths.iter().map(|length| OptionLength(*length)))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Option<DefiniteLength>>, D::Error> {
+        Ok(Vec::<OptionLength>::deserialize(deserializer)?
+            .into_iter()
+            .map(|length| length.0)
+            .collect())
+    }
+}
+
+/// The serialized form of [`CodeBlock`].
+///
+/// The highlight styles are not serialized, they are re-highlighted with the default light theme
+/// when deserializing.
+#[derive(Serialize, Deserialize)]
+pub(super) struct CodeBlockData {
+    lang: Option<SharedString>,
+    /// The full info string of the fence, e.g.: `rust,no_run title="example.rs"`.
+    #[serde(default)]
+    info: Option<SharedString>,
+    code: SharedString,
+    span: Option<Span>,
+}
+
+impl From<CodeBlock> for CodeBlockData {
+    fn from(code_block: CodeBlock) -> Self {
+        Self {
+            lang: code_block.lang(),
+            info: code_block.info(),
+            code: code_block.code(),
+            span: code_block.span,
+        }
+    }
+}
+
+impl From<CodeBlockData> for CodeBlock {
+    fn from(data: CodeBlockData) -> Self {
+        CodeBlock::with_info(
+            data.code,
+            data.info.or(data.lang),
+            &HighlightTheme::default_light(),
+            data.span,
+        )
+    }
+}
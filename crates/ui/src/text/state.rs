@@ -1,39 +1,70 @@
+use futures::Stream as _;
 use std::{
+    ops::Range,
     pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
     task::Poll,
 };
-use futures::Stream as _;
 
 use gpui::{
     App, AppContext as _, Bounds, ClipboardItem, Context, FocusHandle, IntoElement, KeyBinding,
-    ListState, ParentElement as _, Pixels, Point, Render, SharedString, Styled as _, Task, Window,
-    prelude::FluentBuilder as _, px,
+    ListOffset, ListState, Modifiers, ParentElement as _, Pixels, Point, Render, SharedString,
+    Size, Styled as _, Task, WeakEntity, Window, actions, prelude::FluentBuilder as _, px,
 };
+use rust_i18n::t;
 
 use crate::{
     ActiveTheme, ElementExt,
-    async_util::{Sender, Receiver, unbounded},
+    async_util::{Receiver, Sender, unbounded},
     highlighter::HighlightTheme,
     input::{self, Copy},
+    menu::{PopupMenu, PopupMenuItem},
     text::{
-        CodeBlockActionsFn, TextViewStyle,
-        document::ParsedDocument,
+        AnchorClickFn, CheckboxToggleFn, CodeBlockActionsFn, ContextMenuTarget, CopyFn, CopyFormat,
+        ImageClickFn, LinkClickEvent, LinkClickFn, LinkVisitedFn, MathRenderFn, TextViewStyle,
+        document::{BlockPrepaintFn, ParsedDocument, TocEntry},
         format,
-        node::{self, NodeContext},
+        inline::{InlineState, open_link},
+        node::{
+            self, CustomBlockRenderer, Diagnostic, EmbedProvider, ImageNode, LinkMark, Mention,
+            MentionResolveFn, NodeContext, TimeFormatFn,
+        },
+        search::SearchState,
     },
     v_flex,
 };
 
+actions!(text_view, [FocusNextLink, FocusPrevLink, OpenFocusedLink]);
+
 const CONTEXT: &'static str = "TextView";
+/// The distance to the top or bottom edge to auto-scroll while drag-selecting.
+const AUTOSCROLL_MARGIN: Pixels = px(32.);
+/// The max auto-scroll distance per frame, when the mouse is at or beyond the edge.
+const AUTOSCROLL_MAX_SPEED: Pixels = px(24.);
+
 pub(crate) fn init(cx: &mut App) {
     cx.bind_keys(vec![
         #[cfg(target_os = "macos")]
         KeyBinding::new("cmd-c", input::Copy, Some(CONTEXT)),
         #[cfg(not(target_os = "macos"))]
         KeyBinding::new("ctrl-c", input::Copy, Some(CONTEXT)),
+        KeyBinding::new("tab", FocusNextLink, Some(CONTEXT)),
+        KeyBinding::new("shift-tab", FocusPrevLink, Some(CONTEXT)),
+        KeyBinding::new("enter", OpenFocusedLink, Some(CONTEXT)),
+        KeyBinding::new("space", OpenFocusedLink, Some(CONTEXT)),
     ]);
 }
 
+/// The link focused by the keyboard, see [`TextViewState::on_action_focus_next_link`].
+struct FocusedLink {
+    /// The index of the link in document order.
+    ix: usize,
+    /// The state of the inline text containing the link, the focus ring is painted by it.
+    state: Arc<Mutex<InlineState>>,
+    link: LinkMark,
+}
+
 /// The content format of the text view.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub(super) enum TextViewFormat {
@@ -53,15 +84,62 @@ pub struct TextViewState {
 
     pub(super) selectable: bool,
     pub(super) scrollable: bool,
+    sanitize: bool,
+    /// The window viewport size, used to resolve the `vw` and `vh` lengths on parsing.
+    viewport_size: Option<Size<Pixels>>,
+    /// The providers to recognize the `<iframe>` embeds, see [`TextViewState::register_embed_provider`].
+    embed_providers: Arc<Vec<EmbedProvider>>,
+    /// The resolver of the `@mentions`, see [`TextViewState::set_mention_resolver`].
+    mention_resolver: Option<Arc<MentionResolveFn>>,
+    /// The formatter of the `<time>`, see [`TextViewState::set_time_formatter`].
+    time_formatter: Option<Arc<TimeFormatFn>>,
+    /// The renderer of the custom blocks, see [`TextViewState::set_custom_block_renderer`].
+    custom_block_renderer: Option<Arc<CustomBlockRenderer>>,
+    /// Whether to collect the diagnostics, see [`TextViewState::set_collect_diagnostics`].
+    collect_diagnostics: bool,
+    /// Whether to replace the `:shortcode:` with the emoji, see [`TextViewState::set_emoji_shortcodes`].
+    emoji_shortcodes: bool,
+    /// Whether to keep the semantic tags in the Markdown, see [`TextViewState::set_keep_semantic_tags`].
+    keep_semantic_tags: bool,
+    /// The reading speed, see [`TextViewState::set_words_per_minute`].
+    words_per_minute: usize,
     pub(super) text_view_style: TextViewStyle,
     pub(super) code_block_actions: Option<std::sync::Arc<CodeBlockActionsFn>>,
+    pub(super) math_renderer: Option<std::sync::Arc<MathRenderFn>>,
+    pub(super) animate_images: bool,
+    pub(super) copy_hidden_spoilers: bool,
+    pub(super) copy_emoji_shortcodes: bool,
+    pub(super) max_image_width: Option<Pixels>,
+    pub(super) max_image_height: Option<Pixels>,
+    pub(super) on_image_click: Option<std::sync::Arc<ImageClickFn>>,
+    pub(super) on_link_click: Option<std::sync::Arc<LinkClickFn>>,
+    pub(super) link_visited: Option<std::sync::Arc<LinkVisitedFn>>,
+    pub(super) on_checkbox_toggle: Option<std::sync::Arc<CheckboxToggleFn>>,
+    pub(super) on_copy: Option<std::sync::Arc<CopyFn>>,
+    /// The link or image under the mouse on the last right click, for the context menu.
+    pub(super) context_menu_target: Option<ContextMenuTarget>,
+    /// The in-text search, see [`TextViewState::find`].
+    search: Option<SearchState>,
+    focused_link: Option<FocusedLink>,
 
     pub(super) is_selecting: bool,
     /// The local (in TextView) position of the selection.
     selection_positions: (Option<Point<Pixels>>, Option<Point<Pixels>>),
+    /// The last mouse position (in window) while drag-selecting, to auto-scroll near the edges.
+    selection_mouse_position: Option<Point<Pixels>>,
+    /// The vertical range of the top-level blocks in content coordinates, recorded when the block
+    /// is laid out in the scrollable mode, used to select the blocks that are not realized.
+    block_ranges: Vec<Option<Range<Pixels>>>,
 
     pub(super) parsed_content: ParsedContent,
     text: SharedString,
+    /// The format of the view, the text set by [`TextViewState::set_text`] is parsed in it.
+    format: TextViewFormat,
+    /// The format of the `text`, Markdown after [`TextViewState::set_document`].
+    text_format: TextViewFormat,
+    /// Increased on [`TextViewState::set_document`], the results of the parsing started before it
+    /// are dropped.
+    generation: usize,
     parsed_error: Option<SharedString>,
     tx: Sender<UpdateOptions>,
     _parse_task: Task<()>,
@@ -71,34 +149,42 @@ pub struct TextViewState {
 impl TextViewState {
     /// Create a Markdown TextViewState.
     pub fn markdown(text: &str, cx: &mut Context<Self>) -> Self {
-        Self::new(TextViewFormat::Markdown, text, cx)
+        Self::new(TextViewFormat::Markdown, text, false, cx)
     }
 
     /// Create a HTML TextViewState.
     pub fn html(text: &str, cx: &mut Context<Self>) -> Self {
-        Self::new(TextViewFormat::Html, text, cx)
+        Self::new(TextViewFormat::Html, text, false, cx)
     }
 
     /// Create a new TextViewState.
-    fn new(format: TextViewFormat, text: &str, cx: &mut Context<Self>) -> Self {
+    pub(super) fn new(
+        format: TextViewFormat,
+        text: &str,
+        sanitize: bool,
+        cx: &mut Context<Self>,
+    ) -> Self {
         let focus_handle = cx.focus_handle();
 
         let (tx, rx) = unbounded::<UpdateOptions>();
-        let (tx_result, rx_result) = unbounded::<Result<ParsedContent, SharedString>>();
+        let (tx_result, rx_result) = unbounded::<(usize, Result<ParsedContent, SharedString>)>();
         let _receive_task = cx.spawn({
             async move |weak_self, cx| {
-                while let Ok(parsed_result) = rx_result.recv().await {
+                while let Ok((generation, parsed_result)) = rx_result.recv().await {
                     _ = weak_self.update(cx, |state, cx| {
+                        if generation != state.generation {
+                            return;
+                        }
+
                         match parsed_result {
                             Ok(content) => {
-                                state.parsed_content = content;
+                                state.replace_content(content);
                                 state.parsed_error = None;
                             }
                             Err(err) => {
                                 state.parsed_error = Some(err);
                             }
                         }
-                        state.clear_selection();
                         cx.notify();
                     });
                 }
@@ -111,15 +197,44 @@ impl TextViewState {
             focus_handle,
             bounds: Bounds::default(),
             selection_positions: (None, None),
+            selection_mouse_position: None,
+            block_ranges: vec![],
             selectable: false,
             scrollable: false,
+            sanitize,
+            viewport_size: None,
+            embed_providers: Arc::new(EmbedProvider::builtin()),
+            mention_resolver: None,
+            time_formatter: None,
+            custom_block_renderer: None,
+            collect_diagnostics: false,
+            emoji_shortcodes: false,
+            keep_semantic_tags: false,
+            words_per_minute: node::DEFAULT_WORDS_PER_MINUTE,
             list_state: ListState::new(0, gpui::ListAlignment::Top, px(1000.)),
             text_view_style: TextViewStyle::default(),
             code_block_actions: None,
+            math_renderer: None,
+            animate_images: true,
+            copy_hidden_spoilers: false,
+            copy_emoji_shortcodes: false,
+            max_image_width: None,
+            max_image_height: None,
+            on_image_click: None,
+            on_link_click: None,
+            link_visited: None,
+            on_checkbox_toggle: None,
+            on_copy: None,
+            context_menu_target: None,
+            search: None,
+            focused_link: None,
             is_selecting: false,
             parsed_content: Default::default(),
             parsed_error: None,
             text: text.to_string().into(),
+            format,
+            text_format: format,
+            generation: 0,
             tx,
             _parse_task,
             _receive_task,
@@ -157,6 +272,134 @@ impl TextViewState {
         cx.notify();
     }
 
+    /// Set whether to sanitize the untrusted content, default false.
+    ///
+    /// When enabled, the disallowed HTML tags (e.g. `<script>`, `<iframe>`) and event-handler attributes
+    /// are removed, and the links with disallowed schemes (e.g. `javascript:`, `data:`) become plain text.
+    ///
+    /// The content will be re-parsed if changed.
+    pub fn set_sanitize(&mut self, sanitize: bool, cx: &mut Context<Self>) {
+        if self.sanitize == sanitize {
+            return;
+        }
+
+        self.sanitize = sanitize;
+        self.parsed_error = None;
+        self.increment_update(&self.text.clone(), false, cx);
+    }
+
+    /// Register a provider of the `<iframe>` embeds in the HTML, the built-in providers are
+    /// YouTube and Vimeo.
+    ///
+    /// The embeds are rendered as link cards with the title and the thumbnail of the provider,
+    /// the content will be re-parsed.
+    pub fn register_embed_provider(&mut self, provider: EmbedProvider, cx: &mut Context<Self>) {
+        Arc::make_mut(&mut self.embed_providers).push(provider);
+        self.parsed_error = None;
+        self.increment_update(&self.text.clone(), false, cx);
+    }
+
+    /// Set the resolver of the `@mentions`, the argument is the handle without `@`, e.g.: `jason`.
+    ///
+    /// The resolved mentions are rendered as the chips of the display name, and open the url
+    /// on click, the unresolved ones and the mentions inside the code spans are plain text.
+    /// The content will be re-parsed.
+    pub fn set_mention_resolver<F>(&mut self, f: F, cx: &mut Context<Self>)
+    where
+        F: Fn(&str) -> Option<Mention> + Send + Sync + 'static,
+    {
+        self.mention_resolver = Some(Arc::new(f));
+        self.parsed_error = None;
+        self.increment_update(&self.text.clone(), false, cx);
+    }
+
+    /// Set the formatter of the `<time datetime="...">`, e.g.: to the locale and the timezone of
+    /// the user, or the relative time like `2 days ago`.
+    ///
+    /// The arguments are the `datetime` attribute and the original text, the original text is
+    /// kept if it returns `None`. The time is formatted on render, so the relative time is
+    /// refreshed on the next render (e.g.: `cx.notify()` by a timer), the original text is kept in
+    /// the document (e.g.: [`TextViewState::to_html`]), and the formatted text is copied as
+    /// displayed.
+    pub fn set_time_formatter<F>(&mut self, f: F, cx: &mut Context<Self>)
+    where
+        F: Fn(&str, &str) -> Option<SharedString> + Send + Sync + 'static,
+    {
+        self.time_formatter = Some(Arc::new(f));
+        cx.notify();
+    }
+
+    /// Set the renderer of the custom blocks, the HTML elements (including the HTML in Markdown)
+    /// matched by the tag or class are rendered by the app with the attributes and inner text.
+    ///
+    /// The content will be re-parsed.
+    pub fn set_custom_block_renderer(
+        &mut self,
+        renderer: CustomBlockRenderer,
+        cx: &mut Context<Self>,
+    ) {
+        self.custom_block_renderer = Some(Arc::new(renderer));
+        self.parsed_error = None;
+        self.increment_update(&self.text.clone(), false, cx);
+    }
+
+    /// Set whether to replace the `:shortcode:` (e.g.: `:smile:`) with the Unicode emoji, default
+    /// is false.
+    ///
+    /// The emoji are rendered with the color emoji font of the platform, the unknown shortcodes and
+    /// the shortcodes inside the code spans are plain text. The shortcodes are kept in the
+    /// Markdown, see [`TextView::copy_emoji_shortcodes`] to copy them.
+    /// The content will be re-parsed.
+    ///
+    /// [`TextView::copy_emoji_shortcodes`]: crate::text::TextView::copy_emoji_shortcodes
+    pub fn set_emoji_shortcodes(&mut self, enabled: bool, cx: &mut Context<Self>) {
+        if self.emoji_shortcodes == enabled {
+            return;
+        }
+
+        self.emoji_shortcodes = enabled;
+        self.parsed_error = None;
+        self.increment_update(&self.text.clone(), false, cx);
+    }
+
+    /// Set whether to keep the semantic tags (e.g.: `<cite>`, `<var>`) as the inline HTML in the
+    /// Markdown, default is false.
+    ///
+    /// The tags are always styled, e.g.: `<cite>` is italic and `<samp>` is monospace, the
+    /// Markdown uses the italic and the backticks if the tags are not kept.
+    /// The content will be re-parsed.
+    pub fn set_keep_semantic_tags(&mut self, enabled: bool, cx: &mut Context<Self>) {
+        if self.keep_semantic_tags == enabled {
+            return;
+        }
+
+        self.keep_semantic_tags = enabled;
+        self.parsed_error = None;
+        self.increment_update(&self.text.clone(), false, cx);
+    }
+
+    /// Set whether to collect the [`Diagnostic`]s on parsing, default is false.
+    ///
+    /// The diagnostics are the warnings of the content, e.g.: the unsupported HTML tags or the
+    /// urls stripped in sanitize mode, see [`TextViewState::diagnostics`].
+    /// The content will be re-parsed.
+    pub fn set_collect_diagnostics(&mut self, collect: bool, cx: &mut Context<Self>) {
+        if self.collect_diagnostics == collect {
+            return;
+        }
+
+        self.collect_diagnostics = collect;
+        self.parsed_error = None;
+        self.increment_update(&self.text.clone(), false, cx);
+    }
+
+    /// Return the [`Diagnostic`]s of the last parsing, sorted by the source order for Markdown.
+    ///
+    /// Empty if the diagnostics are not collected, see [`TextViewState::set_collect_diagnostics`].
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.parsed_content.node_cx.diagnostics()
+    }
+
     /// Set the text content.
     pub fn set_text(&mut self, text: &str, cx: &mut Context<Self>) {
         if self.text.as_str() == text {
@@ -164,37 +407,367 @@ impl TextViewState {
         }
 
         self.text = text.to_string().into();
+        self.text_format = self.format;
         self.parsed_error = None;
         self.increment_update(text, false, cx);
     }
 
+    /// Set the document directly, e.g.: built by [`crate::text::DocumentBuilder`].
+    ///
+    /// The Markdown source of the document is used as the text content, it is parsed as Markdown
+    /// on re-parsing (e.g.: [`Self::set_sanitize`]) or appending by [`Self::push_str`] even in the
+    /// HTML view. The parsing in progress is discarded.
+    pub fn set_document(&mut self, document: ParsedDocument, cx: &mut Context<Self>) {
+        self.generation += 1;
+        self.text = document.source.clone();
+        self.text_format = TextViewFormat::Markdown;
+        self.replace_content(ParsedContent {
+            document,
+            node_cx: NodeContext {
+                words_per_minute: self.words_per_minute,
+                ..NodeContext::default()
+            },
+        });
+        self.parsed_error = None;
+        self.clear_selection();
+        cx.notify();
+    }
+
     /// Append partial text content to the existing text.
     pub fn push_str(&mut self, new_text: &str, cx: &mut Context<Self>) {
         if new_text.is_empty() {
             return;
         }
+        self.text = format!("{}{}", self.text, new_text).into();
         self.increment_update(new_text, true, cx);
     }
 
+    /// Return the table of contents from the headings.
+    pub fn table_of_contents(&self) -> Vec<TocEntry> {
+        self.parsed_content.document.table_of_contents()
+    }
+
+    /// Set the reading speed for [`Self::reading_time`], default is 200 words per minute.
+    pub fn set_words_per_minute(&mut self, words_per_minute: usize, cx: &mut Context<Self>) {
+        self.words_per_minute = words_per_minute.max(1);
+        self.parsed_content.node_cx.words_per_minute = self.words_per_minute;
+        cx.notify();
+    }
+
+    /// Return the number of words in the content, the image alt texts are not counted.
+    ///
+    /// The code blocks are only counted if `include_code` is true.
+    pub fn word_count(&self, include_code: bool) -> usize {
+        self.parsed_content.document.word_count(include_code)
+    }
+
+    /// Return the estimated reading time of the content, see [`Self::set_words_per_minute`].
+    pub fn reading_time(&self) -> std::time::Duration {
+        self.parsed_content
+            .document
+            .reading_time(self.words_per_minute)
+    }
+
+    /// Return the plain text of the content, without any marks.
+    pub fn to_plain_text(&self) -> String {
+        self.parsed_content.document.to_plain_text()
+    }
+
+    /// Return the content as semantic HTML.
+    pub fn to_html(&self) -> String {
+        self.parsed_content.document.to_html()
+    }
+
+    /// Return all links in document order, the link references are resolved.
+    ///
+    /// If `dedup` is true, the duplicate links are removed.
+    pub fn extract_links(&self, dedup: bool) -> Vec<LinkMark> {
+        let link_refs = &self.parsed_content.node_cx.link_refs;
+        let links = self
+            .parsed_content
+            .document
+            .extract_links(false)
+            .into_iter()
+            .map(|link| {
+                link.identifier
+                    .as_ref()
+                    .and_then(|identifier| link_refs.get(identifier).cloned())
+                    .unwrap_or(link)
+            });
+
+        if !dedup {
+            return links.collect();
+        }
+
+        let mut unique: Vec<LinkMark> = vec![];
+        for link in links {
+            if !unique.contains(&link) {
+                unique.push(link);
+            }
+        }
+        unique
+    }
+
+    /// Return all images in document order.
+    ///
+    /// If `dedup` is true, the images with the same url are removed.
+    pub fn extract_images(&self, dedup: bool) -> Vec<ImageNode> {
+        self.parsed_content.document.extract_images(dedup)
+    }
+
+    /// Set the checked state of the task list item at `index`, the index is in the task list
+    /// items of the document in order (including the nested items).
+    ///
+    /// Only the rendered document is changed, the text content is kept.
+    pub fn set_checked(&mut self, index: usize, checked: bool, cx: &mut Context<Self>) {
+        if self.parsed_content.document.set_checked(index, checked) {
+            cx.notify();
+        }
+    }
+
+    /// Scroll to the heading with the anchor id (without `#`), e.g.: `getting-started`.
+    ///
+    /// This only works in the scrollable mode, do nothing if no matching heading.
+    pub fn scroll_to_anchor(&mut self, id: &str, cx: &mut Context<Self>) {
+        if !self.scrollable {
+            return;
+        }
+
+        let Some(ix) = self.parsed_content.document.anchor_block_index(id) else {
+            return;
+        };
+
+        self.list_state.scroll_to(ListOffset {
+            item_ix: ix,
+            offset_in_item: px(0.),
+        });
+        cx.notify();
+    }
+
+    /// Find the `query` in the text and highlight the matches, returns the ranges in the document
+    /// offsets, which is the text of all the paragraphs and code blocks joined in document order.
+    ///
+    /// Use [`TextViewState::find_next`] and [`TextViewState::find_prev`] to go to the matches,
+    /// an empty `query` clears the search.
+    pub fn find(
+        &mut self,
+        query: &str,
+        case_sensitive: bool,
+        cx: &mut Context<Self>,
+    ) -> Vec<Range<usize>> {
+        if let Some(search) = self.search.take() {
+            search.clear();
+        }
+        cx.notify();
+
+        if query.is_empty() {
+            return vec![];
+        }
+
+        let search = SearchState::new(query, case_sensitive, &self.parsed_content.document);
+        let ranges = search.ranges();
+        self.search = Some(search);
+        ranges
+    }
+
+    /// Go to the next match of [`TextViewState::find`], wraps around to the first match.
+    ///
+    /// Returns the range of the active match, in the scrollable mode it is scrolled into view.
+    pub fn find_next(&mut self, cx: &mut Context<Self>) -> Option<Range<usize>> {
+        self.move_active_match(true, cx)
+    }
+
+    /// Go to the previous match of [`TextViewState::find`], wraps around to the last match.
+    ///
+    /// Returns the range of the active match, in the scrollable mode it is scrolled into view.
+    pub fn find_prev(&mut self, cx: &mut Context<Self>) -> Option<Range<usize>> {
+        self.move_active_match(false, cx)
+    }
+
+    fn move_active_match(&mut self, forward: bool, cx: &mut Context<Self>) -> Option<Range<usize>> {
+        let search = self.search.as_mut()?;
+        let len = search.matches.len();
+        if len == 0 {
+            return None;
+        }
+
+        let ix = match (search.active, forward) {
+            (Some(ix), true) => (ix + 1) % len,
+            (Some(ix), false) => (ix + len - 1) % len,
+            (None, true) => 0,
+            (None, false) => len - 1,
+        };
+        search.set_active(Some(ix));
+
+        let item = &search.matches[ix];
+        if self.scrollable {
+            self.list_state.scroll_to_reveal_item(item.block_ix);
+        }
+        cx.notify();
+        Some(item.range.clone())
+    }
+
     /// Return the selected text.
     pub fn selected_text(&self) -> String {
-        self.parsed_content.document.selected_text()
+        self.parsed_content
+            .document
+            .selected_text(self.selected_block_range())
+    }
+
+    /// Return the selection in markdown, the blocks covered by the selection are fully included.
+    pub fn selected_markdown(&self) -> String {
+        self.parsed_content
+            .document
+            .selected_markdown(self.selected_block_range())
+    }
+
+    /// Return the range of the top-level blocks covered by the selection in the scrollable mode.
+    ///
+    /// The list only lays out the blocks in the viewport, so the blocks scrolled out of view have no
+    /// selection state, the range is found by the recorded block ranges in content coordinates.
+    fn selected_block_range(&self) -> Option<Range<usize>> {
+        if !self.scrollable {
+            return None;
+        }
+
+        let (Some(start), Some(end)) = self.selection_positions else {
+            return None;
+        };
+        let (top, bottom) = (start.y.min(end.y), start.y.max(end.y));
+
+        let first = self
+            .block_ranges
+            .iter()
+            .position(|range| range.as_ref().is_some_and(|range| range.end > top))?;
+        let last = self
+            .block_ranges
+            .iter()
+            .rposition(|range| range.as_ref().is_some_and(|range| range.start <= bottom))?;
+
+        (first <= last).then_some(first..last + 1)
+    }
+
+    /// Return the range of the top-level blocks covered by the selection, the anchors of the
+    /// selection in the document, so it is kept by the unchanged blocks instead of the positions.
+    ///
+    /// In the scrollable mode, it is found by the recorded block ranges, otherwise by the
+    /// selection of the blocks on the last paint.
+    fn selection_anchors(&self) -> Option<Range<usize>> {
+        if self.scrollable {
+            return self.selected_block_range();
+        }
+
+        let blocks = &self.parsed_content.document.blocks;
+        let first = blocks
+            .iter()
+            .position(|block| !block.selected_text().is_empty())?;
+        let last = blocks
+            .iter()
+            .rposition(|block| !block.selected_text().is_empty())?;
+        Some(first..last + 1)
+    }
+
+    /// Record the bounds of the top-level block when it is laid out in the list.
+    fn update_block_bounds(&mut self, ix: usize, bounds: Bounds<Pixels>) {
+        let scroll_offset = self.list_state.scroll_px_offset_for_scrollbar();
+        let top = bounds.top() - self.bounds.origin.y - scroll_offset.y;
+
+        if self.block_ranges.len() <= ix {
+            self.block_ranges.resize(ix + 1, None);
+        }
+        self.block_ranges[ix] = Some(top..top + bounds.size.height);
+    }
+
+    /// Replace the parsed content.
+    ///
+    /// The unchanged leading blocks keep the measured heights in the list, so the scroll position
+    /// is kept when appending the text (e.g. streaming chat messages) to a long document.
+    fn replace_content(&mut self, content: ParsedContent) {
+        let old_blocks = &self.parsed_content.document.blocks;
+        let new_blocks = &content.document.blocks;
+        let unchanged = old_blocks
+            .iter()
+            .zip(new_blocks.iter())
+            .take_while(|(old, new)| old == new)
+            .count();
+
+        if self.list_state.item_count() == old_blocks.len() {
+            self.list_state
+                .splice(unchanged..old_blocks.len(), new_blocks.len() - unchanged);
+        } else {
+            self.list_state.reset(new_blocks.len());
+        }
+        if !keeps_selection(self.has_selection(), self.selection_anchors(), unchanged) {
+            self.clear_selection();
+        }
+        self.block_ranges.truncate(unchanged);
+        self.parsed_content = content;
+        if let Some(item) = self.focused_link.take() {
+            item.state.lock().unwrap().focused_link = None;
+        }
+
+        // Search again in the new content, keep the active match if it still exists.
+        if let Some(search) = self.search.take() {
+            search.clear();
+            let mut new_search = SearchState::new(
+                &search.query,
+                search.case_sensitive,
+                &self.parsed_content.document,
+            );
+            new_search.set_active(search.active);
+            self.search = Some(new_search);
+        }
     }
 
     fn increment_update(&mut self, text: &str, append: bool, cx: &mut Context<Self>) {
         let update_options = UpdateOptions {
+            format: self.text_format,
+            generation: self.generation,
             append,
             content: self.parsed_content.clone(),
             pending_text: text.to_string(),
             highlight_theme: cx.theme().highlight_theme.clone(),
+            sanitize: self.sanitize,
+            viewport_size: self.viewport_size,
+            embed_providers: self.embed_providers.clone(),
+            mention_resolver: self.mention_resolver.clone(),
+            custom_block_renderer: self.custom_block_renderer.clone(),
+            collect_diagnostics: self.collect_diagnostics,
+            emoji_shortcodes: self.emoji_shortcodes,
+            keep_semantic_tags: self.keep_semantic_tags,
+            words_per_minute: self.words_per_minute,
         };
 
         _ = self.tx.try_send(update_options);
     }
 
-    /// Save bounds and unselect if bounds changed.
+    /// Save the window viewport size to resolve the `vw` and `vh` lengths.
+    ///
+    /// Only re-parse if the viewport units or the `<picture>` media queries are resolved on
+    /// parsing, or the text is still parsing with the previous viewport size, to avoid
+    /// re-parsing on each resize.
+    pub(super) fn update_viewport_size(
+        &mut self,
+        viewport_size: Size<Pixels>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.viewport_size == Some(viewport_size) {
+            return;
+        }
+
+        self.viewport_size = Some(viewport_size);
+        if self.parsed_content.node_cx.is_viewport_dependent()
+            || self.parsed_content.document.source != self.text
+        {
+            self.increment_update(&self.text.clone(), false, cx);
+        }
+    }
+
+    /// Save bounds and unselect if the width changed, the text is wrapped again.
+    ///
+    /// The height is changed by the appended text (e.g.: streaming), the selection is kept.
     pub(super) fn update_bounds(&mut self, bounds: Bounds<Pixels>) {
-        if self.bounds.size != bounds.size {
+        if self.bounds.size.width != bounds.size.width {
             self.clear_selection();
         }
         self.bounds = bounds;
@@ -202,6 +775,7 @@ impl TextViewState {
 
     pub(super) fn clear_selection(&mut self) {
         self.selection_positions = (None, None);
+        self.selection_mouse_position = None;
         self.is_selecting = false;
     }
 
@@ -218,6 +792,7 @@ impl TextViewState {
     }
 
     pub(super) fn update_selection(&mut self, pos: Point<Pixels>) {
+        self.selection_mouse_position = Some(pos);
         let scroll_offset = if self.scrollable {
             self.list_state.scroll_px_offset_for_scrollbar()
         } else {
@@ -230,9 +805,33 @@ impl TextViewState {
     }
 
     pub(super) fn end_selection(&mut self) {
+        self.selection_mouse_position = None;
         self.is_selecting = false;
     }
 
+    /// Auto-scroll while drag-selecting with the mouse near the top or bottom edge, and extend
+    /// the selection to the mouse in the scrolled content.
+    ///
+    /// Returns true if scrolled, to continue on the next frame. Only in the scrollable mode.
+    pub(super) fn autoscroll_selection(&mut self) -> bool {
+        if !self.scrollable || !self.is_selecting {
+            return false;
+        }
+        let Some(position) = self.selection_mouse_position else {
+            return false;
+        };
+
+        let delta = autoscroll_delta(position.y, self.bounds);
+        if delta == px(0.) {
+            return false;
+        }
+
+        let scroll_offset = self.list_state.scroll_px_offset_for_scrollbar();
+        self.list_state.scroll_by(delta);
+        self.update_selection(position);
+        self.list_state.scroll_px_offset_for_scrollbar() != scroll_offset
+    }
+
     pub(crate) fn has_selection(&self) -> bool {
         if let (Some(start), Some(end)) = self.selection_positions {
             start != end
@@ -263,12 +862,224 @@ impl TextViewState {
             return;
         }
 
+        let selected_text = self.transform_copy(selected_text, CopyFormat::PlainText);
         cx.write_to_clipboard(ClipboardItem::new_string(selected_text));
     }
 
+    /// Transform the copied text by [`TextView::on_copy`] if set.
+    ///
+    /// [`TextView::on_copy`]: crate::text::TextView::on_copy
+    fn transform_copy(&self, text: String, format: CopyFormat) -> String {
+        match &self.on_copy {
+            Some(on_copy) => on_copy(&text, format),
+            None => text,
+        }
+    }
+
+    /// Move the focus to the next link, or to the next focusable element after the last link.
+    pub(super) fn on_action_focus_next_link(
+        &mut self,
+        _: &FocusNextLink,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if !self.move_focused_link(true, cx) {
+            cx.propagate();
+        }
+    }
+
+    /// Move the focus to the previous link, or to the previous focusable element before the
+    /// first link.
+    pub(super) fn on_action_focus_prev_link(
+        &mut self,
+        _: &FocusPrevLink,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if !self.move_focused_link(false, cx) {
+            cx.propagate();
+        }
+    }
+
+    /// Open the focused link the same as clicking it.
+    pub(super) fn on_action_open_focused_link(
+        &mut self,
+        _: &OpenFocusedLink,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(url) = self.focused_link.as_ref().map(|item| item.link.url.clone()) else {
+            cx.propagate();
+            return;
+        };
+
+        // Scroll to the anchor directly, the anchor handler updates this state.
+        if let Some(id) = url.strip_prefix('#') {
+            self.scroll_to_anchor(id, cx);
+            return;
+        }
+
+        open_link(
+            LinkClickEvent {
+                url,
+                modifiers: Modifiers::default(),
+                background: false,
+            },
+            None,
+            self.on_link_click.as_ref(),
+            window,
+            cx,
+        );
+    }
+
+    /// Move the focus to the next or previous link, returns `false` if there is no more link,
+    /// the focus is removed then.
+    fn move_focused_link(&mut self, forward: bool, cx: &mut Context<Self>) -> bool {
+        let node_cx = &self.parsed_content.node_cx;
+        let mut links = vec![];
+        for (block_ix, block) in self.parsed_content.document.blocks.iter().enumerate() {
+            let mut segments = vec![];
+            block.link_segments(node_cx, &mut segments);
+            links.extend(segments.into_iter().map(|segment| (block_ix, segment)));
+        }
+
+        let ix = match (self.focused_link.as_ref().map(|item| item.ix), forward) {
+            (Some(ix), true) => Some(ix + 1),
+            (Some(ix), false) => ix.checked_sub(1),
+            (None, true) => Some(0),
+            (None, false) => links.len().checked_sub(1),
+        };
+
+        if let Some(item) = self.focused_link.take() {
+            item.state.lock().unwrap().focused_link = None;
+        }
+        cx.notify();
+
+        let Some((ix, (block_ix, (state, range, link)))) =
+            ix.and_then(|ix| links.into_iter().nth(ix).map(|item| (ix, item)))
+        else {
+            return false;
+        };
+
+        state.lock().unwrap().focused_link = Some(range);
+        if self.scrollable {
+            self.list_state.scroll_to_reveal_item(block_ix);
+        }
+        self.focused_link = Some(FocusedLink { ix, state, link });
+        true
+    }
+
     pub(crate) fn is_selectable(&self) -> bool {
         self.selectable
     }
+
+    /// The handler to scroll to the internal anchor links, e.g.: `#section-2`.
+    fn anchor_click_handler(state: WeakEntity<Self>) -> std::sync::Arc<AnchorClickFn> {
+        std::sync::Arc::new(move |id, _, cx| {
+            _ = state.update(cx, |state, cx| state.scroll_to_anchor(id, cx));
+        })
+    }
+
+    /// Build the context menu for the link or image under the mouse, and the selection.
+    pub(super) fn context_menu(&self, mut menu: PopupMenu, state: WeakEntity<Self>) -> PopupMenu {
+        match self.context_menu_target.clone() {
+            Some(ContextMenuTarget::Link(link)) => {
+                let on_anchor_click = Self::anchor_click_handler(state.clone());
+                let on_link_click = self.on_link_click.clone();
+                let url = link.url.clone();
+                menu = menu
+                    .item(PopupMenuItem::new(t!("TextView.Open Link")).on_click(
+                        move |_, window, cx| {
+                            open_link(
+                                LinkClickEvent {
+                                    url: url.clone(),
+                                    modifiers: Modifiers::default(),
+                                    background: false,
+                                },
+                                Some(&on_anchor_click),
+                                on_link_click.as_ref(),
+                                window,
+                                cx,
+                            )
+                        },
+                    ))
+                    .item(
+                        PopupMenuItem::new(t!("TextView.Copy Link Address")).on_click(
+                            move |_, _, cx| {
+                                cx.write_to_clipboard(ClipboardItem::new_string(
+                                    link.url.to_string(),
+                                ))
+                            },
+                        ),
+                    );
+            }
+            Some(ContextMenuTarget::Image(image)) => {
+                let on_image_click = self.on_image_click.clone();
+                let alt = image.alt.clone().filter(|alt| !alt.is_empty());
+                menu = menu
+                    .item(PopupMenuItem::new(t!("TextView.Open Image")).on_click({
+                        let image = image.clone();
+                        move |_, window, cx| match &on_image_click {
+                            Some(on_image_click) => on_image_click(&image, window, cx),
+                            None => cx.open_url(&image.url),
+                        }
+                    }))
+                    .item(PopupMenuItem::new(t!("TextView.Copy Image")).on_click({
+                        let image = image.clone();
+                        move |_, _, cx| image.copy_image(cx)
+                    }))
+                    .item(PopupMenuItem::new(t!("TextView.Save Image")).on_click({
+                        let image = image.clone();
+                        move |_, _, cx| image.save_image(cx)
+                    }))
+                    .item(
+                        PopupMenuItem::new(t!("TextView.Copy Image Address")).on_click(
+                            move |_, _, cx| {
+                                cx.write_to_clipboard(ClipboardItem::new_string(
+                                    image.url.to_string(),
+                                ))
+                            },
+                        ),
+                    )
+                    .when_some(alt, |menu, alt| {
+                        menu.item(PopupMenuItem::new(t!("TextView.Copy Alt Text")).on_click(
+                            move |_, _, cx| {
+                                cx.write_to_clipboard(ClipboardItem::new_string(alt.to_string()))
+                            },
+                        ))
+                    });
+            }
+            None => {}
+        }
+
+        if self.has_selection() {
+            let text = self.selected_text().trim().to_string();
+            let markdown = self.selected_markdown();
+            if !text.is_empty() {
+                let text = self.transform_copy(text, CopyFormat::PlainText);
+                let markdown = self.transform_copy(markdown, CopyFormat::Markdown);
+                if !menu.is_empty() {
+                    menu = menu.separator();
+                }
+
+                menu = menu
+                    .item(
+                        PopupMenuItem::new(t!("TextView.Copy")).on_click(move |_, _, cx| {
+                            cx.write_to_clipboard(ClipboardItem::new_string(text.clone()))
+                        }),
+                    )
+                    .item(
+                        PopupMenuItem::new(t!("TextView.Copy as Markdown")).on_click(
+                            move |_, _, cx| {
+                                cx.write_to_clipboard(ClipboardItem::new_string(markdown.clone()))
+                            },
+                        ),
+                    );
+            }
+        }
+
+        menu
+    }
 }
 
 impl Render for TextViewState {
@@ -278,14 +1089,57 @@ impl Render for TextViewState {
         let mut node_cx = self.parsed_content.node_cx.clone();
 
         node_cx.code_block_actions = self.code_block_actions.clone();
+        node_cx.math_renderer = self.math_renderer.clone();
+        node_cx.time_formatter = self.time_formatter.clone();
+        node_cx.custom_block_renderer = self.custom_block_renderer.clone();
+        node_cx.animate_images = self.animate_images;
+        node_cx.copy_hidden_spoilers = self.copy_hidden_spoilers;
+        node_cx.copy_emoji_shortcodes = self.copy_emoji_shortcodes;
+        node_cx.max_image_width = self.max_image_width;
+        node_cx.max_image_height = self.max_image_height;
+        node_cx.on_image_click = self.on_image_click.clone();
+        node_cx.on_link_click = self.on_link_click.clone();
+        node_cx.link_visited = self.link_visited.clone();
+        node_cx.on_checkbox_toggle = self.on_checkbox_toggle.clone().map(|on_toggle| {
+            let state = state.downgrade();
+            let on_checkbox_toggle: std::sync::Arc<CheckboxToggleFn> =
+                std::sync::Arc::new(move |index, checked, window, cx| {
+                    _ = state.update(cx, |state, cx| state.set_checked(index, checked, cx));
+                    on_toggle(index, checked, window, cx);
+                });
+            on_checkbox_toggle
+        });
         node_cx.style = self.text_view_style.clone();
+        // The drop cap is on the first paragraph of the document.
+        node_cx.drop_cap_paragraph = (node_cx.style.drop_cap_lines > 0)
+            .then(|| {
+                document.blocks.iter().find_map(|block| match block {
+                    node::BlockNode::Paragraph(paragraph) => Some(paragraph.state.clone()),
+                    _ => None,
+                })
+            })
+            .flatten();
+        node_cx.on_anchor_click = Some(Self::anchor_click_handler(state.downgrade()));
+        node_cx.on_context_menu = Some(std::sync::Arc::new({
+            let state = state.downgrade();
+            move |target, cx| {
+                _ = state.update(cx, |state, _| state.context_menu_target = Some(target));
+            }
+        }));
+
+        let on_block_prepaint: Rc<BlockPrepaintFn> = Rc::new({
+            let state = state.downgrade();
+            move |ix, bounds, cx| {
+                _ = state.update(cx, |state, _| state.update_block_bounds(ix, bounds));
+            }
+        });
 
         v_flex()
             .size_full()
             .map(|this| match &mut self.parsed_error {
                 None => this.child(document.render_root(
                     if self.scrollable {
-                        Some(self.list_state.clone())
+                        Some((self.list_state.clone(), on_block_prepaint))
                     } else {
                         None
                     },
@@ -315,31 +1169,43 @@ pub(crate) struct ParsedContent {
 }
 
 struct UpdateFuture {
-    format: TextViewFormat,
     options: UpdateOptions,
     pending_text: String,
     rx: Pin<Box<Receiver<UpdateOptions>>>,
-    tx_result: Sender<Result<ParsedContent, SharedString>>,
+    tx_result: Sender<(usize, Result<ParsedContent, SharedString>)>,
+    /// The last parsed content, to append the text that arrives before it is applied.
+    last_content: Option<ParsedContent>,
 }
 
 impl UpdateFuture {
     fn new(
         format: TextViewFormat,
         rx: Receiver<UpdateOptions>,
-        tx_result: Sender<Result<ParsedContent, SharedString>>,
+        tx_result: Sender<(usize, Result<ParsedContent, SharedString>)>,
         cx: &App,
     ) -> Self {
         Self {
-            format,
             pending_text: String::new(),
             options: UpdateOptions {
+                format,
+                generation: 0,
                 append: false,
                 pending_text: String::new(),
                 content: Default::default(),
                 highlight_theme: cx.theme().highlight_theme.clone(),
+                sanitize: false,
+                viewport_size: None,
+                embed_providers: Arc::new(EmbedProvider::builtin()),
+                mention_resolver: None,
+                custom_block_renderer: None,
+                collect_diagnostics: false,
+                emoji_shortcodes: false,
+                keep_semantic_tags: false,
+                words_per_minute: node::DEFAULT_WORDS_PER_MINUTE,
             },
             rx: Box::pin(rx),
             tx_result,
+            last_content: None,
         }
     }
 }
@@ -350,7 +1216,20 @@ impl Future for UpdateFuture {
     fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
         loop {
             match self.rx.as_mut().poll_next(cx) {
-                Poll::Ready(Some(options)) => {
+                Poll::Ready(Some(mut options)) => {
+                    // The content is replaced by `set_document`, the last result is stale.
+                    if options.generation != self.options.generation {
+                        self.last_content = None;
+                    }
+                    // The text may be appended again before the last result is applied, then
+                    // the content sent with it misses the previously appended text.
+                    if options.append
+                        && let Some(last_content) = &self.last_content
+                        && is_behind(&options.content, last_content)
+                    {
+                        options.content = last_content.clone();
+                    }
+
                     if options.append {
                         self.pending_text.push_str(options.pending_text.as_str());
                     } else {
@@ -360,14 +1239,12 @@ impl Future for UpdateFuture {
 
                     // Process immediately without debounce
                     let pending_text = std::mem::take(&mut self.pending_text);
-                    let res = parse_content(
-                        self.format,
-                        &UpdateOptions {
-                            pending_text,
-                            ..self.options.clone()
-                        },
-                    );
-                    _ = self.tx_result.try_send(res);
+                    let res = parse_content(&UpdateOptions {
+                        pending_text,
+                        ..self.options.clone()
+                    });
+                    self.last_content = res.as_ref().ok().cloned();
+                    _ = self.tx_result.try_send((self.options.generation, res));
                     continue;
                 }
                 Poll::Ready(None) => return Poll::Ready(()),
@@ -379,49 +1256,158 @@ impl Future for UpdateFuture {
 
 #[derive(Clone)]
 struct UpdateOptions {
+    format: TextViewFormat,
+    generation: usize,
     content: ParsedContent,
     pending_text: String,
     append: bool,
     highlight_theme: std::sync::Arc<HighlightTheme>,
+    sanitize: bool,
+    viewport_size: Option<Size<Pixels>>,
+    embed_providers: Arc<Vec<EmbedProvider>>,
+    mention_resolver: Option<Arc<MentionResolveFn>>,
+    custom_block_renderer: Option<Arc<CustomBlockRenderer>>,
+    collect_diagnostics: bool,
+    emoji_shortcodes: bool,
+    keep_semantic_tags: bool,
+    words_per_minute: usize,
+}
+
+/// Whether the `content` is an earlier version of the `last_content` when appending the text.
+fn is_behind(content: &ParsedContent, last_content: &ParsedContent) -> bool {
+    let source = content.document.source.as_str();
+    let last_source = last_content.document.source.as_str();
+    last_source.len() > source.len() && last_source.starts_with(source)
 }
 
-fn parse_content(format: TextViewFormat, options: &UpdateOptions) -> Result<ParsedContent, SharedString> {
+fn parse_content(options: &UpdateOptions) -> Result<ParsedContent, SharedString> {
     let mut node_cx = NodeContext {
+        style: TextViewStyle {
+            highlight_theme: options.highlight_theme.clone(),
+            ..Default::default()
+        },
+        sanitize: options.sanitize,
+        viewport_size: options.viewport_size,
+        embed_providers: options.embed_providers.clone(),
+        mention_resolver: options.mention_resolver.clone(),
+        custom_block_renderer: options.custom_block_renderer.clone(),
+        diagnostics: options.collect_diagnostics.then(Default::default),
+        emoji_shortcodes: options.emoji_shortcodes,
+        keep_semantic_tags: options.keep_semantic_tags,
         ..NodeContext::default()
     };
 
     let mut content = options.content.clone();
-    let mut source = String::new();
-    if options.append
-        && let Some(last_block) = content.document.blocks.pop()
-        && let Some(span) = last_block.span()
-    {
-        node_cx.offset = span.start;
-        let last_source = &content.document.source[span.start..];
-        source.push_str(last_source);
-        source.push_str(&options.pending_text);
+    let source = if options.append {
+        format!("{}{}", content.document.source, options.pending_text)
     } else {
-        source = options.pending_text.to_string();
-    }
+        options.pending_text.clone()
+    };
+
+    // Only re-parse the last block when appending, it may be incomplete (e.g.: an unclosed
+    // fenced code block) and changed by the new text, the previous blocks are kept.
+    //
+    // The whole source is re-parsed if the last block has no span, e.g.: the HTML document.
+    let last_span = content
+        .document
+        .blocks
+        .last()
+        .and_then(|block| block.span());
+    // The frontmatter is only detected on parsing from the start, so re-parse the whole source if
+    // the appended text closes the frontmatter, e.g.: streaming the `---` fences.
+    let frontmatter_changed = options.format == TextViewFormat::Markdown
+        && format::markdown::has_frontmatter(&source)
+            != format::markdown::has_frontmatter(&content.document.source);
+    let mut stable_len = match last_span {
+        Some(span) if options.append && !frontmatter_changed => {
+            node_cx.offset = span.start;
+            content.document.blocks.len() - 1
+        }
+        _ => 0,
+    };
 
-    let new_document = match format {
+    let parse = |node_cx: &mut NodeContext| match options.format {
         TextViewFormat::Markdown => {
-            format::markdown::parse(&source, &mut node_cx, &options.highlight_theme)
+            format::markdown::parse(&source[node_cx.offset..], node_cx, &options.highlight_theme)
         }
-        TextViewFormat::Html => format::html::parse(&source, &mut node_cx),
-    }?;
+        TextViewFormat::Html => format::html::parse(&source[node_cx.offset..], node_cx),
+    };
+    let mut new_document = parse(&mut node_cx)?;
+    // The new footnote definitions may be referenced by the previous blocks.
+    if stable_len > 0
+        && new_document
+            .blocks
+            .iter()
+            .any(|block| matches!(block, node::BlockNode::Footnotes { .. }))
+    {
+        stable_len = 0;
+        node_cx.offset = 0;
+        node_cx.diagnostics = options.collect_diagnostics.then(Default::default);
+        new_document = parse(&mut node_cx)?;
+    }
 
-    if options.append {
-        content.document.source =
-            format!("{}{}", content.document.source, options.pending_text).into();
-        content.document.blocks.extend(new_document.blocks);
+    content.document.source = source.into();
+    content.document.blocks.truncate(stable_len);
+    content.document.blocks.extend(new_document.blocks);
+    if stable_len > 0 {
+        // Keep the diagnostics of the previous blocks, the last block is re-parsed.
+        let mut diagnostics = content.node_cx.diagnostics();
+        diagnostics.retain(|diagnostic| {
+            diagnostic
+                .span
+                .is_some_and(|span| span.start < node_cx.offset)
+        });
+        diagnostics.extend(node_cx.diagnostics());
+        content.node_cx.diagnostics = node_cx
+            .diagnostics
+            .is_some()
+            .then(|| Arc::new(Mutex::new(diagnostics)));
+        content.node_cx.link_refs.extend(node_cx.link_refs);
+        if node_cx.is_viewport_dependent() {
+            content.node_cx.use_viewport();
+        }
     } else {
-        content.document = new_document;
+        content.node_cx.diagnostics = node_cx.diagnostics;
+        content.node_cx.link_refs = node_cx.link_refs;
+        content.node_cx.uses_viewport = node_cx.uses_viewport;
+        content.document.frontmatter = new_document.frontmatter;
     }
+    content.node_cx.words_per_minute = options.words_per_minute;
+    content.document.assign_heading_ids();
 
     Ok(content)
 }
 
+/// Whether to keep the selection after the blocks from `unchanged` are re-parsed.
+///
+/// The selection is kept if the `selected` top-level blocks are all before the changed blocks,
+/// e.g.: the text is appended below the selection, or re-parsed with the same text.
+fn keeps_selection(has_selection: bool, selected: Option<Range<usize>>, unchanged: usize) -> bool {
+    if !has_selection {
+        return true;
+    }
+
+    selected.is_some_and(|selected| selected.end <= unchanged)
+}
+
+/// Return the distance to auto-scroll per frame for the mouse `y` while drag-selecting, negative
+/// to scroll up, the speed is proportional to the proximity to the edge within the margin.
+fn autoscroll_delta(y: Pixels, bounds: Bounds<Pixels>) -> Pixels {
+    let margin = AUTOSCROLL_MARGIN.min(bounds.size.height / 2.);
+    if margin <= px(0.) {
+        return px(0.);
+    }
+
+    let speed = |distance: Pixels| AUTOSCROLL_MAX_SPEED * ((margin - distance) / margin).min(1.);
+    if y < bounds.top() + margin {
+        -speed(y - bounds.top())
+    } else if y > bounds.bottom() - margin {
+        speed(bounds.bottom() - y)
+    } else {
+        px(0.)
+    }
+}
+
 fn selection_points(
     start: Option<Point<Pixels>>,
     end: Option<Point<Pixels>>,
@@ -441,7 +1427,39 @@ fn selection_points(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use gpui::point;
+    use gpui::{point, size};
+
+    #[test]
+    fn test_keeps_selection() {
+        assert!(keeps_selection(false, None, 0));
+        // Changed below the selection, or the same blocks re-parsed.
+        assert!(keeps_selection(true, Some(0..2), 2));
+        assert!(keeps_selection(true, Some(1..2), 4));
+        // Changed above or in the selection, or the selected blocks are unknown.
+        assert!(!keeps_selection(true, Some(0..2), 0));
+        assert!(!keeps_selection(true, Some(0..2), 1));
+        assert!(!keeps_selection(true, None, 4));
+    }
+
+    #[test]
+    fn test_autoscroll_delta() {
+        let bounds = Bounds::new(point(px(0.), px(100.)), size(px(200.), px(400.)));
+        assert_eq!(autoscroll_delta(px(300.), bounds), px(0.));
+        assert_eq!(autoscroll_delta(px(132.), bounds), px(0.));
+        assert_eq!(autoscroll_delta(px(468.), bounds), px(0.));
+        // Proportional to the proximity to the edge.
+        assert_eq!(autoscroll_delta(px(116.), bounds), px(-12.));
+        assert_eq!(autoscroll_delta(px(100.), bounds), px(-24.));
+        assert_eq!(autoscroll_delta(px(484.), bounds), px(12.));
+        // Beyond the edge.
+        assert_eq!(autoscroll_delta(px(50.), bounds), px(-24.));
+        assert_eq!(autoscroll_delta(px(600.), bounds), px(24.));
+        // The margin is limited to the half height.
+        let bounds = Bounds::new(point(px(0.), px(0.)), size(px(200.), px(20.)));
+        assert_eq!(autoscroll_delta(px(10.), bounds), px(0.));
+        assert_eq!(autoscroll_delta(px(0.), bounds), px(-24.));
+        assert_eq!(autoscroll_delta(px(0.), Bounds::default()), px(0.));
+    }
 
     #[test]
     fn test_text_view_state_selection_points() {
@@ -528,4 +1546,122 @@ mod tests {
             Some((point(px(10.), px(50.)), point(px(50.), px(10.))))
         );
     }
+
+    /// Parse the `text` with the default options, appended to the `content` if `append`.
+    fn parse(
+        format: TextViewFormat,
+        content: ParsedContent,
+        text: &str,
+        append: bool,
+    ) -> ParsedContent {
+        parse_content(&UpdateOptions {
+            format,
+            generation: 0,
+            content,
+            pending_text: text.to_string(),
+            append,
+            highlight_theme: HighlightTheme::default_light(),
+            sanitize: false,
+            viewport_size: None,
+            embed_providers: Arc::new(EmbedProvider::builtin()),
+            mention_resolver: None,
+            custom_block_renderer: None,
+            collect_diagnostics: false,
+            emoji_shortcodes: false,
+            keep_semantic_tags: false,
+            words_per_minute: node::DEFAULT_WORDS_PER_MINUTE,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_content_append() {
+        let markdown = TextViewFormat::Markdown;
+        let content = parse(
+            markdown,
+            ParsedContent::default(),
+            "# Title\n\n```rust\nfn",
+            false,
+        );
+        assert_eq!(content.document.blocks.len(), 2);
+        let content = parse(markdown, content, " main() {}\n```\n\nDone", true);
+        // The unclosed code block is re-parsed with the appended text, the heading is kept.
+        assert_eq!(
+            content.document.source.as_str(),
+            "# Title\n\n```rust\nfn main() {}\n```\n\nDone"
+        );
+        assert_eq!(
+            content.document.to_markdown(),
+            parse(
+                markdown,
+                ParsedContent::default(),
+                &content.document.source,
+                false
+            )
+            .document
+            .to_markdown()
+        );
+        assert_eq!(content.document.blocks.len(), 3);
+
+        // The frontmatter closed by the appended text is detected from the whole source.
+        let content = parse(
+            markdown,
+            ParsedContent::default(),
+            "Intro\n\n---\ntitle: Hello\n",
+            false,
+        );
+        let content = parse(markdown, content, "---\n\nBody", true);
+        assert_eq!(content.document.frontmatter(), None);
+        let content = parse(
+            markdown,
+            ParsedContent::default(),
+            "---\ntitle: Hello\n",
+            false,
+        );
+        assert_eq!(content.document.frontmatter(), None);
+        let content = parse(markdown, content, "---\n\n# Body", true);
+        assert_eq!(
+            content
+                .document
+                .frontmatter()
+                .map(|frontmatter| &frontmatter["title"]),
+            Some(&"Hello".to_string())
+        );
+        assert_eq!(content.document.to_markdown(), "# Body");
+
+        // The HTML document has no spans, it is re-parsed with the whole source.
+        let html = TextViewFormat::Html;
+        let content = parse(html, ParsedContent::default(), "<p>Hello</p>", false);
+        let content = parse(html, content, "<p>World</p>", true);
+        assert_eq!(content.document.to_plain_text(), "Hello\n\nWorld");
+
+        let behind = parse(html, ParsedContent::default(), "<p>Hello</p>", false);
+        assert!(is_behind(&behind, &content));
+        assert!(!is_behind(&content, &content));
+    }
+
+    #[test]
+    fn test_append_keeps_image() {
+        let parse = |content: ParsedContent, text: &str, append: bool| {
+            parse(TextViewFormat::Markdown, content, text, append)
+        };
+        let paragraph = |content: &ParsedContent| match content.document.blocks.last() {
+            Some(node::BlockNode::Paragraph(paragraph)) => paragraph.clone(),
+            _ => panic!("expected paragraph"),
+        };
+
+        let content = parse(
+            ParsedContent::default(),
+            "Hi\n\nLook ![img](https://a.com/1.png)",
+            false,
+        );
+        let old = paragraph(&content);
+        let content = parse(content, " here", true);
+        let new = paragraph(&content);
+
+        // The element id and the image are kept, so the image element state is reused.
+        assert_eq!(new.element_id(), old.element_id());
+        assert_eq!(new.children[1].image, old.children[1].image);
+        assert_eq!(new.to_plain_text(), "Look img here");
+    }
 }
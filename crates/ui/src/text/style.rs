@@ -1,14 +1,60 @@
 use std::sync::Arc;
 
-use gpui::{Pixels, Rems, StyleRefinement, px, rems};
+use gpui::{Pixels, Rems, SharedString, StyleRefinement, px, rems};
 
-use crate::highlighter::HighlightTheme;
+use crate::{highlighter::HighlightTheme, text::ListMarker};
 
 /// TextViewStyle used to customize the style for [`TextView`].
 #[derive(Clone)]
 pub struct TextViewStyle {
     /// Gap of each paragraphs, default is 1 rem.
     pub paragraph_gap: Rems,
+    /// Indent of the first line of the paragraphs in `em` (the font size), rounded to the half
+    /// `em`, default is 0.
+    ///
+    /// The paragraphs in the lists and the paragraph with the drop cap are not indented.
+    pub first_line_indent: f32,
+    /// The lines spanned by the drop cap, the enlarged first letter of the first paragraph of
+    /// the document, default is 0 to not show the drop cap.
+    ///
+    /// The drop cap is not floated, the whole text of the paragraph is laid out beside it, so the
+    /// lines after the first `drop_cap_lines` are indented by the drop cap too. It suits the
+    /// short opening paragraphs, e.g.: the lead of an article.
+    pub drop_cap_lines: usize,
+    /// Gap of each list items, default is 0.
+    pub list_item_gap: Rems,
+    /// Indent of each nested list level, default is 1 rem.
+    pub list_indent: Rems,
+    /// The max nesting depth of the lists to indent, the deeper lists continue at this indent
+    /// to keep the content readable in narrow columns.
+    ///
+    /// Default is 4, `0` to not indent the nested lists.
+    pub list_max_indent_depth: usize,
+    /// The bullets of the unordered lists, cycled by the nesting depth.
+    ///
+    /// Default is `•` (disc), `◦` (circle) and `▪` (square).
+    pub list_bullets: Vec<SharedString>,
+    /// The markers of the ordered lists without the `type` attribute, cycled by the nesting depth.
+    ///
+    /// Default is [`ListMarker::Decimal`], [`ListMarker::LowerAlpha`] and
+    /// [`ListMarker::LowerRoman`].
+    pub list_markers: Vec<ListMarker>,
+    /// Horizontal padding of blockquotes, default is 1 rem, the nested blockquotes use 3/4 of it.
+    pub blockquote_padding: Rems,
+    /// Max height of table bodies, the header row is pinned while the body scrolls.
+    ///
+    /// Default is `None` to show all rows.
+    pub table_max_height: Option<Rems>,
+    /// Whether to style the header rows of tables with the `table.head` theme colors, default is
+    /// true.
+    pub table_header: bool,
+    /// Whether to stripe the even body rows of tables with the `table.even` theme color, default
+    /// is true.
+    pub table_striped: bool,
+    /// Font size multiplier of headings level 1 to 6, relative to the `heading_base_font_size`.
+    ///
+    /// Default is `[2., 1.5, 1.25, 1.125, 1., 1.]`.
+    pub heading_scale: [f32; 6],
     /// Base font size for headings, default is 14px.
     pub heading_base_font_size: Pixels,
     /// Function to calculate heading font size based on heading level (1-6).
@@ -20,14 +66,54 @@ pub struct TextViewStyle {
     pub highlight_theme: Arc<HighlightTheme>,
     /// The style refinement for code blocks.
     pub code_block: StyleRefinement,
+    /// Font family of code blocks, default is the `mono_font_family` of the theme.
+    pub code_font_family: Option<SharedString>,
+    /// Font size of code blocks, default is the `mono_font_size` of the theme.
+    pub code_font_size: Option<Pixels>,
+    /// Whether the inline code uses the `code_font_family` too, default is false.
+    ///
+    /// The font size of the inline code is always the same as the text.
+    pub inline_code_font: bool,
+    /// The language to hyphenate the long words of the paragraphs, e.g.: `en-us` or `de-1996`.
+    ///
+    /// The soft hyphens are inserted on render to break the lines more evenly (e.g.: the
+    /// justified text), a hyphen is painted at the end of the line wrapped at them, and they are
+    /// excluded from the copied text.
+    ///
+    /// Default is `None` to not hyphenate, the unsupported languages are ignored.
+    ///
+    /// Only the `en-us` dictionary is embedded, the other languages need the `embed_<lang>`
+    /// features of the `hyphenation` crate enabled by the app, e.g.: `embed_de-1996`.
+    #[cfg(feature = "hyphenation")]
+    pub hyphenation: Option<SharedString>,
     pub is_dark: bool,
 }
 
 impl PartialEq for TextViewStyle {
     fn eq(&self, other: &Self) -> bool {
+        #[cfg(feature = "hyphenation")]
+        if self.hyphenation != other.hyphenation {
+            return false;
+        }
+
         self.paragraph_gap == other.paragraph_gap
+            && self.first_line_indent == other.first_line_indent
+            && self.drop_cap_lines == other.drop_cap_lines
+            && self.list_item_gap == other.list_item_gap
+            && self.list_indent == other.list_indent
+            && self.list_max_indent_depth == other.list_max_indent_depth
+            && self.list_bullets == other.list_bullets
+            && self.list_markers == other.list_markers
+            && self.blockquote_padding == other.blockquote_padding
+            && self.table_max_height == other.table_max_height
+            && self.table_header == other.table_header
+            && self.table_striped == other.table_striped
+            && self.heading_scale == other.heading_scale
             && self.heading_base_font_size == other.heading_base_font_size
             && self.highlight_theme == other.highlight_theme
+            && self.code_font_family == other.code_font_family
+            && self.code_font_size == other.code_font_size
+            && self.inline_code_font == other.inline_code_font
     }
 }
 
@@ -35,10 +121,31 @@ impl Default for TextViewStyle {
     fn default() -> Self {
         Self {
             paragraph_gap: rems(1.),
+            first_line_indent: 0.,
+            drop_cap_lines: 0,
+            list_item_gap: rems(0.),
+            list_indent: rems(1.),
+            list_max_indent_depth: 4,
+            list_bullets: vec!["•".into(), "◦".into(), "▪".into()],
+            list_markers: vec![
+                ListMarker::Decimal,
+                ListMarker::LowerAlpha,
+                ListMarker::LowerRoman,
+            ],
+            blockquote_padding: rems(1.),
+            table_max_height: None,
+            table_header: true,
+            table_striped: true,
+            heading_scale: [2., 1.5, 1.25, 1.125, 1., 1.],
             heading_base_font_size: px(14.),
             heading_font_size: None,
             highlight_theme: HighlightTheme::default_light().clone(),
             code_block: StyleRefinement::default(),
+            code_font_family: None,
+            code_font_size: None,
+            inline_code_font: false,
+            #[cfg(feature = "hyphenation")]
+            hyphenation: None,
             is_dark: false,
         }
     }
@@ -51,6 +158,91 @@ impl TextViewStyle {
         self
     }
 
+    /// Set the indent of the first line of the paragraphs in `em`, see
+    /// [`TextViewStyle::first_line_indent`].
+    pub fn first_line_indent(mut self, indent: f32) -> Self {
+        self.first_line_indent = indent;
+        self
+    }
+
+    /// Set the lines spanned by the drop cap of the first paragraph, `0` to not show it, see
+    /// [`TextViewStyle::drop_cap_lines`].
+    pub fn drop_cap_lines(mut self, lines: usize) -> Self {
+        self.drop_cap_lines = lines;
+        self
+    }
+
+    /// Set list item gap, default is 0.
+    pub fn list_item_gap(mut self, gap: Rems) -> Self {
+        self.list_item_gap = gap;
+        self
+    }
+
+    /// Set indent of each nested list level, default is 1 rem.
+    pub fn list_indent(mut self, indent: Rems) -> Self {
+        self.list_indent = indent;
+        self
+    }
+
+    /// Set the max nesting depth of the lists to indent, default is 4.
+    ///
+    /// The deeper lists are not indented further, `0` to not indent the nested lists.
+    pub fn list_max_indent_depth(mut self, depth: usize) -> Self {
+        self.list_max_indent_depth = depth;
+        self
+    }
+
+    /// Set the bullets of the unordered lists cycled by the nesting depth, default is `•`, `◦`
+    /// and `▪`.
+    pub fn list_bullets(
+        mut self,
+        bullets: impl IntoIterator<Item = impl Into<SharedString>>,
+    ) -> Self {
+        self.list_bullets = bullets.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the markers of the ordered lists cycled by the nesting depth, default is decimal,
+    /// lower alpha and lower roman.
+    ///
+    /// The `type` attribute of `<ol>` takes precedence.
+    pub fn list_markers(mut self, markers: impl IntoIterator<Item = ListMarker>) -> Self {
+        self.list_markers = markers.into_iter().collect();
+        self
+    }
+
+    /// Set blockquote horizontal padding, default is 1 rem.
+    pub fn blockquote_padding(mut self, padding: Rems) -> Self {
+        self.blockquote_padding = padding;
+        self
+    }
+
+    /// Set max height of table bodies, `None` to show all rows, default is `None`.
+    pub fn table_max_height(mut self, height: Option<Rems>) -> Self {
+        self.table_max_height = height;
+        self
+    }
+
+    /// Set whether to style the header rows of tables, default is true.
+    pub fn table_header(mut self, table_header: bool) -> Self {
+        self.table_header = table_header;
+        self
+    }
+
+    /// Set whether to stripe the even body rows of tables, default is true.
+    pub fn table_striped(mut self, table_striped: bool) -> Self {
+        self.table_striped = table_striped;
+        self
+    }
+
+    /// Set font size multipliers of headings level 1 to 6, see [`TextViewStyle::heading_scale`].
+    ///
+    /// The `heading_font_size` takes precedence if it is set.
+    pub fn heading_scale(mut self, scale: [f32; 6]) -> Self {
+        self.heading_scale = scale;
+        self
+    }
+
     pub fn heading_font_size<F>(mut self, f: F) -> Self
     where
         F: Fn(u8, Pixels) -> Pixels + Send + Sync + 'static,
@@ -64,4 +256,41 @@ impl TextViewStyle {
         self.code_block = style;
         self
     }
+
+    /// Set font family of code blocks, default is the `mono_font_family` of the theme.
+    pub fn code_font_family(mut self, family: impl Into<SharedString>) -> Self {
+        self.code_font_family = Some(family.into());
+        self
+    }
+
+    /// Set font size of code blocks, default is the `mono_font_size` of the theme.
+    pub fn code_font_size(mut self, size: impl Into<Pixels>) -> Self {
+        self.code_font_size = Some(size.into());
+        self
+    }
+
+    /// Set whether the inline code uses the font family of code blocks, default is false.
+    pub fn inline_code_font(mut self, inline_code_font: bool) -> Self {
+        self.inline_code_font = inline_code_font;
+        self
+    }
+
+    /// Set the language to hyphenate the long words, see [`TextViewStyle::hyphenation`].
+    #[cfg(feature = "hyphenation")]
+    pub fn hyphenation(mut self, lang: impl Into<SharedString>) -> Self {
+        self.hyphenation = Some(lang.into());
+        self
+    }
+
+    /// The font size of the heading `level`, by the `heading_font_size` or the `heading_scale`.
+    pub(crate) fn heading_size(&self, level: u8) -> Pixels {
+        if let Some(f) = self.heading_font_size.as_ref() {
+            return (f)(level, self.heading_base_font_size);
+        }
+        let scale = match level {
+            1..=6 => self.heading_scale[level as usize - 1],
+            _ => 1.,
+        };
+        rems(scale).to_pixels(self.heading_base_font_size)
+    }
 }
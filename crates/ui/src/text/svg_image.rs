@@ -0,0 +1,180 @@
+//! Rasterize the SVG images at the display size and the scale factor of the window.
+//!
+//! The SVG is loaded with the global asset cache, first rasterized at the intrinsic size to get
+//! the aspect ratio, then re-rasterized when the display scale changes significantly (by the
+//! [`SCALE_STEPS`]), e.g.: the window is moved to a display with a different DPI.
+//!
+//! The rasterized image is larger than the display size on the HiDPI display, so the image
+//! element is sized by the [`intrinsic_size`] at 1x.
+
+use std::{future::Future, sync::Arc};
+
+use anyhow::anyhow;
+use gpui::{
+    AbsoluteLength, App, Asset, DefiniteLength, ImageCacheError, Pixels, RenderImage, SharedUri,
+    Size, Window, px, size,
+};
+
+use super::image_data;
+
+/// The display scale is rounded to the steps per 1x, to avoid re-rasterizing on tiny changes.
+const SCALE_STEPS: u32 = 4;
+/// The max display scale, to limit the size of the rasterized image.
+const MAX_SCALE: u32 = 8 * SCALE_STEPS;
+
+/// Return true if the image url is an SVG, by the `.svg` extension or the `data:image/svg+xml`.
+pub(super) fn is_svg(url: &str) -> bool {
+    if let Some(data) = url.strip_prefix("data:") {
+        return data
+            .split([';', ','])
+            .next()
+            .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("image/svg+xml"));
+    }
+
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.to_ascii_lowercase().ends_with(".svg")
+}
+
+/// Load the SVG image rasterized for the display `width` (the intrinsic width if None).
+///
+/// Returns None if it is loading, the image rasterized at the intrinsic size is returned while
+/// the image is re-rasterizing.
+pub(super) fn load_svg(
+    url: &SharedUri,
+    width: Option<DefiniteLength>,
+    window: &mut Window,
+    cx: &mut App,
+) -> Option<Result<Arc<RenderImage>, ImageCacheError>> {
+    let intrinsic = match window.use_asset::<SvgImageLoader>(
+        &SvgSource {
+            url: url.clone(),
+            scale: SCALE_STEPS,
+        },
+        cx,
+    )? {
+        Ok(data) => data,
+        Err(err) => return Some(Err(err)),
+    };
+
+    let width = match width {
+        Some(DefiniteLength::Absolute(AbsoluteLength::Pixels(width))) => Some(width),
+        Some(DefiniteLength::Absolute(AbsoluteLength::Rems(width))) => {
+            Some(width.to_pixels(window.rem_size()))
+        }
+        _ => None,
+    };
+    let scale = scale_steps(
+        window.scale_factor(),
+        width,
+        intrinsic.size(0).width.0 as f32,
+    );
+    if scale == SCALE_STEPS {
+        return Some(Ok(intrinsic));
+    }
+
+    let source = SvgSource {
+        url: url.clone(),
+        scale,
+    };
+    match window.use_asset::<SvgImageLoader>(&source, cx) {
+        Some(Ok(data)) => Some(Ok(data)),
+        _ => Some(Ok(intrinsic)),
+    }
+}
+
+/// The intrinsic size of the SVG at 1x, the size to display the image rasterized by
+/// [`load_svg`] without the explicit size.
+///
+/// Returns None if it is loading or failed to load.
+pub(super) fn intrinsic_size(
+    url: &SharedUri,
+    window: &mut Window,
+    cx: &mut App,
+) -> Option<Size<Pixels>> {
+    let source = SvgSource {
+        url: url.clone(),
+        scale: SCALE_STEPS,
+    };
+    let data = window.use_asset::<SvgImageLoader>(&source, cx)?.ok()?;
+    let intrinsic = data.size(0);
+    Some(size(
+        px(intrinsic.width.0 as f32),
+        px(intrinsic.height.0 as f32),
+    ))
+}
+
+/// The display scale of the SVG in [`SCALE_STEPS`], the ratio of the display size in device
+/// pixels to the intrinsic size.
+fn scale_steps(scale_factor: f32, width: Option<Pixels>, intrinsic_width: f32) -> u32 {
+    let scale = match width {
+        Some(width) if intrinsic_width > 0. => scale_factor * width.0 / intrinsic_width,
+        _ => scale_factor,
+    };
+    ((scale * SCALE_STEPS as f32).ceil() as u32).clamp(1, MAX_SCALE)
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct SvgSource {
+    url: SharedUri,
+    /// The scale to rasterize in [`SCALE_STEPS`].
+    scale: u32,
+}
+
+enum SvgImageLoader {}
+
+impl Asset for SvgImageLoader {
+    type Source = SvgSource;
+    type Output = Result<Arc<RenderImage>, ImageCacheError>;
+
+    fn load(
+        source: Self::Source,
+        cx: &mut App,
+    ) -> impl Future<Output = Self::Output> + Send + 'static {
+        let bytes = image_data::load_bytes(source.url.clone(), cx);
+        let svg_renderer = cx.svg_renderer();
+        async move {
+            let bytes = bytes.await.map_err(other_error)?;
+
+            let scale = source.scale as f32 / SCALE_STEPS as f32;
+            svg_renderer
+                .render_single_frame(&bytes, scale, true)
+                .map_err(|err| other_error(anyhow!(err)))
+        }
+    }
+}
+
+fn other_error(err: anyhow::Error) -> ImageCacheError {
+    ImageCacheError::Other(Arc::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use gpui::px;
+
+    use super::{is_svg, scale_steps};
+
+    #[test]
+    fn test_is_svg() {
+        assert!(is_svg("https://example.com/logo.svg"));
+        assert!(is_svg("https://example.com/logo.SVG?v=1#top"));
+        assert!(is_svg("data:image/svg+xml;base64,PHN2Zz4="));
+        assert!(is_svg("data:image/svg+xml,%3Csvg%3E"));
+        assert!(!is_svg("https://example.com/logo.png"));
+        assert!(!is_svg("https://example.com/svg?format=png"));
+        assert!(!is_svg("data:image/png;base64,AAAA"));
+    }
+
+    #[test]
+    fn test_scale_steps() {
+        // The intrinsic size on the 1x and 2x displays.
+        assert_eq!(scale_steps(1., None, 100.), 4);
+        assert_eq!(scale_steps(2., None, 100.), 8);
+        // Scaled up to the display width.
+        assert_eq!(scale_steps(2., Some(px(300.)), 100.), 24);
+        // Rounded up to the steps.
+        assert_eq!(scale_steps(1., Some(px(110.)), 100.), 5);
+        // Clamped.
+        assert_eq!(scale_steps(2., Some(px(10000.)), 100.), 32);
+        assert_eq!(scale_steps(1., Some(px(1.)), 100.), 1);
+    }
+}
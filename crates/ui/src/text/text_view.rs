@@ -3,14 +3,17 @@ use std::sync::Arc;
 use gpui::prelude::FluentBuilder as _;
 use gpui::{
     AnyElement, App, Bounds, Element, ElementId, Entity, GlobalElementId, Hitbox, HitboxBehavior,
-    InspectorElementId, InteractiveElement, IntoElement, LayoutId, MouseDownEvent, MouseMoveEvent,
-    MouseUpEvent, ParentElement, Pixels, SharedString, StyleRefinement, Styled, Window, div,
+    InspectorElementId, InteractiveElement, IntoElement, LayoutId, Modifiers, MouseButton,
+    MouseDownEvent, MouseMoveEvent, MouseUpEvent, ParentElement, Pixels, SharedString,
+    StyleRefinement, Styled, Window, div,
 };
 
 use crate::StyledExt;
+use crate::menu::ContextMenuExt;
 use crate::scroll::ScrollableElement;
 use crate::text::TextViewFormat;
-use crate::text::node::CodeBlock;
+use crate::text::document::ParsedDocument;
+use crate::text::node::{CodeBlock, ImageNode, LinkMark};
 use crate::text::state::TextViewState;
 use crate::{global_state::GlobalState, text::TextViewStyle};
 
@@ -18,6 +21,60 @@ use crate::{global_state::GlobalState, text::TextViewStyle};
 pub(crate) type CodeBlockActionsFn =
     dyn Fn(&CodeBlock, &mut Window, &mut App) -> AnyElement + Send + Sync;
 
+/// Type for the math renderer, the arguments are the raw formula and whether it is the display
+/// math, see [`TextView::math_renderer`].
+pub(crate) type MathRenderFn =
+    dyn Fn(&str, bool, &mut Window, &mut App) -> AnyElement + Send + Sync;
+
+/// Type for the internal anchor link (e.g.: `#section-2`) click handler, the argument is the id.
+pub(crate) type AnchorClickFn = dyn Fn(&str, &mut Window, &mut App) + Send + Sync;
+
+/// Type for the image click handler, e.g.: to open the image in a lightbox.
+pub(crate) type ImageClickFn = dyn Fn(&ImageNode, &mut Window, &mut App) + Send + Sync;
+
+/// The node under the mouse when opening the context menu, the selection is used if none.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ContextMenuTarget {
+    Link(LinkMark),
+    Image(ImageNode),
+}
+
+/// Type for recording the [`ContextMenuTarget`] on right mouse down.
+pub(crate) type ContextMenuTargetFn = dyn Fn(ContextMenuTarget, &mut App) + Send + Sync;
+
+/// Type for the task list checkbox toggle handler, see [`TextView::on_checkbox_toggle`].
+pub(crate) type CheckboxToggleFn = dyn Fn(usize, bool, &mut Window, &mut App) + Send + Sync;
+
+/// Type for the link click handler, see [`TextView::on_link_click`].
+pub(crate) type LinkClickFn = dyn Fn(&LinkClickEvent, &mut Window, &mut App) + Send + Sync;
+
+/// Type for the visited link predicate, see [`TextView::link_visited`].
+pub(crate) type LinkVisitedFn = dyn Fn(&str) -> bool + Send + Sync;
+
+/// The format of the selected text copied to the clipboard, see [`TextView::on_copy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    /// The plain text, e.g.: copied by `cmd-c` or the "Copy" of the context menu.
+    PlainText,
+    /// The Markdown, copied by the "Copy as Markdown" of the context menu.
+    Markdown,
+}
+
+/// Type for the copy transform, see [`TextView::on_copy`].
+pub(crate) type CopyFn = dyn Fn(&str, CopyFormat) -> String + Send + Sync;
+
+/// The event of clicking a link (not the internal anchor links), see [`TextView::on_link_click`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkClickEvent {
+    /// The url of the link.
+    pub url: SharedString,
+    /// The modifiers held when clicking.
+    pub modifiers: Modifiers,
+    /// Whether to open the link in the background (e.g.: a background tab), it is `true` if
+    /// clicking with the secondary modifier (`cmd` on macOS, `ctrl` on others) or the middle button.
+    pub background: bool,
+}
+
 /// A text view that can render Markdown or HTML.
 ///
 /// ## Goals
@@ -39,12 +96,26 @@ pub struct TextView {
     id: ElementId,
     format: Option<TextViewFormat>,
     text: Option<SharedString>,
+    /// The document to render without parsing, see [`TextView::from_document`].
+    document: Option<ParsedDocument>,
     pub(crate) state: Option<Entity<TextViewState>>,
     text_view_style: TextViewStyle,
     style: StyleRefinement,
     selectable: bool,
     scrollable: bool,
+    sanitize: bool,
     code_block_actions: Option<Arc<CodeBlockActionsFn>>,
+    math_renderer: Option<Arc<MathRenderFn>>,
+    animate_images: bool,
+    copy_hidden_spoilers: bool,
+    copy_emoji_shortcodes: bool,
+    max_image_width: Option<Pixels>,
+    max_image_height: Option<Pixels>,
+    on_image_click: Option<Arc<ImageClickFn>>,
+    on_link_click: Option<Arc<LinkClickFn>>,
+    link_visited: Option<Arc<LinkVisitedFn>>,
+    on_checkbox_toggle: Option<Arc<CheckboxToggleFn>>,
+    on_copy: Option<Arc<CopyFn>>,
 }
 
 impl Styled for TextView {
@@ -54,48 +125,70 @@ impl Styled for TextView {
 }
 
 impl TextView {
-    /// Create new TextView with managed state.
-    pub fn new(state: &Entity<TextViewState>) -> Self {
+    /// Create the TextView with the default options, the base of the other constructors.
+    fn base(id: ElementId) -> Self {
         Self {
-            id: ElementId::Name(state.entity_id().to_string().into()),
-            state: Some(state.clone()),
+            id,
             format: None,
             text: None,
+            document: None,
+            state: None,
             text_view_style: TextViewStyle::default(),
             style: StyleRefinement::default(),
             selectable: false,
             scrollable: false,
+            sanitize: false,
             code_block_actions: None,
+            math_renderer: None,
+            animate_images: true,
+            copy_hidden_spoilers: false,
+            copy_emoji_shortcodes: false,
+            max_image_width: None,
+            max_image_height: None,
+            on_image_click: None,
+            on_link_click: None,
+            link_visited: None,
+            on_checkbox_toggle: None,
+            on_copy: None,
+        }
+    }
+
+    /// Create new TextView with managed state.
+    pub fn new(state: &Entity<TextViewState>) -> Self {
+        Self {
+            state: Some(state.clone()),
+            ..Self::base(ElementId::Name(state.entity_id().to_string().into()))
         }
     }
 
     /// Create a new markdown text view.
     pub fn markdown(id: impl Into<ElementId>, markdown: impl Into<SharedString>) -> Self {
         Self {
-            id: id.into(),
             format: Some(TextViewFormat::Markdown),
             text: Some(markdown.into()),
-            text_view_style: TextViewStyle::default(),
-            style: StyleRefinement::default(),
-            state: None,
-            selectable: false,
-            scrollable: false,
-            code_block_actions: None,
+            ..Self::base(id.into())
         }
     }
 
     /// Create a new html text view.
     pub fn html(id: impl Into<ElementId>, html: impl Into<SharedString>) -> Self {
         Self {
-            id: id.into(),
             format: Some(TextViewFormat::Html),
             text: Some(html.into()),
-            text_view_style: TextViewStyle::default(),
-            style: StyleRefinement::default(),
-            state: None,
-            selectable: false,
-            scrollable: false,
-            code_block_actions: None,
+            ..Self::base(id.into())
+        }
+    }
+
+    /// Create a text view to render the [`ParsedDocument`] without parsing, e.g.: built by
+    /// [`DocumentBuilder`], deserialized or cached.
+    ///
+    /// The document is set to the state if changed, see [`TextViewState::set_document`].
+    ///
+    /// [`DocumentBuilder`]: crate::text::DocumentBuilder
+    pub fn from_document(id: impl Into<ElementId>, document: ParsedDocument) -> Self {
+        Self {
+            document: Some(document),
+            ..Self::base(id.into())
         }
     }
 
@@ -128,6 +221,14 @@ impl TextView {
         self
     }
 
+    /// Set to sanitize the untrusted content, default is false.
+    ///
+    /// See [`TextViewState::set_sanitize`] for details.
+    pub fn sanitize(mut self, sanitize: bool) -> Self {
+        self.sanitize = sanitize;
+        self
+    }
+
     /// Set custom block actions for code blocks.
     ///
     /// The closure receives the [`CodeBlock`],
@@ -142,6 +243,135 @@ impl TextView {
         }));
         self
     }
+
+    /// Set the renderer of the math, to plug in a TeX engine.
+    ///
+    /// The closure receives the raw formula and whether it is the display math (e.g.: `$$ ... $$`
+    /// or the fenced `math` code block) or the inline math (e.g.: `$E = mc^2$`), and returns an
+    /// element to display. Without the renderer, the raw formula is displayed in monospace.
+    ///
+    /// NOTE: The rendered inline math is an element of the paragraph like the inline image,
+    /// so the text before and after it is laid out in separate lines.
+    pub fn math_renderer<F, E>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, bool, &mut Window, &mut App) -> E + Send + Sync + 'static,
+        E: IntoElement,
+    {
+        self.math_renderer = Some(Arc::new(move |formula, display, window, cx| {
+            f(formula, display, window, cx).into_any_element()
+        }));
+        self
+    }
+
+    /// Set to play the animated images (e.g.: GIF), default is true.
+    ///
+    /// If false, only the first frame is displayed, e.g.: to save the CPU for the long document.
+    /// The animations are paused while the images are out of the visible area anyway.
+    pub fn animate_images(mut self, animate: bool) -> Self {
+        self.animate_images = animate;
+        self
+    }
+
+    /// Set to include the hidden spoilers (e.g.: `<span class="spoiler">`) in the copied text,
+    /// default is false.
+    ///
+    /// The spoilers are hidden until clicked, the revealed spoilers are always copied.
+    pub fn copy_hidden_spoilers(mut self, copy: bool) -> Self {
+        self.copy_hidden_spoilers = copy;
+        self
+    }
+
+    /// Set to copy the emoji as the `:shortcode:` (e.g.: `:smile:`) instead of the Unicode emoji,
+    /// default is false.
+    ///
+    /// Only the emoji replaced from the shortcodes on parsing are copied as the shortcodes, see
+    /// [`TextViewState::set_emoji_shortcodes`].
+    pub fn copy_emoji_shortcodes(mut self, copy: bool) -> Self {
+        self.copy_emoji_shortcodes = copy;
+        self
+    }
+
+    /// Set the max width of the rendered images, default is None (unconstrained).
+    ///
+    /// The images are scaled down with the aspect ratio regardless of the declared `width` and
+    /// `height`, a zero value means unconstrained.
+    pub fn max_image_width(mut self, width: impl Into<Option<Pixels>>) -> Self {
+        self.max_image_width = width.into();
+        self
+    }
+
+    /// Set the max height of the rendered images, default is None (unconstrained).
+    ///
+    /// See [`TextView::max_image_width`].
+    pub fn max_image_height(mut self, height: impl Into<Option<Pixels>>) -> Self {
+        self.max_image_height = height.into();
+        self
+    }
+
+    /// Set the handler for clicking the images, e.g.: to show the image in a lightbox.
+    ///
+    /// The emoji images (with the `emoji` class) are not clickable.
+    /// The linked images open the link by default, hold the secondary modifier
+    /// (`cmd` on macOS, `ctrl` on others) to call this handler instead.
+    pub fn on_image_click<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ImageNode, &mut Window, &mut App) + Send + Sync + 'static,
+    {
+        self.on_image_click = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the handler for clicking the links, e.g.: to open the link in a background tab if
+    /// [`LinkClickEvent::background`] is `true`.
+    ///
+    /// Default is to open the link with the system browser, the internal anchor links
+    /// (e.g.: `#section-2`) are not passed to this handler, they scroll to the anchor.
+    pub fn on_link_click<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&LinkClickEvent, &mut Window, &mut App) + Send + Sync + 'static,
+    {
+        self.on_link_click = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the predicate to check if the url of a link has been visited, the visited links are
+    /// rendered in a muted color.
+    ///
+    /// It is checked on every render, so the newly visited links are updated on the next render,
+    /// e.g.: after [`TextView::on_link_click`] records the url and notifies.
+    pub fn link_visited<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.link_visited = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the handler for toggling the checkboxes of the task list items (e.g.: `- [ ] Todo`),
+    /// the checkboxes are only clickable if this is set.
+    ///
+    /// The arguments are the index of the item in the task list items of the document (in
+    /// document order, including the nested items) and the new checked state, e.g.: to update
+    /// the `[ ]` of the n-th task item in the source. The checkbox is repainted in the new state.
+    pub fn on_checkbox_toggle<F>(mut self, f: F) -> Self
+    where
+        F: Fn(usize, bool, &mut Window, &mut App) + Send + Sync + 'static,
+    {
+        self.on_checkbox_toggle = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the transform of the selected text before it is written to the clipboard, e.g.: to
+    /// strip the tracking parameters of the urls, default is to copy the text as it is.
+    ///
+    /// The arguments are the selected text and the [`CopyFormat`], returns the text to copy.
+    pub fn on_copy<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, CopyFormat) -> String + Send + Sync + 'static,
+    {
+        self.on_copy = Some(Arc::new(f));
+        self
+    }
 }
 
 impl IntoElement for TextView {
@@ -181,31 +411,47 @@ impl Element for TextView {
         } else {
             let default_format = self.format.unwrap_or(TextViewFormat::Markdown);
             let default_text = self.text.clone().unwrap_or_default();
+            let sanitize = self.sanitize;
 
             let state = window.use_keyed_state(
                 SharedString::from(format!("{}/state", self.id)),
                 cx,
                 move |_, cx| {
-                    if default_format == TextViewFormat::Markdown {
-                        TextViewState::markdown(default_text.as_str(), cx)
-                    } else {
-                        TextViewState::html(default_text.as_str(), cx)
-                    }
+                    TextViewState::new(default_format, default_text.as_str(), sanitize, cx)
                 },
             );
             self.state = Some(state.clone());
             state
         };
 
+        let viewport_size = window.viewport_size();
         state.update(cx, |state, cx| {
             state.code_block_actions = self.code_block_actions.clone();
+            state.math_renderer = self.math_renderer.clone();
+            state.animate_images = self.animate_images;
+            state.copy_hidden_spoilers = self.copy_hidden_spoilers;
+            state.copy_emoji_shortcodes = self.copy_emoji_shortcodes;
+            state.max_image_width = self.max_image_width;
+            state.max_image_height = self.max_image_height;
+            state.on_image_click = self.on_image_click.clone();
+            state.on_link_click = self.on_link_click.clone();
+            state.link_visited = self.link_visited.clone();
+            state.on_checkbox_toggle = self.on_checkbox_toggle.clone();
+            state.on_copy = self.on_copy.clone();
             state.selectable = self.selectable;
             state.scrollable = self.scrollable;
             state.text_view_style = self.text_view_style.clone();
+            state.set_sanitize(self.sanitize, cx);
+            state.update_viewport_size(viewport_size, cx);
 
             if let Some(text) = self.text.clone() {
                 state.set_text(text.as_str(), cx);
             }
+            if let Some(document) = self.document.as_ref()
+                && state.parsed_content.document != *document
+            {
+                state.set_document(document.clone(), cx);
+            }
         });
 
         let focus_handle = state.read(cx).focus_handle.clone();
@@ -219,8 +465,21 @@ impl Element for TextView {
             })
             .relative()
             .on_action(window.listener_for(&state, TextViewState::on_action_copy))
+            .on_action(window.listener_for(&state, TextViewState::on_action_focus_next_link))
+            .on_action(window.listener_for(&state, TextViewState::on_action_focus_prev_link))
+            .on_action(window.listener_for(&state, TextViewState::on_action_open_focused_link))
             .child(state.clone())
             .refine_style(&self.style)
+            .id(self.id.clone())
+            .context_menu({
+                let state = state.downgrade();
+                move |menu, _, cx| {
+                    let Some(state) = state.upgrade() else {
+                        return menu;
+                    };
+                    state.read(cx).context_menu(menu, state.downgrade())
+                }
+            })
             .into_any_element();
         let layout_id = el.request_layout(window, cx);
         (layout_id, TextViewLayoutState { state, element: el })
@@ -256,6 +515,24 @@ impl Element for TextView {
         request_layout.element.paint(window, cx);
         GlobalState::global_mut(cx).text_view_state_stack.pop();
 
+        // Clear the context menu target first, the links and images under the mouse record it later.
+        window.on_mouse_event({
+            let state = state.clone();
+            let hitbox = hitbox.clone();
+            move |event: &MouseDownEvent, phase, window, cx| {
+                if !phase.bubble()
+                    || event.button != MouseButton::Right
+                    || !hitbox.is_hovered(window)
+                {
+                    return;
+                }
+
+                state.update(cx, |state, _| {
+                    state.context_menu_target = None;
+                });
+            }
+        });
+
         if self.selectable {
             let is_selecting = state.read(cx).is_selecting;
             let has_selection = state.read(cx).has_selection();
@@ -265,7 +542,11 @@ impl Element for TextView {
                 let state = state.clone();
                 let hitbox = hitbox.clone();
                 move |event: &MouseDownEvent, phase, window, cx| {
-                    if !phase.bubble() || !hitbox.is_hovered(window) {
+                    // Keep the selection on right click, for copying in the context menu.
+                    if !phase.bubble()
+                        || event.button != MouseButton::Left
+                        || !hitbox.is_hovered(window)
+                    {
                         return;
                     }
 
@@ -277,6 +558,11 @@ impl Element for TextView {
             });
 
             if is_selecting {
+                // Auto-scroll while the mouse is held near the top or bottom edge.
+                if state.update(cx, |state, _| state.autoscroll_selection()) {
+                    window.request_animation_frame();
+                }
+
                 // move to update end position.
                 window.on_mouse_event({
                     let state = state.clone();
@@ -331,35 +617,60 @@ impl Element for TextView {
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        rc::Rc,
+        sync::{Arc, Mutex},
+    };
+
     use super::TextView;
-    use crate::text::TextViewState;
+    use crate::text::{
+        TextViewState,
+        node::{BlockNode, MarkerGutter},
+    };
     use gpui::{
         AppContext as _, Context, Entity, IntoElement, Modifiers, MouseButton, ParentElement as _,
-        Render, Styled as _, TestAppContext, VisualTestContext, Window, div, point, px,
+        Render, Styled as _, TestAppContext, TextStyleRefinement, VisualTestContext, Window, div,
+        http_client::{AsyncBody, FakeHttpClient, Response},
+        point, px,
     };
 
     struct TextViewTestRoot {
         text_view: Entity<TextViewState>,
+        configure: Rc<dyn Fn(TextView) -> TextView>,
     }
 
     impl TextViewTestRoot {
         fn new(text: &str, cx: &mut Context<Self>) -> Self {
             let text = text.to_string();
             let text_view = cx.new(|cx| TextViewState::markdown(&text, cx));
-            Self { text_view }
+            Self {
+                text_view,
+                configure: Rc::new(|text_view| text_view),
+            }
+        }
+
+        fn html(text: &str, cx: &mut Context<Self>) -> Self {
+            let text = text.to_string();
+            let text_view = cx.new(|cx| TextViewState::html(&text, cx));
+            Self {
+                text_view,
+                configure: Rc::new(|text_view| text_view),
+            }
+        }
+
+        /// Set the options of the rendered [`TextView`].
+        fn configure(mut self, f: impl Fn(TextView) -> TextView + 'static) -> Self {
+            self.configure = Rc::new(f);
+            self
         }
     }
 
     impl Render for TextViewTestRoot {
         fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+            let text_view = (self.configure)(TextView::new(&self.text_view).selectable(true));
             div()
                 .w(px(160.))
-                .child(
-                    div()
-                        .h(px(24.))
-                        .overflow_hidden()
-                        .child(TextView::new(&self.text_view).selectable(true)),
-                )
+                .child(div().h(px(24.)).overflow_hidden().child(text_view))
                 .child(div().h(px(40.)).child("footer"))
         }
     }
@@ -406,4 +717,246 @@ mod tests {
             "unexpected selection: {selected_text:?}"
         );
     }
+
+    #[gpui::test]
+    fn inline_math_uses_math_renderer(cx: &mut TestAppContext) {
+        cx.update(crate::init);
+        let formulas = Arc::new(Mutex::new(vec![]));
+        let (_, cx) = cx.add_window_view({
+            let formulas = formulas.clone();
+            move |_, cx| {
+                TextViewTestRoot::new("Energy $E = mc^2$", cx).configure(move |text_view| {
+                    let formulas = formulas.clone();
+                    text_view.math_renderer(move |formula, display, _, _| {
+                        formulas
+                            .lock()
+                            .unwrap()
+                            .push((formula.to_string(), display));
+                        div().child(formula.to_string())
+                    })
+                })
+            }
+        });
+        cx.run_until_parked();
+
+        assert_eq!(
+            formulas.lock().unwrap().last(),
+            Some(&("E = mc^2".to_string(), false))
+        );
+    }
+
+    #[gpui::test]
+    fn adjacent_inline_emoji_are_evenly_spaced(cx: &mut TestAppContext) {
+        cx.update(crate::init);
+        let emoji = r#"<img class="emoji" alt="😀" src="https://example.com/emoji.png">"#;
+        let (view, cx) = cx.add_window_view(|_, cx| {
+            TextViewTestRoot::html(&format!("<p>{}</p>", emoji.repeat(5)), cx)
+        });
+        cx.run_until_parked();
+
+        let bounds = view.read_with(cx, |root, cx| {
+            let BlockNode::Paragraph(paragraph) =
+                &root.text_view.read(cx).parsed_content.document.blocks[0]
+            else {
+                panic!("expected a paragraph");
+            };
+            paragraph.state.lock().unwrap().image_bounds.clone()
+        });
+
+        assert_eq!(bounds.len(), 5);
+        let step = bounds[1].left() - bounds[0].left();
+        assert!(step > px(0.));
+        for pair in bounds.windows(2) {
+            assert_eq!(pair[1].top(), pair[0].top());
+            assert_eq!(pair[1].left() - pair[0].left(), step);
+            assert!(pair[0].right() <= pair[1].left());
+        }
+    }
+
+    #[gpui::test]
+    fn appending_below_keeps_selection(cx: &mut TestAppContext) {
+        cx.update(crate::init);
+        let (view, cx) = cx.add_window_view(|_, cx| TextViewTestRoot::new("selected\n\nlast", cx));
+        cx.run_until_parked();
+
+        cx.simulate_mouse_down(
+            point(px(1.), px(8.)),
+            MouseButton::Left,
+            Modifiers::default(),
+        );
+        cx.simulate_mouse_move(
+            point(px(150.), px(8.)),
+            Some(MouseButton::Left),
+            Modifiers::default(),
+        );
+        cx.simulate_mouse_up(
+            point(px(150.), px(8.)),
+            MouseButton::Left,
+            Modifiers::default(),
+        );
+        cx.run_until_parked();
+        let selected_text = view.read_with(cx, |root, cx| root.text_view.read(cx).selected_text());
+        assert_eq!(selected_text.trim(), "selected");
+
+        let text_view = view.read_with(cx, |root, _| root.text_view.clone());
+        text_view.update(cx, |state, cx| state.push_str(" paragraph\n\nmore", cx));
+        cx.run_until_parked();
+
+        let selected_text = view.read_with(cx, |root, cx| root.text_view.read(cx).selected_text());
+        assert_eq!(selected_text.trim(), "selected");
+    }
+
+    #[gpui::test]
+    fn formatted_time_is_copied_as_displayed(cx: &mut TestAppContext) {
+        cx.update(crate::init);
+        let (view, cx) = cx.add_window_view(|_, cx| {
+            TextViewTestRoot::html(
+                r#"<p>Posted <time datetime="2024-01-02">Jan 2</time></p>"#,
+                cx,
+            )
+        });
+        let text_view = view.read_with(cx, |root, _| root.text_view.clone());
+        text_view.update(cx, |state, cx| {
+            state.set_time_formatter(|_, _| Some("2 days ago".into()), cx)
+        });
+        cx.run_until_parked();
+
+        cx.simulate_mouse_down(
+            point(px(1.), px(8.)),
+            MouseButton::Left,
+            Modifiers::default(),
+        );
+        cx.simulate_mouse_move(
+            point(px(150.), px(8.)),
+            Some(MouseButton::Left),
+            Modifiers::default(),
+        );
+        cx.simulate_mouse_up(
+            point(px(150.), px(8.)),
+            MouseButton::Left,
+            Modifiers::default(),
+        );
+        cx.run_until_parked();
+
+        let selected_text = view.read_with(cx, |root, cx| root.text_view.read(cx).selected_text());
+        assert_eq!(selected_text.trim(), "Posted 2 days ago");
+    }
+
+    /// Record the urls requested by the http client.
+    fn record_requests(cx: &mut TestAppContext) -> Arc<Mutex<Vec<String>>> {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        cx.update(|cx| {
+            let requests = requests.clone();
+            cx.set_http_client(FakeHttpClient::create(move |request| {
+                requests.lock().unwrap().push(request.uri().to_string());
+                async move { Ok(Response::builder().status(404).body(AsyncBody::default())?) }
+            }));
+        });
+        requests
+    }
+
+    #[gpui::test]
+    fn lazy_image_outside_content_mask_is_not_fetched(cx: &mut TestAppContext) {
+        cx.update(crate::init);
+        let requests = record_requests(cx);
+
+        // Far below the 24px clipped area and the margin to start loading.
+        let html = format!(
+            r#"<img loading="lazy" src="https://example.com/visible.png" width="8" height="8">{}<img loading="lazy" src="https://example.com/hidden.png" width="8" height="8">"#,
+            "<p>line</p>".repeat(100)
+        );
+        let (_, cx) = cx.add_window_view(|_, cx| TextViewTestRoot::html(&html, cx));
+        cx.run_until_parked();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests.iter().any(|url| url.contains("visible.png")));
+        assert!(!requests.iter().any(|url| url.contains("hidden.png")));
+    }
+
+    #[gpui::test]
+    fn inline_emoji_outside_content_mask_is_not_fetched(cx: &mut TestAppContext) {
+        cx.update(crate::init);
+        let requests = record_requests(cx);
+
+        // Far below the 24px clipped area and the margin to start loading.
+        let html = format!(
+            r#"<p>a <img class="emoji" alt="😀" src="https://example.com/visible.png"></p>{}<p>b <img class="emoji" alt="😀" src="https://example.com/hidden.png"></p>"#,
+            "<p>line</p>".repeat(100)
+        );
+        let (_, cx) = cx.add_window_view(|_, cx| TextViewTestRoot::html(&html, cx));
+        cx.run_until_parked();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests.iter().any(|url| url.contains("visible.png")));
+        assert!(!requests.iter().any(|url| url.contains("hidden.png")));
+    }
+
+    #[gpui::test]
+    fn marker_gutter_fits_widest_shaped_marker(cx: &mut TestAppContext) {
+        cx.update(crate::init);
+        let (_, cx) = cx.add_window_view(|_, cx| TextViewTestRoot::new("", cx));
+
+        let markers = (1..=12).map(|n| format!("{}.", n)).collect::<Vec<_>>();
+        let gutter = MarkerGutter::new(markers);
+        cx.update(|window, _| {
+            let shaped_width = |marker: &str, window: &mut Window| {
+                let text_style = window.text_style();
+                let font_size = text_style.font_size.to_pixels(window.rem_size());
+                window
+                    .text_system()
+                    .shape_line(
+                        marker.to_string().into(),
+                        font_size,
+                        &[text_style.to_run(marker.len())],
+                        None,
+                    )
+                    .width
+            };
+
+            let width = gutter.width(window);
+            assert_eq!(width, shaped_width("10.", window));
+            assert!(width > shaped_width("9.", window));
+            // Measured again in the larger font of the list item, e.g.: in a heading.
+            let style = TextStyleRefinement {
+                font_size: Some(px(32.).into()),
+                ..Default::default()
+            };
+            window.with_text_style(Some(style), |window| {
+                let large_width = gutter.width(window);
+                assert_eq!(large_width, shaped_width("10.", window));
+                assert!(large_width > width);
+            });
+            assert_eq!(gutter.width(window), width);
+        });
+    }
+
+    #[cfg(feature = "hyphenation")]
+    #[gpui::test]
+    fn hyphen_is_painted_at_soft_wrap(cx: &mut TestAppContext) {
+        cx.update(crate::init);
+        let (view, cx) = cx.add_window_view(|_, cx| {
+            TextViewTestRoot::new("Incomprehensibilities incomprehensibilities", cx).configure(
+                |text_view| {
+                    text_view.style(crate::text::TextViewStyle::default().hyphenation("en-us"))
+                },
+            )
+        });
+        cx.run_until_parked();
+
+        let (text, hyphens) = view.read_with(cx, |root, cx| {
+            let BlockNode::Paragraph(paragraph) =
+                &root.text_view.read(cx).parsed_content.document.blocks[0]
+            else {
+                panic!("expected a paragraph");
+            };
+            let state = paragraph.state.lock().unwrap();
+            (state.text.clone(), state.hyphens.clone())
+        });
+
+        // The long words are wrapped at the soft hyphens, painted as the hyphens at the line end.
+        assert!(!hyphens.is_empty());
+        for ix in hyphens {
+            assert_eq!(&text[ix..ix + 2], "\u{ad}");
+        }
+    }
 }
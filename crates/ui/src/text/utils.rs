@@ -1,61 +1,132 @@
-const NUMBERED_PREFIXES_1: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
-const NUMBERED_PREFIXES_2: &str = "abcdefghijklmnopqrstuvwxyz";
+use std::{collections::HashSet, hash::Hash};
 
-const BULLETS: [&str; 5] = ["•", "◦", "▪", "‣", "⁃"];
+/// Returns the alphabetic number, e.g.: `1` -> `a`, `26` -> `z`, `27` -> `aa`.
+pub(super) fn alpha_number(number: usize, uppercase: bool) -> String {
+    let base = if uppercase { b'A' } else { b'a' };
+    let mut number = number;
+    let mut chars = vec![];
+    while number > 0 {
+        number -= 1;
+        chars.push((base + (number % 26) as u8) as char);
+        number /= 26;
+    }
+    chars.iter().rev().collect()
+}
+
+/// Returns the roman number, e.g.: `4` -> `iv`, `1999` -> `mcmxcix`.
+///
+/// The `0` and the numbers greater than 3999 are not supported, fallback to decimal.
+pub(super) fn roman_number(number: usize, uppercase: bool) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+
+    if number == 0 || number > 3999 {
+        return number.to_string();
+    }
+
+    let mut number = number;
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            out.push_str(numeral);
+            number -= value;
+        }
+    }
+
+    if uppercase { out.to_uppercase() } else { out }
+}
 
-/// Returns the prefix for a list item.
-pub(super) fn list_item_prefix(ix: usize, ordered: bool, depth: usize) -> String {
-    if ordered {
-        if depth == 0 {
-            return format!("{}. ", ix + 1);
+/// Escapes the text for use in HTML content or attribute values.
+pub(super) fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
         }
+    }
+    out
+}
 
-        if depth == 1 {
-            return format!(
-                "{}. ",
-                NUMBERED_PREFIXES_1
-                    .chars()
-                    .nth(ix % NUMBERED_PREFIXES_1.len())
-                    .unwrap()
-            );
-        } else {
-            return format!(
-                "{}. ",
-                NUMBERED_PREFIXES_2
-                    .chars()
-                    .nth(ix % NUMBERED_PREFIXES_2.len())
-                    .unwrap()
-            );
+/// Returns the slug of the text for use as an anchor id, e.g.: `Hello, World!` -> `hello-world`.
+///
+/// Lowercase, keep the alphanumeric, `-` and `_`, replace the whitespace with `-`.
+pub(super) fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() || c == '_' {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-') && !slug.ends_with('-') {
+            slug.push('-');
         }
-    } else {
-        let depth = depth.min(BULLETS.len() - 1);
-        let bullet = BULLETS[depth];
-        return format!("{} ", bullet);
     }
+
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Remove the items with the same key as a previous one, keep the first in order.
+pub(super) fn dedup_by_key<T, K: Eq + Hash>(items: &mut Vec<T>, mut key: impl FnMut(&T) -> K) {
+    let mut seen = HashSet::new();
+    items.retain(|item| seen.insert(key(item)));
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::text::utils::list_item_prefix;
+    use crate::text::utils::{alpha_number, dedup_by_key, escape_html, roman_number, slugify};
+
+    #[test]
+    fn test_alpha_and_roman_number() {
+        assert_eq!(alpha_number(1, false), "a");
+        assert_eq!(alpha_number(26, false), "z");
+        assert_eq!(alpha_number(27, true), "AA");
+        assert_eq!(alpha_number(53, false), "ba");
+        assert_eq!(roman_number(1, false), "i");
+        assert_eq!(roman_number(4, true), "IV");
+        assert_eq!(roman_number(14, false), "xiv");
+        assert_eq!(roman_number(1999, false), "mcmxcix");
+        assert_eq!(roman_number(0, false), "0");
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_dedup_by_key() {
+        let mut items = vec!["a", "B", "b", "c", "A"];
+        dedup_by_key(&mut items, |item| item.to_lowercase());
+        assert_eq!(items, vec!["a", "B", "c"]);
+    }
 
     #[test]
-    fn test_list_item_prefix() {
-        assert_eq!(list_item_prefix(0, true, 0), "1. ");
-        assert_eq!(list_item_prefix(1, true, 0), "2. ");
-        assert_eq!(list_item_prefix(2, true, 0), "3. ");
-        assert_eq!(list_item_prefix(10, true, 0), "11. ");
-        assert_eq!(list_item_prefix(0, true, 1), "A. ");
-        assert_eq!(list_item_prefix(1, true, 1), "B. ");
-        assert_eq!(list_item_prefix(2, true, 1), "C. ");
-        assert_eq!(list_item_prefix(0, true, 2), "a. ");
-        assert_eq!(list_item_prefix(1, true, 2), "b. ");
-        assert_eq!(list_item_prefix(6, true, 2), "g. ");
-        assert_eq!(list_item_prefix(0, true, 1), "A. ");
-        assert_eq!(list_item_prefix(0, true, 2), "a. ");
-        assert_eq!(list_item_prefix(0, false, 0), "• ");
-        assert_eq!(list_item_prefix(0, false, 1), "◦ ");
-        assert_eq!(list_item_prefix(0, false, 2), "▪ ");
-        assert_eq!(list_item_prefix(0, false, 3), "‣ ");
-        assert_eq!(list_item_prefix(0, false, 4), "⁃ ");
+    fn test_slugify() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(
+            slugify("  Getting   Started -- Guide "),
+            "getting-started-guide"
+        );
+        assert_eq!(slugify("snake_case & API v2.0"), "snake_case-api-v20");
+        assert_eq!(slugify("你好 世界"), "你好-世界");
     }
 }
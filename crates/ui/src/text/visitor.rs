@@ -0,0 +1,276 @@
+//! The visitors to inspect or transform the [`ParsedDocument`] AST.
+//!
+//! The nodes are visited depth-first in document order:
+//!
+//! 1. [`Visitor::visit_block`] is called for the block before its content.
+//! 2. For the [`BlockNode::Paragraph`] and [`BlockNode::Heading`], each inline node is visited by
+//!    [`Visitor::visit_inline`], then [`Visitor::visit_text`] if it is not an image.
+//! 3. The nested blocks are visited, e.g.: the children of the list item, blockquote and the table cells
+//!    (row by row).
+
+use crate::text::{
+    document::ParsedDocument,
+    node::{BlockNode, InlineNode},
+};
+
+/// A visitor to inspect the nodes of a [`ParsedDocument`], see [`ParsedDocument::walk`].
+///
+/// All methods have a default empty implementation, only implement the needed ones.
+///
+/// ```
+/// use gpui_component::text::{BlockNode, DocumentBuilder, Visitor};
+///
+/// #[derive(Default)]
+/// struct HeadingCounter(usize);
+///
+/// impl Visitor for HeadingCounter {
+///     fn visit_block(&mut self, block: &BlockNode) {
+///         if matches!(block, BlockNode::Heading { .. }) {
+///             self.0 += 1;
+///         }
+///     }
+/// }
+///
+/// let document = DocumentBuilder::new()
+///     .heading(1, "Title")
+///     .paragraph(|p| p.text("Hello"))
+///     .heading(2, "Section")
+///     .build();
+///
+/// let mut counter = HeadingCounter::default();
+/// document.walk(&mut counter);
+/// assert_eq!(counter.0, 2);
+/// ```
+pub trait Visitor {
+    /// Called for each block node, before its inline nodes and nested blocks.
+    fn visit_block(&mut self, _block: &BlockNode) {}
+
+    /// Called for each inline node of the paragraphs and headings, including the images.
+    fn visit_inline(&mut self, _inline: &InlineNode) {}
+
+    /// Called for the text of each inline node, the images are skipped.
+    fn visit_text(&mut self, _text: &str) {}
+}
+
+/// A visitor to transform the nodes of a [`ParsedDocument`], see [`ParsedDocument::walk_mut`].
+///
+/// The nested blocks are visited after [`VisitorMut::visit_block_mut`], so the changed children
+/// will be visited, e.g.: strip the images, rewrite the link URLs.
+pub trait VisitorMut {
+    /// Called for each block node, before its inline nodes and nested blocks.
+    fn visit_block_mut(&mut self, _block: &mut BlockNode) {}
+
+    /// Called for each inline node of the paragraphs and headings, including the images.
+    fn visit_inline_mut(&mut self, _inline: &mut InlineNode) {}
+}
+
+impl ParsedDocument {
+    /// Walk all nodes in document order with the [`Visitor`].
+    pub fn walk(&self, visitor: &mut impl Visitor) {
+        for block in self.blocks.iter() {
+            walk_block(block, visitor);
+        }
+    }
+
+    /// Walk all nodes in document order with the [`VisitorMut`], the nodes can be changed in place.
+    pub fn walk_mut(&mut self, visitor: &mut impl VisitorMut) {
+        for block in self.blocks.iter_mut() {
+            walk_block_mut(block, visitor);
+        }
+    }
+}
+
+fn walk_block(block: &BlockNode, visitor: &mut impl Visitor) {
+    visitor.visit_block(block);
+
+    match block {
+        BlockNode::Paragraph(paragraph)
+        | BlockNode::Heading {
+            children: paragraph,
+            ..
+        } => {
+            for inline in paragraph.children.iter() {
+                visitor.visit_inline(inline);
+                if inline.image.is_none() {
+                    visitor.visit_text(&inline.text);
+                }
+            }
+        }
+        BlockNode::Root { children, .. }
+        | BlockNode::Blockquote { children, .. }
+        | BlockNode::Admonition { children, .. }
+        | BlockNode::List { children, .. }
+        | BlockNode::ListItem { children, .. }
+        | BlockNode::Footnotes { children }
+        | BlockNode::FootnoteDefinition { children, .. } => {
+            for child in children.iter() {
+                walk_block(child, visitor);
+            }
+        }
+        BlockNode::Table(table) => {
+            for row in table.children.iter() {
+                for cell in row.children.iter() {
+                    for child in cell.children.iter() {
+                        walk_block(child, visitor);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+pub(super) fn walk_block_mut(block: &mut BlockNode, visitor: &mut impl VisitorMut) {
+    visitor.visit_block_mut(block);
+
+    match block {
+        BlockNode::Paragraph(paragraph)
+        | BlockNode::Heading {
+            children: paragraph,
+            ..
+        } => {
+            for inline in paragraph.children.iter_mut() {
+                visitor.visit_inline_mut(inline);
+            }
+        }
+        BlockNode::Root { children, .. }
+        | BlockNode::Blockquote { children, .. }
+        | BlockNode::Admonition { children, .. }
+        | BlockNode::List { children, .. }
+        | BlockNode::ListItem { children, .. }
+        | BlockNode::Footnotes { children }
+        | BlockNode::FootnoteDefinition { children, .. } => {
+            for child in children.iter_mut() {
+                walk_block_mut(child, visitor);
+            }
+        }
+        BlockNode::Table(table) => {
+            for row in table.children.iter_mut() {
+                for cell in row.children.iter_mut() {
+                    for child in cell.children.iter_mut() {
+                        walk_block_mut(child, visitor);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        highlighter::HighlightTheme,
+        text::{
+            format,
+            node::{BlockNode, InlineNode, NodeContext},
+        },
+    };
+
+    use super::{Visitor, VisitorMut};
+
+    #[derive(Default)]
+    struct Collector {
+        blocks: Vec<&'static str>,
+        images: usize,
+        texts: Vec<String>,
+    }
+
+    impl Visitor for Collector {
+        fn visit_block(&mut self, block: &BlockNode) {
+            self.blocks.push(match block {
+                BlockNode::Heading { .. } => "heading",
+                BlockNode::Paragraph(_) => "paragraph",
+                BlockNode::List { .. } => "list",
+                BlockNode::ListItem { .. } => "list_item",
+                BlockNode::Blockquote { .. } => "blockquote",
+                BlockNode::CodeBlock(_) => "code_block",
+                _ => "other",
+            });
+        }
+
+        fn visit_inline(&mut self, inline: &InlineNode) {
+            if inline.image.is_some() {
+                self.images += 1;
+            }
+        }
+
+        fn visit_text(&mut self, text: &str) {
+            self.texts.push(text.to_string());
+        }
+    }
+
+    struct RewriteLinks;
+
+    impl VisitorMut for RewriteLinks {
+        fn visit_block_mut(&mut self, block: &mut BlockNode) {
+            // Strip the images.
+            if let BlockNode::Paragraph(paragraph) = block {
+                paragraph
+                    .children_mut()
+                    .retain(|inline| inline.image.is_none());
+            }
+        }
+
+        fn visit_inline_mut(&mut self, inline: &mut InlineNode) {
+            for (_, mark) in inline.marks.iter_mut() {
+                if let Some(link) = mark.link.as_mut() {
+                    link.url = link.url.replace("http://", "https://").into();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let source = indoc::indoc! {r#"
+            # Title
+
+            Hello [link](http://a.com) ![img](https://a.com/1.png)
+
+            > - Item
+
+            ```rust
+            fn main() {}
+            ```
+        "#};
+        let mut cx = NodeContext::default();
+        let mut document =
+            format::markdown::parse(source, &mut cx, &HighlightTheme::default_light()).unwrap();
+
+        let mut collector = Collector::default();
+        document.walk(&mut collector);
+        assert_eq!(
+            collector.blocks,
+            vec![
+                "heading",
+                "paragraph",
+                "blockquote",
+                "list",
+                "list_item",
+                "paragraph",
+                "code_block"
+            ]
+        );
+        assert_eq!(collector.images, 1);
+        assert_eq!(
+            collector.texts,
+            vec!["Title", "Hello ", "link", " ", "Item"]
+        );
+
+        document.walk_mut(&mut RewriteLinks);
+        assert_eq!(
+            document.to_markdown(),
+            indoc::indoc! {r#"
+                # Title
+
+                Hello [link](https://a.com)
+
+                > - Item
+
+                ```rust
+                fn main() {}
+                ```"#}
+        );
+    }
+}
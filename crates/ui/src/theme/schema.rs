@@ -86,6 +86,9 @@ pub struct ThemeConfigColors {
     /// Default background color.
     #[serde(rename = "background")]
     pub background: Option<SharedString>,
+    /// Blockquote bar color in TextView.
+    #[serde(rename = "blockquote.border")]
+    pub blockquote_border: Option<SharedString>,
     /// Default border color
     #[serde(rename = "border")]
     pub border: Option<SharedString>,
@@ -134,6 +137,12 @@ pub struct ThemeConfigColors {
     /// Bearish color for candlestick charts (downward price movement).
     #[serde(rename = "chart_bearish")]
     pub chart_bearish: Option<SharedString>,
+    /// Code block background color in TextView.
+    #[serde(rename = "code_block.background")]
+    pub code_block: Option<SharedString>,
+    /// Code block text color in TextView.
+    #[serde(rename = "code_block.foreground")]
+    pub code_block_foreground: Option<SharedString>,
     /// Danger background color.
     #[serde(rename = "danger.background")]
     pub danger: Option<SharedString>,
@@ -173,6 +182,9 @@ pub struct ThemeConfigColors {
     /// Info hover background color.
     #[serde(rename = "info.hover.background")]
     pub info_hover: Option<SharedString>,
+    /// Inline code background color in TextView.
+    #[serde(rename = "inline_code.background")]
+    pub inline_code: Option<SharedString>,
     /// Border color for inputs such as Input, Select, etc.
     #[serde(rename = "input.border")]
     pub input: Option<SharedString>,
@@ -203,6 +215,9 @@ pub struct ThemeConfigColors {
     /// Hover background color for ListItem.
     #[serde(rename = "list.hover.background")]
     pub list_hover: Option<SharedString>,
+    /// Background color of the highlighted text in TextView, e.g.: search matches.
+    #[serde(rename = "mark.background")]
+    pub mark: Option<SharedString>,
     /// Muted backgrounds such as Skeleton and Switch.
     #[serde(rename = "muted.background")]
     pub muted: Option<SharedString>,
@@ -332,6 +347,9 @@ pub struct ThemeConfigColors {
     /// Table active item border color.
     #[serde(rename = "table.active.border")]
     pub table_active_border: Option<SharedString>,
+    /// Table border color in TextView.
+    #[serde(rename = "table.border")]
+    pub table_border: Option<SharedString>,
     /// Stripe background color for even TableRow.
     #[serde(rename = "table.even.background")]
     pub table_even: Option<SharedString>,
@@ -566,6 +584,7 @@ impl ThemeColor {
                 )
         );
         apply_color!(group_box_foreground, fallback = self.foreground);
+        apply_color!(blockquote_border, fallback = self.secondary_active);
         apply_color!(caret, fallback = self.primary);
         apply_color!(chart_1, fallback = self.blue.lighten(0.4));
         apply_color!(chart_2, fallback = self.blue.lighten(0.2));
@@ -574,6 +593,8 @@ impl ThemeColor {
         apply_color!(chart_5, fallback = self.blue.darken(0.4));
         apply_color!(chart_bullish, fallback = self.green);
         apply_color!(chart_bearish, fallback = self.red);
+        apply_color!(code_block, fallback = self.muted);
+        apply_color!(code_block_foreground, fallback = self.foreground);
         apply_color!(danger, fallback = self.red);
         apply_color!(danger_active, fallback = self.danger.darken(active_darken));
         apply_color!(danger_foreground, fallback = self.primary_foreground);
@@ -591,6 +612,7 @@ impl ThemeColor {
         );
         apply_color!(drag_border, fallback = self.primary.opacity(0.65));
         apply_color!(drop_target, fallback = self.primary.opacity(0.2));
+        apply_color!(inline_code, fallback = self.accent);
         apply_color!(input, fallback = self.border);
         apply_color!(link, fallback = self.primary);
         apply_color!(link_active, fallback = self.link);
@@ -642,6 +664,7 @@ impl ThemeColor {
         apply_color!(table, fallback = self.list);
         apply_color!(table_active, fallback = self.list_active);
         apply_color!(table_active_border, fallback = self.list_active_border);
+        apply_color!(table_border, fallback = self.border);
         apply_color!(table_even, fallback = self.list_even);
         apply_color!(table_head, fallback = self.list_head);
         apply_color!(table_head_foreground, fallback = self.muted_foreground);
@@ -661,6 +684,9 @@ impl ThemeColor {
         self.list_active = self.list_active.alpha(self.list_active.a.min(0.2));
         self.table_active = self.table_active.alpha(self.table_active.a.min(0.2));
         self.selection = self.selection.alpha(self.selection.a.min(0.3));
+
+        // Fallback to the selection with the opacity ensured above.
+        apply_color!(mark, fallback = self.selection.saturation(0.1));
     }
 }
 
@@ -19,6 +19,8 @@ pub struct ThemeColor {
     pub accordion_hover: Hsla,
     /// Default background color.
     pub background: Hsla,
+    /// Blockquote bar color in TextView.
+    pub blockquote_border: Hsla,
     /// Default border color
     pub border: Hsla,
     /// Button primary background color, fallback to `primary`.
@@ -49,6 +51,10 @@ pub struct ThemeColor {
     pub chart_bullish: Hsla,
     /// Bearish color for candlestick charts (downward price movement).
     pub chart_bearish: Hsla,
+    /// Code block background color in TextView.
+    pub code_block: Hsla,
+    /// Code block text color in TextView.
+    pub code_block_foreground: Hsla,
     /// Danger background color.
     pub danger: Hsla,
     /// Danger active background color.
@@ -75,6 +81,8 @@ pub struct ThemeColor {
     pub info_foreground: Hsla,
     /// Info hover background color.
     pub info_hover: Hsla,
+    /// Inline code background color in TextView.
+    pub inline_code: Hsla,
     /// Border color for inputs such as Input, Select, etc.
     pub input: Hsla,
     /// Link text color.
@@ -95,6 +103,8 @@ pub struct ThemeColor {
     pub list_head: Hsla,
     /// Hover background color for ListItem.
     pub list_hover: Hsla,
+    /// Background color of the highlighted text in TextView, e.g.: search matches.
+    pub mark: Hsla,
     /// Muted backgrounds such as Skeleton and Switch.
     pub muted: Hsla,
     /// Muted text color, as used in disabled text.
@@ -181,6 +191,8 @@ pub struct ThemeColor {
     pub table_active: Hsla,
     /// Table active item border color.
     pub table_active_border: Hsla,
+    /// Table border color in TextView.
+    pub table_border: Hsla,
     /// Stripe background color for even TableRow.
     pub table_even: Hsla,
     /// Table head background color.